@@ -0,0 +1,103 @@
+//! Benchmarks of tokenizing, parsing and evaluating, run with `cargo bench`
+//!
+//! `cargo bench -- NAME` runs only the benchmarks whose names contain NAME. Criterion keeps the
+//! results of the previous run in `target/criterion` and reports the change against them.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use sc::ast::Expr;
+use sc::tokenizer::Tokenizer;
+use sc::{Calculator, CompiledExpr, Session, Value};
+use std::hint::black_box;
+
+const SESSION: &str = include_str!("fixtures/session.sc");
+const BIGINT: &str = include_str!("fixtures/bigint.sc");
+
+fn tokenizing(c: &mut Criterion) {
+    let long = "12.5 * (3 + 4h30m) / 0xff - 2 ** 10 >= 1000 && x != y || ".repeat(1000) + "1";
+    c.bench_function("tokenize/long", |b| b.iter(|| tokenize(&long)));
+    c.bench_function("tokenize/bigint", |b| {
+        b.iter(|| lines(BIGINT).for_each(tokenize))
+    });
+}
+
+fn parsing(c: &mut Criterion) {
+    let nested = format!("{}1{}", "(".repeat(900), " + 1)".repeat(900));
+    let chain = format!("1{}", " + 1".repeat(900));
+    c.bench_function("parse/nested", |b| b.iter(|| parse(&nested)));
+    c.bench_function("parse/chain", |b| b.iter(|| parse(&chain)));
+}
+
+fn evaluating(c: &mut Criterion) {
+    let nested = format!("{}1{}", "(".repeat(900), " + 1)".repeat(900));
+    let chain = format!("1{}", " + 1".repeat(900));
+    let tower = format!("1{}", " ** 1".repeat(900));
+    c.bench_function("eval/nested", |b| b.iter(|| eval(&nested)));
+    c.bench_function("eval/chain", |b| b.iter(|| eval(&chain)));
+    c.bench_function("eval/tower", |b| b.iter(|| eval(&tower)));
+    c.bench_function("eval/factorial", |b| b.iter(|| eval("factorial(5000)")));
+    c.bench_function("eval/pow", |b| b.iter(|| eval("3 ** 200000")));
+    c.bench_function("eval/fraction", |b| {
+        b.iter(|| eval("1.0 / 7 * 10 ** 300 + 1.0 / 3"))
+    });
+}
+
+fn sessions(c: &mut Criterion) {
+    c.bench_function("session/fixture", |b| b.iter(|| session(SESSION)));
+    c.bench_function("session/bigint", |b| b.iter(|| session(BIGINT)));
+}
+
+fn compiling(c: &mut Criterion) {
+    c.bench_function("compiled/reparse", |b| b.iter(reparse));
+    c.bench_function("compiled/eval_with", |b| b.iter(compiled));
+}
+
+criterion_group!(benches, tokenizing, parsing, evaluating, sessions, compiling);
+criterion_main!(benches);
+
+fn tokenize(text: &str) {
+    for token in Tokenizer::tokens(text.chars()) {
+        black_box(token.unwrap());
+    }
+}
+
+fn parse(text: &str) -> Expr {
+    let mut calculator = Calculator::default();
+    for token in Tokenizer::tokens(text.chars()) {
+        calculator.handle_token(token.unwrap()).unwrap();
+    }
+    calculator.parse().unwrap()
+}
+
+fn eval(text: &str) {
+    let expr = parse(text);
+    black_box(Calculator::default().evaluate(&expr).unwrap());
+}
+
+/// Lines of a fixture, except for comments
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().filter(|line| !line.starts_with('#'))
+}
+
+fn session(text: &str) {
+    let mut session = Session::default();
+    for line in lines(text) {
+        black_box(session.eval(line).unwrap());
+    }
+}
+
+/// Evaluate an expression for many values of `x` by parsing it every time
+fn reparse() {
+    let calculator = Calculator::default();
+    for x in 0..100 {
+        let expr = parse(&format!("{} ** 3 - 2 * {} + 1", x, x));
+        black_box(calculator.evaluate(&expr).unwrap());
+    }
+}
+
+/// Same as [`reparse`], with the expression parsed once
+fn compiled() {
+    let mut expr: CompiledExpr = "x ** 3 - 2 * x + 1".parse().unwrap();
+    for x in 0..100 {
+        black_box(expr.eval_with(&[("x", Value::from(x))]).unwrap());
+    }
+}
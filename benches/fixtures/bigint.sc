@@ -0,0 +1,11 @@
+# Big integer workloads
+factorial(2000)
+factorial(5000) / factorial(4990)
+2 ** 20000 - 1
+3 ** 12345 + 7 ** 4321
+(10 ** 500 + 1) * (10 ** 500 - 1)
+factorial(3000) / factorial(1500) ** 2
+fib(20000)
+isprime(2 ** 127 - 1)
+gcd(2 ** 3000 - 1, 2 ** 2000 - 1)
+1.0 / 7 * 10 ** 300
@@ -0,0 +1,19 @@
+# Definitions and expressions of a typical interactive session
+rate = 0.0425
+principal = 250000
+months = 360
+monthly = rate / 12
+payment(p, r, n) = p * r / (1 - (1 + r) ** -n)
+payment(principal, monthly, months)
+payment(principal, monthly, months) * months - principal
+tax = 0.21
+net(x) = x * (1 - tax)
+net(84000) / 12
+1h30m + 45m * 3
+(2h15m - 20m) / 3
+popcount(0xdeadbeef) + rotl(0xff, 4, 16) * 0b1010
+0x7fff < 0o777 || 2 ** 10 >= 1000 && 0xff != 255
+max(1, 2, 3) + min(4, 5, 6) * abs(-7)
+gcd(1071, 462) + lcm(21, 6)
+3.5 km / 2 h to m / s
+powmod(7, 10 ** 20, 1000000007) + isqrt(10 ** 40)
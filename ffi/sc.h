@@ -0,0 +1,22 @@
+#ifndef SC_H
+#define SC_H
+
+typedef struct sc_session sc_session;
+
+/* Evaluates the expression. Returns 0 and sets *out to the result, or returns -1 and sets *err
+ * to the error message. The other pointer is set to NULL, either may be NULL if not needed.
+ * Returned strings must be released with sc_free. */
+int sc_eval(const char *expr, char **out, char **err);
+
+void sc_free(char *s);
+
+/* Session that keeps variables and functions between evaluations, i.e. `x = 2` then `x * 3`.
+ * Must be released with sc_session_free. */
+sc_session *sc_session_new(void);
+
+/* Same as sc_eval, function definitions set *out to an empty string. */
+int sc_session_eval(sc_session *session, const char *line, char **out, char **err);
+
+void sc_session_free(sc_session *session);
+
+#endif
@@ -0,0 +1,174 @@
+//! C interface of the calculator, see `sc.h`
+
+use sc::Session;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Evaluate the expression with the default settings
+///
+/// Returns 0 and sets `*out` to the result, or returns -1 and sets `*err` to the error message.
+/// The other one is set to null. Returned strings must be released with [`sc_free`].
+///
+/// # Safety
+///
+/// `expr` must be a NUL-terminated string, `out` and `err` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sc_eval(
+    expr: *const c_char,
+    out: *mut *mut c_char,
+    err: *mut *mut c_char,
+) -> c_int {
+    let result = text(expr).and_then(|text| {
+        // Panics must not unwind into C
+        panic::catch_unwind(|| sc::eval(text).map_err(|e| e.to_string()))
+            .unwrap_or_else(|_| Err("Internal error".to_owned()))
+    });
+    set_result(result, out, err)
+}
+
+/// Create a session that keeps variables and functions between evaluations
+///
+/// The session must be released with [`sc_session_free`].
+#[no_mangle]
+pub extern "C" fn sc_session_new() -> *mut Session {
+    Box::into_raw(Box::default())
+}
+
+/// Evaluate an expression or a definition like `x = 2` in the session
+///
+/// Same as [`sc_eval`] otherwise, function definitions set `*out` to an empty string.
+///
+/// # Safety
+///
+/// `session` must be returned by [`sc_session_new`] and not released, the other arguments are
+/// the same as for [`sc_eval`].
+#[no_mangle]
+pub unsafe extern "C" fn sc_session_eval(
+    session: *mut Session,
+    line: *const c_char,
+    out: *mut *mut c_char,
+    err: *mut *mut c_char,
+) -> c_int {
+    let Some(session) = session.as_mut() else {
+        return set_result(Err("No session".to_owned()), out, err);
+    };
+    let result = text(line).and_then(|text| {
+        let eval = AssertUnwindSafe(|| session.eval_text(text).map_err(|e| e.to_string()));
+        match panic::catch_unwind(eval) {
+            Ok(result) => result.map(Option::unwrap_or_default),
+            Err(_) => Err("Internal error".to_owned()),
+        }
+    });
+    set_result(result, out, err)
+}
+
+/// Release a session returned by [`sc_session_new`]
+///
+/// # Safety
+///
+/// `session` must be null or returned by [`sc_session_new`] and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn sc_session_free(session: *mut Session) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+/// Release a string returned by [`sc_eval`]
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by [`sc_eval`] that wasn't released yet.
+#[no_mangle]
+pub unsafe extern "C" fn sc_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Text of a C string argument
+unsafe fn text<'a>(s: *const c_char) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err("No expression".to_owned());
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| "Expression is not valid UTF-8".to_owned())
+}
+
+/// Return the result through the output pointers, see [`sc_eval`]
+unsafe fn set_result(
+    result: Result<String, String>,
+    out: *mut *mut c_char,
+    err: *mut *mut c_char,
+) -> c_int {
+    let (status, text, target, other) = match result {
+        Ok(value) => (0, value, out, err),
+        Err(message) => (-1, message, err, out),
+    };
+    if !other.is_null() {
+        *other = ptr::null_mut();
+    }
+    if !target.is_null() {
+        *target = into_raw(text);
+    }
+    status
+}
+
+fn into_raw(text: String) -> *mut c_char {
+    let text = CString::new(text.replace('\0', "")).unwrap_or_default();
+    text.into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Status and text of the output that was set
+    fn eval(expr: &CStr) -> (c_int, String) {
+        call(|out, err| unsafe { sc_eval(expr.as_ptr(), out, err) })
+    }
+
+    fn call(f: impl FnOnce(*mut *mut c_char, *mut *mut c_char) -> c_int) -> (c_int, String) {
+        let mut out = ptr::null_mut();
+        let mut err = ptr::null_mut();
+        let status = f(&mut out, &mut err);
+        unsafe {
+            let text = if status == 0 { out } else { err };
+            assert!(!text.is_null());
+            assert!(if status == 0 {
+                err.is_null()
+            } else {
+                out.is_null()
+            });
+            let result = CStr::from_ptr(text).to_str().unwrap().to_owned();
+            sc_free(text);
+            (status, result)
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval(c"2 ** 64"), (0, "18446744073709551616".into()));
+        assert_eq!(eval(c"1 +"), (-1, "Number expected".into()));
+        let status = unsafe { sc_eval(c"1".as_ptr(), ptr::null_mut(), ptr::null_mut()) };
+        assert_eq!(status, 0);
+    }
+
+    #[test]
+    fn test_session() {
+        let session = sc_session_new();
+        let eval = |line: &CStr| {
+            call(|out, err| unsafe { sc_session_eval(session, line.as_ptr(), out, err) })
+        };
+        assert_eq!(eval(c"x = 6"), (0, "6".into()));
+        assert_eq!(eval(c"f(a) = a * x"), (0, "".into()));
+        assert_eq!(eval(c"f(7)"), (0, "42".into()));
+        assert_eq!(eval(c"y"), (-1, "Unknown identifier: y".into()));
+        // Deep expressions don't overflow the stack of the caller
+        let nested = format!("{}x{}", "abs(".repeat(1000), ")".repeat(1000));
+        assert_eq!(eval(&CString::new(nested).unwrap()), (0, "6".into()));
+        unsafe { sc_session_free(session) };
+    }
+}
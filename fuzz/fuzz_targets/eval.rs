@@ -0,0 +1,13 @@
+//! Arbitrary input for [`sc::eval`], run with `cargo fuzz run eval -- -timeout=10`
+//!
+//! Any panic is a bug, invalid input must be reported as an error. Slow inputs like
+//! `factorial(10 ** 9)` show up as timeouts, they're limited by the caller with a timeout.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = sc::eval(text);
+    }
+});
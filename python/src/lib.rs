@@ -0,0 +1,88 @@
+//! Python module of the calculator
+//!
+//! ```python
+//! >>> import sc
+//! >>> sc.eval("2**128")
+//! 340282366920938463463374607431768211456
+//! >>> session = sc.Session()
+//! >>> session.eval("x = 1.5")
+//! '1.5'
+//! >>> session.eval("x * 4")
+//! 6
+//! ```
+//!
+//! Integer results are returned as `int`, anything else as the formatted `str`. Expressions that
+//! can't be evaluated raise `sc.Error`.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyInt;
+use sc::format::Format;
+use sc::Value;
+
+create_exception!(
+    sc,
+    Error,
+    PyException,
+    "Expression that can't be evaluated."
+);
+
+/// Evaluate the expression with the default settings.
+#[pyfunction]
+fn eval(py: Python<'_>, expr: &str) -> PyResult<Py<PyAny>> {
+    let mut session = sc::Session::default();
+    // Other Python threads run while the expression is evaluated
+    let value = py
+        .detach(|| session.eval(expr).map_err(|e| e.to_string()))
+        .map_err(Error::new_err)?;
+    match value {
+        Some(value) => to_python(py, value, session.format()),
+        None => Err(Error::new_err("Expected an expression, not a definition")),
+    }
+}
+
+/// Evaluation that keeps variables and functions, i.e. `x = 2` or `f(x) = x * 2`.
+#[pyclass(unsendable)]
+#[derive(Default)]
+struct Session(sc::Session);
+
+#[pymethods]
+impl Session {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evaluate an expression or a definition, function definitions return `None`.
+    fn eval(&mut self, py: Python<'_>, line: &str) -> PyResult<Option<Py<PyAny>>> {
+        let session = &mut self.0;
+        let value = py
+            .detach(|| session.eval(line).map_err(|e| e.to_string()))
+            .map_err(Error::new_err)?;
+        value
+            .map(|value| to_python(py, value, self.0.format()))
+            .transpose()
+    }
+}
+
+/// Integral numbers as `int` of any size, other values formatted for display
+fn to_python(py: Python<'_>, value: Value, format: &Format) -> PyResult<Py<PyAny>> {
+    let integral = matches!(value, Value::Int(_) | Value::Real(_))
+        && value
+            .to_rational()
+            .is_some_and(|r| *r.denominator_ref() == 1u32);
+    let text = format.display(&value).to_string();
+    match integral {
+        true => Ok(py.get_type::<PyInt>().call1((text,))?.unbind()),
+        false => Ok(text.into_pyobject(py)?.into_any().unbind()),
+    }
+}
+
+#[pymodule(name = "sc")]
+fn sc_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(eval, m)?)?;
+    m.add_class::<Session>()?;
+    m.add("Error", m.py().get_type::<Error>())?;
+    Ok(())
+}
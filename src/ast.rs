@@ -0,0 +1,226 @@
+use crate::printer;
+use crate::tokenizer::Operator;
+use crate::value::Value;
+use compact_str::CompactString;
+use std::{fmt, mem};
+
+/// Parsed expression, evaluated by the calculator
+///
+/// Chains of binary operations like `1 + 2 + 3` are trees as high as they are long, so they are
+/// cloned and dropped in a loop rather than recursively.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Expr {
+    Val(Value),
+    /// Constant name
+    Ident(CompactString),
+    Neg(Box<Expr>),
+    /// Logical negation
+    Not(Box<Expr>),
+    /// Postfix percent, relative to the left operand when it is added or subtracted
+    Percent(Box<Expr>),
+    Binary {
+        op: Operator,
+        l: Box<Expr>,
+        r: Box<Expr>,
+    },
+    Call {
+        name: CompactString,
+        args: Vec<Expr>,
+    },
+    /// Bits `low..=high` of an integer, a single bit if `low` is omitted
+    Slice {
+        value: Box<Expr>,
+        high: Box<Expr>,
+        low: Option<Box<Expr>>,
+    },
+    /// `cond ? then : otherwise`
+    Cond {
+        cond: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Box<Expr>,
+    },
+}
+
+impl Expr {
+    pub fn binary(op: Operator, l: Expr, r: Expr) -> Self {
+        Expr::Binary {
+            op,
+            l: Box::new(l),
+            r: Box::new(r),
+        }
+    }
+
+    /// Copy of the expression with the identifier replaced by a value
+    pub fn substitute(&self, name: &str, value: &Value) -> Expr {
+        self.map(&|ident| (ident == name).then(|| Expr::Val(value.clone())))
+    }
+
+    /// Whether the identifier occurs in the expression
+    pub fn contains(&self, name: &str) -> bool {
+        let (first, chain) = self.chain();
+        let contains = |e: &Expr| match e {
+            Expr::Ident(ident) => *ident == name,
+            Expr::Val(_) => false,
+            Expr::Neg(e) | Expr::Not(e) | Expr::Percent(e) => e.contains(name),
+            Expr::Binary { .. } => unreachable!("chains are flattened"),
+            Expr::Call { args, .. } => args.iter().any(|e| e.contains(name)),
+            Expr::Slice { value, high, low } => {
+                value.contains(name)
+                    || high.contains(name)
+                    || low.as_ref().is_some_and(|low| low.contains(name))
+            }
+            Expr::Cond {
+                cond,
+                then,
+                otherwise,
+            } => cond.contains(name) || then.contains(name) || otherwise.contains(name),
+        };
+        contains(first) || chain.iter().any(|(_, r)| r.contains(name))
+    }
+
+    /// Leftmost operand of a chain of binary operations and the operations applied to it in
+    /// order, e.g. `1` and `+ 2`, `* 3` for `(1 + 2) * 3`
+    fn chain(&self) -> (&Expr, Vec<(Operator, &Expr)>) {
+        let mut chain = vec![];
+        let mut first = self;
+        while let Expr::Binary { op, l, r } = first {
+            chain.push((*op, &**r));
+            first = l;
+        }
+        chain.reverse();
+        (first, chain)
+    }
+
+    /// Copy of the expression with the identifiers for which `f` returns an expression replaced
+    fn map(&self, f: &dyn Fn(&str) -> Option<Expr>) -> Expr {
+        let map = |e: &Expr| Box::new(e.map(f));
+        let (first, chain) = self.chain();
+        let first = match first {
+            Expr::Val(v) => Expr::Val(v.clone()),
+            Expr::Ident(name) => f(name).unwrap_or_else(|| Expr::Ident(name.clone())),
+            Expr::Neg(e) => Expr::Neg(map(e)),
+            Expr::Not(e) => Expr::Not(map(e)),
+            Expr::Percent(e) => Expr::Percent(map(e)),
+            Expr::Binary { .. } => unreachable!("chains are flattened"),
+            Expr::Call { name, args } => Expr::Call {
+                name: name.clone(),
+                args: args.iter().map(|e| e.map(f)).collect(),
+            },
+            Expr::Slice { value, high, low } => Expr::Slice {
+                value: map(value),
+                high: map(high),
+                low: low.as_deref().map(map),
+            },
+            Expr::Cond {
+                cond,
+                then,
+                otherwise,
+            } => Expr::Cond {
+                cond: map(cond),
+                then: map(then),
+                otherwise: map(otherwise),
+            },
+        };
+        chain
+            .into_iter()
+            .fold(first, |l, (op, r)| Expr::binary(op, l, r.map(f)))
+    }
+
+    /// Move the children that have children of their own to `stack`, leaving leaves in place
+    fn take_children(&mut self, stack: &mut Vec<Expr>) {
+        let mut take = |e: &mut Box<Expr>| {
+            if !matches!(**e, Expr::Val(_) | Expr::Ident(_)) {
+                stack.push(mem::replace(e, Expr::Ident(CompactString::default())));
+            }
+        };
+        match self {
+            Expr::Val(_) | Expr::Ident(_) => {}
+            Expr::Neg(e) | Expr::Not(e) | Expr::Percent(e) => take(e),
+            Expr::Binary { l, r, .. } => {
+                take(l);
+                take(r);
+            }
+            Expr::Call { args, .. } => stack.append(args),
+            Expr::Slice { value, high, low } => {
+                take(value);
+                take(high);
+                if let Some(low) = low {
+                    take(low);
+                }
+            }
+            Expr::Cond {
+                cond,
+                then,
+                otherwise,
+            } => {
+                take(cond);
+                take(then);
+                take(otherwise);
+            }
+        }
+    }
+
+    /// Negate the expression if `negative` is set
+    pub fn negate_if(self, negative: bool) -> Self {
+        if negative {
+            Expr::Neg(Box::new(self))
+        } else {
+            self
+        }
+    }
+}
+
+impl Clone for Expr {
+    fn clone(&self) -> Self {
+        self.map(&|_| None)
+    }
+}
+
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        self.take_children(&mut stack);
+        while let Some(mut e) = stack.pop() {
+            e.take_children(&mut stack);
+        }
+    }
+}
+
+/// Source of the expression, see [`printer::print`]
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&printer::print(self))
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use crate::calculator::Calculator;
+    use crate::tokenizer::{Token, Tokenizer};
+
+    #[test]
+    fn test_serde() {
+        let text = "max(2.5, x) ** 2 + 1h30m - [1, 2][0] ? 4KiB : |-3|";
+        let tokens: Vec<Token> = Tokenizer::tokens(text.chars())
+            .map(Result::unwrap)
+            .collect();
+        let json = serde_json::to_string(&tokens).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+
+        let mut calculator = Calculator::default();
+        for token in tokens {
+            calculator.handle_token(token).unwrap();
+        }
+        let expr = calculator.parse().unwrap();
+        let json = serde_json::to_string(&expr).unwrap();
+        assert_eq!(serde_json::from_str::<Expr>(&json).unwrap(), expr);
+
+        for text in ["2 ** 100 / 3", "5 km", "(1 + 2i) ** 2", "[1.5, 2h]"] {
+            let value = text.parse::<crate::CompiledExpr>().unwrap().eval().unwrap();
+            let json = serde_json::to_string(&value).unwrap();
+            assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::format::{Format, Rounding};
+use crate::value::Value;
+use malachite::num::arithmetic::traits::Pow;
+use malachite::{Natural, Rational};
+
+const DECIMAL: [&str; 6] = ["kB", "MB", "GB", "TB", "PB", "EB"];
+const BINARY: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// Number of bytes in a byte size unit, e.g. `KiB` or `GB`
+pub fn multiplier(unit: &str) -> Option<Natural> {
+    if unit == "B" {
+        return Some(Natural::from(1u32));
+    }
+    // `KB` is commonly used for kilobytes as well
+    let unit = if unit == "KB" { "kB" } else { unit };
+    let (base, units) = if unit.ends_with("iB") {
+        (1024u32, &BINARY)
+    } else {
+        (1000u32, &DECIMAL)
+    };
+    let power = units.iter().position(|u| *u == unit)? as u64 + 1;
+    Some(Natural::from(base).pow(power))
+}
+
+/// Byte count in the largest binary unit that keeps the number at least one, e.g. `1.5 MiB`
+pub fn humanize(bytes: &Rational) -> String {
+    let format = Format {
+        precision: 2,
+        rounding: Rounding::HalfEven,
+        ..Format::default()
+    };
+    let abs = if *bytes < 0u32 {
+        -bytes.clone()
+    } else {
+        bytes.clone()
+    };
+    for (power, unit) in BINARY.iter().enumerate().rev() {
+        let scale = Rational::from(Natural::from(1024u32).pow(power as u64 + 1));
+        if abs >= scale {
+            let value = Value::Real(bytes / scale);
+            return format!("{} {}", format.display(&value), unit);
+        }
+    }
+    format!("{} B", format.display(&Value::Real(bytes.clone())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiplier() {
+        assert_eq!(multiplier("KiB"), Some(Natural::from(1024u32)));
+        assert_eq!(multiplier("KB"), Some(Natural::from(1000u32)));
+        assert_eq!(multiplier("GB"), Some(Natural::from(1_000_000_000u32)));
+        assert_eq!(multiplier("EiB"), Some(Natural::from(1u64 << 60)));
+        assert_eq!(multiplier("KiBB"), None);
+        assert_eq!(multiplier("A"), None);
+    }
+
+    #[test]
+    fn test_humanize() {
+        let humanize = |n: i64| humanize(&Rational::from(n));
+        assert_eq!(humanize(1000), "1000 B");
+        assert_eq!(humanize(4096), "4 KiB");
+        assert_eq!(humanize(1572864), "1.5 MiB");
+        assert_eq!(humanize(-3 << 30), "-3 GiB");
+        assert_eq!(humanize(1_000_000_000), "953.67 MiB");
+    }
+}
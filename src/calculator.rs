@@ -1,148 +1,1454 @@
-use crate::tokenizer::{Operator, Token, Value};
-use malachite::num::arithmetic::traits::Pow;
+use crate::ast::Expr;
+use crate::bytesize;
+use crate::complex::{self, Complex, ComplexError};
+use crate::derivative::{self, DerivativeError};
+use crate::functions::{self, modular, FunctionError};
+use crate::interval::{self, IntervalError};
+use crate::matrix::{self, Matrix, MatrixError};
+use crate::parser::{self, Parser};
+use crate::polynomial::{self, Polynomial, PolynomialError};
+use crate::random::{self, Rng, Roll};
+use crate::real::{self, RealError};
+use crate::symbolic::{self, Symbolic, SymbolicError};
+use crate::tokenizer::{Arithmetic, Operator, Token, Tokenizer};
+use crate::units::{self, Quantity, Unit, UnitError};
+use crate::value::Value;
+use compact_str::CompactString;
+use malachite::num::arithmetic::traits::{Abs, CheckedSqrt, Mod, ModPowerOf2, Pow};
+use malachite::num::basic::traits::{One, Zero};
+use malachite::num::logic::traits::SignificantBits;
+use malachite::{Integer, Natural, Rational};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
-use CalculatorState::*;
+/// Size limit of numbers, larger numbers exhaust the memory. Multiplications with products over
+/// 2^25 bits panic in the big number arithmetic, operands are limited to half of that
+pub const MAX_BITS: u64 = 1 << 24;
+/// Functions that take the name of another function as their first argument
+pub const HIGHER_ORDER: [&str; 3] = ["map", "filter", "reduce"];
+/// Functions that take an expression and the name of its variable, e.g. `solve(2*x == 6, x)`
+pub const SYMBOLIC: [&str; 4] = ["solve", "diff", "sum", "prod"];
+/// Maximum number of elements of a range, larger ranges can only be summed, multiplied or counted
+const MAX_RANGE_LENGTH: u32 = 1 << 20;
 
-#[derive(Debug, Default, Clone)]
-enum CalculatorState {
-    #[default]
-    Empty,
-    Neg,
-    Value(Value),
+/// Stack size of evaluation threads, the maximum depth of expressions is derived from it
+#[cfg(not(target_arch = "wasm32"))]
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+/// WebAssembly has no threads, expressions are evaluated on the 1 MiB stack of the module
+#[cfg(target_arch = "wasm32")]
+pub const STACK_SIZE: usize = 1024 * 1024;
+/// Depth evaluated on the stack of the caller, which may be as small as the 2 MiB of spawned
+/// threads. Deeper expressions continue on a thread with a stack of [`STACK_SIZE`]
+#[cfg(not(target_arch = "wasm32"))]
+const CALLER_DEPTH: usize = 16;
+
+thread_local! {
+    /// Set on threads with a stack of [`STACK_SIZE`]
+    static LARGE_STACK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Declare that the current thread has a stack of [`STACK_SIZE`], expressions of any depth are
+/// then evaluated without starting another thread
+pub fn large_stack() {
+    LARGE_STACK.with(|large| large.set(true));
 }
 
-#[derive(Debug, Default)]
+/// Supported bit widths of the fixed-width mode
+pub const WIDTHS: [u64; 5] = [8, 16, 32, 64, 128];
+
+#[derive(Debug)]
 pub struct Calculator {
-    state: CalculatorState,
-    pending: Vec<Action>,
+    parser: Parser,
+    /// Number of decimal places for irrational results
+    precision: u64,
+    /// Integer results wrap modulo 2^width if set
+    width: Option<u64>,
+    /// Integer results are reduced modulo a positive modulus if set
+    modulus: Option<Natural>,
+    /// Operations with evaluated operands and their results, recorded if tracing is enabled
+    trace: Option<RefCell<Vec<(Expr, Value)>>>,
+    /// Dice rolled by the evaluations, recorded if enabled
+    rolls: Option<RefCell<Vec<Roll>>>,
+    /// Variable values, variables shadow constants and units
+    variables: HashMap<CompactString, Value>,
+    /// User defined functions, they shadow builtin functions
+    functions: HashMap<CompactString, Function>,
+    /// Previous results, referenced as `$1`, `$2`, ...
+    history: Vec<Value>,
+    /// Set to stop the evaluation at the next operation
+    cancelled: Arc<AtomicBool>,
+    /// Maximum duration of interruptible evaluations
+    timeout: Option<Duration>,
+    /// Maximum size of numbers, the numerator and the denominator of fractions
+    max_bits: Option<u64>,
+    /// Maximum depth of expressions and of nested function calls
+    max_depth: usize,
+    /// Depth of the running evaluation
+    depth: Cell<usize>,
+    /// Whether an exponent is being evaluated, exponents aren't reduced by the modulus
+    exponent: Cell<bool>,
+    /// Undefined identifiers evaluate to symbolic variables if set
+    symbolic: bool,
+    /// Integer division results in exact fractions, it truncates if unset
+    exact_division: bool,
+    /// Random number generator, seeded from the operating system on first use unless set
+    rng: RefCell<Option<Rng>>,
+    /// Seed of the random number generator, if set
+    seed: Option<u64>,
+}
+
+/// User defined function, e.g. `f(x) = x ** 2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub params: Vec<CompactString>,
+    pub body: Expr,
+}
+
+impl Default for Calculator {
+    fn default() -> Self {
+        Self {
+            parser: Parser::default(),
+            precision: real::DEFAULT_PRECISION,
+            width: None,
+            modulus: None,
+            trace: None,
+            rolls: None,
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            history: vec![],
+            cancelled: Arc::default(),
+            timeout: None,
+            max_bits: None,
+            max_depth: parser::DEFAULT_MAX_DEPTH,
+            depth: Cell::new(0),
+            exponent: Cell::new(false),
+            symbolic: false,
+            exact_division: true,
+            rng: RefCell::default(),
+            seed: None,
+        }
+    }
 }
 
 impl Calculator {
+    /// Set the number of decimal places for irrational results
+    pub fn set_precision(&mut self, precision: u64) {
+        self.precision = precision;
+    }
+
+    pub fn width(&self) -> Option<u64> {
+        self.width
+    }
+
+    /// Enable or disable fixed-width two's complement integer arithmetic
+    pub fn set_width(&mut self, width: Option<u64>) {
+        self.width = width;
+    }
+
+    pub fn modulus(&self) -> Option<&Natural> {
+        self.modulus.as_ref()
+    }
+
+    /// Enable or disable modular integer arithmetic, division multiplies by the modular inverse
+    pub fn set_modulus(&mut self, modulus: Option<Natural>) {
+        self.modulus = modulus.filter(|m| *m > 0u32);
+    }
+
+    /// Evaluate undefined identifiers to symbolic variables instead of failing, so that
+    /// `2*x + 3*x` results in `5*x`
+    pub fn set_symbolic(&mut self, symbolic: bool) {
+        self.symbolic = symbolic;
+    }
+
+    /// Divide integers exactly, so that `10 / 4` is `2.5`, or truncate the quotient to `2`.
+    /// Division is exact by default, and fixed-width integers always truncate as in C
+    pub fn set_exact_division(&mut self, exact: bool) {
+        self.exact_division = exact;
+    }
+
+    /// Seed the random number generator for reproducible results, or seed it from the operating
+    /// system
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.rng = RefCell::new(seed.map(Rng::from_seed));
+        self.seed = seed;
+    }
+
+    /// Restart the random numbers of a seeded calculator at the ones of input line `line`, so
+    /// that lines evaluated on other threads have the same numbers as in order
+    pub fn seed_line(&mut self, line: u64) {
+        if let Some(seed) = self.seed {
+            self.rng = RefCell::new(Some(Rng::from_line(seed, line)));
+        }
+    }
+
+    /// Limit the duration of [`Calculator::evaluate_interruptible`]
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Limit the size of intermediate and final results, [`MAX_BITS`] if `None` or larger
+    pub fn set_max_bits(&mut self, max_bits: Option<u64>) {
+        self.max_bits = max_bits;
+    }
+
+    /// Limit the nesting of expressions and of user function calls
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+        self.parser.set_max_depth(max_depth);
+    }
+
+    pub fn set_variable(&mut self, name: impl Into<CompactString>, value: Value) {
+        self.variables.insert(name.into(), value);
+    }
+
+    pub fn variables(&self) -> impl Iterator<Item = (&CompactString, &Value)> {
+        self.variables.iter()
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = (&CompactString, &Function)> {
+        self.functions.iter()
+    }
+
+    /// Previous results, the first one is `$1`
+    pub fn history(&self) -> &[Value] {
+        &self.history
+    }
+
+    /// Remember a result, returns its number
+    pub fn push_history(&mut self, value: Value) -> usize {
+        self.history.push(value);
+        self.history.len()
+    }
+
+    fn history_entry(&self, index: usize) -> Result<Value, CalculatorError> {
+        index
+            .checked_sub(1)
+            .and_then(|i| self.history.get(i))
+            .cloned()
+            .ok_or(CalculatorError::NoResult(index))
+    }
+
+    pub fn define_function(&mut self, name: impl Into<CompactString>, function: Function) {
+        self.functions.insert(name.into(), function);
+    }
+
+    /// Enable or disable recording of evaluation steps
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.trace = enabled.then(RefCell::default);
+    }
+
+    /// Evaluation steps recorded since the last call, in evaluation order
+    pub fn take_trace(&self) -> Vec<(Expr, Value)> {
+        self.trace.as_ref().map(RefCell::take).unwrap_or_default()
+    }
+
+    /// Record the individual dice of rolls like `3d6`
+    pub fn set_rolls(&mut self, enabled: bool) {
+        self.rolls = enabled.then(RefCell::default);
+    }
+
+    /// Dice rolled since the last call, in evaluation order
+    pub fn take_rolls(&self) -> Vec<Roll> {
+        self.rolls.as_ref().map(RefCell::take).unwrap_or_default()
+    }
+
     pub fn handle_token(&mut self, token: Token) -> Result<(), CalculatorError> {
-        use Token::*;
-
-        match (&self.state, token) {
-            (Empty, Val(v)) => self.state = Value(v),
-            (Neg, Val(v)) => self.state = Value(-v),
-            // Negative sign
-            (Empty, Op(Operator::Sub)) => self.state = Neg,
-            // Double negative sign, cancel each other out
-            (Neg, Op(Operator::Sub)) => self.state = Empty,
-            // Positive sign, do nothing
-            (Empty | Neg, Op(Operator::Add)) => {}
-            (Empty | Neg, Op(_) | ParenClose) => return Err(CalculatorError::NumberExpected),
-            (Empty, ParenOpen) => self.pending.push(Action::Parentheses(false)),
-            (Neg, ParenOpen) => {
-                self.pending.push(Action::Parentheses(true));
-                self.state = Empty
-            }
-            (Value(_), Val(_)) => return Err(CalculatorError::OperationExpected),
-            (Value(v), Op(op)) => {
-                self.prioritized_execute(Operation { l: v.clone(), op });
-                self.state = Empty;
-            }
-            (Value(v), ParenOpen) => {
-                self.pending.push(Action::Operation(Operation {
-                    l: v.clone(),
-                    op: Operator::Mul,
-                }));
-                self.pending.push(Action::Parentheses(false));
-                self.state = Empty;
-            }
-            (Value(_), ParenClose) => self.finalize_expr()?,
+        self.parser.handle_token(token)
+    }
+
+    pub fn finalize(&mut self) -> Result<Value, CalculatorError> {
+        let expr = self.parse()?;
+        self.evaluate(&expr)
+    }
+
+    /// Discard the incomplete expression, e.g. after an error
+    pub fn reset(&mut self) {
+        self.parser.reset();
+        self.take_trace();
+        self.take_rolls();
+    }
+
+    /// Complete the expression without evaluating it
+    pub fn parse(&mut self) -> Result<Expr, CalculatorError> {
+        self.parser.finalize()
+    }
+
+    pub fn evaluate(&self, expr: &Expr) -> Result<Value, CalculatorError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(CalculatorError::Interrupted);
+        }
+        // Subexpressions and function bodies are evaluated recursively
+        let depth = self.depth.get();
+        if depth >= self.max_depth {
+            return Err(CalculatorError::TooDeep);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if depth == CALLER_DEPTH && !LARGE_STACK.with(Cell::get) {
+            return self.evaluate_on_large_stack(expr);
+        }
+        self.depth.set(depth + 1);
+        let value = self.evaluate_unwrapped(expr);
+        self.depth.set(depth);
+        self.checked(value?)
+    }
+
+    /// Continue the evaluation at the same depth on a thread with a stack of [`STACK_SIZE`]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn evaluate_on_large_stack(&self, expr: &Expr) -> Result<Value, CalculatorError> {
+        let mut worker = self.fork();
+        worker.cancelled = self.cancelled.clone();
+        worker.depth.set(self.depth.get());
+        worker.exponent.set(self.exponent.get());
+        // Random numbers continue the sequence of this calculator
+        worker.rng.swap(&self.rng);
+        let (result, worker) = thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, || {
+                    large_stack();
+                    (worker.evaluate(expr), worker)
+                })
+                .map(|evaluation| {
+                    evaluation
+                        .join()
+                        .unwrap_or_else(|e| panic::resume_unwind(e))
+                })
+        })
+        .map_err(|_| CalculatorError::ResourceLimit("failed to start the evaluation"))?;
+        self.rng.swap(&worker.rng);
+        if let Some(own) = &self.trace {
+            own.borrow_mut().extend(worker.take_trace());
+        }
+        if let Some(own) = &self.rolls {
+            own.borrow_mut().extend(worker.take_rolls());
+        }
+        result
+    }
+
+    /// Value reduced by the active modulus, fails if it's larger than the maximum number of bits
+    fn checked(&self, value: Value) -> Result<Value, CalculatorError> {
+        let value = self.wrap(value);
+        if bits(&value) > self.max_bits() {
+            return Err(CalculatorError::ResourceLimit(
+                "result is larger than the maximum number of bits",
+            ));
+        }
+        Ok(value)
+    }
+
+    /// Binary operation on the value of the left operand and the right operand
+    fn binary(&self, op: Operator, l: Value, r: &Expr) -> Result<Value, CalculatorError> {
+        let r = match (op, r) {
+            // Percentage of the left operand for addition and subtraction, e.g. `200 + 10%`
+            (Operator::Add | Operator::Sub, Expr::Percent(r)) => {
+                percent(&self.evaluate(r)?, Some(&l))?
+            }
+            (Operator::Pow, r) => self.evaluate_exponent(r)?,
+            (_, r) => self.evaluate(r)?,
+        };
+        // Fixed-width integers are two's complement where the sign matters, e.g. `-6 / 2`
+        let (l, r) = match self.width {
+            Some(width)
+                if matches!(
+                    op,
+                    Operator::Div | Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge
+                ) =>
+            {
+                (signed(l, width), signed(r, width))
+            }
+            _ => (l, r),
+        };
+        let modulus = self.active_modulus();
+        let modular = modulus.is_some()
+            && matches!(op, Operator::Div | Operator::Pow)
+            && matches!((&l, &r), (Value::Int(_), Value::Int(_)));
+        if op == Operator::Pow && !modular {
+            if let Err(e) = self.check_power(&l, &r) {
+                // Powers too small to compute exactly round to zero
+                return match (l.to_rational(), r.to_rational()) {
+                    (Some(b), Some(exp)) if real::underflows(&b, &exp, self.precision) => {
+                        Ok(Value::from(0))
+                    }
+                    _ => Err(e),
+                };
+            }
         }
+        if !modular {
+            self.check_product(op, &l, &r)?;
+        }
+        let traced = self.trace.as_ref().map(|_| (l.clone(), r.clone()));
+        let result = match (modulus, l, r) {
+            (Some(m), Value::Int(l), Value::Int(r)) if modular => execute_modular(op, l, r, m),
+            (_, Value::Int(l), Value::Int(r))
+                if op == Operator::Div && (!self.exact_division || self.width.is_some()) =>
+            {
+                truncated_division(l, r)
+            }
+            (_, l, r) => execute(op, l, r, self.precision),
+        };
+        if let Some((l, r)) = traced {
+            self.record(|| Expr::binary(op, Expr::Val(l), Expr::Val(r)), &result);
+        }
+        result
+    }
+
+    fn max_bits(&self) -> u64 {
+        self.max_bits.map_or(MAX_BITS, |bits| bits.min(MAX_BITS))
+    }
 
+    /// Fail before multiplying numbers whose product would be larger than the maximum number of
+    /// bits, fractions are multiplied by all arithmetic operators
+    fn check_product(&self, op: Operator, l: &Value, r: &Value) -> Result<(), CalculatorError> {
+        let multiplies = match (l, r) {
+            (Value::Int(_), Value::Int(_)) => op == Operator::Mul,
+            _ => matches!(
+                op,
+                Operator::Add | Operator::Sub | Operator::Mul | Operator::Div
+            ),
+        };
+        if multiplies && bits(l) + bits(r) > self.max_bits() {
+            return Err(CalculatorError::ResourceLimit(
+                "result is larger than the maximum number of bits",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fail before computing a power that would be larger than the maximum number of bits
+    fn check_power(&self, base: &Value, exponent: &Value) -> Result<(), CalculatorError> {
+        let Some(exponent) = exponent.to_rational() else {
+            return Ok(());
+        };
+        // Elements of matrix powers grow with the norm
+        let base_bits = match Matrix::from_value(base) {
+            Some(m) => bits(&Value::Real(m.norm())),
+            None => bits(base),
+        };
+        let estimate = Rational::from(base_bits.saturating_sub(1)) * exponent.abs();
+        if estimate > self.max_bits() {
+            return Err(CalculatorError::ResourceLimit(
+                "result is larger than the maximum number of bits",
+            ));
+        }
         Ok(())
     }
 
-    fn prioritized_execute(&mut self, mut new: Operation) {
-        while let Some(pending) = self.pending.pop() {
-            match pending {
-                Action::Operation(op) if op.priority() >= new.priority() => {
-                    new.l = op.execute(new.l)
+    /// Calculator with the same settings, variables, functions and history, for evaluating on
+    /// another thread. The incomplete expression, the trace and the rolls aren't copied
+    pub fn fork(&self) -> Calculator {
+        let mut parser = Parser::default();
+        parser.set_max_depth(self.max_depth);
+        Calculator {
+            parser,
+            precision: self.precision,
+            width: self.width,
+            modulus: self.modulus.clone(),
+            trace: self.trace.as_ref().map(|_| RefCell::default()),
+            rolls: self.rolls.as_ref().map(|_| RefCell::default()),
+            variables: self.variables.clone(),
+            functions: self.functions.clone(),
+            history: self.history.clone(),
+            cancelled: Arc::default(),
+            timeout: self.timeout,
+            max_bits: self.max_bits,
+            max_depth: self.max_depth,
+            depth: Cell::new(0),
+            exponent: Cell::new(false),
+            symbolic: self.symbolic,
+            exact_division: self.exact_division,
+            rng: RefCell::new(Some(
+                self.rng
+                    .borrow_mut()
+                    .get_or_insert_with(Rng::from_entropy)
+                    .fork(),
+            )),
+            seed: self.seed,
+        }
+    }
+
+    /// Evaluate on a separate thread, returns early if `interrupted` returns true while waiting
+    ///
+    /// An interrupted evaluation stops at the next operation, a single long operation keeps
+    /// running in the background until it completes.
+    pub fn evaluate_interruptible(
+        &self,
+        expr: &Expr,
+        interrupted: impl Fn() -> bool,
+    ) -> Result<Value, CalculatorError> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut worker = self.fork();
+        worker.timeout = None;
+        let cancelled = worker.cancelled.clone();
+        let (sender, receiver) = mpsc::channel();
+        let expr = expr.clone();
+        // As large as the stack of the thread of the command line session, so that the depth limit
+        // applies to both
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                large_stack();
+                let result = worker.evaluate(&expr);
+                let _ = sender.send((result, worker.take_trace(), worker.take_rolls()));
+            })
+            .map_err(|_| CalculatorError::ResourceLimit("failed to start the evaluation"))?;
+        loop {
+            match receiver.recv_timeout(Duration::from_millis(20)) {
+                Ok((result, trace, rolls)) => {
+                    if let Some(own) = &self.trace {
+                        own.borrow_mut().extend(trace);
+                    }
+                    if let Some(own) = &self.rolls {
+                        own.borrow_mut().extend(rolls);
+                    }
+                    return result;
+                }
+                Err(RecvTimeoutError::Timeout) if interrupted() => {
+                    cancelled.store(true, Ordering::Relaxed);
+                    return Err(CalculatorError::Interrupted);
+                }
+                Err(RecvTimeoutError::Timeout) if deadline.is_some_and(|d| Instant::now() > d) => {
+                    cancelled.store(true, Ordering::Relaxed);
+                    return Err(CalculatorError::ResourceLimit(
+                        "evaluation took longer than the timeout",
+                    ));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                // The worker panicked
+                Err(RecvTimeoutError::Disconnected) => return Err(CalculatorError::Interrupted),
+            }
+        }
+    }
+
+    fn call(
+        &self,
+        name: &str,
+        function: &Function,
+        args: Vec<Value>,
+    ) -> Result<Value, CalculatorError> {
+        if args.len() != function.params.len() {
+            return Err(FunctionError::ArgumentCount {
+                name: name.into(),
+                expected: function.params.len(),
+                got: args.len(),
+            }
+            .into());
+        }
+        let body = function
+            .params
+            .iter()
+            .zip(&args)
+            .fold(function.body.clone(), |body, (param, arg)| {
+                body.substitute(param, arg)
+            });
+        self.evaluate(&body)
+    }
+
+    fn wrap(&self, value: Value) -> Value {
+        let value = match (value, self.width) {
+            (Value::Int(i), Some(width)) => Integer::from(i.mod_power_of_2(width)).into(),
+            (value, _) => value,
+        };
+        match (value, self.active_modulus()) {
+            (Value::Int(i), Some(m)) => i.mod_op(Integer::from(m)).into(),
+            (value, _) => value,
+        }
+    }
+
+    /// Modulus of the running evaluation, `None` in exponents
+    fn active_modulus(&self) -> Option<&Natural> {
+        self.modulus.as_ref().filter(|_| !self.exponent.get())
+    }
+
+    /// Evaluate the exponent of a power as a plain integer
+    fn evaluate_exponent(&self, expr: &Expr) -> Result<Value, CalculatorError> {
+        let exponent = self.exponent.replace(true);
+        let value = self.evaluate(expr);
+        self.exponent.set(exponent);
+        value
+    }
+
+    /// Record an operation on evaluated operands if tracing is enabled
+    fn record(&self, expr: impl FnOnce() -> Expr, result: &Result<Value, CalculatorError>) {
+        if let (Some(trace), Ok(result)) = (&self.trace, result) {
+            trace.borrow_mut().push((expr(), self.wrap(result.clone())));
+        }
+    }
+
+    fn evaluate_unwrapped(&self, expr: &Expr) -> Result<Value, CalculatorError> {
+        match expr {
+            Expr::Val(v) => Ok(v.clone()),
+            Expr::Ident(name) if name.starts_with('$') => match name[1..].parse() {
+                Ok(index) => self.history_entry(index),
+                Err(_) => environment(&name[1..]),
+            },
+            Expr::Call { name, args } if *name == "out" && !self.functions.contains_key(name) => {
+                let index = match args.as_slice() {
+                    [arg] => self.evaluate(arg)?.into_int(),
+                    _ => None,
+                };
+                let index = index
+                    .and_then(|i| usize::try_from(&i).ok())
+                    .ok_or_else(|| FunctionError::InvalidArgument {
+                        name: name.clone(),
+                        reason: "expected the number of a result",
+                    })?;
+                self.history_entry(index)
+            }
+            Expr::Call { name, args } if *name == "env" => match args.as_slice() {
+                [Expr::Ident(var)] => environment(var),
+                _ => Err(FunctionError::InvalidArgument {
+                    name: name.clone(),
+                    reason: "expected the name of an environment variable",
+                }
+                .into()),
+            },
+            Expr::Call { name, args }
+                if HIGHER_ORDER.contains(&name.as_str()) && !self.functions.contains_key(name) =>
+            {
+                self.higher_order(name, args)
+            }
+            // `sum` binds its index like `prod` only in the form `sum(k, 1, 100, k**2)`, otherwise
+            // it adds its arguments
+            Expr::Call { name, args }
+                if SYMBOLIC.contains(&name.as_str())
+                    && !self.functions.contains_key(name)
+                    && (*name != "sum" || matches!(args.as_slice(), [Expr::Ident(_), _, _, _])) =>
+            {
+                match name.as_str() {
+                    "solve" => self.solve(args),
+                    "diff" => self.diff(args),
+                    _ => self.series(name, args),
+                }
+            }
+            // Ranges are reduced without creating the list of their elements, e.g. `sum(1..=10**9)`
+            Expr::Call { name, args } if self.reduces_range(name, args) => {
+                let [Expr::Binary { op, l, r }] = args.as_slice() else {
+                    unreachable!("checked by reduces_range")
+                };
+                let (l, r) = (self.evaluate(l)?, self.evaluate(r)?);
+                let traced = self.trace.as_ref().map(|_| (l.clone(), r.clone()));
+                let (start, end) = range_bounds(*op, l, r)?;
+                let result = self.reduce_range(name, start, end);
+                if let Some((l, r)) = traced {
+                    let range = Expr::binary(*op, Expr::Val(l), Expr::Val(r));
+                    self.record(
+                        || Expr::Call {
+                            name: name.clone(),
+                            args: vec![range],
+                        },
+                        &result,
+                    );
+                }
+                result
+            }
+            Expr::Ident(name) => self
+                .variables
+                .get(name)
+                .cloned()
+                .or_else(|| functions::constant(name, self.precision))
+                .or_else(|| Unit::lookup(name).map(|u| Value::Quantity(Quantity::unit(u))))
+                // Byte sizes apart from the number, e.g. `1.5 KiB` is the same as `1.5KiB`
+                .or_else(|| bytesize::multiplier(name).map(|n| Value::Int(n.into())))
+                .or_else(|| {
+                    self.symbolic
+                        .then(|| Value::Symbolic(Symbolic::variable(name)))
+                })
+                .ok_or_else(|| CalculatorError::UnknownIdentifier(name.clone())),
+            Expr::Neg(e) => match self.evaluate(e)? {
+                Value::Str(_) => Err(CalculatorError::UnsupportedOperands),
+                v => Ok(-v),
+            },
+            Expr::Not(e) => Ok((!truthy(&self.evaluate(e)?)?).into()),
+            Expr::Percent(e) => percent(&self.evaluate(e)?, None),
+            Expr::Call { name, args } => {
+                let args: Vec<Value> = args
+                    .iter()
+                    .map(|arg| self.evaluate(arg))
+                    .collect::<Result<_, _>>()?;
+                let traced = self.trace.as_ref().map(|_| args.clone());
+                let result = match self.functions.get(name) {
+                    Some(function) => self.call(name, function, args),
+                    None if random::FUNCTIONS.contains(&name.as_str()) => self.random(name, args),
+                    None => functions::call(name, args, self.precision).map_err(Into::into),
+                };
+                self.record(
+                    || Expr::Call {
+                        name: name.clone(),
+                        args: traced.into_iter().flatten().map(Expr::Val).collect(),
+                    },
+                    &result,
+                );
+                result
+            }
+            // Right operand is only evaluated if it affects the result
+            Expr::Binary {
+                op: Operator::And,
+                l,
+                r,
+            } => Ok((truthy(&self.evaluate(l)?)? && truthy(&self.evaluate(r)?)?).into()),
+            Expr::Binary {
+                op: Operator::Or,
+                l,
+                r,
+            } => Ok((truthy(&self.evaluate(l)?)? || truthy(&self.evaluate(r)?)?).into()),
+            // Left-associative chains such as `1 + 2 + ... + n` are folded in a loop instead of
+            // recursively, so that long ones don't overflow the stack
+            Expr::Binary { op, l, r } => {
+                let mut chain = vec![(*op, &**r)];
+                let mut first = &**l;
+                while let Expr::Binary { op, l, r } = first {
+                    if matches!(op, Operator::And | Operator::Or) {
+                        break;
+                    }
+                    chain.push((*op, r));
+                    first = l;
+                }
+                let mut value = self.evaluate(first)?;
+                for (op, r) in chain.into_iter().rev() {
+                    if self.cancelled.load(Ordering::Relaxed) {
+                        return Err(CalculatorError::Interrupted);
+                    }
+                    value = self.checked(self.binary(op, value, r)?)?;
+                }
+                Ok(value)
+            }
+            // Element or elements of a list, bits of an integer
+            Expr::Slice { value, high, low } => {
+                let value = self.evaluate(value)?;
+                let high = self.evaluate(high)?;
+                let low = low.as_ref().map(|low| self.evaluate(low)).transpose()?;
+                match (value, low) {
+                    (Value::List(list), low) => index(list, high, low),
+                    (value, low) => {
+                        let low = low.unwrap_or_else(|| high.clone());
+                        Ok(functions::call(
+                            "bits",
+                            vec![value, high, low],
+                            self.precision,
+                        )?)
+                    }
                 }
-                _ => {
-                    self.pending.push(pending);
-                    break;
+            }
+            Expr::Cond {
+                cond,
+                then,
+                otherwise,
+            } => {
+                if truthy(&self.evaluate(cond)?)? {
+                    self.evaluate(then)
+                } else {
+                    self.evaluate(otherwise)
                 }
             }
         }
-        self.pending.push(Action::Operation(new));
     }
+}
 
-    fn finalize_expr(&mut self) -> Result<(), CalculatorError> {
-        match self.state.clone() {
-            Empty | Neg => Err(CalculatorError::NumberExpected),
-            Value(mut v) => {
-                while let Some(pending) = self.pending.pop() {
-                    match pending {
-                        Action::Parentheses(is_negative) => {
-                            v = if is_negative { -v } else { v };
-                            break;
-                        }
-                        Action::Operation(op) => v = op.execute(v),
+impl Calculator {
+    /// `map(f, xs)`, `filter(p, xs)` or `reduce(f, init, xs)`
+    fn higher_order(&self, name: &str, args: &[Expr]) -> Result<Value, CalculatorError> {
+        let expected = if name == "reduce" { 3 } else { 2 };
+        if args.len() != expected {
+            return Err(FunctionError::ArgumentCount {
+                name: name.into(),
+                expected,
+                got: args.len(),
+            }
+            .into());
+        }
+        let Expr::Ident(function) = &args[0] else {
+            return Err(FunctionError::InvalidArgument {
+                name: name.into(),
+                reason: "expected the name of a function",
+            }
+            .into());
+        };
+        let values = args[1..]
+            .iter()
+            .map(|arg| self.evaluate(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let traced = self.trace.as_ref().map(|_| values.clone());
+        let mut values = values.into_iter();
+        let init = if name == "reduce" {
+            values.next()
+        } else {
+            None
+        };
+        let Some(Value::List(list)) = values.next() else {
+            return Err(FunctionError::InvalidArgument {
+                name: name.into(),
+                reason: "list expected",
+            }
+            .into());
+        };
+        let apply = |args: Vec<Value>| match self.functions.get(function) {
+            Some(f) => self.call(function, f, args),
+            None => functions::call(function, args, self.precision).map_err(Into::into),
+        };
+        let result = match (name, init) {
+            ("map", _) => list
+                .into_iter()
+                .map(|x| apply(vec![x]))
+                .collect::<Result<_, _>>()
+                .map(Value::List),
+            ("filter", _) => {
+                let mut result = vec![];
+                for x in list {
+                    if truthy(&apply(vec![x.clone()])?)? {
+                        result.push(x);
                     }
                 }
-                self.state = Value(v);
-                Ok(())
+                Ok(Value::List(result))
+            }
+            (_, init) => list
+                .into_iter()
+                .try_fold(init.expect("reduce has an initial value"), |acc, x| {
+                    apply(vec![acc, x])
+                }),
+        };
+        self.record(
+            || Expr::Call {
+                name: name.into(),
+                args: std::iter::once(Expr::Ident(function.clone()))
+                    .chain(traced.into_iter().flatten().map(Expr::Val))
+                    .collect(),
+            },
+            &result,
+        );
+        result
+    }
+
+    /// Whether the call is a built-in reduction of a single range argument
+    fn reduces_range(&self, name: &str, args: &[Expr]) -> bool {
+        matches!(name, "sum" | "product" | "len")
+            && !self.functions.contains_key(name)
+            && matches!(
+                args,
+                [Expr::Binary {
+                    op: Operator::Range | Operator::RangeInclusive,
+                    ..
+                }]
+            )
+    }
+
+    /// Sum or product of `body` for the integer values of `index` from `start` to `end`
+    fn series(&self, name: &str, args: &[Expr]) -> Result<Value, CalculatorError> {
+        let [index, start, end, body] = args else {
+            return Err(FunctionError::ArgumentCount {
+                name: name.into(),
+                expected: 4,
+                got: args.len(),
+            }
+            .into());
+        };
+        let Expr::Ident(index) = index else {
+            return Err(FunctionError::InvalidArgument {
+                name: name.into(),
+                reason: "expected the name of the index",
+            }
+            .into());
+        };
+        let (start, end) = range_bounds(
+            Operator::RangeInclusive,
+            self.evaluate(start)?,
+            self.evaluate(end)?,
+        )?;
+        if range_length(&start, &end) > MAX_RANGE_LENGTH {
+            return Err(CalculatorError::ResourceLimit("too many terms"));
+        }
+        let (op, mut result) = match name {
+            "sum" => (Operator::Add, Value::from(0)),
+            _ => (Operator::Mul, Value::from(1)),
+        };
+        let mut k = start;
+        while k <= end {
+            let term = self.evaluate(&body.substitute(index, &Value::Int(k.clone())))?;
+            self.check_product(op, &result, &term)?;
+            result = self.wrap(execute(op, result, term, self.precision)?);
+            if bits(&result) > self.max_bits() {
+                return Err(CalculatorError::ResourceLimit(
+                    "result is larger than the maximum number of bits",
+                ));
             }
+            k += Integer::ONE;
         }
+        Ok(result)
     }
 
-    pub fn finalize(&mut self) -> Result<Value, CalculatorError> {
-        self.finalize_expr()?;
-        let result = match self.state.clone() {
-            Empty | Neg => Err(CalculatorError::NumberExpected),
-            Value(v) => Ok(v),
+    /// Derivative of an expression in a variable, at a point if given, e.g. `diff(x**2, x, 3)`
+    fn diff(&self, args: &[Expr]) -> Result<Value, CalculatorError> {
+        let name = "diff";
+        let (expr, variable, point) = match args {
+            [expr, variable] => (expr, variable, None),
+            [expr, variable, point] => (expr, variable, Some(point)),
+            _ => {
+                return Err(FunctionError::ArgumentCount {
+                    name: name.into(),
+                    expected: 2,
+                    got: args.len(),
+                }
+                .into())
+            }
+        };
+        let Expr::Ident(variable) = variable else {
+            return Err(FunctionError::InvalidArgument {
+                name: name.into(),
+                reason: "expected the name of a variable",
+            }
+            .into());
         };
-        self.state = Empty;
+        let derivative = derivative::derivative(expr, variable)?;
+        if let Some(point) = point {
+            let x = self.evaluate(point)?;
+            return self.evaluate(&derivative.substitute(variable, &x));
+        }
+        let x = Value::Symbolic(Symbolic::variable(variable));
+        match self.evaluate(&derivative.substitute(variable, &x)) {
+            // Functions like `cos` can only be evaluated at a point
+            Err(CalculatorError::Function(_) | CalculatorError::Symbolic(_)) => {
+                Err(FunctionError::InvalidArgument {
+                    name: name.into(),
+                    reason: "the derivative isn't a polynomial, expected a point",
+                }
+                .into())
+            }
+            result => result,
+        }
+    }
 
-        if !self.pending.is_empty() {
-            return Err(CalculatorError::UnmatchedParen);
+    /// Roots of a linear or quadratic equation in one variable, a number for linear equations and
+    /// a list of the distinct roots for quadratic equations. Rational roots are exact
+    fn solve(&self, args: &[Expr]) -> Result<Value, CalculatorError> {
+        let name = "solve";
+        let invalid = |reason| -> CalculatorError {
+            FunctionError::InvalidArgument {
+                name: name.into(),
+                reason,
+            }
+            .into()
+        };
+        let [equation, variable] = args else {
+            return Err(FunctionError::ArgumentCount {
+                name: name.into(),
+                expected: 2,
+                got: args.len(),
+            }
+            .into());
+        };
+        let Expr::Ident(variable) = variable else {
+            return Err(invalid("expected the name of a variable"));
+        };
+        // Both sides of an equation, or an expression that equals zero
+        let expr = match equation {
+            Expr::Binary {
+                op: Operator::Eq,
+                l,
+                r,
+            } => Expr::binary(Operator::Sub, (**l).clone(), (**r).clone()),
+            expr => expr.clone(),
+        };
+        let x = Value::Symbolic(Symbolic::variable(variable));
+        let polynomial = match self.evaluate(&expr.substitute(variable, &x))? {
+            Value::Symbolic(s) => s.to_polynomial(variable),
+            v => v.to_rational().map(|c| Polynomial::new(vec![c])),
+        };
+        let polynomial = polynomial.ok_or_else(|| invalid("expected a polynomial equation"))?;
+        let coefficients: Vec<&Rational> = polynomial.coefficients().collect();
+        match coefficients.as_slice() {
+            [] => Err(invalid("every number is a solution")),
+            [_] => Err(invalid("the equation has no solution")),
+            [a, b] => Ok(functions::exact(-(*b) / *a)),
+            [a, b, c] => {
+                let two_a = Rational::from(2) * *a;
+                let vertex = -(*b) / &two_a;
+                let discriminant = *b * *b - Rational::from(4) * *a * *c;
+                // A single square root of `discriminant / (2a)**2` rounds only once
+                let root = sqrt(&(&discriminant / (&two_a * &two_a)).abs(), self.precision);
+                if discriminant < 0u32 {
+                    let conjugates = [-&root, root].map(|im| Complex::new(vertex.clone(), im));
+                    return Ok(Value::List(conjugates.map(Value::from).to_vec()));
+                }
+                let roots = match discriminant == 0u32 {
+                    true => vec![vertex],
+                    false => vec![&vertex - &root, &vertex + root],
+                };
+                Ok(Value::List(
+                    roots.into_iter().map(functions::exact).collect(),
+                ))
+            }
+            _ => Err(invalid("only linear and quadratic equations can be solved")),
         }
+    }
 
-        result
+    /// Random integer from `lo` to `hi` inclusive, random number of `n` bits, or the total of
+    /// `count` dice with `sides` sides
+    fn random(&self, name: &str, args: Vec<Value>) -> Result<Value, CalculatorError> {
+        let expected = if name == "randbits" { 1 } else { 2 };
+        if args.len() != expected {
+            return Err(FunctionError::ArgumentCount {
+                name: name.into(),
+                expected,
+                got: args.len(),
+            }
+            .into());
+        }
+        let invalid = |reason| FunctionError::InvalidArgument {
+            name: name.into(),
+            reason,
+        };
+        let ints = args
+            .into_iter()
+            .map(Value::into_int)
+            .collect::<Option<Vec<Integer>>>()
+            .ok_or_else(|| invalid("integer expected"))?;
+        let mut rng = self.rng.borrow_mut();
+        let rng = rng.get_or_insert_with(Rng::from_entropy);
+        let result = match ints.as_slice() {
+            [count, sides] if name == "dice" => {
+                let count = u64::try_from(count)
+                    .ok()
+                    .filter(|&count| count <= random::MAX_DICE)
+                    .ok_or_else(|| invalid("expected a number of dice up to 2**20"))?;
+                let sides = Natural::try_from(sides)
+                    .ok()
+                    .filter(|sides| *sides > 0u32)
+                    .ok_or_else(|| invalid("expected a positive number of sides"))?;
+                let roll = rng.roll(count, sides);
+                let total = Integer::from(roll.total());
+                if let Some(rolls) = &self.rolls {
+                    rolls.borrow_mut().push(roll);
+                }
+                total
+            }
+            [lo, hi] => {
+                if hi < lo {
+                    return Err(invalid("the upper bound is less than the lower bound").into());
+                }
+                let span = Natural::try_from(hi - lo + Integer::ONE).expect("span is positive");
+                lo + Integer::from(rng.below(&span))
+            }
+            [n] => {
+                let n = u64::try_from(n).map_err(|_| invalid("expected a non-negative number"))?;
+                if n > self.max_bits() {
+                    return Err(CalculatorError::ResourceLimit(
+                        "result is larger than the maximum number of bits",
+                    ));
+                }
+                Integer::from(rng.bits(n))
+            }
+            _ => unreachable!("argument count is checked"),
+        };
+        Ok(Value::Int(result))
+    }
+
+    /// Sum, product or length of the integers from `start` to `end`
+    fn reduce_range(
+        &self,
+        name: &str,
+        start: Integer,
+        end: Integer,
+    ) -> Result<Value, CalculatorError> {
+        let len = range_length(&start, &end);
+        let result = match name {
+            "len" => len,
+            "sum" => len * (start + end) / Integer::from(2),
+            _ if len == 0 => Integer::ONE,
+            _ if start <= 0 && end >= 0 => Integer::ZERO,
+            _ => {
+                let bits = start.significant_bits().max(end.significant_bits());
+                let estimate = Rational::from(&len) * Rational::from(bits);
+                if estimate > self.max_bits() {
+                    return Err(CalculatorError::ResourceLimit(
+                        "result is larger than the maximum number of bits",
+                    ));
+                }
+                let mut product = Integer::ONE;
+                let mut i = start;
+                while i <= end {
+                    if self.cancelled.load(Ordering::Relaxed) {
+                        return Err(CalculatorError::Interrupted);
+                    }
+                    product *= &i;
+                    i += Integer::ONE;
+                }
+                product
+            }
+        };
+        Ok(result.into())
     }
 }
 
-#[derive(Debug)]
-enum Action {
-    Parentheses(bool),
-    Operation(Operation),
+/// First and last integer of `l..r` or `l..=r`
+fn range_bounds(op: Operator, l: Value, r: Value) -> Result<(Integer, Integer), CalculatorError> {
+    let (Some(start), Some(end)) = (l.into_int(), r.into_int()) else {
+        return Err(CalculatorError::RangeBounds);
+    };
+    match op {
+        Operator::Range => Ok((start, end - Integer::ONE)),
+        _ => Ok((start, end)),
+    }
 }
 
-#[derive(Debug)]
-struct Operation {
+/// Number of integers from `start` to `end`, zero if `end` is smaller
+fn range_length(start: &Integer, end: &Integer) -> Integer {
+    if end < start {
+        Integer::ZERO
+    } else {
+        end - start + Integer::ONE
+    }
+}
+
+/// List of the integers from `start` to `end`
+fn range(start: Integer, end: Integer) -> Result<Value, CalculatorError> {
+    if range_length(&start, &end) > MAX_RANGE_LENGTH {
+        return Err(CalculatorError::ResourceLimit(
+            "range is longer than the maximum length",
+        ));
+    }
+    let mut list = vec![];
+    let mut i = start;
+    while i <= end {
+        list.push(Value::Int(i.clone()));
+        i += Integer::ONE;
+    }
+    Ok(Value::List(list))
+}
+
+/// Square root of a non-negative number, exact for squares of rational numbers
+fn sqrt(x: &Rational, precision: u64) -> Rational {
+    let (numerator, denominator) = x.numerator_and_denominator_ref();
+    match (numerator.checked_sqrt(), denominator.checked_sqrt()) {
+        (Some(n), Some(d)) => Rational::from_naturals(n, d),
+        _ => real::sqrt(x, precision).expect("the number is not negative"),
+    }
+}
+
+/// Size of a number in bits, the larger of the numerator and the denominator for fractions
+fn bits(v: &Value) -> u64 {
+    let rational_bits = |r: &Rational| {
+        let (numerator, denominator) = r.numerator_and_denominator_ref();
+        numerator
+            .significant_bits()
+            .max(denominator.significant_bits())
+    };
+    match v {
+        Value::Int(i) => i.significant_bits(),
+        Value::Real(r) | Value::Duration(r) => rational_bits(r),
+        Value::Quantity(q) => rational_bits(&q.magnitude),
+        Value::List(l) => l.iter().map(bits).max().unwrap_or(0),
+        Value::Str(_) => 0,
+        // Powers grow with the sum of the parts, which is at least the absolute value
+        Value::Complex(c) => rational_bits(&((&c.re).abs() + (&c.im).abs())),
+        Value::Interval(i) => rational_bits(&i.lo).max(rational_bits(&i.hi)),
+        Value::Poly(p) => rational_bits(&p.norm()),
+        Value::Symbolic(s) => rational_bits(&s.norm()),
+    }
+}
+
+fn truthy(v: &Value) -> Result<bool, CalculatorError> {
+    v.to_bool().ok_or(CalculatorError::UnsupportedOperands)
+}
+
+fn execute(op: Operator, l: Value, r: Value, precision: u64) -> Result<Value, CalculatorError> {
+    match (op, op.arithmetic()) {
+        (_, Some(op)) => arithmetic(op, l, r, precision),
+        (Operator::Range | Operator::RangeInclusive, None) => {
+            let (start, end) = range_bounds(op, l, r)?;
+            range(start, end)
+        }
+        (Operator::PlusMinus, None) => broadcast(l, r, &|l, r| Ok(interval::plus_minus(l, r)?)),
+        (Operator::To, None) => broadcast(l, r, &|l, r| Ok(units::convert(l, r)?)),
+        (_, None) => unreachable!("logical operators are evaluated lazily"),
+    }
+}
+
+/// Operation applied to each element of the list operands, lists of the same length are combined
+/// element by element
+fn broadcast(
     l: Value,
-    op: Operator,
+    r: Value,
+    f: &dyn Fn(Value, Value) -> Result<Value, CalculatorError>,
+) -> Result<Value, CalculatorError> {
+    match (l, r) {
+        (Value::List(l), Value::List(r)) => {
+            if l.len() != r.len() {
+                return Err(CalculatorError::LengthMismatch(l.len(), r.len()));
+            }
+            let result = l.into_iter().zip(r).map(|(l, r)| broadcast(l, r, f));
+            Ok(Value::List(result.collect::<Result<_, _>>()?))
+        }
+        (Value::List(l), r) => {
+            let result = l.into_iter().map(|l| broadcast(l, r.clone(), f));
+            Ok(Value::List(result.collect::<Result<_, _>>()?))
+        }
+        (l, Value::List(r)) => {
+            let result = r.into_iter().map(|r| broadcast(l.clone(), r, f));
+            Ok(Value::List(result.collect::<Result<_, _>>()?))
+        }
+        (l, r) => f(l, r),
+    }
 }
 
-impl Operation {
-    fn execute(self, r: Value) -> Value {
-        match self.op {
-            Operator::Add => self.l + r,
-            Operator::Sub => self.l - r,
-            Operator::Mul => self.l * r,
-            // TODO: Sane div/0 handling, return NaN
-            Operator::Div => {
-                if r == 0 {
-                    0.into()
-                } else {
-                    self.l / r
-                }
+fn arithmetic(
+    op: Arithmetic,
+    l: Value,
+    r: Value,
+    precision: u64,
+) -> Result<Value, CalculatorError> {
+    if let Some(result) = matrix::execute(op, &l, &r) {
+        return Ok(result?);
+    }
+    match (l, r) {
+        // Lists are compared as a whole, other operations apply to each element
+        (l @ Value::List(_), r) | (l, r @ Value::List(_))
+            if matches!(op, Arithmetic::Eq | Arithmetic::Ne) =>
+        {
+            Ok(((l == r) == (op == Arithmetic::Eq)).into())
+        }
+        (l @ Value::List(_), r) | (l, r @ Value::List(_)) => {
+            broadcast(l, r, &|l, r| arithmetic(op, l, r, precision))
+        }
+        (l @ Value::Interval(_), r) | (l, r @ Value::Interval(_)) => {
+            Ok(interval::execute(op, l, r, precision)?)
+        }
+        (l @ Value::Poly(_), r) | (l, r @ Value::Poly(_)) => Ok(polynomial::execute(op, l, r)?),
+        (l @ Value::Symbolic(_), r) | (l, r @ Value::Symbolic(_)) => {
+            Ok(symbolic::execute(op, l, r)?)
+        }
+        // `m` is the metre, but minutes when it's added to or compared with a duration, so that
+        // `45m + 1h` is the same as `1h + 45m`
+        (Value::Quantity(l), r @ Value::Duration(_)) if is_minutes(op, &l) => arithmetic(
+            op,
+            Value::Duration(l.magnitude * Rational::from(60)),
+            r,
+            precision,
+        ),
+        (l @ Value::Duration(_), Value::Quantity(r)) if is_minutes(op, &r) => arithmetic(
+            op,
+            l,
+            Value::Duration(r.magnitude * Rational::from(60)),
+            precision,
+        ),
+        (l @ Value::Quantity(_), r) | (l, r @ Value::Quantity(_)) => Ok(units::execute(op, l, r)?),
+        (Value::Duration(l), r) => execute_duration(op, l, r),
+        (l, Value::Duration(r)) if op == Arithmetic::Mul => execute_duration(op, r, l),
+        (_, Value::Duration(_)) => Err(CalculatorError::DurationOperands),
+        (l @ Value::Complex(_), r) | (l, r @ Value::Complex(_)) => Ok(complex::execute(op, l, r)?),
+        // Negative powers of integers are fractions
+        (Value::Int(l), Value::Int(r)) if op != Arithmetic::Pow || r >= 0 => execute_int(op, l, r),
+        (l, r) => {
+            let (Some(l), Some(r)) = (l.to_rational(), r.to_rational()) else {
+                return Err(CalculatorError::UnsupportedOperands);
+            };
+            execute_real(op, l, r, precision)
+        }
+    }
+}
+
+/// Element `i` of a list, or the elements from `i` up to `j` excluding `j`. Negative indices
+/// count from the end
+fn index(list: Vec<Value>, i: Value, j: Option<Value>) -> Result<Value, CalculatorError> {
+    let len = list.len();
+    let position = |v: Value| {
+        let i = v.into_int().ok_or(CalculatorError::InvalidIndex)?;
+        let i = if i < 0 { i + Integer::from(len) } else { i };
+        usize::try_from(&i).map_err(|_| CalculatorError::IndexOutOfRange(len))
+    };
+    let i = position(i)?;
+    match j {
+        None => list
+            .into_iter()
+            .nth(i)
+            .ok_or(CalculatorError::IndexOutOfRange(len)),
+        Some(j) => {
+            let j = position(j)?;
+            if i > j || j > len {
+                return Err(CalculatorError::IndexOutOfRange(len));
             }
-            // TODO: Validate POW number
-            Operator::Pow => self.l.pow(r.to_twos_complement_limbs_asc()[0]),
+            Ok(Value::List(list[i..j].to_vec()))
         }
     }
+}
+
+/// `v` percent of `base`, or of one if there is no base
+fn percent(v: &Value, base: Option<&Value>) -> Result<Value, CalculatorError> {
+    let v = v
+        .to_rational()
+        .ok_or(CalculatorError::UnsupportedOperands)?;
+    let base = match base {
+        Some(base) => base
+            .to_rational()
+            .ok_or(CalculatorError::UnsupportedOperands)?,
+        None => Rational::ONE,
+    };
+    let result = v * base / Rational::from(100);
+    Ok(match Integer::try_from(&result) {
+        Ok(i) => Value::Int(i),
+        Err(_) => Value::Real(result),
+    })
+}
 
-    fn priority(&self) -> u8 {
-        match self.op {
-            Operator::Add | Operator::Sub => 10,
-            Operator::Mul | Operator::Div => 20,
-            Operator::Pow => 30,
+/// Whether a quantity in metres stands for minutes in an operation with a duration
+fn is_minutes(op: Arithmetic, q: &Quantity) -> bool {
+    use Arithmetic::*;
+    q.unit.name == "m" && matches!(op, Add | Sub | Eq | Ne | Lt | Le | Gt | Ge)
+}
+
+/// Operation with a duration on the left
+fn execute_duration(op: Arithmetic, l: Rational, r: Value) -> Result<Value, CalculatorError> {
+    let result = match (op, r) {
+        (Arithmetic::Add, Value::Duration(r)) => l + r,
+        (Arithmetic::Sub, Value::Duration(r)) => l - r,
+        (Arithmetic::Div, Value::Duration(r)) if r == 0u32 => {
+            return Err(CalculatorError::DivisionByZero)
+        }
+        (Arithmetic::Div, Value::Duration(r)) => {
+            let ratio = l / r;
+            return Ok(match Integer::try_from(&ratio) {
+                Ok(i) => Value::Int(i),
+                Err(_) => Value::Real(ratio),
+            });
         }
+        (Arithmetic::Eq, Value::Duration(r)) => return Ok((l == r).into()),
+        (Arithmetic::Ne, Value::Duration(r)) => return Ok((l != r).into()),
+        (Arithmetic::Lt, Value::Duration(r)) => return Ok((l < r).into()),
+        (Arithmetic::Le, Value::Duration(r)) => return Ok((l <= r).into()),
+        (Arithmetic::Gt, Value::Duration(r)) => return Ok((l > r).into()),
+        (Arithmetic::Ge, Value::Duration(r)) => return Ok((l >= r).into()),
+        (Arithmetic::Mul | Arithmetic::Div, r) => {
+            let r = r.to_rational().ok_or(CalculatorError::DurationOperands)?;
+            match op {
+                Arithmetic::Div if r == 0u32 => return Err(CalculatorError::DivisionByZero),
+                Arithmetic::Div => l / r,
+                _ => l * r,
+            }
+        }
+        _ => return Err(CalculatorError::DurationOperands),
+    };
+    Ok(Value::Duration(result))
+}
+
+/// Division by the modular inverse and powers modulo `m`, other operations are reduced by
+/// [`Calculator::wrap`]
+fn execute_modular(
+    op: Operator,
+    l: Integer,
+    r: Integer,
+    m: &Natural,
+) -> Result<Value, CalculatorError> {
+    let not_invertible = || CalculatorError::NotInvertible(m.clone());
+    let result = match op {
+        Operator::Div => l * modular::invmod(r, m.clone()).ok_or_else(not_invertible)?,
+        _ => modular::powmod(l, r, m.clone()).ok_or_else(not_invertible)?,
+    };
+    Ok(result.into())
+}
+
+fn execute_int(op: Arithmetic, l: Integer, r: Integer) -> Result<Value, CalculatorError> {
+    let result = match op {
+        Arithmetic::Add => l + r,
+        Arithmetic::Sub => l - r,
+        Arithmetic::Mul => l * r,
+        Arithmetic::Div => return exact_division(l, r),
+        Arithmetic::Pow => {
+            let exp = u64::try_from(&r).map_err(|_| CalculatorError::ExponentTooLarge)?;
+            l.pow(exp)
+        }
+        Arithmetic::Eq => return Ok((l == r).into()),
+        Arithmetic::Ne => return Ok((l != r).into()),
+        Arithmetic::Lt => return Ok((l < r).into()),
+        Arithmetic::Le => return Ok((l <= r).into()),
+        Arithmetic::Gt => return Ok((l > r).into()),
+        Arithmetic::Ge => return Ok((l >= r).into()),
+    };
+    Ok(result.into())
+}
+
+/// Integer wrapped to `width` bits read as a two's complement signed integer
+fn signed(value: Value, width: u64) -> Value {
+    match value {
+        Value::Int(i) if i.significant_bits() == width => (i - (Integer::ONE << width)).into(),
+        value => value,
+    }
+}
+
+/// Quotient of integers as a fraction, an integer if it divides evenly
+fn exact_division(l: Integer, r: Integer) -> Result<Value, CalculatorError> {
+    if r == 0 {
+        return Err(CalculatorError::DivisionByZero);
+    }
+    let x = Rational::from(l) / Rational::from(r);
+    Ok(match Integer::try_from(&x) {
+        Ok(i) => Value::Int(i),
+        Err(_) => Value::Real(x),
+    })
+}
+
+/// Quotient of integers rounded toward zero
+fn truncated_division(l: Integer, r: Integer) -> Result<Value, CalculatorError> {
+    if r == 0 {
+        return Err(CalculatorError::DivisionByZero);
+    }
+    Ok((l / r).into())
+}
+
+fn execute_real(
+    op: Arithmetic,
+    l: Rational,
+    r: Rational,
+    precision: u64,
+) -> Result<Value, CalculatorError> {
+    let result = match op {
+        Arithmetic::Add => l + r,
+        Arithmetic::Sub => l - r,
+        Arithmetic::Mul => l * r,
+        Arithmetic::Div if r == 0u32 => return Err(CalculatorError::DivisionByZero),
+        Arithmetic::Div => l / r,
+        Arithmetic::Pow => match Integer::try_from(&r) {
+            Ok(_) if l == 0u32 && r < 0u32 => return Err(CalculatorError::DivisionByZero),
+            Ok(exp) => {
+                let exp = i64::try_from(&exp).map_err(|_| CalculatorError::ExponentTooLarge)?;
+                l.pow(exp)
+            }
+            Err(_) => real::pow(&l, &r, precision).map_err(|e| match e {
+                RealError::DivisionByZero => CalculatorError::DivisionByZero,
+                RealError::TooLarge => CalculatorError::ExponentTooLarge,
+                _ => CalculatorError::FractionalPowerOfNegative,
+            })?,
+        },
+        Arithmetic::Eq => return Ok((l == r).into()),
+        Arithmetic::Ne => return Ok((l != r).into()),
+        Arithmetic::Lt => return Ok((l < r).into()),
+        Arithmetic::Le => return Ok((l <= r).into()),
+        Arithmetic::Gt => return Ok((l > r).into()),
+        Arithmetic::Ge => return Ok((l >= r).into()),
+    };
+    Ok(Value::Real(result))
+}
+
+/// Value of a numeric environment variable
+fn environment(name: &str) -> Result<Value, CalculatorError> {
+    let text =
+        std::env::var(name).map_err(|_| CalculatorError::UndefinedEnvironment(name.into()))?;
+    let invalid = || CalculatorError::InvalidEnvironment(name.into());
+    let tokens: Vec<Token> = Tokenizer::tokens(text.trim().chars())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    match tokens.as_slice() {
+        [Token::Val(value)] => Ok(value.clone()),
+        _ => Err(invalid()),
     }
 }
 
@@ -154,6 +1460,83 @@ pub enum CalculatorError {
     OperationExpected,
     #[error("Unmatched parentheses")]
     UnmatchedParen,
+    #[error("Unmatched brackets")]
+    UnmatchedBracket,
+    #[error("Mismatched brackets, expected '{0}'")]
+    MismatchedBracket(char),
+    #[error("Unmatched absolute value bars")]
+    UnmatchedBar,
+    #[error("Unknown identifier: {0}")]
+    UnknownIdentifier(CompactString),
+    #[error("Resource limit exceeded: {0}")]
+    ResourceLimit(&'static str),
+    #[error("Interrupted")]
+    Interrupted,
+    #[error("Expression is nested too deeply")]
+    TooDeep,
+    #[error("There is no result ${0}")]
+    NoResult(usize),
+    #[error("Environment variable {0} is not set")]
+    UndefinedEnvironment(CompactString),
+    #[error("Environment variable {0} is not a number")]
+    InvalidEnvironment(CompactString),
+    #[error("Unexpected comma outside of function arguments")]
+    UnexpectedComma,
+    #[error("Expected ':' of a conditional expression")]
+    ColonExpected,
+    #[error("Unexpected ':' outside of a conditional expression")]
+    UnexpectedColon,
+    #[error("Unexpected '=' outside of a definition")]
+    UnexpectedAssignment,
+    #[error("Operation is only supported for numbers")]
+    UnsupportedOperands,
+    #[error("Durations can only be added, subtracted, compared, divided, or scaled by a number")]
+    DurationOperands,
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Division by a number that is not invertible modulo {0}")]
+    NotInvertible(Natural),
+    #[error("Exponent is too large")]
+    ExponentTooLarge,
+    #[error("Fractional power of a negative number")]
+    FractionalPowerOfNegative,
+    #[error("Lists of different lengths: {0} and {1}")]
+    LengthMismatch(usize, usize),
+    #[error("Index must be an integer")]
+    InvalidIndex,
+    #[error("Index is out of range of a list of {0} elements")]
+    IndexOutOfRange(usize),
+    #[error("Range bounds must be integers")]
+    RangeBounds,
+    #[error(transparent)]
+    Function(#[from] FunctionError),
+    #[error(transparent)]
+    Unit(#[from] UnitError),
+    #[error(transparent)]
+    Complex(#[from] ComplexError),
+    #[error(transparent)]
+    Matrix(#[from] MatrixError),
+    #[error(transparent)]
+    Polynomial(#[from] PolynomialError),
+    #[error(transparent)]
+    Symbolic(#[from] SymbolicError),
+    #[error(transparent)]
+    Derivative(#[from] DerivativeError),
+    #[error(transparent)]
+    Interval(#[from] IntervalError),
+}
+
+impl CalculatorError {
+    /// Identifier or function that caused the error, to locate it in the input
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            CalculatorError::UnknownIdentifier(name)
+            | CalculatorError::UndefinedEnvironment(name)
+            | CalculatorError::InvalidEnvironment(name) => Some(name),
+            CalculatorError::Function(e) => Some(e.name()),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -165,6 +1548,11 @@ mod tests {
     const MUL: Token = Token::Op(Operator::Mul);
     const OP: Token = Token::ParenOpen;
     const CL: Token = Token::ParenClose;
+    const COMMA: Token = Token::Comma;
+
+    fn ident(name: &str) -> Token {
+        Token::Ident(name.into())
+    }
 
     fn calculate(tokens: Vec<Token>) -> Result<Value, CalculatorError> {
         let mut calculator = Calculator::default();
@@ -175,10 +1563,1085 @@ mod tests {
         calculator.finalize()
     }
 
+    fn eval_with(calculator: &mut Calculator, text: &str) -> Result<String, CalculatorError> {
+        for token in Tokenizer::tokens(text.chars()) {
+            calculator.handle_token(token.unwrap())?;
+        }
+        calculator.finalize().map(|v| v.to_string())
+    }
+
+    fn eval(text: &str) -> Result<String, CalculatorError> {
+        eval_with(&mut Calculator::default(), text)
+    }
+
+    #[test]
+    fn test_reset() {
+        // 2 * ( <error> 3 + 4
+        let mut calculator = Calculator::default();
+        for t in [2.into(), MUL, OP] {
+            calculator.handle_token(t).unwrap();
+        }
+        calculator.reset();
+        for t in [3.into(), ADD, 4.into()] {
+            calculator.handle_token(t).unwrap();
+        }
+        assert_eq!(calculator.finalize(), Ok(Value::from(7)));
+    }
+
     #[test]
     fn test_negative_braces() {
         // 2 * -(2 + 2)
         let res = calculate(vec![2.into(), MUL, SUB, OP, 2.into(), ADD, 2.into(), CL]);
-        assert_eq!(res, Ok(-8));
+        assert_eq!(res, Ok(Value::from(-8)));
+    }
+
+    #[test]
+    fn test_function_call() {
+        // -max(1, 2 * 3, abs(-7)) + 1
+        let res = calculate(vec![
+            SUB,
+            ident("max"),
+            OP,
+            1.into(),
+            COMMA,
+            2.into(),
+            MUL,
+            3.into(),
+            COMMA,
+            ident("abs"),
+            OP,
+            SUB,
+            7.into(),
+            CL,
+            CL,
+            ADD,
+            1.into(),
+        ]);
+        assert_eq!(res, Ok(Value::from(-6)));
+    }
+
+    #[test]
+    fn test_unexpected_comma() {
+        // (1, 2)
+        let res = calculate(vec![OP, 1.into(), COMMA, 2.into(), CL]);
+        assert_eq!(res, Err(CalculatorError::UnexpectedComma));
+
+        // abs
+        let res = calculate(vec![ident("abs")]);
+        assert_eq!(res, Err(CalculatorError::UnknownIdentifier("abs".into())));
+    }
+
+    #[test]
+    fn test_real() {
+        let real = |n, d| Token::Val(Value::Real(Rational::from_signeds(n, d)));
+        const DIV: Token = Token::Op(Operator::Div);
+        const POW: Token = Token::Op(Operator::Pow);
+
+        // 1.5 * 3 - 1 / 2
+        let res = calculate(vec![
+            real(3, 2),
+            MUL,
+            3.into(),
+            SUB,
+            1.into(),
+            DIV,
+            2.into(),
+        ]);
+        assert_eq!(res, Ok(Value::Real(Rational::from(4))));
+
+        // 2 ** -2 + 1 / 4.0
+        let res = calculate(vec![
+            2.into(),
+            POW,
+            SUB,
+            2.into(),
+            ADD,
+            1.into(),
+            DIV,
+            real(4, 1),
+        ]);
+        assert_eq!(res, Ok(Value::Real(Rational::from_signeds(1, 2))));
+
+        // 2 ** 0
+        let res = calculate(vec![2.into(), POW, 0.into()]);
+        assert_eq!(res, Ok(Value::from(1)));
+
+        // 1 / 0.0
+        let res = calculate(vec![1.into(), DIV, real(0, 1)]);
+        assert_eq!(res, Err(CalculatorError::DivisionByZero));
+
+        // 1 / 0
+        let res = calculate(vec![1.into(), DIV, 0.into()]);
+        assert_eq!(res, Err(CalculatorError::DivisionByZero));
+
+        // (-2) ** 0.5
+        let res = calculate(vec![OP, SUB, 2.into(), CL, POW, real(1, 2)]);
+        assert_eq!(res, Err(CalculatorError::FractionalPowerOfNegative));
+    }
+
+    #[test]
+    fn test_percent() {
+        const PCT: Token = Token::Percent;
+
+        // 200 + 10%
+        let res = calculate(vec![200.into(), ADD, 10.into(), PCT]);
+        assert_eq!(res, Ok(Value::from(220)));
+
+        // 15% * 80
+        let res = calculate(vec![15.into(), PCT, MUL, 80.into()]);
+        assert_eq!(res, Ok(Value::Real(Rational::from(12))));
+
+        // 50 - 3%
+        let res = calculate(vec![50.into(), SUB, 3.into(), PCT]);
+        assert_eq!(res, Ok(Value::Real(Rational::from_signeds(97, 2))));
+    }
+
+    #[test]
+    fn test_comparison() {
+        const LT: Token = Token::Op(Operator::Lt);
+        const EQ: Token = Token::Op(Operator::Eq);
+        let real = |n, d| Token::Val(Value::Real(Rational::from_signeds(n, d)));
+
+        // 1 + 2 < 2 * 2
+        let res = calculate(vec![1.into(), ADD, 2.into(), LT, 2.into(), MUL, 2.into()]);
+        assert_eq!(res, Ok(Value::from(1)));
+
+        // 2.0 == 4 / 2
+        let div = Token::Op(Operator::Div);
+        let res = calculate(vec![real(2, 1), EQ, 4.into(), div, 2.into()]);
+        assert_eq!(res, Ok(Value::from(1)));
+
+        // (3 < 2) + 1
+        let res = calculate(vec![OP, 3.into(), LT, 2.into(), CL, ADD, 1.into()]);
+        assert_eq!(res, Ok(Value::from(1)));
+    }
+
+    #[test]
+    fn test_logical() {
+        const AND: Token = Token::Op(Operator::And);
+        const OR: Token = Token::Op(Operator::Or);
+        const GT: Token = Token::Op(Operator::Gt);
+        let div = Token::Op(Operator::Div);
+        let real_zero = Token::Val(Value::Real(Rational::from(0)));
+
+        // !0 && 2 > 1
+        let res = calculate(vec![Token::Not, 0.into(), AND, 2.into(), GT, 1.into()]);
+        assert_eq!(res, Ok(Value::from(1)));
+
+        // -!(1 || 0)
+        let res = calculate(vec![SUB, Token::Not, OP, 1.into(), OR, 0.into(), CL]);
+        assert_eq!(res, Ok(Value::from(0)));
+
+        // 0 && 1 / 0.0, right operand is not evaluated
+        let res = calculate(vec![
+            0.into(),
+            AND,
+            1.into(),
+            div.clone(),
+            real_zero.clone(),
+        ]);
+        assert_eq!(res, Ok(Value::from(0)));
+
+        // 1 || 1 / 0.0
+        let res = calculate(vec![1.into(), OR, 1.into(), div, real_zero]);
+        assert_eq!(res, Ok(Value::from(1)));
+    }
+
+    #[test]
+    fn test_conditional() {
+        const GT: Token = Token::Op(Operator::Gt);
+        const Q: Token = Token::Question;
+        const COLON: Token = Token::Colon;
+
+        // 150 > 100 ? 150 : 0
+        let res = calculate(vec![
+            150.into(),
+            GT,
+            100.into(),
+            Q,
+            150.into(),
+            COLON,
+            0.into(),
+        ]);
+        assert_eq!(res, Ok(Value::from(150)));
+
+        // max(0 ? abs : 1, 2) + 1, unevaluated branch may contain unknown names
+        let res = calculate(vec![
+            ident("max"),
+            OP,
+            0.into(),
+            Q,
+            ident("abs"),
+            COLON,
+            1.into(),
+            COMMA,
+            2.into(),
+            CL,
+            ADD,
+            1.into(),
+        ]);
+        assert_eq!(res, Ok(Value::from(3)));
+
+        // (1 ? 2) : 3
+        let res = calculate(vec![OP, 1.into(), Q, 2.into(), CL, COLON, 3.into()]);
+        assert_eq!(res, Err(CalculatorError::ColonExpected));
+    }
+
+    #[test]
+    fn test_width() {
+        const DIV: Token = Token::Op(Operator::Div);
+        const LT: Token = Token::Op(Operator::Lt);
+        const GT: Token = Token::Op(Operator::Gt);
+        let mut calculator = Calculator::default();
+        calculator.set_width(Some(8));
+        let mut calculate = |tokens: Vec<Token>| {
+            for t in tokens {
+                calculator.handle_token(t)?;
+            }
+            calculator.finalize()
+        };
+
+        // 200 + 100
+        let res = calculate(vec![200.into(), ADD, 100.into()]);
+        assert_eq!(res, Ok(Value::from(44)));
+
+        // 1 - 2
+        let res = calculate(vec![1.into(), SUB, 2.into()]);
+        assert_eq!(res, Ok(Value::from(255)));
+
+        // -8 * 2
+        let res = calculate(vec![SUB, 8.into(), MUL, 2.into()]);
+        assert_eq!(res, Ok(Value::from(240)));
+
+        // -6 / 2, not 250 / 2
+        let res = calculate(vec![SUB, 6.into(), DIV, 2.into()]);
+        assert_eq!(res, Ok(Value::from(253)));
+
+        // -128 / -1 overflows
+        let res = calculate(vec![128.into(), DIV, SUB, 1.into()]);
+        assert_eq!(res, Ok(Value::from(128)));
+
+        // -1 < 1, 200 > 100 is -56 > 100
+        let res = calculate(vec![SUB, 1.into(), LT, 1.into()]);
+        assert_eq!(res, Ok(Value::from(true)));
+        let res = calculate(vec![200.into(), GT, 100.into()]);
+        assert_eq!(res, Ok(Value::from(false)));
+    }
+
+    #[test]
+    fn test_reinterpret() {
+        assert_eq!(eval("i32(0xfffffff0)"), Ok("-16".into()));
+        assert_eq!(eval("u32(-16)"), Ok("4294967280".into()));
+        assert_eq!(eval("i16(0x8000)"), Ok("-32768".into()));
+        assert_eq!(eval("u8(256 + 7)"), Ok("7".into()));
+        assert_eq!(eval("u64(-1)"), Ok("18446744073709551615".into()));
+        assert_eq!(eval("i64(2**63)"), Ok("-9223372036854775808".into()));
+        assert_eq!(
+            eval("i128(2**127 - 1)"),
+            Ok("170141183460469231731687303715884105727".into())
+        );
+        assert_eq!(eval("u128(2**128)"), Ok("0".into()));
+        assert!(eval("i8(1.5)").is_err());
+    }
+
+    #[test]
+    fn test_modulus() {
+        const DIV: Token = Token::Op(Operator::Div);
+        const POW: Token = Token::Op(Operator::Pow);
+        let mut calculator = Calculator::default();
+        calculator.set_modulus(Some(Natural::from(7u32)));
+        let mut calculate = |tokens: Vec<Token>| {
+            for t in tokens {
+                calculator.handle_token(t)?;
+            }
+            calculator.finalize()
+        };
+
+        // 5 + 4
+        let res = calculate(vec![5.into(), ADD, 4.into()]);
+        assert_eq!(res, Ok(Value::from(2)));
+
+        // 1 - 2
+        let res = calculate(vec![1.into(), SUB, 2.into()]);
+        assert_eq!(res, Ok(Value::from(6)));
+
+        // 3 / 4, 4 * 2 = 1 (mod 7)
+        let res = calculate(vec![3.into(), DIV, 4.into()]);
+        assert_eq!(res, Ok(Value::from(6)));
+
+        // 3 / 7
+        let res = calculate(vec![3.into(), DIV, 7.into()]);
+        assert_eq!(
+            res,
+            Err(CalculatorError::NotInvertible(Natural::from(7u32)))
+        );
+
+        // 3 ** -1
+        let res = calculate(vec![3.into(), POW, SUB, 1.into()]);
+        assert_eq!(res, Ok(Value::from(5)));
+
+        // 2 ** (10 - 3), the exponent isn't reduced
+        let res = calculate(vec![2.into(), POW, OP, 10.into(), SUB, 3.into(), CL]);
+        assert_eq!(res, Ok(Value::from(2)));
+
+        // 2 ** 100000000000 is reduced without computing the power
+        let res = calculate(vec![2.into(), POW, 100000000000.into()]);
+        assert_eq!(res, Ok(Value::from(2)));
+
+        calculator.set_modulus(Some(Natural::from(0u32)));
+        assert_eq!(calculator.modulus(), None);
+    }
+
+    #[test]
+    fn test_complex() {
+        let i = || ident("i");
+        const POW: Token = Token::Op(Operator::Pow);
+
+        // (2 + 3i) * (1 - i)
+        let res = calculate(vec![
+            OP,
+            2.into(),
+            ADD,
+            3.into(),
+            i(),
+            CL,
+            MUL,
+            OP,
+            1.into(),
+            SUB,
+            i(),
+            CL,
+        ]);
+        assert_eq!(res.map(|v| v.to_string()), Ok("5+i".into()));
+
+        // i ** 2
+        let res = calculate(vec![i(), POW, 2.into()]);
+        assert_eq!(res, Ok(Value::from(-1)));
+
+        // (1 + i) ** 100000000 is too large
+        let res = calculate(vec![OP, 1.into(), ADD, i(), CL, POW, 100000000.into()]);
+        assert!(matches!(res, Err(CalculatorError::ResourceLimit(_))));
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(eval("[1, 2, 3] * 2"), Ok("[2, 4, 6]".into()));
+        assert_eq!(eval("[1, 2] + [3, 4]"), Ok("[4, 6]".into()));
+        assert_eq!(
+            eval("[1, 2] + [3]"),
+            Err(CalculatorError::LengthMismatch(2, 1))
+        );
+        assert_eq!(eval("[1, 2, 3][-1]"), Ok("3".into()));
+        assert_eq!(eval("[1, 2, 3][1:3]"), Ok("[2, 3]".into()));
+        assert_eq!(
+            eval("[1, 2, 3][3]"),
+            Err(CalculatorError::IndexOutOfRange(3))
+        );
+        assert_eq!(eval("[1, 2] == [1, 2]"), Ok("1".into()));
+        // Elements in parentheses are lists
+        assert_eq!(
+            eval("[(1, 2), -(3, 4), (5)]"),
+            Ok("[[1, 2], [-3, -4], 5]".into())
+        );
+        assert_eq!(eval("crt([(2, 3), (3, 5), (2, 7)])"), Ok("23".into()));
+        assert_eq!(eval("[1 + (2, 3)]"), Err(CalculatorError::UnexpectedComma));
+        assert_eq!(eval("(2, 3)"), Err(CalculatorError::UnexpectedComma));
+    }
+
+    #[test]
+    fn test_matrix() {
+        let m = "[[1, 2], [3, 4]]";
+        assert_eq!(eval(&format!("{m} + {m}")), Ok("[[2, 4], [6, 8]]".into()));
+        assert_eq!(
+            eval(&format!("{m} * {m}")),
+            Ok("[[7, 10], [15, 22]]".into())
+        );
+        assert_eq!(eval(&format!("{m} * 2")), Ok("[[2, 4], [6, 8]]".into()));
+        // Matrices with a single row or column
+        assert_eq!(eval("det([[5]])"), Ok("5".into()));
+        assert_eq!(eval("transpose([[1, 2]])"), Ok("[[1], [2]]".into()));
+        assert_eq!(eval("[[1], [2]]"), Ok("[[1], [2]]".into()));
+        assert_eq!(
+            eval(&format!("{m} ** 3")),
+            Ok("[[37, 54], [81, 118]]".into())
+        );
+        assert_eq!(
+            eval(&format!("transpose({m})")),
+            Ok("[[1, 3], [2, 4]]".into())
+        );
+        assert_eq!(eval(&format!("det({m})")), Ok("-2".into()));
+        assert_eq!(
+            eval(&format!("inv({m})")),
+            Ok("[[-2, 1], [1.5, -0.5]]".into())
+        );
+        assert_eq!(
+            eval(&format!("{m} * [[1, 2, 3]]")),
+            Err(CalculatorError::Matrix(MatrixError::DimensionMismatch))
+        );
+        assert!(eval("det([[1, 2], [2, 4]] ** -1)").is_err());
+        assert!(matches!(
+            eval("[[1, 1], [1, 0]] ** 1000000000"),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+    }
+
+    #[test]
+    fn test_polynomial() {
+        assert_eq!(eval("poly([1, 0, -2])"), Ok("x**2 - 2".into()));
+        assert_eq!(eval("poly(1, 1) * poly(1, -1)"), Ok("x**2 - 1".into()));
+        assert_eq!(eval("poly(1, 1) ** 2 + 1"), Ok("x**2 + 2x + 2".into()));
+        assert_eq!(eval("-poly(1, 0) / 2"), Ok("-0.5x".into()));
+        assert_eq!(eval("polyval(poly(1, 0, -2), 3)"), Ok("7".into()));
+        assert_eq!(eval("polyval([1, 0, -2], 0.5)"), Ok("-1.75".into()));
+        assert_eq!(
+            eval("polydiv(poly(1, -2, 0, -4), poly(1, -3))"),
+            Ok("[x**2 + x + 3, 5]".into())
+        );
+        assert_eq!(eval("coeffs(poly(2, 0) - poly(2, 0))"), Ok("[]".into()));
+        assert_eq!(
+            eval("poly(1) < poly(2)"),
+            Err(CalculatorError::Polynomial(
+                PolynomialError::UnsupportedOperands
+            ))
+        );
+        assert!(matches!(
+            eval("poly(2, 0) ** 100000000"),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+    }
+
+    #[test]
+    fn test_solve() {
+        assert_eq!(eval("solve(2*x + 6 == 20, x)"), Ok("7".into()));
+        assert_eq!(eval("solve(3*t == 1, t) * 3"), Ok("1".into()));
+        assert_eq!(eval("solve(x**2 - 5*x + 6, x)"), Ok("[2, 3]".into()));
+        assert_eq!(eval("solve(4*x**2 == 1, x)"), Ok("[-0.5, 0.5]".into()));
+        assert_eq!(eval("solve((x - 1)**2, x)"), Ok("[1]".into()));
+        assert_eq!(
+            eval("solve(x**2 + 2*x + 5, x)"),
+            Ok("[-1-2i, -1+2i]".into())
+        );
+        assert_eq!(
+            eval("solve(x**2 == 2, x) == [-sqrt(2), sqrt(2)]"),
+            Ok("1".into())
+        );
+        let invalid = |reason| {
+            Err(CalculatorError::Function(FunctionError::InvalidArgument {
+                name: "solve".into(),
+                reason,
+            }))
+        };
+        assert_eq!(
+            eval("solve(x == x + 1, x)"),
+            invalid("the equation has no solution")
+        );
+        assert_eq!(
+            eval("solve(2*x == x + x, x)"),
+            invalid("every number is a solution")
+        );
+        assert_eq!(
+            eval("solve(x**3 == 1, x)"),
+            invalid("only linear and quadratic equations can be solved")
+        );
+        assert_eq!(
+            eval("solve(x, 2)"),
+            invalid("expected the name of a variable")
+        );
+        assert_eq!(
+            eval("solve(x*y, x)"),
+            Err(CalculatorError::UnknownIdentifier("y".into()))
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(eval("diff(x**3 + 2*x, x)"), Ok("3*x**2 + 2".into()));
+        assert_eq!(eval("diff((t + 1)**2 / 2, t)"), Ok("t + 1".into()));
+        assert_eq!(eval("diff(x**3 + 2*x, x, 2)"), Ok("14".into()));
+        assert_eq!(eval("diff(sin(x), x, 0)"), Ok("1".into()));
+        assert_eq!(eval("diff((x + 1) / (x - 1), x, 2)"), Ok("-2".into()));
+        assert_eq!(eval("diff(pi, x)"), Ok("0".into()));
+        assert_eq!(
+            eval("diff(sin(x), x)"),
+            Err(CalculatorError::Function(FunctionError::InvalidArgument {
+                name: "diff".into(),
+                reason: "the derivative isn't a polynomial, expected a point",
+            }))
+        );
+        assert_eq!(
+            eval("diff(x < 3, x)"),
+            Err(CalculatorError::Derivative(DerivativeError::Unsupported(
+                "<".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_series() {
+        assert_eq!(eval("sum(k, 1, 100, k**2)"), Ok("338350".into()));
+        assert_eq!(eval("prod(k, 1, 20, k)"), Ok("2432902008176640000".into()));
+        assert_eq!(eval("sum(k, 0, 3, 1.0 / 2**k)"), Ok("1.875".into()));
+        assert_eq!(eval("sum(i, 1, 3, sum(j, 1, i, j))"), Ok("10".into()));
+        assert_eq!(eval("sum(k, 1, 0, k) + prod(k, 1, 0, k)"), Ok("1".into()));
+        // Other forms of `sum` are unchanged
+        assert_eq!(eval("sum(1, 2, 3, 4)"), Ok("10".into()));
+        assert_eq!(eval("sum(1..=4)"), Ok("10".into()));
+        // The index shadows a variable of the same name
+        let mut calculator = Calculator::default();
+        calculator.set_variable("k", Value::from(2));
+        assert_eq!(
+            eval_with(&mut calculator, "sum(k, 1, 100, k**2)"),
+            Ok("338350".into())
+        );
+        assert_eq!(eval_with(&mut calculator, "k"), Ok("2".into()));
+        assert_eq!(eval("sum(k, 1, 2.5, k)"), Err(CalculatorError::RangeBounds));
+        assert!(matches!(
+            eval("prod(k, 1, 10**9, k)"),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+        assert_eq!(
+            eval("prod(k, 1, 2)"),
+            Err(CalculatorError::Function(FunctionError::ArgumentCount {
+                name: "prod".into(),
+                expected: 4,
+                got: 3,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_symbolic() {
+        let mut calculator = Calculator::default();
+        calculator.set_symbolic(true);
+        calculator.set_variable("y", Value::from(2));
+        assert_eq!(eval_with(&mut calculator, "2*x + 3*x"), Ok("5*x".into()));
+        assert_eq!(
+            eval_with(&mut calculator, "(x + 1)**2 - 2*x"),
+            Ok("x**2 + 1".into())
+        );
+        assert_eq!(
+            eval_with(&mut calculator, "(a - b) * (a + b)"),
+            Ok("a**2 - b**2".into())
+        );
+        assert_eq!(eval_with(&mut calculator, "x*y + x/2"), Ok("2.5*x".into()));
+        assert_eq!(eval_with(&mut calculator, "pi*x - x*pi"), Ok("0".into()));
+        assert_eq!(
+            eval_with(&mut calculator, "1 / x"),
+            Err(CalculatorError::Symbolic(SymbolicError::SymbolicDivisor))
+        );
+        assert_eq!(eval_with(&mut calculator, "x > 1"), {
+            Err(CalculatorError::Symbolic(
+                SymbolicError::UnsupportedOperands,
+            ))
+        });
+
+        calculator.set_symbolic(false);
+        assert_eq!(
+            eval_with(&mut calculator, "2*x"),
+            Err(CalculatorError::UnknownIdentifier("x".into()))
+        );
+    }
+
+    #[test]
+    fn test_exact_division() {
+        assert_eq!(eval("10 / 4"), Ok("2.5".into()));
+        assert_eq!(eval("10 / 5"), Ok("2".into()));
+        assert_eq!(eval("-7 ÷ 2"), Ok("-3.5".into()));
+        assert_eq!(eval("1 / 0"), Err(CalculatorError::DivisionByZero));
+
+        let mut calculator = Calculator::default();
+        calculator.set_exact_division(false);
+        assert_eq!(eval_with(&mut calculator, "10 / 4"), Ok("2".into()));
+        assert_eq!(eval_with(&mut calculator, "-7 / 2"), Ok("-3".into()));
+        assert_eq!(eval_with(&mut calculator, "10 / 4.0"), Ok("2.5".into()));
+
+        // Fixed-width integers truncate
+        let mut calculator = Calculator::default();
+        calculator.set_width(Some(8));
+        assert_eq!(eval_with(&mut calculator, "7 / 2"), Ok("3".into()));
+    }
+
+    #[test]
+    fn test_continued_fraction() {
+        assert_eq!(eval("cfrac(415/93, 5)"), Ok("[4, 2, 6, 7]".into()));
+        assert_eq!(eval("cfrac(-7/2, 10)"), Ok("[-4, 2]".into()));
+        assert_eq!(
+            eval("fromcfrac(cfrac(415/93, 10)) == 415/93"),
+            Ok("1".into())
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(eval("1..=4"), Ok("[1, 2, 3, 4]".into()));
+        assert_eq!(eval("(1..4) * 2"), Ok("[2, 4, 6]".into()));
+        assert_eq!(eval("2 * 2..=2 + 3"), Ok("[4, 5]".into()));
+        assert_eq!(eval("len(3..1)"), Ok("0".into()));
+        assert_eq!(eval("sum(1..=100)"), Ok("5050".into()));
+        assert_eq!(eval("product(1..=20)"), Ok("2432902008176640000".into()));
+        assert_eq!(eval("product(-3..=-1)"), Ok("-6".into()));
+        assert_eq!(eval("1..2.5"), Err(CalculatorError::RangeBounds));
+
+        // Huge ranges can be reduced, but not created
+        assert_eq!(
+            eval("sum(1..=10**12)"),
+            Ok("500000000000500000000000".into())
+        );
+        assert_eq!(eval("len(0..10**12)"), Ok("1000000000000".into()));
+        assert!(matches!(
+            eval("sum((1..=10**12) * 2)"),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+        assert!(matches!(
+            eval("product(1..=10**12)"),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+    }
+
+    #[test]
+    fn test_random() {
+        let seeded = || {
+            let mut calculator = Calculator::default();
+            calculator.set_seed(Some(7));
+            calculator
+        };
+        let (mut a, mut b) = (seeded(), seeded());
+        let rolls = "[rand(1, 6), rand(1, 6), rand(1, 6), rand(1, 6), rand(1, 6)]";
+        assert!(eval_with(&mut a, rolls).is_ok());
+        assert_eq!(
+            eval_with(&mut a, rolls),
+            eval_with(&mut b, rolls).and(eval_with(&mut b, rolls))
+        );
+        assert_eq!(
+            eval_with(&mut a, "randbits(128)"),
+            eval_with(&mut b, "randbits(128)")
+        );
+        for _ in 0..50 {
+            let x: i32 = eval_with(&mut a, "rand(-2, 2)").unwrap().parse().unwrap();
+            assert!((-2..=2).contains(&x));
+        }
+        assert_eq!(eval_with(&mut a, "rand(3, 3)"), Ok("3".into()));
+        let x: u32 = eval_with(&mut a, "randbits(4)").unwrap().parse().unwrap();
+        assert!(x < 16);
+        assert!(eval_with(&mut a, "rand(2, 1)").is_err());
+        assert!(eval_with(&mut a, "rand(1.5, 2)").is_err());
+        assert!(matches!(
+            eval_with(&mut a, "randbits(2**30)"),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+
+        a.set_rolls(true);
+        let total: i64 = eval_with(&mut a, "3d6 + d4").unwrap().parse().unwrap();
+        let rolls = a.take_rolls();
+        assert_eq!(rolls.len(), 2);
+        assert_eq!(rolls[0].results.len(), 3);
+        assert_eq!(total, rolls[0].total() + rolls[1].total());
+        assert_eq!(eval_with(&mut a, "0d6"), Ok("0".into()));
+        assert!(eval_with(&mut a, "dice(1, 0)").is_err());
+    }
+
+    #[test]
+    fn test_duration() {
+        let duration = |s| Token::Val(Value::Duration(Rational::from(s)));
+        const DIV: Token = Token::Op(Operator::Div);
+
+        // 1h30m + 45m * 2
+        let res = calculate(vec![duration(5400), ADD, duration(2700), MUL, 2.into()]);
+        assert_eq!(res, Ok(Value::Duration(Rational::from(10800))));
+
+        // 1h / 20m
+        let res = calculate(vec![duration(3600), DIV, duration(1200)]);
+        assert_eq!(res, Ok(Value::from(3)));
+
+        // 1h * 1h
+        let res = calculate(vec![duration(3600), MUL, duration(3600)]);
+        assert_eq!(res, Err(CalculatorError::DurationOperands));
+
+        // 1h + 1
+        let res = calculate(vec![duration(3600), ADD, 1.into()]);
+        assert_eq!(res, Err(CalculatorError::DurationOperands));
+
+        // A single `m` is minutes next to a duration, in either order
+        assert_eq!(eval("45m + 1h"), Ok("1h45m".into()));
+        assert_eq!(eval("1h + 45m"), Ok("1h45m".into()));
+        assert_eq!(eval("2m + 30s"), Ok("2m30s".into()));
+        assert_eq!(eval("30s + 2m"), Ok("2m30s".into()));
+        assert_eq!(eval("45m - 27s"), Ok("44m33s".into()));
+        assert_eq!(eval("1h30m + 45m - 27s"), Ok("2h14m33s".into()));
+        assert_eq!(eval("90m > 1h"), Ok("1".into()));
+        assert_eq!(eval("100m / 10s").map(|_| ()), Ok(()));
+    }
+
+    #[test]
+    fn test_constant() {
+        // -pi
+        let res = calculate(vec![SUB, ident("pi")]).map(|v| v.to_string());
+        assert_eq!(
+            res,
+            Ok("-3.14159265358979323846264338327950288419716939937511".into())
+        );
+    }
+
+    #[test]
+    fn test_rounding() {
+        // Results keep guard digits and are only rounded for display
+        assert_eq!(eval("atan(1) * 4"), eval("pi"));
+        assert_eq!(
+            eval("pi ** pi"),
+            Ok("36.46215960720791177099082602269212366636550840222882".into())
+        );
+        assert_eq!(eval("exp(-(10 ** 10))"), Ok("0".into()));
+        assert_eq!(eval("e ** -(10 ** 10)"), Ok("0".into()));
+        assert_eq!(eval("2 ** -200 * 2 ** 200"), Ok("1".into()));
+        assert!(matches!(
+            eval("e ** (10 ** 10)"),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+    }
+
+    #[test]
+    fn test_units() {
+        const TO: Token = Token::Op(Operator::To);
+
+        // 5 km + 300 m
+        let res = calculate(vec![5.into(), ident("km"), ADD, 300.into(), ident("m")]);
+        assert_eq!(res.map(|v| v.to_string()), Ok("5.3 km".into()));
+
+        // 72 F to C
+        let res = calculate(vec![72.into(), ident("F"), TO, ident("C")]);
+        assert_eq!(
+            res.map(|v| v.to_string()),
+            Ok("22.22222222222222222222222222222222222222222222222222 C".into())
+        );
+
+        // 1h30m to min
+        let duration = Token::Val(Value::Duration(Rational::from(5400)));
+        let res = calculate(vec![duration, TO, ident("min")]);
+        assert_eq!(res.map(|v| v.to_string()), Ok("90 min".into()));
+
+        // 2d to h, the same as 2 d to h
+        let days = Token::Val(Value::Duration(Rational::from(172800)));
+        let res = calculate(vec![days, TO, ident("h")]);
+        assert_eq!(res.map(|v| v.to_string()), Ok("48 h".into()));
+        let res = calculate(vec![2.into(), ident("d"), TO, ident("h")]);
+        assert_eq!(res.map(|v| v.to_string()), Ok("48 h".into()));
+
+        // 1.5 KiB + 1 KB
+        let res = eval("1.5 KiB + 1 KB");
+        assert_eq!(res.map(|v| v.to_string()), Ok("2536".into()));
+
+        // 5 to m
+        let res = calculate(vec![5.into(), TO, ident("m")]);
+        assert_eq!(
+            res,
+            Err(CalculatorError::Unit(UnitError::Incompatible(
+                "1".into(),
+                "m".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        // (2)(3)
+        let res = calculate(vec![OP, 2.into(), CL, OP, 3.into(), CL]);
+        assert_eq!(res, Ok(Value::from(6)));
+
+        // 1 / 2pi
+        let res = calculate(vec![
+            1.into(),
+            Token::Op(Operator::Div),
+            2.into(),
+            ident("pi"),
+        ])
+        .map(|v| v.to_string());
+        assert_eq!(
+            res,
+            Ok("0.15915494309189533576888376337251436203445964574046".into())
+        );
+
+        // 2 3
+        let res = calculate(vec![2.into(), 3.into()]);
+        assert_eq!(res, Err(CalculatorError::OperationExpected));
+    }
+
+    #[test]
+    fn test_abs() {
+        const BAR: Token = Token::Bar;
+
+        // |2 - 5| * 2
+        let res = calculate(vec![BAR, 2.into(), SUB, 5.into(), BAR, MUL, 2.into()]);
+        assert_eq!(res, Ok(Value::from(6)));
+
+        // -|1 - |-3||
+        let res = calculate(vec![SUB, BAR, 1.into(), SUB, BAR, SUB, 3.into(), BAR, BAR]);
+        assert_eq!(res, Ok(Value::from(-2)));
+
+        // (|1)|
+        let res = calculate(vec![OP, BAR, 1.into(), CL, BAR]);
+        assert_eq!(res, Err(CalculatorError::UnmatchedBar));
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut calculator = Calculator::default();
+        calculator.set_trace(true);
+        // 2 + 3 * max(4, 1)
+        for t in [
+            2.into(),
+            ADD,
+            3.into(),
+            MUL,
+            ident("max"),
+            OP,
+            4.into(),
+            COMMA,
+            1.into(),
+            CL,
+        ] {
+            calculator.handle_token(t).unwrap();
+        }
+        assert_eq!(calculator.finalize(), Ok(Value::from(14)));
+
+        let val = |v: i64| Expr::Val(v.into());
+        let max = Expr::Call {
+            name: "max".into(),
+            args: vec![val(4), val(1)],
+        };
+        let steps = vec![
+            (max, Value::from(4)),
+            (Expr::binary(Operator::Mul, val(3), val(4)), Value::from(12)),
+            (
+                Expr::binary(Operator::Add, val(2), val(12)),
+                Value::from(14),
+            ),
+        ];
+        assert_eq!(calculator.take_trace(), steps);
+        assert!(calculator.take_trace().is_empty());
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut calculator = Calculator::default();
+        calculator.set_variable("x", Value::from(3));
+        calculator.set_variable("pi", Value::from(4));
+        // x * pi
+        for t in [ident("x"), MUL, ident("pi")] {
+            calculator.handle_token(t).unwrap();
+        }
+        assert_eq!(calculator.finalize(), Ok(Value::from(12)));
+    }
+
+    #[test]
+    fn test_user_functions() {
+        let mut calculator = Calculator::default();
+        // sq(x) = x * x
+        let body = Expr::binary(
+            Operator::Mul,
+            Expr::Ident("x".into()),
+            Expr::Ident("x".into()),
+        );
+        let params = vec!["x".into()];
+        calculator.define_function("sq", Function { params, body });
+        // sq(3) + sq(-2)
+        for t in [
+            ident("sq"),
+            OP,
+            3.into(),
+            CL,
+            ADD,
+            ident("sq"),
+            OP,
+            SUB,
+            2.into(),
+            CL,
+        ] {
+            calculator.handle_token(t).unwrap();
+        }
+        assert_eq!(calculator.finalize(), Ok(Value::from(13)));
+
+        for t in [ident("sq"), OP, 1.into(), COMMA, 2.into(), CL] {
+            calculator.handle_token(t).unwrap();
+        }
+        let err = FunctionError::ArgumentCount {
+            name: "sq".into(),
+            expected: 1,
+            got: 2,
+        };
+        assert_eq!(calculator.finalize(), Err(err.into()));
+    }
+
+    #[test]
+    fn test_environment() {
+        std::env::set_var("SC_TEST_COLUMNS", " 80\n");
+        std::env::set_var("SC_TEST_NAME", "sc");
+        // $SC_TEST_COLUMNS / 2
+        let res = calculate(vec![
+            ident("$SC_TEST_COLUMNS"),
+            Token::Op(Operator::Div),
+            2.into(),
+        ]);
+        assert_eq!(res, Ok(Value::from(40)));
+        let res = calculate(vec![ident("env"), OP, ident("SC_TEST_COLUMNS"), CL]);
+        assert_eq!(res, Ok(Value::from(80)));
+        let res = calculate(vec![ident("$SC_TEST_NAME")]);
+        assert_eq!(
+            res,
+            Err(CalculatorError::InvalidEnvironment("SC_TEST_NAME".into()))
+        );
+        let res = calculate(vec![ident("$SC_TEST_UNSET")]);
+        assert_eq!(
+            res,
+            Err(CalculatorError::UndefinedEnvironment(
+                "SC_TEST_UNSET".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let mut calculator = Calculator::default();
+        assert_eq!(calculator.push_history(Value::from(5)), 1);
+        assert_eq!(calculator.push_history(Value::from(7)), 2);
+        // $1 * out(2)
+        let tokens = [ident("$1"), MUL, ident("out"), OP, 2.into(), CL];
+        for t in tokens {
+            calculator.handle_token(t).unwrap();
+        }
+        assert_eq!(calculator.finalize(), Ok(Value::from(35)));
+        calculator.handle_token(ident("$3")).unwrap();
+        assert_eq!(calculator.finalize(), Err(CalculatorError::NoResult(3)));
+        calculator.handle_token(ident("$0")).unwrap();
+        assert_eq!(calculator.finalize(), Err(CalculatorError::NoResult(0)));
+    }
+
+    #[test]
+    fn test_interruptible() {
+        let mut calculator = Calculator::default();
+        calculator.set_trace(true);
+        // 2 ** 3 * 2
+        let pow = Expr::binary(Operator::Pow, Expr::Val(2.into()), Expr::Val(3.into()));
+        let expr = Expr::binary(Operator::Mul, pow, Expr::Val(2.into()));
+        let res = calculator.evaluate_interruptible(&expr, || false);
+        assert_eq!(res, Ok(Value::from(16)));
+        assert_eq!(calculator.take_trace().len(), 2);
+
+        // 3 ** 10 ** 7
+        let exponent = Expr::binary(Operator::Pow, Expr::Val(10.into()), Expr::Val(7.into()));
+        let expr = Expr::binary(Operator::Pow, Expr::Val(3.into()), exponent);
+        let res = calculator.evaluate_interruptible(&expr, || true);
+        assert_eq!(res, Err(CalculatorError::Interrupted));
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut calculator = Calculator::default();
+        calculator.set_max_bits(Some(64));
+        let pow =
+            |exp: i64| Expr::binary(Operator::Pow, Expr::Val(2.into()), Expr::Val(exp.into()));
+        assert!(calculator.evaluate(&pow(63)).is_ok());
+        assert!(matches!(
+            calculator.evaluate(&pow(64)),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+        let huge = Expr::binary(Operator::Pow, Expr::Val(10.into()), pow(62));
+        assert!(matches!(
+            calculator.evaluate(&huge),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+
+        // Numbers that would exhaust the memory are limited even without a maximum
+        let calculator = Calculator::default();
+        let big = Expr::binary(
+            Operator::Pow,
+            Expr::Val(Rational::from_signeds(3, 2).into()),
+            Expr::Val(100_000_000.into()),
+        );
+        assert!(matches!(
+            calculator.evaluate(&big),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+        // Products larger than the big number arithmetic can compute, also with a larger maximum
+        let mut calculator = Calculator::default();
+        calculator.set_max_bits(Some(u64::MAX));
+        for text in [
+            "2**(2**26-1)",
+            "(2**(2**25))*(2**(2**25))",
+            "(2**(2**24-1))*(2**(2**24-1))",
+        ] {
+            assert!(matches!(
+                eval_with(&mut calculator, text),
+                Err(CalculatorError::ResourceLimit(_))
+            ));
+        }
+
+        let mut calculator = Calculator::default();
+        calculator.set_timeout(Some(Duration::from_millis(10)));
+        let huge = Expr::binary(
+            Operator::Pow,
+            Expr::Val(10.into()),
+            Expr::Val(100_000_000.into()),
+        );
+        assert!(matches!(
+            calculator.evaluate_interruptible(&huge, || false),
+            Err(CalculatorError::ResourceLimit(_))
+        ));
+    }
+
+    #[test]
+    fn test_depth() {
+        let mut calculator = Calculator::default();
+        calculator.set_max_depth(10);
+        // 1 + (1 + (1 + ...))
+        let mut expr = Expr::Val(1.into());
+        for _ in 0..9 {
+            expr = Expr::binary(Operator::Add, Expr::Val(1.into()), expr);
+        }
+        assert_eq!(calculator.evaluate(&expr), Ok(10.into()));
+        let expr = Expr::binary(Operator::Add, Expr::Val(1.into()), expr);
+        assert_eq!(calculator.evaluate(&expr), Err(CalculatorError::TooDeep));
+
+        // Left-associative chains are folded without nesting
+        let mut chain = Expr::Val(1.into());
+        for _ in 0..9_999 {
+            chain = Expr::binary(Operator::Add, chain, Expr::Val(1.into()));
+        }
+        assert_eq!(calculator.evaluate(&chain), Ok(10_000.into()));
+
+        // f(x) = f(x) never ends without the limit
+        let args = vec![Expr::Ident("x".into())];
+        let body = Expr::Call {
+            name: "f".into(),
+            args,
+        };
+        let params = vec!["x".into()];
+        calculator.define_function("f", Function { params, body });
+        let call = Expr::Call {
+            name: "f".into(),
+            args: vec![Expr::Val(1.into())],
+        };
+        assert_eq!(calculator.evaluate(&call), Err(CalculatorError::TooDeep));
+        assert_eq!(calculator.evaluate(&Expr::Val(1.into())), Ok(1.into()));
+    }
+
+    #[test]
+    fn test_long_chain() {
+        // 1 + 1 + ... + 1 isn't limited by the depth
+        let mut tokens = vec![1.into()];
+        for _ in 0..9_999 {
+            tokens.extend([Token::Op(Operator::Add), 1.into()]);
+        }
+        assert_eq!(calculate(tokens), Ok(10_000.into()));
+
+        let mut tokens = vec![2.into()];
+        for _ in 0..5_000 {
+            tokens.extend([
+                Token::Op(Operator::Mul),
+                3.into(),
+                Token::Op(Operator::Div),
+                3.into(),
+            ]);
+        }
+        assert_eq!(calculate(tokens), Ok(2.into()));
     }
 }
@@ -0,0 +1,38 @@
+use arboard::Clipboard;
+use compact_str::{CompactString, ToCompactString};
+use thiserror::Error;
+
+/// Place the text on the system clipboard
+///
+/// On X11 and Wayland the text is handed to the clipboard manager when the clipboard is closed,
+/// so that it stays available after the program exits.
+pub fn copy(text: &str) -> Result<(), ClipboardError> {
+    let mut clipboard = Clipboard::new()?;
+    clipboard.set_text(text)?;
+    Ok(())
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ClipboardError {
+    #[error("Failed to copy to the clipboard: {0}")]
+    Failed(CompactString),
+}
+
+impl From<arboard::Error> for ClipboardError {
+    fn from(e: arboard::Error) -> Self {
+        ClipboardError::Failed(e.to_compact_string())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy() {
+        // Without a display server the error is reported instead of discarding the text
+        std::env::remove_var("DISPLAY");
+        std::env::remove_var("WAYLAND_DISPLAY");
+        assert!(matches!(copy("1"), Err(ClipboardError::Failed(_))));
+    }
+}
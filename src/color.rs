@@ -0,0 +1,125 @@
+use compact_str::CompactString;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// When to use ANSI colors
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only if the output is a terminal and `NO_COLOR` isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub const NAMES: [&'static str; 3] = ["auto", "always", "never"];
+
+    /// Whether to color output written to a terminal or not
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal && std::env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = ColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(ColorError(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorChoice::Auto => write!(f, "auto"),
+            ColorChoice::Always => write!(f, "always"),
+            ColorChoice::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// ANSI escape sequence of a text style
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style(&'static str);
+
+impl Style {
+    pub const RESULT: Style = Style("\x1b[36m");
+    pub const ERROR: Style = Style("\x1b[31m");
+    /// Offending part of an expression
+    pub const SPAN: Style = Style("\x1b[4;31m");
+    /// Bracket matching the one at the cursor
+    pub const BRACKET: Style = Style("\x1b[1;4m");
+}
+
+/// Text wrapped in the escape sequences of the style
+pub fn paint(text: impl fmt::Display, style: Style) -> String {
+    format!("{}{}\x1b[0m", style.0, text)
+}
+
+/// Line with the characters `start..end` highlighted, a space is highlighted past the end
+pub fn highlight(line: &str, start: usize, end: usize) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let end = end.min(chars.len()).max(start + 1);
+    let before: String = chars.iter().take(start).collect();
+    let span: String = chars.iter().take(end).skip(start).collect();
+    let after: String = chars.iter().skip(end).collect();
+    let span = if span.is_empty() { " ".into() } else { span };
+    format!("{}{}{}", before, paint(span, Style::SPAN), after)
+}
+
+/// Character range of the word at the column, or the word before it if the column is whitespace
+pub fn word_at(line: &str, column: usize) -> (usize, usize) {
+    let chars: Vec<char> = line.chars().collect();
+    let mut end = column.min(chars.len());
+    if chars.get(end).is_some_and(|c| !c.is_whitespace()) {
+        while chars.get(end).is_some_and(|c| !c.is_whitespace()) {
+            end += 1;
+        }
+    } else {
+        while end > 0 && chars[end - 1].is_whitespace() {
+            end -= 1;
+        }
+    }
+    let mut start = end;
+    while start > 0 && !chars[start - 1].is_whitespace() {
+        start -= 1;
+    }
+    if start == end {
+        (column, column + 1)
+    } else {
+        (start, end)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Unknown color choice: {0}, expected one of: {}", ColorChoice::NAMES.join(", "))]
+pub struct ColorError(CompactString);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(paint(42, Style::RESULT), "\x1b[36m42\x1b[0m");
+        assert_eq!(highlight("1 + $", 4, 5), "1 + \x1b[4;31m$\x1b[0m");
+        assert_eq!(highlight("1 +", 3, 3), "1 +\x1b[4;31m \x1b[0m");
+        assert_eq!(word_at("2 + & 3", 5), (4, 5));
+        assert_eq!(word_at("2 + &x 3", 5), (4, 6));
+        assert_eq!(word_at("  ", 1), (1, 2));
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+        assert_eq!("never".parse(), Ok(ColorChoice::Never));
+    }
+}
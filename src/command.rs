@@ -0,0 +1,489 @@
+use crate::calculator::{Calculator, WIDTHS};
+use crate::compiled::CompiledExpr;
+use crate::format::{FormatError, Fractions, Notation, Output, Rounding};
+use crate::printer;
+use crate::session::Session;
+use crate::value::Value;
+use compact_str::CompactString;
+use malachite::Natural;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Interactive session command, entered as a line starting with `:`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Show or set the number of decimal places
+    Precision(Option<u64>),
+    /// Show or set the rounding mode of displayed results
+    Rounding(Option<Rounding>),
+    /// Show or set how non-integer results are displayed
+    Fractions(Option<Fractions>),
+    /// Show or set the notation of decimal results
+    Notation(Option<Notation>),
+    /// Show, set or disable (`off`) rounding to significant figures
+    Sigfig(Option<Option<u64>>),
+    /// Show, set or disable (`off`) the fixed integer width
+    Width(Option<Option<u64>>),
+    /// Show, set or disable (`off`) the modulus of integer arithmetic
+    Mod(Option<Option<Natural>>),
+    /// Show or set how integer results are displayed
+    As(Option<Output>),
+    /// List the previous results with their numbers
+    History,
+    /// Write variables, functions and previous results to a file
+    Save(PathBuf),
+    /// Evaluate the definitions of a file, e.g. one written by `:save`
+    Load(PathBuf),
+    /// Write the inputs of the session with their results to a file as a Markdown table
+    Export(PathBuf),
+    /// Place the last result on the system clipboard
+    Copy,
+    /// End the session
+    Quit,
+}
+
+impl Command {
+    pub const NAMES: [&'static str; 15] = [
+        "precision",
+        "rounding",
+        "fractions",
+        "notation",
+        "sigfig",
+        "width",
+        "mod",
+        "as",
+        "history",
+        "save",
+        "load",
+        "export",
+        "copy",
+        "quit",
+        "exit",
+    ];
+
+    /// Parse a command line with the leading `:` stripped
+    pub fn parse(line: &str) -> Result<Self, CommandError> {
+        // File names take the rest of the line, they may contain spaces
+        let (name, rest) = line
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((line, ""));
+        let path = || match rest.trim() {
+            "" => Err(CommandError::MissingArgument(name.into())),
+            path => Ok(PathBuf::from(path)),
+        };
+        match name.trim() {
+            "save" => return Ok(Command::Save(path()?)),
+            "load" => return Ok(Command::Load(path()?)),
+            "export" => return Ok(Command::Export(path()?)),
+            // The modulus is an expression, e.g. `2**61 - 1`
+            "mod" => {
+                let modulus = match rest.trim() {
+                    "" => None,
+                    "off" => Some(None),
+                    arg => Some(Some(
+                        parse_modulus(arg)
+                            .ok_or_else(|| CommandError::InvalidArgument(arg.into()))?,
+                    )),
+                };
+                return Ok(Command::Mod(modulus));
+            }
+            _ => {}
+        }
+
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let arg = words.next();
+        if words.next().is_some() {
+            return Err(CommandError::TooManyArguments(name.into()));
+        }
+
+        match name {
+            "precision" => {
+                let precision = arg
+                    .map(|arg| {
+                        arg.parse()
+                            .map_err(|_| CommandError::InvalidArgument(arg.into()))
+                    })
+                    .transpose()?;
+                Ok(Command::Precision(precision))
+            }
+            "rounding" => Ok(Command::Rounding(arg.map(str::parse).transpose()?)),
+            "fractions" => Ok(Command::Fractions(arg.map(str::parse).transpose()?)),
+            "notation" => Ok(Command::Notation(arg.map(str::parse).transpose()?)),
+            "as" => Ok(Command::As(arg.map(str::parse).transpose()?)),
+            "width" => {
+                let width = arg
+                    .map(|arg| match arg {
+                        "off" => Ok(None),
+                        _ => parse_width(arg)
+                            .map(Some)
+                            .ok_or_else(|| CommandError::InvalidArgument(arg.into())),
+                    })
+                    .transpose()?;
+                Ok(Command::Width(width))
+            }
+            "sigfig" => {
+                let digits = arg
+                    .map(|arg| match arg {
+                        "off" => Ok(None),
+                        _ => arg
+                            .parse()
+                            .ok()
+                            .filter(|digits| *digits > 0)
+                            .map(Some)
+                            .ok_or_else(|| CommandError::InvalidArgument(arg.into())),
+                    })
+                    .transpose()?;
+                Ok(Command::Sigfig(digits))
+            }
+            "history" if arg.is_none() => Ok(Command::History),
+            "copy" if arg.is_none() => Ok(Command::Copy),
+            "quit" | "exit" if arg.is_none() => Ok(Command::Quit),
+            "quit" | "exit" => Err(CommandError::TooManyArguments(name.into())),
+            "history" | "copy" => Err(CommandError::TooManyArguments(name.into())),
+            _ => Err(CommandError::UnknownCommand(name.into())),
+        }
+    }
+
+    /// Apply the command, returns a message for the user if there is one
+    ///
+    /// `Load`, `Export`, `Copy` and `Quit` affect the whole session, so they're executed by the
+    /// caller and do nothing here.
+    pub fn execute(self, session: &mut Session) -> Result<Option<String>, CommandError> {
+        let message = match self {
+            Command::Precision(None) => Some(session.format().precision.to_string()),
+            Command::Precision(Some(precision)) => {
+                session.calculator_mut().set_precision(precision);
+                session.format_mut().precision = precision;
+                None
+            }
+            Command::Rounding(None) => Some(session.format().rounding.to_string()),
+            Command::Rounding(Some(rounding)) => {
+                session.format_mut().rounding = rounding;
+                None
+            }
+            Command::Fractions(None) => Some(session.format().fractions.to_string()),
+            Command::Fractions(Some(fractions)) => {
+                session.format_mut().fractions = fractions;
+                None
+            }
+            Command::Notation(None) => Some(session.format().notation.to_string()),
+            Command::Notation(Some(notation)) => {
+                session.format_mut().notation = notation;
+                None
+            }
+            Command::Sigfig(None) => Some(match session.format().significant {
+                Some(digits) => digits.to_string(),
+                None => "off".into(),
+            }),
+            Command::Sigfig(Some(digits)) => {
+                session.format_mut().significant = digits;
+                None
+            }
+            Command::Width(None) => Some(match session.calculator().width() {
+                Some(width) => width.to_string(),
+                None => "off".into(),
+            }),
+            Command::Width(Some(width)) => {
+                session.calculator_mut().set_width(width);
+                None
+            }
+            Command::Mod(None) => Some(match session.calculator().modulus() {
+                Some(modulus) => modulus.to_string(),
+                None => "off".into(),
+            }),
+            Command::Mod(Some(modulus)) => {
+                session.calculator_mut().set_modulus(modulus);
+                None
+            }
+            Command::As(None) => Some(session.format().output.to_string()),
+            Command::As(Some(output)) => {
+                session.format_mut().output = output;
+                None
+            }
+            Command::History => {
+                let lines: Vec<String> = (session.calculator().history().iter().enumerate())
+                    .map(|(i, value)| format!("${} = {}", i + 1, session.format().display(value)))
+                    .collect();
+                Some(lines.join("\n")).filter(|lines| !lines.is_empty())
+            }
+            Command::Save(path) => {
+                std::fs::write(&path, save(session.calculator())?)
+                    .map_err(|e| CommandError::Io(e.to_string().into()))?;
+                None
+            }
+            Command::Load(_) | Command::Export(_) | Command::Copy | Command::Quit => None,
+        };
+        Ok(message)
+    }
+}
+
+/// Markdown table of inputs and their results, one row per input. Function definitions have an
+/// empty result
+pub fn export(entries: &[(String, String)]) -> String {
+    let cell = |text: &str| match text.is_empty() {
+        true => String::new(),
+        false => format!("`{}`", text.replace('|', "\\|")),
+    };
+    let mut text = String::from("| Expression | Result |\n| --- | --- |\n");
+    for (input, result) in entries {
+        text.push_str(&format!("| {} | {} |\n", cell(input), cell(result)));
+    }
+    text
+}
+
+/// Definitions of the variables and functions of the calculator followed by the previous results,
+/// one per line
+pub fn save(calculator: &Calculator) -> Result<String, CommandError> {
+    let mut text = String::from("# sc session\n");
+    let mut variables: Vec<_> = calculator.variables().collect();
+    variables.sort_by_key(|(name, _)| *name);
+    for (name, value) in variables {
+        text.push_str(&format!("{} = {}\n", name, saved_literal(value)?));
+    }
+    let mut functions: Vec<_> = calculator.functions().collect();
+    functions.sort_by_key(|(name, _)| *name);
+    for (name, function) in functions {
+        let params = function.params.join(", ");
+        let body = function.body.to_string();
+        text.push_str(&format!("{}({}) = {}\n", name, params, body));
+    }
+    // Results are added to the history again when the file is loaded
+    for value in calculator.history() {
+        text.push_str(&saved_literal(value)?);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Literal of a saved value, text such as the result of `roman(5)` can't be read back
+fn saved_literal(value: &Value) -> Result<String, CommandError> {
+    match value {
+        Value::Str(text) => return Err(CommandError::Text(text.clone())),
+        Value::List(list) => {
+            for element in list {
+                saved_literal(element)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(printer::literal(value))
+}
+
+/// Parse one of the supported fixed integer widths
+pub fn parse_width(s: &str) -> Option<u64> {
+    s.parse().ok().filter(|width| WIDTHS.contains(width))
+}
+
+/// Parse a positive integer modulus, which may be written as an expression
+pub fn parse_modulus(s: &str) -> Option<Natural> {
+    let value = s.parse::<CompiledExpr>().ok()?.eval().ok()?;
+    match value {
+        Value::Int(i) if i > 0 => Natural::try_from(i).ok(),
+        _ => None,
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    #[error("Unknown command: {0}")]
+    UnknownCommand(CompactString),
+    #[error("Invalid command argument: {0}")]
+    InvalidArgument(CompactString),
+    #[error("Missing argument for command {0}")]
+    MissingArgument(CompactString),
+    #[error("{0}")]
+    Io(CompactString),
+    #[error("Too many arguments for command {0}")]
+    TooManyArguments(CompactString),
+    #[error("Text can't be saved: {0}")]
+    Text(CompactString),
+    #[error(transparent)]
+    Format(#[from] FormatError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::parse("precision"), Ok(Command::Precision(None)));
+        assert_eq!(
+            Command::parse(" precision  10 "),
+            Ok(Command::Precision(Some(10)))
+        );
+        assert_eq!(
+            Command::parse("rounding half-up"),
+            Ok(Command::Rounding(Some(Rounding::HalfUp)))
+        );
+        assert_eq!(
+            Command::parse("fractions mixed"),
+            Ok(Command::Fractions(Some(Fractions::Mixed)))
+        );
+        assert_eq!(
+            Command::parse("fractions repeating"),
+            Ok(Command::Fractions(Some(Fractions::Repeating)))
+        );
+        assert_eq!(
+            Command::parse("notation eng"),
+            Ok(Command::Notation(Some(Notation::Engineering)))
+        );
+        assert_eq!(
+            Command::parse("precision ten"),
+            Err(CommandError::InvalidArgument("ten".into()))
+        );
+        assert_eq!(
+            Command::parse("rounding sideways"),
+            Err(FormatError::Rounding("sideways".into()).into())
+        );
+        assert_eq!(
+            Command::parse("width 32"),
+            Ok(Command::Width(Some(Some(32))))
+        );
+        assert_eq!(Command::parse("width off"), Ok(Command::Width(Some(None))));
+        assert_eq!(
+            Command::parse("sigfig 3"),
+            Ok(Command::Sigfig(Some(Some(3))))
+        );
+        assert_eq!(
+            Command::parse("sigfig 0"),
+            Err(CommandError::InvalidArgument("0".into()))
+        );
+        assert_eq!(
+            Command::parse("mod 2**61 - 1"),
+            Ok(Command::Mod(Some(Some(Natural::from((1u64 << 61) - 1)))))
+        );
+        assert_eq!(Command::parse("mod off"), Ok(Command::Mod(Some(None))));
+        assert_eq!(
+            Command::parse("mod -7"),
+            Err(CommandError::InvalidArgument("-7".into()))
+        );
+        assert_eq!(
+            Command::parse("width 12"),
+            Err(CommandError::InvalidArgument("12".into()))
+        );
+        assert_eq!(
+            Command::parse("save my session.sc"),
+            Ok(Command::Save("my session.sc".into()))
+        );
+        assert_eq!(
+            Command::parse("export notes.md"),
+            Ok(Command::Export("notes.md".into()))
+        );
+        assert_eq!(Command::parse("copy"), Ok(Command::Copy));
+        assert_eq!(
+            Command::parse("copy 2"),
+            Err(CommandError::TooManyArguments("copy".into()))
+        );
+        assert_eq!(
+            Command::parse("load"),
+            Err(CommandError::MissingArgument("load".into()))
+        );
+        assert_eq!(
+            Command::parse("foo"),
+            Err(CommandError::UnknownCommand("foo".into()))
+        );
+    }
+
+    #[test]
+    fn test_export() {
+        let entries = [
+            ("2 + 3*4".to_owned(), "14".to_owned()),
+            ("|-2|".to_owned(), "2".to_owned()),
+            ("f(x) = x*2".to_owned(), String::new()),
+        ];
+        assert_eq!(
+            export(&entries),
+            "| Expression | Result |\n\
+             | --- | --- |\n\
+             | `2 + 3*4` | `14` |\n\
+             | `\\|-2\\|` | `2` |\n\
+             | `f(x) = x*2` |  |\n"
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        for name in Command::NAMES {
+            let res = Command::parse(&format!("{} x", name));
+            assert_ne!(res, Err(CommandError::UnknownCommand(name.into())));
+        }
+    }
+
+    #[test]
+    fn test_save() {
+        use crate::ast::Expr;
+        use crate::calculator::Function;
+        use crate::tokenizer::Operator;
+        use malachite::Rational;
+
+        let mut calculator = Calculator::default();
+        calculator.set_variable("b", Value::Real(Rational::from_signeds(-1, 3)));
+        calculator.set_variable("a", Value::Real(Rational::from_signeds(5, 4)));
+        let body = Expr::binary(
+            Operator::Add,
+            Expr::Ident("x".into()),
+            Expr::Ident("y".into()),
+        );
+        let params = vec!["x".into(), "y".into()];
+        calculator.define_function("f", Function { params, body });
+        calculator.push_history(Value::from(42));
+        let expected = "# sc session\na = 1.25\nb = -1 / 3\nf(x, y) = x + y\n42\n";
+        assert_eq!(save(&calculator), Ok(expected.into()));
+        calculator.push_history(Value::Str("V".into()));
+        assert_eq!(save(&calculator), Err(CommandError::Text("V".into())));
+    }
+
+    #[test]
+    fn test_save_load() {
+        use crate::format::Format;
+
+        // Results are displayed rounded and as fractions, but saved exactly
+        let format = Format {
+            precision: 3,
+            fractions: Fractions::Fraction,
+            ..Format::default()
+        };
+        let mut calculator = Calculator::default();
+        calculator.set_precision(3);
+        let mut session = Session::new(calculator, format.clone());
+        for line in [
+            "x = sqrt(2)",
+            "h = 1/2.0",
+            "t = 1.0/3",
+            "q = 5 km / 7",
+            "d = 1s / 3",
+            "c = 1/3.0 - 2i/3",
+            "l = [1/3.0, 2]",
+            "frac(0.5)",
+            "2.0/3",
+        ] {
+            session.eval(line).unwrap();
+        }
+        let saved = save(session.calculator()).unwrap();
+
+        let mut loaded = Session::new(Calculator::default(), format);
+        for line in saved.lines().filter(|line| !line.starts_with('#')) {
+            loaded.eval(line).unwrap();
+        }
+        let variables = |session: &Session| {
+            let mut variables: Vec<_> = session.calculator().variables().collect();
+            variables.sort_by_key(|(name, _)| *name);
+            format!("{:?}", variables)
+        };
+        assert_eq!(variables(&loaded), variables(&session));
+        assert_eq!(
+            loaded.calculator().history(),
+            session.calculator().history()
+        );
+
+        // Text would be read back as something else, e.g. `V` as volts
+        session.eval("roman(5)").unwrap();
+        assert_eq!(
+            save(session.calculator()),
+            Err(CommandError::Text("V".into()))
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use crate::ast::Expr;
+use crate::calculator::{Calculator, CalculatorError};
+use crate::tokenizer::{TokenizeError, Tokenizer};
+use crate::value::Value;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Expression parsed once and evaluated many times, e.g. for every line of input
+///
+/// Variables are bound with [`CompiledExpr::eval_with`] without tokenizing the expression again.
+#[derive(Debug)]
+pub struct CompiledExpr {
+    expr: Expr,
+    calculator: Calculator,
+}
+
+impl CompiledExpr {
+    /// Parse the expression, it's evaluated with the settings of `calculator`
+    pub fn new(text: &str, mut calculator: Calculator) -> Result<Self, CompileError> {
+        for token in Tokenizer::tokens(text.chars()) {
+            calculator.handle_token(token?)?;
+        }
+        let expr = calculator.parse()?;
+        Ok(Self::from_expr(expr, calculator))
+    }
+
+    pub fn from_expr(expr: Expr, calculator: Calculator) -> Self {
+        Self { expr, calculator }
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    /// Evaluate the expression, on a separate thread if the calculator has a timeout
+    pub fn eval(&self) -> Result<Value, CalculatorError> {
+        match self.calculator.timeout() {
+            Some(_) => self.calculator.evaluate_interruptible(&self.expr, || false),
+            None => self.calculator.evaluate(&self.expr),
+        }
+    }
+
+    /// Evaluate with the variables set, variables keep their values for later evaluations
+    pub fn eval_with(&mut self, variables: &[(&str, Value)]) -> Result<Value, CalculatorError> {
+        for (name, value) in variables {
+            self.calculator.set_variable(*name, value.clone());
+        }
+        self.eval()
+    }
+}
+
+impl FromStr for CompiledExpr {
+    type Err = CompileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s, Calculator::default())
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CompileError {
+    #[error(transparent)]
+    Tokenize(#[from] TokenizeError),
+    #[error(transparent)]
+    Calculator(#[from] CalculatorError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_with() {
+        let mut expr: CompiledExpr = "x ** 2 + y".parse().unwrap();
+        assert_eq!(
+            expr.eval(),
+            Err(CalculatorError::UnknownIdentifier("x".into()))
+        );
+        let res = expr.eval_with(&[("x", 3.into()), ("y", 1.into())]);
+        assert_eq!(res, Ok(10.into()));
+        assert_eq!(expr.eval_with(&[("x", 4.into())]), Ok(17.into()));
+
+        let mut calculator = Calculator::default();
+        calculator.set_width(Some(8));
+        let mut expr = CompiledExpr::new("x + 1", calculator).unwrap();
+        assert_eq!(expr.eval_with(&[("x", 255.into())]), Ok(0.into()));
+
+        assert!(matches!(
+            "1 +".parse::<CompiledExpr>(),
+            Err(CompileError::Calculator(_))
+        ));
+    }
+}
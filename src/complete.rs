@@ -0,0 +1,128 @@
+use crate::calculator::{Calculator, HIGHER_ORDER, SYMBOLIC};
+use crate::command::Command;
+use crate::functions;
+use crate::random;
+use crate::units::Unit;
+
+/// Completions of the word before the cursor, returns the byte position where the word starts
+///
+/// Commands are completed after a leading `:`, otherwise built-in and user defined functions,
+/// constants and variables. Function names are completed with the opening parenthesis.
+pub fn complete(line: &str, cursor: usize, calculator: &Calculator) -> (usize, Vec<String>) {
+    complete_names(line, cursor, &user_names(calculator))
+}
+
+/// Variables and functions defined in the calculator, functions with the opening parenthesis
+pub fn user_names(calculator: &Calculator) -> Vec<String> {
+    let variables = calculator.variables().map(|(name, _)| name.to_string());
+    let functions = calculator.functions().map(|(name, _)| format!("{}(", name));
+    variables.chain(functions).collect()
+}
+
+/// Completions of the word before the cursor with the names defined by the user, see [`complete`]
+pub fn complete_names(line: &str, cursor: usize, user_names: &[String]) -> (usize, Vec<String>) {
+    let Some(before) = line.get(..cursor) else {
+        return (cursor, vec![]);
+    };
+    let start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .map_or(0, |i| {
+            i + before[i..].chars().next().map_or(1, char::len_utf8)
+        });
+    let prefix = &before[start..];
+
+    let mut candidates: Vec<String> = if before[..start].trim() == ":" {
+        Command::NAMES.iter().map(|name| name.to_string()).collect()
+    } else if prefix.is_empty() || line.trim_start().starts_with(':') {
+        vec![]
+    } else {
+        let constants = functions::CONSTANTS.iter().map(|name| name.to_string());
+        let builtins = functions::FUNCTIONS
+            .iter()
+            .chain(&HIGHER_ORDER)
+            .chain(&SYMBOLIC)
+            .chain(&random::FUNCTIONS)
+            .map(|name| format!("{}(", name));
+        user_names
+            .iter()
+            .cloned()
+            .chain(constants)
+            .chain(builtins)
+            .collect()
+    };
+    candidates.retain(|c| c.starts_with(prefix));
+    candidates.sort();
+    candidates.dedup();
+    // Units are only suggested for an exact match, there are too many of them
+    if candidates.is_empty() && Unit::lookup(prefix).is_some() {
+        candidates.push(prefix.to_owned());
+    }
+    (start, candidates)
+}
+
+/// Byte position of the bracket matching the one at `position`, if there is one
+pub fn matching_bracket(line: &str, position: usize) -> Option<usize> {
+    // Brackets in the direction of the search, the one at `position` is the opening one
+    let (open, close, forward) = match line.get(position..)?.chars().next()? {
+        '(' => ('(', ')', true),
+        '[' => ('[', ']', true),
+        '{' => ('{', '}', true),
+        ')' => (')', '(', false),
+        ']' => (']', '[', false),
+        '}' => ('}', '{', false),
+        _ => return None,
+    };
+    let chars: Box<dyn Iterator<Item = (usize, char)>> = if forward {
+        Box::new(line.char_indices().skip_while(|(i, _)| *i < position))
+    } else {
+        Box::new(line[..position + 1].char_indices().rev())
+    };
+    let mut depth = 0;
+    for (i, c) in chars {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    #[test]
+    fn test_complete() {
+        let mut calculator = Calculator::default();
+        calculator.set_variable("total", Value::from(1));
+        let complete_at = |line: &str, cursor| complete(line, cursor, &calculator);
+        let complete = |line: &str| complete_at(line, line.len());
+        assert_eq!(
+            complete("2 * ta"),
+            (4, vec!["tan(".into(), "tanh(".into(), "tau".into()])
+        );
+        assert_eq!(complete("1+tot"), (2, vec!["total".into()]));
+        assert_eq!(complete(":pre"), (1, vec!["precision".into()]));
+        assert_eq!(complete(":precision pre"), (11, vec![]));
+        assert_eq!(complete("1 + "), (4, vec![]));
+        assert_eq!(complete("5 km"), (2, vec!["km".into()]));
+        assert_eq!(complete_at("πr", 1), (1, vec![]));
+    }
+
+    #[test]
+    fn test_matching_bracket() {
+        let line = "max(1, (2 + 3)) * [4]";
+        assert_eq!(matching_bracket(line, 3), Some(14));
+        assert_eq!(matching_bracket(line, 14), Some(3));
+        assert_eq!(matching_bracket(line, 13), Some(7));
+        assert_eq!(matching_bracket(line, 20), Some(18));
+        assert_eq!(matching_bracket("(1", 0), None);
+        assert_eq!(matching_bracket(line, 0), None);
+        assert_eq!(matching_bracket("π(", 1), None);
+    }
+}
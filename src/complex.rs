@@ -0,0 +1,205 @@
+use crate::real;
+use crate::tokenizer::Arithmetic;
+use crate::value::Value;
+use malachite::num::basic::traits::{One, Zero};
+use malachite::{Integer, Rational};
+use thiserror::Error;
+
+/// Complex number with exact real and imaginary parts, e.g. `2+3i`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Complex {
+    pub re: Rational,
+    pub im: Rational,
+}
+
+impl Complex {
+    pub fn new(re: Rational, im: Rational) -> Self {
+        Self { re, im }
+    }
+
+    /// Imaginary unit, the value of the `i` constant
+    pub fn i() -> Self {
+        Self::new(Rational::ZERO, Rational::ONE)
+    }
+
+    pub fn conj(&self) -> Self {
+        Self::new(self.re.clone(), -&self.im)
+    }
+
+    /// Square of the absolute value, exact unlike [`Complex::abs`]
+    pub fn norm(&self) -> Rational {
+        &self.re * &self.re + &self.im * &self.im
+    }
+
+    /// Absolute value rounded to `precision` decimal places
+    pub fn abs(&self, precision: u64) -> Rational {
+        real::sqrt(&self.norm(), precision).expect("the norm is not negative")
+    }
+
+    fn add(&self, r: &Self) -> Self {
+        Self::new(&self.re + &r.re, &self.im + &r.im)
+    }
+
+    fn sub(&self, r: &Self) -> Self {
+        Self::new(&self.re - &r.re, &self.im - &r.im)
+    }
+
+    fn mul(&self, r: &Self) -> Self {
+        Self::new(
+            &self.re * &r.re - &self.im * &r.im,
+            &self.re * &r.im + &self.im * &r.re,
+        )
+    }
+
+    /// `1 / self`, `None` for zero
+    fn recip(&self) -> Option<Self> {
+        let norm = self.norm();
+        if norm == 0u32 {
+            return None;
+        }
+        Some(Self::new(&self.re / &norm, -&self.im / norm))
+    }
+
+    /// Integer power by squaring, `None` for negative powers of zero
+    fn pow(&self, exp: i64) -> Option<Self> {
+        let mut result = Self::new(Rational::ONE, Rational::ZERO);
+        let mut base = self.clone();
+        let mut n = exp.unsigned_abs();
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.mul(&base);
+            }
+            n >>= 1;
+            if n > 0 {
+                base = base.mul(&base);
+            }
+        }
+        if exp < 0 {
+            result.recip()
+        } else {
+            Some(result)
+        }
+    }
+}
+
+/// Complex result, numbers without an imaginary part are real
+impl From<Complex> for Value {
+    fn from(c: Complex) -> Self {
+        if c.im != 0u32 {
+            return Value::Complex(c);
+        }
+        match Integer::try_from(&c.re) {
+            Ok(i) => Value::Int(i),
+            Err(_) => Value::Real(c.re),
+        }
+    }
+}
+
+fn complex(v: Value) -> Result<Complex, ComplexError> {
+    match v {
+        Value::Complex(c) => Ok(c),
+        v => v
+            .to_rational()
+            .map(|re| Complex::new(re, Rational::ZERO))
+            .ok_or(ComplexError::UnsupportedOperands),
+    }
+}
+
+/// Execute an operation where at least one of the operands is a complex number
+pub fn execute(op: Arithmetic, l: Value, r: Value) -> Result<Value, ComplexError> {
+    let (l, r) = (complex(l)?, complex(r)?);
+    let result = match op {
+        Arithmetic::Add => l.add(&r),
+        Arithmetic::Sub => l.sub(&r),
+        Arithmetic::Mul => l.mul(&r),
+        Arithmetic::Div => l.mul(&r.recip().ok_or(ComplexError::DivisionByZero)?),
+        Arithmetic::Pow => {
+            let exp = Some(r.re)
+                .filter(|_| r.im == 0u32)
+                .and_then(|re| Integer::try_from(&re).ok())
+                .and_then(|exp| i64::try_from(&exp).ok())
+                .ok_or(ComplexError::InvalidPower)?;
+            l.pow(exp).ok_or(ComplexError::DivisionByZero)?
+        }
+        Arithmetic::Eq => return Ok((l == r).into()),
+        Arithmetic::Ne => return Ok((l != r).into()),
+        Arithmetic::Lt | Arithmetic::Le | Arithmetic::Gt | Arithmetic::Ge => {
+            return Err(ComplexError::Unordered)
+        }
+    };
+    Ok(result.into())
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ComplexError {
+    #[error("Complex numbers can only be raised to integer powers")]
+    InvalidPower,
+    #[error("Complex numbers can't be compared")]
+    Unordered,
+    #[error("Operation is not supported for complex numbers")]
+    UnsupportedOperands,
+    #[error("Division by zero")]
+    DivisionByZero,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(re: i64, im: i64) -> Value {
+        Complex::new(Rational::from(re), Rational::from(im)).into()
+    }
+
+    #[test]
+    fn test_execute() {
+        assert_eq!(execute(Arithmetic::Mul, c(2, 3), c(1, -1)), Ok(c(5, 1)));
+        assert_eq!(
+            execute(Arithmetic::Add, c(2, 3), c(0, -3)),
+            Ok(Value::from(2))
+        );
+        assert_eq!(
+            execute(Arithmetic::Mul, c(0, 1), c(0, 1)),
+            Ok(Value::from(-1))
+        );
+        assert_eq!(
+            execute(Arithmetic::Div, c(1, 0), c(0, 2)),
+            Ok(Value::Complex(Complex::new(
+                Rational::ZERO,
+                Rational::from_signeds(-1, 2)
+            )))
+        );
+        assert_eq!(
+            execute(Arithmetic::Pow, c(1, 1), Value::from(4)),
+            Ok(c(-4, 0))
+        );
+        assert_eq!(
+            execute(Arithmetic::Pow, c(0, 1), Value::from(-1)),
+            Ok(c(0, -1))
+        );
+        assert_eq!(
+            execute(Arithmetic::Eq, c(1, 2), c(1, 2)),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            execute(Arithmetic::Div, c(0, 1), Value::from(0)),
+            Err(ComplexError::DivisionByZero)
+        );
+        assert_eq!(
+            execute(Arithmetic::Lt, c(0, 1), c(0, 2)),
+            Err(ComplexError::Unordered)
+        );
+        let half = Value::Real(Rational::from_signeds(1, 2));
+        assert_eq!(
+            execute(Arithmetic::Pow, c(0, 1), half),
+            Err(ComplexError::InvalidPower)
+        );
+    }
+
+    #[test]
+    fn test_abs() {
+        let c = Complex::new(Rational::from(3), Rational::from(-4));
+        assert_eq!(c.abs(10), Rational::from(5));
+        assert_eq!(c.conj(), Complex::new(Rational::from(3), Rational::from(4)));
+    }
+}
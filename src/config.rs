@@ -0,0 +1,158 @@
+use crate::color::ColorChoice;
+use crate::format::{Fractions, Notation, Output, Rounding};
+use compact_str::CompactString;
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// User defaults, read from `$XDG_CONFIG_HOME/sc/config.toml` or `~/.config/sc/config.toml`
+///
+/// Keys are at the top level of the TOML document and take the values of the command line
+/// options, e.g.
+///
+/// ```toml
+/// precision = 10
+/// output = "hex"
+/// color = "never"
+/// prelude = "~/.config/sc/prelude.sc"
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub precision: Option<u64>,
+    #[serde(deserialize_with = "parsed")]
+    pub rounding: Option<Rounding>,
+    /// How non-integer results are displayed, e.g. as fractions
+    #[serde(deserialize_with = "parsed")]
+    pub fractions: Option<Fractions>,
+    /// How decimal results are displayed, e.g. in scientific notation
+    #[serde(deserialize_with = "parsed")]
+    pub notation: Option<Notation>,
+    /// How integer results are displayed, e.g. in hex
+    #[serde(deserialize_with = "parsed")]
+    pub output: Option<Output>,
+    /// Prompt of interactive sessions
+    pub prompt: Option<String>,
+    /// When to color results and errors
+    #[serde(deserialize_with = "parsed")]
+    pub color: Option<ColorChoice>,
+    /// Number of lines of interactive sessions recalled with the up arrow
+    pub history_size: Option<usize>,
+    /// File of definitions evaluated before the first expression
+    #[serde(deserialize_with = "home_path")]
+    pub prelude: Option<PathBuf>,
+}
+
+impl Config {
+    /// Default location of the config file
+    pub fn path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".config")))?;
+        Some(config_home.join("sc").join("config.toml"))
+    }
+
+    /// Read the config file, a missing file is an empty config
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ConfigError::Io(e.to_string().into())),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        toml::from_str(text).map_err(|e| {
+            let line = e
+                .span()
+                .map_or(0, |span| text[..span.start].lines().count());
+            ConfigError::Syntax {
+                line: line.max(1),
+                message: e.message().into(),
+            }
+        })
+    }
+}
+
+/// Value of a string key read with `FromStr`, like the command line option of the same name
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Path with a leading `~` replaced with the home directory
+fn home_path<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<PathBuf>, D::Error> {
+    Ok(Some(expand_home(&String::deserialize(deserializer)?)))
+}
+
+fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Replace a leading `~` with the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("Failed to read the config file: {0}")]
+    Io(CompactString),
+    #[error("Invalid config file on line {line}: {message}")]
+    Syntax { line: usize, message: CompactString },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = r#"
+            # Defaults
+            precision = 10 # decimal places
+            output = "hex"
+            fractions = "mixed"
+            notation = "sci"
+            prompt = "sc> "
+            color = "never"
+            history_size = 1000
+            prelude = "/etc/sc/prelude # \"main\""
+        "#;
+        let config = Config {
+            precision: Some(10),
+            rounding: None,
+            fractions: Some(Fractions::Mixed),
+            notation: Some(Notation::Scientific),
+            output: Some(Output::Hex),
+            prompt: Some("sc> ".into()),
+            color: Some(ColorChoice::Never),
+            history_size: Some(1000),
+            prelude: Some("/etc/sc/prelude # \"main\"".into()),
+        };
+        assert_eq!(Config::parse(text), Ok(config));
+
+        let error = |text| match Config::parse(text) {
+            Err(ConfigError::Syntax { line, message }) => format!("{}: {}", line, message),
+            result => panic!("{}: {:?}", text, result),
+        };
+        assert!(error("\nprecision").starts_with("2: expected"));
+        assert!(error("precision = \"ten\"").contains("invalid type"));
+        assert!(error("color = \"sometimes\"").contains("sometimes"));
+        assert!(error("base = 16").contains("unknown field `base`"));
+        assert!(error("[defaults]\nprecision = 10").starts_with("1: unknown field `defaults`"));
+    }
+}
@@ -0,0 +1,109 @@
+/// Unix timestamp of a UTC date and time in `YYYY-MM-DD[THH:MM[:SS]]` format
+pub fn parse(text: &str) -> Option<i64> {
+    let (date, time) = match text.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    let mut date = date.split('-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    if date.next().is_some() || !(1..=12).contains(&month) || day < 1 {
+        return None;
+    }
+    if day > days_in_month(year, month) {
+        return None;
+    }
+
+    let mut seconds = 0;
+    if let Some(time) = time {
+        let parts = time
+            .split(':')
+            .map(str::parse::<i64>)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        let (h, m, s) = match parts.as_slice() {
+            [h, m] => (*h, *m, 0),
+            [h, m, s] => (*h, *m, *s),
+            _ => return None,
+        };
+        if h > 23 || m > 59 || s > 59 {
+            return None;
+        }
+        seconds = h * 3600 + m * 60 + s;
+    }
+    Some(days_from_civil(year, month, day) * 86400 + seconds)
+}
+
+/// UTC date and time of a Unix timestamp in `YYYY-MM-DDTHH:MM:SS` format
+pub fn format(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86400);
+    let seconds = timestamp.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Years start in March so that the leap day is at the end
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of [`days_from_civil`]
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("1970-01-01"), Some(0));
+        assert_eq!(parse("2024-05-01T12:00"), Some(1714564800));
+        assert_eq!(parse("2000-02-29T23:59:59"), Some(951868799));
+        assert_eq!(parse("1969-12-31T23:59:59"), Some(-1));
+        assert_eq!(parse("2023-02-29"), None);
+        assert_eq!(parse("2024-13-01"), None);
+        assert_eq!(parse("2024-05-01T24:00"), None);
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format(0), "1970-01-01T00:00:00");
+        assert_eq!(format(1714564800), "2024-05-01T12:00:00");
+        assert_eq!(format(-1), "1969-12-31T23:59:59");
+        assert_eq!(format(951868799), "2000-02-29T23:59:59");
+    }
+}
@@ -0,0 +1,201 @@
+use crate::ast::Expr;
+use crate::tokenizer::Operator;
+use crate::value::Value;
+use compact_str::CompactString;
+use malachite::Integer;
+use std::mem;
+use thiserror::Error;
+
+/// Derivative of the expression with respect to the variable `x`, simplified where terms are
+/// zero or factors are one
+pub fn derivative(expr: &Expr, x: &str) -> Result<Expr, DerivativeError> {
+    if !expr.contains(x) {
+        return Ok(int(0));
+    }
+    let d = |e: &Expr| derivative(e, x);
+    Ok(match expr {
+        Expr::Ident(_) => int(1),
+        Expr::Neg(e) => neg(d(e)?),
+        Expr::Percent(e) => div(d(e)?, int(100)),
+        Expr::Binary { op, l, r } => match op {
+            Operator::Add => add(d(l)?, d(r)?),
+            Operator::Sub => sub(d(l)?, d(r)?),
+            // Product rule
+            Operator::Mul => add(mul(d(l)?, (**r).clone()), mul((**l).clone(), d(r)?)),
+            // Quotient rule
+            Operator::Div => div(
+                sub(mul(d(l)?, (**r).clone()), mul((**l).clone(), d(r)?)),
+                pow((**r).clone(), int(2)),
+            ),
+            // Power rule
+            Operator::Pow if !r.contains(x) => {
+                let exponent = match &**r {
+                    Expr::Val(Value::Int(n)) => Expr::Val(Value::Int(n - Integer::from(1))),
+                    r => sub(r.clone(), int(1)),
+                };
+                mul(mul((**r).clone(), pow((**l).clone(), exponent)), d(l)?)
+            }
+            // Exponential, e.g. `2**x`
+            Operator::Pow if !l.contains(x) => {
+                mul(mul(expr.clone(), call("ln", (**l).clone())), d(r)?)
+            }
+            // `l**r = exp(r ln l)`
+            Operator::Pow => mul(
+                expr.clone(),
+                add(
+                    mul(d(r)?, call("ln", (**l).clone())),
+                    div(mul((**r).clone(), d(l)?), (**l).clone()),
+                ),
+            ),
+            op => return Err(DerivativeError::Unsupported(op.to_string().into())),
+        },
+        // Chain rule for functions of one argument
+        Expr::Call { name, args } => {
+            let [u] = args.as_slice() else {
+                return Err(DerivativeError::Unsupported(name.clone()));
+            };
+            let outer = match name.as_str() {
+                "sqrt" => div(int(1), mul(int(2), expr.clone())),
+                "exp" => expr.clone(),
+                "ln" => div(int(1), u.clone()),
+                "log" => div(int(1), mul(u.clone(), call("ln", int(10)))),
+                "log2" => div(int(1), mul(u.clone(), call("ln", int(2)))),
+                "sin" => call("cos", u.clone()),
+                "cos" => neg(call("sin", u.clone())),
+                "tan" => div(int(1), pow(call("cos", u.clone()), int(2))),
+                "asin" => div(int(1), call("sqrt", sub(int(1), pow(u.clone(), int(2))))),
+                "acos" => neg(div(
+                    int(1),
+                    call("sqrt", sub(int(1), pow(u.clone(), int(2)))),
+                )),
+                "atan" => div(int(1), add(int(1), pow(u.clone(), int(2)))),
+                "sinh" => call("cosh", u.clone()),
+                "cosh" => call("sinh", u.clone()),
+                "tanh" => div(int(1), pow(call("cosh", u.clone()), int(2))),
+                _ => return Err(DerivativeError::Unsupported(name.clone())),
+            };
+            mul(outer, d(u)?)
+        }
+        Expr::Val(_) => unreachable!("values don't contain variables"),
+        Expr::Not(_) => return Err(DerivativeError::Unsupported("!".into())),
+        Expr::Slice { .. } => return Err(DerivativeError::Unsupported("[]".into())),
+        Expr::Cond { .. } => return Err(DerivativeError::Unsupported("?:".into())),
+    })
+}
+
+fn int(i: i64) -> Expr {
+    Expr::Val(Value::from(i))
+}
+
+fn is_int(e: &Expr, i: i64) -> bool {
+    *e == int(i)
+}
+
+fn call(name: &str, arg: Expr) -> Expr {
+    Expr::Call {
+        name: name.into(),
+        args: vec![arg],
+    }
+}
+
+fn neg(mut e: Expr) -> Expr {
+    if is_int(&e, 0) {
+        return e;
+    }
+    match &mut e {
+        Expr::Neg(inner) => mem::replace(inner, int(0)),
+        _ => Expr::Neg(Box::new(e)),
+    }
+}
+
+fn add(l: Expr, r: Expr) -> Expr {
+    match (l, r) {
+        (l, r) if is_int(&r, 0) => l,
+        (l, r) if is_int(&l, 0) => r,
+        (l, r) => Expr::binary(Operator::Add, l, r),
+    }
+}
+
+fn sub(l: Expr, r: Expr) -> Expr {
+    match (l, r) {
+        (l, r) if is_int(&r, 0) => l,
+        (l, r) if is_int(&l, 0) => neg(r),
+        (l, r) => Expr::binary(Operator::Sub, l, r),
+    }
+}
+
+fn mul(l: Expr, r: Expr) -> Expr {
+    match (l, r) {
+        (l, _) if is_int(&l, 0) => int(0),
+        (_, r) if is_int(&r, 0) => int(0),
+        (l, r) if is_int(&r, 1) => l,
+        (l, r) if is_int(&l, 1) => r,
+        (l, r) => Expr::binary(Operator::Mul, l, r),
+    }
+}
+
+fn div(l: Expr, r: Expr) -> Expr {
+    match (l, r) {
+        (l, _) if is_int(&l, 0) => int(0),
+        (l, r) if is_int(&r, 1) => l,
+        (l, r) => Expr::binary(Operator::Div, l, r),
+    }
+}
+
+fn pow(l: Expr, r: Expr) -> Expr {
+    match (l, r) {
+        (_, r) if is_int(&r, 0) => int(1),
+        (l, r) if is_int(&r, 1) => l,
+        (l, r) => Expr::binary(Operator::Pow, l, r),
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum DerivativeError {
+    #[error("Derivative of {0} is not supported")]
+    Unsupported(CompactString),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    fn parse(text: &str) -> Expr {
+        let mut parser = Parser::default();
+        for token in Tokenizer::tokens(text.chars()) {
+            parser.handle_token(token.unwrap()).unwrap();
+        }
+        parser.finalize().unwrap()
+    }
+
+    fn diff(text: &str) -> Result<String, DerivativeError> {
+        derivative(&parse(text), "x").map(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_rules() {
+        assert_eq!(diff("x**3 + 2*x"), Ok("3 * x ** 2 + 2".into()));
+        assert_eq!(diff("5 - y"), Ok("0".into()));
+        assert_eq!(diff("-x"), Ok("-1".into()));
+        assert_eq!(diff("x * y"), Ok("y".into()));
+        assert_eq!(diff("1 / x"), Ok("-1 / x ** 2".into()));
+        assert_eq!(diff("2 ** x"), Ok("2 ** x * ln(2)".into()));
+        assert_eq!(diff("sin(2 * x)"), Ok("cos(2 * x) * 2".into()));
+        assert_eq!(diff("ln(x)"), Ok("1 / x".into()));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert_eq!(
+            diff("max(x, 1)"),
+            Err(DerivativeError::Unsupported("max".into()))
+        );
+        assert_eq!(
+            diff("x > 1 ? x : 1"),
+            Err(DerivativeError::Unsupported("?:".into()))
+        );
+        assert_eq!(diff("max(y, 1)"), Ok("0".into()));
+    }
+}
@@ -0,0 +1,58 @@
+use malachite::num::conversion::traits::RoundingFrom;
+use malachite::rounding_modes::RoundingMode;
+use malachite::{Integer, Rational};
+
+/// Length of a duration unit in seconds, e.g. `h` or `ms`
+pub fn unit(name: &str) -> Option<Rational> {
+    let (n, d) = match name {
+        "ns" => (1, 1_000_000_000),
+        "us" => (1, 1_000_000),
+        "ms" => (1, 1000),
+        "s" => (1, 1),
+        "m" | "min" => (60, 1),
+        "h" => (3600, 1),
+        "d" => (86400, 1),
+        "w" => (604800, 1),
+        _ => return None,
+    };
+    Some(Rational::from_signeds(n, d))
+}
+
+/// Components of a normalized duration, largest first
+const COMPONENTS: [(&str, u32); 3] = [("d", 86400), ("h", 3600), ("m", 60)];
+
+/// Split a non-negative duration in seconds into non-zero whole days, hours and minutes, and
+/// the remaining seconds
+pub fn split(seconds: &Rational) -> (Vec<(&'static str, Integer)>, Rational) {
+    let mut rest = seconds.clone();
+    let mut parts = vec![];
+    for (name, length) in COMPONENTS {
+        let length = Rational::from(length);
+        let count = Integer::rounding_from(&rest / &length, RoundingMode::Floor).0;
+        if count != 0 {
+            rest -= Rational::from(&count) * length;
+            parts.push((name, count));
+        }
+    }
+    (parts, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let (parts, rest) = split(&Rational::from(93784));
+        let parts: Vec<_> = parts
+            .into_iter()
+            .map(|(n, c)| (n, i64::try_from(&c).unwrap()))
+            .collect();
+        assert_eq!(parts, vec![("d", 1), ("h", 2), ("m", 3)]);
+        assert_eq!(rest, 4);
+
+        let (parts, rest) = split(&Rational::from_signeds(3, 2));
+        assert!(parts.is_empty());
+        assert_eq!(rest, Rational::from_signeds(3, 2));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::calculator::Calculator;
+use crate::color::{self, Style};
+use crate::complete::{complete_names, matching_bracket, user_names};
+use crate::interrupt::Input;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context};
+use std::borrow::Cow;
+use std::cell::Cell;
+
+/// Number of lines kept in the history of a terminal session by default
+pub const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+/// Line editor of an interactive session in a terminal
+///
+/// Tab completes the word before the cursor, the arrows and Ctrl-R search the lines entered
+/// before, and the bracket matching the one at the cursor is highlighted if colors are enabled.
+pub struct Editor(rustyline::Editor<Helper, DefaultHistory>);
+
+impl Editor {
+    /// Editor keeping up to `history_size` lines entered in the terminal
+    pub fn new(history_size: usize, color: bool) -> rustyline::Result<Self> {
+        let config = Config::builder()
+            .max_history_size(history_size)?
+            .history_ignore_dups(true)?
+            .completion_type(CompletionType::List)
+            .build();
+        let mut editor = rustyline::Editor::with_config(config)?;
+        editor.set_helper(Some(Helper {
+            names: vec![],
+            color,
+            highlight: Cell::new(false),
+        }));
+        Ok(Self(editor))
+    }
+
+    /// Edit a line after printing the prompt, completing the names defined in the calculator
+    pub fn edit_line(&mut self, prompt: &str, calculator: &Calculator) -> rustyline::Result<Input> {
+        if let Some(helper) = self.0.helper_mut() {
+            helper.names = user_names(calculator);
+        }
+        match self.0.readline(prompt) {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    self.0.add_history_entry(line.as_str())?;
+                }
+                Ok(Input::Line(line.into_bytes()))
+            }
+            Err(ReadlineError::Interrupted) => Ok(Input::Interrupted),
+            Err(ReadlineError::Eof) => Ok(Input::End),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Completion and highlighting of the line being edited
+pub struct Helper {
+    /// Variables and functions of the calculator when the line was started
+    names: Vec<String>,
+    color: bool,
+    /// Unset when the line is entered, so that it stays in the terminal without the highlight
+    highlight: Cell<bool>,
+}
+
+impl Completer for Helper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete_names(line, pos, &self.names))
+    }
+}
+
+impl Highlighter for Helper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        // Bracket at the cursor, or the one just before it
+        let bracket = [pos, pos.saturating_sub(1)]
+            .into_iter()
+            .find_map(|i| matching_bracket(line, i))
+            .filter(|_| self.highlight.get());
+        let Some(i) = bracket else {
+            return Cow::Borrowed(line);
+        };
+        let c = line[i..].chars().next().unwrap_or_default();
+        let painted = color::paint(c, Style::BRACKET);
+        Cow::Owned(format!(
+            "{}{}{}",
+            &line[..i],
+            painted,
+            &line[i + c.len_utf8()..]
+        ))
+    }
+
+    fn highlight_char(&self, _: &str, _: usize, kind: CmdKind) -> bool {
+        self.highlight
+            .set(self.color && kind != CmdKind::ForcedRefresh);
+        self.color
+    }
+}
+
+impl Hinter for Helper {
+    type Hint = String;
+}
+
+impl Validator for Helper {}
+
+impl rustyline::Helper for Helper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let helper = Helper {
+            names: vec![],
+            color: true,
+            highlight: Cell::new(true),
+        };
+        assert_eq!(
+            helper.highlight("(1 + 2)", 7),
+            format!("{}1 + 2)", color::paint('(', Style::BRACKET))
+        );
+        assert_eq!(
+            helper.highlight("π(1)", 2),
+            format!("π(1{}", color::paint(')', Style::BRACKET))
+        );
+        assert_eq!(helper.highlight("(1 + 2)", 3), "(1 + 2)");
+        helper.highlight_char("(1 + 2)", 7, CmdKind::ForcedRefresh);
+        assert_eq!(helper.highlight("(1 + 2)", 7), "(1 + 2)");
+    }
+}
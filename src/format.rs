@@ -0,0 +1,1001 @@
+use crate::ast::Expr;
+use crate::complex::Complex;
+use crate::datetime;
+use crate::duration;
+use crate::interval::Interval;
+use crate::polynomial::Polynomial;
+use crate::real;
+use crate::symbolic::Symbolic;
+use crate::value::Value;
+use compact_str::CompactString;
+use malachite::num::arithmetic::traits::{Abs, DivRem, Pow, UnsignedAbs};
+use malachite::num::conversion::traits::RoundingFrom;
+use malachite::rounding_modes::RoundingMode;
+use malachite::{Integer, Natural, Rational};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Longest exact decimal expansion of the repeating display style
+const MAX_REPEATING_DIGITS: usize = 1000;
+
+/// Result formatting options
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    /// Number of decimal places for non-integer results
+    pub precision: u64,
+    pub rounding: Rounding,
+    pub fractions: Fractions,
+    pub durations: DurationStyle,
+    pub output: Output,
+    /// Write decimals with exactly `precision` places, integers included, e.g. `12.50`
+    pub fixed: bool,
+    /// Write `,` as the decimal separator and separate list elements by `;`
+    pub decimal_comma: bool,
+    pub notation: Notation,
+    /// Round decimal results to this many significant figures, keeping significant trailing
+    /// zeros, e.g. `2.50` for three
+    pub significant: Option<u64>,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self {
+            precision: real::DEFAULT_PRECISION,
+            rounding: Rounding::default(),
+            fractions: Fractions::default(),
+            durations: DurationStyle::default(),
+            output: Output::default(),
+            fixed: false,
+            decimal_comma: false,
+            notation: Notation::default(),
+            significant: None,
+        }
+    }
+}
+
+impl Format {
+    /// This format with the display chosen by the expression, if it does, see
+    /// [`Fractions::of_expr`]
+    pub fn for_expr(&self, expr: &Expr) -> Format {
+        Format {
+            fractions: Fractions::of_expr(expr).unwrap_or(self.fractions),
+            ..self.clone()
+        }
+    }
+
+    pub fn display<'a>(&'a self, value: &'a Value) -> Display<'a> {
+        Display {
+            format: self,
+            value,
+        }
+    }
+
+    fn write_value(&self, f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+        match value {
+            Value::Int(i) => match (self.output, i64::try_from(i)) {
+                (Output::Datetime, Ok(timestamp)) => write!(f, "{}", datetime::format(timestamp)),
+                (Output::Hex | Output::Octal | Output::Binary, _) => {
+                    if *i < 0 {
+                        write!(f, "-")?;
+                    }
+                    let abs = i.unsigned_abs();
+                    match self.output {
+                        Output::Hex => write!(f, "{:#x}", abs),
+                        Output::Octal => write!(f, "{:#o}", abs),
+                        _ => write!(f, "{:#b}", abs),
+                    }
+                }
+                _ if self.significant.is_some() => self.write_significant(f, &Rational::from(i)),
+                _ if self.notation != Notation::Plain => {
+                    self.write_scientific(f, &Rational::from(i))
+                }
+                _ if self.fixed => self.write_decimal(f, &Rational::from(i)),
+                _ => write!(f, "{}", i),
+            },
+            Value::Real(r) => self.write_real(f, r),
+            Value::List(l) => {
+                write!(f, "[")?;
+                for (i, v) in l.iter().enumerate() {
+                    match i {
+                        0 => {}
+                        _ if self.decimal_comma => write!(f, "; ")?,
+                        _ => write!(f, ", ")?,
+                    }
+                    self.write_value(f, v)?;
+                }
+                write!(f, "]")
+            }
+            Value::Duration(d) => match self.durations {
+                DurationStyle::Normalized => self.write_duration(f, d),
+                DurationStyle::Seconds => self.write_decimal(f, d),
+            },
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Quantity(q) => {
+                self.write_decimal(f, &q.magnitude)?;
+                write!(f, " {}", q.unit.name)
+            }
+            Value::Complex(c) => self.write_complex(f, c),
+            Value::Interval(i) => self.write_interval(f, i),
+            Value::Poly(p) => self.write_polynomial(f, p),
+            Value::Symbolic(s) => self.write_symbolic(f, s),
+        }
+    }
+
+    /// Write a non-integer number as a decimal or as a fraction, see [`Fractions`]
+    fn write_real(&self, f: &mut fmt::Formatter<'_>, x: &Rational) -> fmt::Result {
+        // Irrational results are rounded to the precision, their denominators are about as long
+        let rounded = || x.denominator_ref().pow(2) >= Natural::from(10u32).pow(self.precision);
+        match self.fractions {
+            Fractions::Decimal if self.significant.is_some() => self.write_significant(f, x),
+            Fractions::Decimal if self.notation != Notation::Plain => self.write_scientific(f, x),
+            Fractions::Decimal => self.write_decimal(f, x),
+            Fractions::Repeating => match repeating(x) {
+                Some(text) if self.decimal_comma => write!(f, "{}", text.replacen('.', ",", 1)),
+                Some(text) => write!(f, "{}", text),
+                None => self.write_decimal(f, x),
+            },
+            _ if rounded() => self.write_decimal(f, x),
+            Fractions::Fraction => write!(f, "{}", x),
+            Fractions::Mixed => {
+                let denominator = x.denominator_ref();
+                let (whole, numerator) = x.numerator_ref().div_rem(denominator);
+                if whole == 0u32 {
+                    return write!(f, "{}", x);
+                }
+                if *x < 0u32 {
+                    write!(f, "-")?;
+                }
+                write!(f, "{} {}/{}", whole, numerator, denominator)
+            }
+        }
+    }
+
+    /// Write a complex number as `a+bi`, the real part is omitted if it's zero
+    fn write_complex(&self, f: &mut fmt::Formatter<'_>, c: &Complex) -> fmt::Result {
+        if c.re != 0u32 {
+            self.write_real(f, &c.re)?;
+            if c.im > 0u32 {
+                write!(f, "+")?;
+            }
+        }
+        if c.im == -1 {
+            write!(f, "-")?;
+        } else if c.im != 1u32 {
+            self.write_real(f, &c.im)?;
+        }
+        write!(f, "i")
+    }
+
+    /// Write an interval as its midpoint and radius, e.g. `2.5 ± 0.1`
+    fn write_interval(&self, f: &mut fmt::Formatter<'_>, i: &Interval) -> fmt::Result {
+        self.write_real(f, &i.mid())?;
+        write!(f, " ± ")?;
+        self.write_real(f, &i.radius())
+    }
+
+    /// Write a polynomial as a sum of terms, e.g. `x**2 - 0.5x + 1`
+    fn write_polynomial(&self, f: &mut fmt::Formatter<'_>, p: &Polynomial) -> fmt::Result {
+        let Some(degree) = p.degree() else {
+            return write!(f, "0");
+        };
+        let terms = p.coefficients().enumerate().map(|(i, c)| (degree - i, c));
+        for (i, (power, c)) in terms.filter(|(_, c)| **c != 0u32).enumerate() {
+            match (i, *c < 0u32) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => {}
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+            let abs = c.abs();
+            if abs != 1u32 || power == 0 {
+                match Integer::try_from(&abs) {
+                    Ok(i) => write!(f, "{}", i)?,
+                    Err(_) => self.write_real(f, &abs)?,
+                }
+            }
+            match power {
+                0 => {}
+                1 => write!(f, "x")?,
+                _ => write!(f, "x**{}", power)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a symbolic expression as a sum of terms, e.g. `5*x**2 - x*y`
+    fn write_symbolic(&self, f: &mut fmt::Formatter<'_>, s: &Symbolic) -> fmt::Result {
+        for (i, (monomial, c)) in s.terms().into_iter().enumerate() {
+            match (i, *c < 0u32) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => {}
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+            let abs = c.abs();
+            let coefficient = abs != 1u32 || monomial.is_empty();
+            if coefficient {
+                match Integer::try_from(&abs) {
+                    Ok(i) => write!(f, "{}", i)?,
+                    Err(_) => self.write_real(f, &abs)?,
+                }
+            }
+            for (j, (name, exponent)) in monomial.iter().enumerate() {
+                if coefficient || j > 0 {
+                    write!(f, "*")?;
+                }
+                match exponent {
+                    1 => write!(f, "{}", name)?,
+                    _ => write!(f, "{}**{}", name, exponent)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a number in decimal notation rounded to `precision` places, without trailing zeros
+    /// unless the format is fixed
+    fn write_decimal(&self, f: &mut fmt::Formatter<'_>, x: &Rational) -> fmt::Result {
+        let scale = Integer::from(10u32).pow(self.precision);
+        let scaled = self.rounding.round(x * Rational::from(scale));
+        if scaled < 0 {
+            write!(f, "-")?;
+        }
+        let digits = self.precision as usize;
+        let abs = format!("{:0>width$}", scaled.unsigned_abs(), width = digits + 1);
+        let (int, frac) = abs.split_at(abs.len() - digits);
+        let frac = match self.fixed {
+            true => frac,
+            false => frac.trim_end_matches('0'),
+        };
+        let separator = if self.decimal_comma { ',' } else { '.' };
+        if frac.is_empty() {
+            write!(f, "{}", int)
+        } else {
+            write!(f, "{}{}{}", int, separator, frac)
+        }
+    }
+
+    /// Write a number as a mantissa rounded to `precision` places and a power of ten, e.g.
+    /// `1.2345678e7`, or `12.345678e6` with an exponent that's a multiple of three
+    fn write_scientific(&self, f: &mut fmt::Formatter<'_>, x: &Rational) -> fmt::Result {
+        if *x == 0u32 {
+            return self.write_decimal(f, x);
+        }
+        let step = match self.notation {
+            Notation::Engineering => 3,
+            _ => 1,
+        };
+        let mut exponent = decimal_exponent(x);
+        exponent -= exponent.rem_euclid(step);
+        let mut mantissa = x / power_of_ten(exponent);
+        // Rounding may carry into the next power, e.g. `9.996` to `10.00`
+        let scale = Rational::from(Integer::from(10u32).pow(self.precision));
+        let limit = Integer::from(10u32).pow(step as u64 + self.precision);
+        if self.rounding.round(&mantissa * scale).unsigned_abs() >= limit.unsigned_abs() {
+            exponent += step;
+            mantissa = x / power_of_ten(exponent);
+        }
+        self.write_decimal(f, &mantissa)?;
+        write!(f, "e{}", exponent)
+    }
+
+    /// Write a number rounded to `significant` figures, with as many decimal places as there are
+    /// significant digits after the decimal separator
+    fn write_significant(&self, f: &mut fmt::Formatter<'_>, x: &Rational) -> fmt::Result {
+        let digits = self.significant.unwrap_or(1).max(1);
+        let rounded = significant(x, digits, self.rounding);
+        if rounded == 0u32 {
+            return self.write_decimal(f, &rounded);
+        }
+        let exponent = decimal_exponent(&rounded);
+        // Digits of the integer part of the mantissa beyond the first
+        let shift = match self.notation {
+            Notation::Plain => exponent,
+            Notation::Scientific => 0,
+            Notation::Engineering => exponent.rem_euclid(3),
+        };
+        let format = Format {
+            precision: (digits as i64 - 1 - shift).max(0) as u64,
+            fixed: true,
+            significant: None,
+            ..self.clone()
+        };
+        match self.notation {
+            Notation::Plain => format.write_decimal(f, &rounded),
+            _ => format.write_scientific(f, &rounded),
+        }
+    }
+
+    /// Write a duration as days, hours, minutes and seconds, e.g. `1h30m`
+    fn write_duration(&self, f: &mut fmt::Formatter<'_>, seconds: &Rational) -> fmt::Result {
+        if *seconds < 0u32 {
+            write!(f, "-")?;
+        }
+        let (parts, rest) = duration::split(&seconds.abs());
+        for (unit, count) in &parts {
+            // A single `m` would be read as metres
+            let unit = match (*unit, parts.len(), rest == 0u32) {
+                ("m", 1, true) => "min",
+                (unit, ..) => unit,
+            };
+            write!(f, "{}{}", count, unit)?;
+        }
+        if rest != 0u32 || parts.is_empty() {
+            self.write_decimal(f, &rest)?;
+            write!(f, "s")?;
+        }
+        Ok(())
+    }
+}
+
+/// Number rounded to `digits` significant figures, e.g. `1234.5` to `1200` for two
+pub fn significant(x: &Rational, digits: u64, rounding: Rounding) -> Rational {
+    if *x == 0u32 {
+        return Rational::from(0u32);
+    }
+    let scale = power_of_ten(digits as i64 - 1 - decimal_exponent(x));
+    Rational::from(rounding.round(x * &scale)) / scale
+}
+
+/// Exponent of the largest power of ten that isn't larger than the absolute value of `x`, which
+/// isn't zero
+fn decimal_exponent(x: &Rational) -> i64 {
+    let digits = |n: &Natural| n.to_string().len() as i64;
+    let exponent = digits(x.numerator_ref()) - digits(x.denominator_ref());
+    if x.abs() < power_of_ten(exponent) {
+        exponent - 1
+    } else {
+        exponent
+    }
+}
+
+fn power_of_ten(exponent: i64) -> Rational {
+    let power = Rational::from(Natural::from(10u32).pow(exponent.unsigned_abs()));
+    if exponent < 0 {
+        Rational::from(1u32) / power
+    } else {
+        power
+    }
+}
+
+/// Exact decimal expansion with the repetend in parentheses, e.g. `-0.1(6)`, `None` if it has
+/// more than [`MAX_REPEATING_DIGITS`] fractional digits
+fn repeating(x: &Rational) -> Option<String> {
+    let denominator = x.denominator_ref();
+    let (int, mut remainder) = x.numerator_ref().div_rem(denominator);
+    let mut text = String::new();
+    if *x < 0u32 {
+        text.push('-');
+    }
+    text += &format!("{}.", int);
+    let start = text.len();
+    // Position of the digit produced by each remainder, the digits repeat from a seen remainder
+    let mut seen = HashMap::new();
+    while remainder != 0u32 {
+        if let Some(position) = seen.insert(remainder.clone(), text.len()) {
+            text.insert(position, '(');
+            text.push(')');
+            break;
+        }
+        if text.len() - start >= MAX_REPEATING_DIGITS {
+            return None;
+        }
+        let (digit, rest) = (remainder * Natural::from(10u32)).div_rem(denominator);
+        text += &digit.to_string();
+        remainder = rest;
+    }
+    Some(text)
+}
+
+/// Prompt with the placeholders replaced: `{base}` by the output format, `{precision}` by the
+/// number of decimal places and `{next}` by the number of the next result, e.g.
+/// `[{base} {next}]> `
+pub fn prompt(template: &str, format: &Format, next_result: usize) -> String {
+    template
+        .replace("{base}", &format.output.to_string())
+        .replace("{precision}", &format.precision.to_string())
+        .replace("{next}", &next_result.to_string())
+}
+
+/// Value formatted with specific options, created with [`Format::display`]
+pub struct Display<'a> {
+    format: &'a Format,
+    value: &'a Value,
+}
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.format.write_value(f, self.value)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round to nearest, ties to even
+    #[default]
+    HalfEven,
+    /// Round to nearest, ties away from zero
+    HalfUp,
+    /// Round to nearest, ties towards zero
+    HalfDown,
+    /// Towards zero
+    Down,
+    /// Away from zero
+    Up,
+    /// Towards negative infinity
+    Floor,
+    /// Towards positive infinity
+    Ceiling,
+}
+
+impl Rounding {
+    pub const NAMES: [&'static str; 7] = [
+        "half-even",
+        "half-up",
+        "half-down",
+        "down",
+        "up",
+        "floor",
+        "ceiling",
+    ];
+
+    const VARIANTS: [Rounding; 7] = [
+        Rounding::HalfEven,
+        Rounding::HalfUp,
+        Rounding::HalfDown,
+        Rounding::Down,
+        Rounding::Up,
+        Rounding::Floor,
+        Rounding::Ceiling,
+    ];
+
+    /// Round to an integer
+    pub fn round(self, x: Rational) -> Integer {
+        let half = || Rational::from_signeds(1, 2);
+        let mode = match self {
+            Rounding::HalfEven => RoundingMode::Nearest,
+            Rounding::Down => RoundingMode::Down,
+            Rounding::Up => RoundingMode::Up,
+            Rounding::Floor => RoundingMode::Floor,
+            Rounding::Ceiling => RoundingMode::Ceiling,
+            Rounding::HalfUp if x < 0u32 => {
+                return -Integer::rounding_from(half() - x, RoundingMode::Floor).0
+            }
+            Rounding::HalfUp => return Integer::rounding_from(x + half(), RoundingMode::Floor).0,
+            Rounding::HalfDown if x < 0u32 => {
+                return -Integer::rounding_from(-x - half(), RoundingMode::Ceiling).0
+            }
+            Rounding::HalfDown => {
+                return Integer::rounding_from(x - half(), RoundingMode::Ceiling).0
+            }
+        };
+        Integer::rounding_from(x, mode).0
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::NAMES
+            .iter()
+            .position(|name| *name == s)
+            .map(|i| Self::VARIANTS[i])
+            .ok_or_else(|| FormatError::Rounding(s.into()))
+    }
+}
+
+impl fmt::Display for Rounding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let i = Self::VARIANTS.iter().position(|r| r == self).unwrap_or(0);
+        write!(f, "{}", Self::NAMES[i])
+    }
+}
+
+/// How integer results are displayed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    #[default]
+    Decimal,
+    /// UTC date and time of a Unix timestamp
+    Datetime,
+    /// Hexadecimal with the `0x` prefix
+    Hex,
+    /// Octal with the `0o` prefix
+    Octal,
+    /// Binary with the `0b` prefix
+    Binary,
+}
+
+impl Output {
+    pub const NAMES: [&'static str; 5] = ["decimal", "datetime", "hex", "oct", "bin"];
+}
+
+impl FromStr for Output {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "decimal" | "dec" => Ok(Output::Decimal),
+            "datetime" => Ok(Output::Datetime),
+            "hex" => Ok(Output::Hex),
+            "oct" => Ok(Output::Octal),
+            "bin" => Ok(Output::Binary),
+            _ => Err(FormatError::Output(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Decimal => write!(f, "decimal"),
+            Output::Datetime => write!(f, "datetime"),
+            Output::Hex => write!(f, "hex"),
+            Output::Octal => write!(f, "oct"),
+            Output::Binary => write!(f, "bin"),
+        }
+    }
+}
+
+/// How non-integer results are displayed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Fractions {
+    /// Decimal rounded to the precision, e.g. `3.5`
+    #[default]
+    Decimal,
+    /// Exact fraction, e.g. `7/2`
+    Fraction,
+    /// Whole part and a proper fraction, e.g. `3 1/2`
+    Mixed,
+    /// Exact decimal with the repeating digits in parentheses, e.g. `0.(142857)`
+    Repeating,
+}
+
+impl Fractions {
+    pub const NAMES: [&'static str; 4] = ["decimal", "fraction", "mixed", "repeating"];
+
+    /// Display of the result of the expression regardless of the settings, `frac(x)` and
+    /// `approx(x, n)` are fractions and `dec(x)` a decimal
+    pub fn of_expr(expr: &Expr) -> Option<Fractions> {
+        match expr {
+            Expr::Call { name, .. } if *name == "frac" || *name == "approx" => {
+                Some(Fractions::Fraction)
+            }
+            Expr::Call { name, .. } if *name == "dec" => Some(Fractions::Decimal),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Fractions {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "decimal" => Ok(Fractions::Decimal),
+            "fraction" => Ok(Fractions::Fraction),
+            "mixed" => Ok(Fractions::Mixed),
+            "repeating" => Ok(Fractions::Repeating),
+            _ => Err(FormatError::Fractions(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Fractions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fractions::Decimal => write!(f, "decimal"),
+            Fractions::Fraction => write!(f, "fraction"),
+            Fractions::Mixed => write!(f, "mixed"),
+            Fractions::Repeating => write!(f, "repeating"),
+        }
+    }
+}
+
+/// When to use a decimal comma, e.g. `3,14`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DecimalComma {
+    /// Decimal point
+    #[default]
+    Never,
+    Always,
+    /// If the numeric locale of the environment uses one, e.g. `de_DE.UTF-8`
+    Locale,
+}
+
+impl DecimalComma {
+    pub const NAMES: [&'static str; 3] = ["never", "always", "locale"];
+
+    pub fn enabled(self) -> bool {
+        match self {
+            DecimalComma::Never => false,
+            DecimalComma::Always => true,
+            DecimalComma::Locale => ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .into_iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find(|locale| !locale.is_empty())
+                .is_some_and(|locale| locale_decimal_comma(&locale)),
+        }
+    }
+}
+
+/// Languages that write numbers with a decimal comma
+const DECIMAL_COMMA_LANGUAGES: [&str; 36] = [
+    "af", "az", "be", "bg", "bs", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fo", "fr",
+    "gl", "hr", "hu", "id", "is", "it", "kk", "lt", "lv", "mk", "nb", "nl", "nn", "pl", "pt", "ro",
+    "ru", "sk", "sl", "sv",
+];
+
+/// Whether the locale, e.g. `de_DE.UTF-8`, uses a decimal comma. Only the language is considered
+fn locale_decimal_comma(locale: &str) -> bool {
+    let language = locale
+        .split(['_', '.', '@', '-'])
+        .next()
+        .unwrap_or_default();
+    DECIMAL_COMMA_LANGUAGES.contains(&language)
+}
+
+impl FromStr for DecimalComma {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(DecimalComma::Never),
+            "always" => Ok(DecimalComma::Always),
+            "locale" => Ok(DecimalComma::Locale),
+            _ => Err(FormatError::DecimalComma(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for DecimalComma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecimalComma::Never => write!(f, "never"),
+            DecimalComma::Always => write!(f, "always"),
+            DecimalComma::Locale => write!(f, "locale"),
+        }
+    }
+}
+
+/// How decimal results are displayed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// All digits, e.g. `12345678`
+    #[default]
+    Plain,
+    /// Mantissa between 1 and 10, e.g. `1.2345678e7`
+    Scientific,
+    /// Exponent that's a multiple of three, e.g. `12.345678e6`
+    Engineering,
+}
+
+impl Notation {
+    pub const NAMES: [&'static str; 3] = ["plain", "sci", "eng"];
+}
+
+impl FromStr for Notation {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Notation::Plain),
+            "sci" => Ok(Notation::Scientific),
+            "eng" => Ok(Notation::Engineering),
+            _ => Err(FormatError::Notation(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Notation::Plain => write!(f, "plain"),
+            Notation::Scientific => write!(f, "sci"),
+            Notation::Engineering => write!(f, "eng"),
+        }
+    }
+}
+
+/// How durations are displayed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DurationStyle {
+    /// Days, hours, minutes and seconds, e.g. `1h30m`
+    #[default]
+    Normalized,
+    /// Number of seconds
+    Seconds,
+}
+
+impl DurationStyle {
+    pub const NAMES: [&'static str; 2] = ["normalized", "seconds"];
+}
+
+impl FromStr for DurationStyle {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normalized" => Ok(DurationStyle::Normalized),
+            "seconds" => Ok(DurationStyle::Seconds),
+            _ => Err(FormatError::DurationStyle(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for DurationStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DurationStyle::Normalized => write!(f, "normalized"),
+            DurationStyle::Seconds => write!(f, "seconds"),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    #[error("Unknown rounding mode: {0}, expected one of: {}", Rounding::NAMES.join(", "))]
+    Rounding(CompactString),
+    #[error("Unknown fraction style: {0}, expected one of: {}", Fractions::NAMES.join(", "))]
+    Fractions(CompactString),
+    #[error("Unknown duration format: {0}, expected one of: {}", DurationStyle::NAMES.join(", "))]
+    DurationStyle(CompactString),
+    #[error("Unknown output format: {0}, expected one of: {}", Output::NAMES.join(", "))]
+    Output(CompactString),
+    #[error("Unknown decimal comma setting: {0}, expected one of: {}", DecimalComma::NAMES.join(", "))]
+    DecimalComma(CompactString),
+    #[error("Unknown notation: {0}, expected one of: {}", Notation::NAMES.join(", "))]
+    Notation(CompactString),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rounding() {
+        let round = |rounding: &str, n, d| {
+            let rounding: Rounding = rounding.parse().unwrap();
+            i64::try_from(&rounding.round(Rational::from_signeds(n, d))).unwrap()
+        };
+        let cases = [(5, 2), (-5, 2), (7, 2), (-7, 4)];
+        let expected = [
+            ("half-even", [2, -2, 4, -2]),
+            ("half-up", [3, -3, 4, -2]),
+            ("half-down", [2, -2, 3, -2]),
+            ("down", [2, -2, 3, -1]),
+            ("up", [3, -3, 4, -2]),
+            ("floor", [2, -3, 3, -2]),
+            ("ceiling", [3, -2, 4, -1]),
+        ];
+        for (rounding, results) in expected {
+            for ((n, d), result) in cases.iter().zip(results) {
+                assert_eq!(round(rounding, *n, *d), result, "{rounding} {n}/{d}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_precision() {
+        let format = Format {
+            precision: 3,
+            rounding: Rounding::Down,
+            ..Format::default()
+        };
+        let value = Value::Real(Rational::from_signeds(-2, 3));
+        assert_eq!(format.display(&value).to_string(), "-0.666");
+        let value = Value::Real(Rational::from_signeds(1, 2000));
+        assert_eq!(format.display(&value).to_string(), "0");
+    }
+
+    #[test]
+    fn test_fixed() {
+        let format = Format {
+            precision: 2,
+            fixed: true,
+            ..Format::default()
+        };
+        let display = |value: Value| format.display(&value).to_string();
+        assert_eq!(display(Value::Real(Rational::from_signeds(1, 2))), "0.50");
+        assert_eq!(display(Value::from(-3)), "-3.00");
+        // Ties are rounded to even
+        assert_eq!(display(Value::Real(Rational::from_signeds(1, 8))), "0.12");
+        assert_eq!(display(Value::Real(Rational::from_signeds(3, 8))), "0.38");
+        let format = Format {
+            precision: 0,
+            ..format
+        };
+        assert_eq!(format.display(&Value::from(5)).to_string(), "5");
+    }
+
+    #[test]
+    fn test_decimal_comma() {
+        let format = Format {
+            decimal_comma: true,
+            ..Format::default()
+        };
+        let value = Value::List(vec![
+            Value::Real(Rational::from_signeds(-7, 2)),
+            Value::from(2),
+        ]);
+        assert_eq!(format.display(&value).to_string(), "[-3,5; 2]");
+        assert!(locale_decimal_comma("de_DE.UTF-8"));
+        assert!(!locale_decimal_comma("en_US.UTF-8"));
+        assert!(!locale_decimal_comma("C"));
+    }
+
+    #[test]
+    fn test_notation() {
+        let format = |notation: &str, precision, value: Value| {
+            let format = Format {
+                notation: notation.parse().unwrap(),
+                precision,
+                ..Format::default()
+            };
+            format.display(&value).to_string()
+        };
+        assert_eq!(format("sci", 50, Value::from(12345678)), "1.2345678e7");
+        assert_eq!(format("eng", 50, Value::from(12345678)), "12.345678e6");
+        assert_eq!(format("plain", 50, Value::from(12345678)), "12345678");
+        assert_eq!(format("sci", 2, Value::from(12345678)), "1.23e7");
+        assert_eq!(format("sci", 2, Value::from(-9999)), "-1e4");
+        let milli = Value::Real(Rational::from_signeds(-3, 2000));
+        assert_eq!(format("sci", 50, milli.clone()), "-1.5e-3");
+        assert_eq!(format("eng", 50, milli.clone()), "-1.5e-3");
+        let micro = Value::Real(Rational::from_signeds(1, 20000));
+        assert_eq!(format("eng", 50, micro.clone()), "50e-6");
+        assert_eq!(format("eng", 50, Value::from(0)), "0");
+
+        // Results read back as the same number
+        for value in [Value::from(12345678), Value::from(-9999), milli, micro] {
+            for notation in ["sci", "eng"] {
+                let text = format(notation, 50, value.clone());
+                let plain = Format::default().display(&value).to_string();
+                assert_eq!(crate::eval(&text), Ok(plain));
+            }
+        }
+    }
+
+    #[test]
+    fn test_significant() {
+        let format = |notation: &str, significant, value: Value| {
+            let format = Format {
+                notation: notation.parse().unwrap(),
+                significant: Some(significant),
+                ..Format::default()
+            };
+            format.display(&value).to_string()
+        };
+        let real = |n, d| Value::Real(Rational::from_signeds(n, d));
+        assert_eq!(format("plain", 3, real(5, 2)), "2.50");
+        assert_eq!(format("plain", 2, Value::from(12345678)), "12000000");
+        assert_eq!(format("plain", 2, real(-1, 3)), "-0.33");
+        assert_eq!(format("plain", 3, real(9999, 1000)), "10.0");
+        assert_eq!(format("sci", 3, Value::from(12345678)), "1.23e7");
+        assert_eq!(format("eng", 2, Value::from(12345678)), "12e6");
+        assert_eq!(format("plain", 3, Value::from(0)), "0");
+        assert_eq!(
+            significant(&Rational::from(1250), 2, Rounding::HalfEven),
+            Rational::from(1200)
+        );
+    }
+
+    #[test]
+    fn test_fractions() {
+        let format = |fractions: &str, n, d| {
+            let format = Format {
+                fractions: fractions.parse().unwrap(),
+                ..Format::default()
+            };
+            format
+                .display(&Value::Real(Rational::from_signeds(n, d)))
+                .to_string()
+        };
+        assert_eq!(format("decimal", 7, 2), "3.5");
+        assert_eq!(format("fraction", 7, 2), "7/2");
+        assert_eq!(format("fraction", -1, 3), "-1/3");
+        assert_eq!(format("mixed", 7, 2), "3 1/2");
+        assert_eq!(format("mixed", -7, 2), "-3 1/2");
+        assert_eq!(format("mixed", -1, 2), "-1/2");
+        assert_eq!(format("repeating", 1, 7), "0.(142857)");
+        assert_eq!(format("repeating", -1, 6), "-0.1(6)");
+        assert_eq!(format("repeating", 22, 8), "2.75");
+        // The period of 1/1019 is 1018 digits long
+        assert_eq!(format("repeating", 1, 1019), format("decimal", 1, 1019));
+
+        // Rounded irrational results stay decimal
+        let sqrt2 = real::sqrt(&Rational::from(2), 10).unwrap();
+        let format = Format {
+            precision: 10,
+            fractions: Fractions::Fraction,
+            ..Format::default()
+        };
+        assert_eq!(
+            format.display(&Value::Real(sqrt2)).to_string(),
+            "1.4142135624"
+        );
+        assert_eq!(
+            "sideways".parse::<Fractions>(),
+            Err(FormatError::Fractions("sideways".into()))
+        );
+    }
+
+    #[test]
+    fn test_complex() {
+        let complex = |re, im| Value::Complex(Complex::new(Rational::from(re), im)).to_string();
+        assert_eq!(complex(2, Rational::from(3)), "2+3i");
+        assert_eq!(complex(1, Rational::from(-1)), "1-i");
+        assert_eq!(complex(0, Rational::from(1)), "i");
+        assert_eq!(complex(0, Rational::from_signeds(-5, 2)), "-2.5i");
+    }
+
+    #[test]
+    fn test_interval() {
+        let interval = |lo, hi| {
+            let (lo, hi) = (
+                Rational::from_signeds(lo, 10),
+                Rational::from_signeds(hi, 10),
+            );
+            Value::Interval(Interval { lo, hi }).to_string()
+        };
+        assert_eq!(interval(24, 26), "2.5 ± 0.1");
+        assert_eq!(interval(-10, -10), "-1 ± 0");
+    }
+
+    #[test]
+    fn test_duration() {
+        let duration = |n, d| Value::Duration(Rational::from_signeds(n, d)).to_string();
+        assert_eq!(duration(5400, 1), "1h30m");
+        assert_eq!(duration(300, 1), "5min");
+        assert_eq!(duration(330, 1), "5m30s");
+        assert_eq!(duration(-93784, 1), "-1d2h3m4s");
+        assert_eq!(duration(3, 2), "1.5s");
+        assert_eq!(duration(0, 1), "0s");
+
+        let format = Format {
+            durations: DurationStyle::Seconds,
+            ..Format::default()
+        };
+        let value = Value::Duration(Rational::from(5400));
+        assert_eq!(format.display(&value).to_string(), "5400");
+    }
+
+    #[test]
+    fn test_datetime() {
+        let format = Format {
+            output: Output::Datetime,
+            ..Format::default()
+        };
+        let value = Value::from(1714564800);
+        assert_eq!(format.display(&value).to_string(), "2024-05-01T12:00:00");
+        let value = Value::Real(Rational::from_signeds(1, 2));
+        assert_eq!(format.display(&value).to_string(), "0.5");
+    }
+
+    #[test]
+    fn test_radix() {
+        let format = |output: &str, value: i64| {
+            let format = Format {
+                output: output.parse().unwrap(),
+                ..Format::default()
+            };
+            format.display(&Value::from(value)).to_string()
+        };
+        assert_eq!(format("hex", 255), "0xff");
+        assert_eq!(format("oct", 8), "0o10");
+        assert_eq!(format("bin", -5), "-0b101");
+    }
+
+    #[test]
+    fn test_prompt() {
+        let format = Format {
+            output: Output::Hex,
+            ..Format::default()
+        };
+        assert_eq!(prompt("[{base} {next}]> ", &format, 3), "[hex 3]> ");
+        assert_eq!(prompt("{precision}{x}>", &format, 1), "50{x}>");
+    }
+}
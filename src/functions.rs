@@ -0,0 +1,1302 @@
+use crate::bytesize;
+use crate::calculator::MAX_BITS;
+use crate::complex::Complex;
+use crate::datetime;
+use crate::format::{self, Rounding};
+use crate::matrix::{Matrix, MatrixError};
+use crate::polynomial::Polynomial;
+use crate::real::{self, RealError};
+use crate::roman;
+use crate::units::Quantity;
+use crate::value::Value;
+use compact_str::CompactString;
+use malachite::num::arithmetic::traits::{
+    Abs, CheckedSqrt, FloorLogBase, FloorLogBase2, FloorRoot, FloorSqrt, Sign, UnsignedAbs,
+};
+use malachite::num::conversion::traits::{PowerOf2Digits, WrappingFrom};
+use malachite::num::logic::traits::{BitAccess, SignificantBits};
+use malachite::{Integer, Natural, Rational};
+use std::cmp::Ordering;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+mod checksum;
+mod combinatorics;
+mod gf2;
+pub mod modular;
+pub mod number_theory;
+mod programmer;
+mod rational;
+mod stats;
+
+/// Names of the built-in constants
+pub const CONSTANTS: [&str; 4] = ["pi", "tau", "e", "i"];
+
+/// Names of the built-in functions
+pub const FUNCTIONS: [&str; 97] = [
+    "abs",
+    "sign",
+    "min",
+    "max",
+    "clamp",
+    "sqrt",
+    "exp",
+    "ln",
+    "log",
+    "log2",
+    "sin",
+    "cos",
+    "tan",
+    "asin",
+    "acos",
+    "atan",
+    "sinh",
+    "cosh",
+    "tanh",
+    "gcd",
+    "lcm",
+    "factorial",
+    "isprime",
+    "nextprime",
+    "factor",
+    "powmod",
+    "invmod",
+    "crt",
+    "isqrt",
+    "iroot",
+    "ilog2",
+    "ilog10",
+    "ilog",
+    "fib",
+    "popcount",
+    "bit",
+    "setbit",
+    "clearbit",
+    "bits",
+    "bswap16",
+    "bswap32",
+    "bswap64",
+    "clz",
+    "ctz",
+    "rotl",
+    "rotr",
+    "clmul",
+    "gf2_mod",
+    "gf2_inv",
+    "crc32",
+    "crc16",
+    "adler32",
+    "f32bits",
+    "f64bits",
+    "bitsf32",
+    "bitsf64",
+    "humansize",
+    "roman",
+    "frac",
+    "dec",
+    "cfrac",
+    "fromcfrac",
+    "approx",
+    "sigfig",
+    "re",
+    "im",
+    "conj",
+    "absc",
+    "list",
+    "len",
+    "sum",
+    "product",
+    "mean",
+    "median",
+    "variance",
+    "stddev",
+    "percentile",
+    "sort",
+    "transpose",
+    "det",
+    "inv",
+    "poly",
+    "polyval",
+    "polydiv",
+    "coeffs",
+    "now",
+    "unix",
+    "u8",
+    "u16",
+    "u32",
+    "u64",
+    "u128",
+    "i8",
+    "i16",
+    "i32",
+    "i64",
+    "i128",
+];
+
+/// Value of a built-in constant, irrational constants are correct to `precision` decimal places
+pub fn constant(name: &str, precision: u64) -> Option<Value> {
+    match name {
+        "pi" => Some(real::pi(precision).into()),
+        "tau" => Some((real::pi(precision) * Rational::from(2)).into()),
+        "e" => Some(real::e(precision).into()),
+        "i" => Some(Value::Complex(Complex::i())),
+        _ => None,
+    }
+}
+
+/// Call a built-in function, irrational results are correct to `precision` decimal places
+pub fn call(name: &str, args: Vec<Value>, precision: u64) -> Result<Value, FunctionError> {
+    match name {
+        "abs" => {
+            let [x] = unpack(name, args)?;
+            match x {
+                Value::Int(i) => Ok(i.abs().into()),
+                Value::Real(r) => Ok(r.abs().into()),
+                Value::Duration(d) => Ok(Value::Duration(d.abs())),
+                Value::Quantity(q) => Ok(Value::Quantity(Quantity {
+                    magnitude: q.magnitude.abs(),
+                    unit: q.unit,
+                })),
+                Value::Complex(c) => Ok(exact(c.abs(precision))),
+                Value::Interval(i) => Ok(Value::Interval(i.abs())),
+                Value::List(_) | Value::Str(_) | Value::Poly(_) | Value::Symbolic(_) => {
+                    Err(invalid(name, "number expected"))
+                }
+            }
+        }
+        "sign" => {
+            let [x] = unpack_reals(name, args)?;
+            Ok(match x.sign() {
+                Ordering::Less => Value::from(-1),
+                Ordering::Equal => Value::from(0),
+                Ordering::Greater => Value::from(1),
+            })
+        }
+        "min" => numbers(name, args)?
+            .into_iter()
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, v)| v)
+            .ok_or_else(|| FunctionError::NotEnoughArguments(name.into())),
+        "max" => numbers(name, args)?
+            .into_iter()
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, v)| v)
+            .ok_or_else(|| FunctionError::NotEnoughArguments(name.into())),
+        "clamp" => {
+            let [x, lo, hi] = unpack(name, numbers(name, args)?)?;
+            if lo.0 > hi.0 {
+                return Err(invalid(name, "lower bound is greater than upper bound"));
+            }
+            Ok(if x.0 < lo.0 {
+                lo.1
+            } else if x.0 > hi.0 {
+                hi.1
+            } else {
+                x.1
+            })
+        }
+        "sqrt" => {
+            let [x] = unpack(name, args)?;
+            if let Value::Int(i) = &x {
+                if let Ok(n) = Natural::try_from(i) {
+                    if let Some(root) = n.checked_sqrt() {
+                        return Ok(Integer::from(root).into());
+                    }
+                }
+            }
+            let x = real_arg(name, x)?;
+            real::sqrt(&x, precision)
+                .map(Value::from)
+                .map_err(domain(name))
+        }
+        "exp" => {
+            let [x] = unpack_reals(name, args)?;
+            real::exp(&x, precision)
+                .map(Value::from)
+                .map_err(domain(name))
+        }
+        "ln" => {
+            let [x] = unpack_reals(name, args)?;
+            real::ln(&x, precision)
+                .map(Value::from)
+                .map_err(domain(name))
+        }
+        "log" => {
+            let (x, base) = match reals(name, args)?.as_slice() {
+                [x] => (x.clone(), Rational::from(10)),
+                [x, base] => (x.clone(), base.clone()),
+                args => {
+                    return Err(FunctionError::ArgumentCount {
+                        name: name.into(),
+                        expected: 2,
+                        got: args.len(),
+                    })
+                }
+            };
+            real::log(&x, &base, precision)
+                .map(Value::from)
+                .map_err(domain(name))
+        }
+        "log2" => {
+            let [x] = unpack_reals(name, args)?;
+            real::log(&x, &Rational::from(2), precision)
+                .map(Value::from)
+                .map_err(domain(name))
+        }
+        "sin" => {
+            let [x] = unpack_reals(name, args)?;
+            Ok(real::sin(&x, precision).into())
+        }
+        "cos" => {
+            let [x] = unpack_reals(name, args)?;
+            Ok(real::cos(&x, precision).into())
+        }
+        "tan" => {
+            let [x] = unpack_reals(name, args)?;
+            Ok(real::tan(&x, precision).into())
+        }
+        "asin" => {
+            let [x] = unpack_reals(name, args)?;
+            real::asin(&x, precision)
+                .map(Value::from)
+                .map_err(domain(name))
+        }
+        "acos" => {
+            let [x] = unpack_reals(name, args)?;
+            real::acos(&x, precision)
+                .map(Value::from)
+                .map_err(domain(name))
+        }
+        "atan" => {
+            let [x] = unpack_reals(name, args)?;
+            Ok(real::atan(&x, precision).into())
+        }
+        "sinh" => {
+            let [x] = unpack_reals(name, args)?;
+            real::sinh(&x, precision)
+                .map(Value::from)
+                .map_err(domain(name))
+        }
+        "cosh" => {
+            let [x] = unpack_reals(name, args)?;
+            real::cosh(&x, precision)
+                .map(Value::from)
+                .map_err(domain(name))
+        }
+        "tanh" => {
+            let [x] = unpack_reals(name, args)?;
+            Ok(real::tanh(&x, precision).into())
+        }
+        "gcd" => {
+            let args = non_empty(name, ints(name, args)?)?;
+            Ok(number_theory::gcd(args).into())
+        }
+        "lcm" => {
+            let args = non_empty(name, ints(name, args)?)?;
+            Ok(number_theory::lcm(args).into())
+        }
+        "factorial" => {
+            let [n] = unpack_ints(name, args)?;
+            let n =
+                u64::try_from(&n).map_err(|_| invalid(name, "non-negative integer expected"))?;
+            // n! < n**n
+            check_size(name, Integer::from(n) * Integer::from(n.significant_bits()))?;
+            Ok(number_theory::factorial(n).into())
+        }
+        "isprime" => {
+            let [n] = unpack_ints(name, args)?;
+            let is_prime = Natural::try_from(n).is_ok_and(|n| number_theory::is_prime(&n));
+            Ok(is_prime.into())
+        }
+        "nextprime" => {
+            let [n] = unpack_ints(name, args)?;
+            Ok(number_theory::next_prime(&n).into())
+        }
+        "factor" => {
+            let [n] = unpack_ints(name, args)?;
+            if n == 0 {
+                return Err(invalid(name, "cannot factor zero"));
+            }
+            let mut factors = vec![];
+            if n < 0 {
+                factors.push(Value::from(-1));
+            }
+            for (p, k) in number_theory::factor(n.unsigned_abs()) {
+                let p = Value::from(Integer::from(p));
+                factors.extend(std::iter::repeat_n(p, k as usize));
+            }
+            Ok(Value::List(factors))
+        }
+        "powmod" => {
+            let [b, e, m] = unpack_ints(name, args)?;
+            modular::powmod(b, e, modulus(name, m)?)
+                .map(Value::from)
+                .ok_or_else(|| invalid(name, "base is not invertible"))
+        }
+        "invmod" => {
+            let [a, m] = unpack_ints(name, args)?;
+            modular::invmod(a, modulus(name, m)?)
+                .map(Value::from)
+                .ok_or_else(|| invalid(name, "value is not invertible"))
+        }
+        // List of residue and modulus pairs, e.g. `crt([(2, 3), (3, 5)])`, or the pairs flattened
+        "crt" => {
+            let mut flat = vec![];
+            for arg in elements(args) {
+                match arg {
+                    Value::List(pair) if pair.len() == 2 => flat.extend(pair),
+                    Value::List(_) => {
+                        return Err(invalid(name, "residue and modulus pairs expected"))
+                    }
+                    x => flat.push(x),
+                }
+            }
+            let args = non_empty(name, ints(name, flat)?)?;
+            if args.len() % 2 != 0 {
+                return Err(invalid(name, "residue and modulus pairs expected"));
+            }
+            let congruences = args
+                .chunks_exact(2)
+                .map(|pair| Ok((pair[0].clone(), modulus(name, pair[1].clone())?)))
+                .collect::<Result<_, _>>()?;
+            modular::crt(congruences)
+                .map(Value::from)
+                .ok_or_else(|| invalid(name, "congruences have no common solution"))
+        }
+        "isqrt" => {
+            let [x] = unpack_ints(name, args)?;
+            Ok(Integer::from(non_negative(name, x)?.floor_sqrt()).into())
+        }
+        "iroot" => {
+            let [x, n] = unpack_ints(name, args)?;
+            let n = match u64::try_from(&n) {
+                Ok(n) if n > 0 => n,
+                _ => return Err(invalid(name, "root degree must be positive")),
+            };
+            if x < 0 && n % 2 == 0 {
+                return Err(invalid(name, "even root of a negative number"));
+            }
+            Ok(x.floor_root(n).into())
+        }
+        "ilog2" => {
+            let [x] = unpack_ints(name, args)?;
+            Ok(Value::from(Integer::from(
+                positive(name, x)?.floor_log_base_2(),
+            )))
+        }
+        "ilog10" => {
+            let [x] = unpack_ints(name, args)?;
+            let log = positive(name, x)?.floor_log_base(&Natural::from(10u32));
+            Ok(Value::from(Integer::from(log)))
+        }
+        "ilog" => {
+            let [x, base] = unpack_ints(name, args)?;
+            let x = positive(name, x)?;
+            let base = match Natural::try_from(base) {
+                Ok(base) if base >= 2u32 => base,
+                _ => return Err(invalid(name, "logarithm base must be at least 2")),
+            };
+            Ok(Value::from(Integer::from(x.floor_log_base(&base))))
+        }
+        "C" => {
+            let [n, k] = unpack_ints(name, args)?;
+            // C(n, k) = C(n, n - k) < n**k
+            if k > 0u32 {
+                let k = if n >= k {
+                    (&n - &k).min(k.clone())
+                } else {
+                    k.clone()
+                };
+                let bits = ((&n).unsigned_abs() + (&k).unsigned_abs()).significant_bits();
+                check_size(name, k * Integer::from(bits))?;
+            }
+            Ok(combinatorics::binomial(n, k).into())
+        }
+        "P" => {
+            let [n, k] = unpack_ints(name, args)?;
+            let to_u64 = |x: Integer| {
+                u64::try_from(&x).map_err(|_| invalid(name, "non-negative integer expected"))
+            };
+            Ok(combinatorics::permutations(to_u64(n)?, to_u64(k)?).into())
+        }
+        "fib" => {
+            let [n] = unpack_ints(name, args)?;
+            // fib(n) grows by about 0.7 bits with each n
+            check_size(name, (&n).abs() * Integer::from(7) / Integer::from(10))?;
+            Ok(combinatorics::fibonacci(n).into())
+        }
+        "popcount" => {
+            let [x] = unpack_ints(name, args)?;
+            programmer::popcount(&x)
+                .map(|n| Value::from(Integer::from(n)))
+                .ok_or_else(|| invalid(name, "argument must not be negative"))
+        }
+        "bit" => {
+            let [x, n] = unpack_ints(name, args)?;
+            Ok(x.get_bit(bit_index(name, n)?).into())
+        }
+        "setbit" => {
+            let [x, n] = unpack_ints(name, args)?;
+            let n = bit_index(name, n)?;
+            if n >= MAX_BITS {
+                return Err(invalid(name, "bit index is too large"));
+            }
+            Ok(programmer::with_bit(x, n, true).into())
+        }
+        "clearbit" => {
+            let [x, n] = unpack_ints(name, args)?;
+            let n = bit_index(name, n)?;
+            if n >= MAX_BITS {
+                return Err(invalid(name, "bit index is too large"));
+            }
+            Ok(programmer::with_bit(x, n, false).into())
+        }
+        "bits" => {
+            let [x, high, low] = unpack_ints(name, args)?;
+            let (high, low) = (bit_index(name, high)?, bit_index(name, low)?);
+            if high < low {
+                return Err(invalid(name, "high bit index is less than low bit index"));
+            }
+            Ok(Integer::from(programmer::bits(&x, high, low)).into())
+        }
+        "bswap16" => {
+            let [x] = unpack_ints(name, args)?;
+            Ok(Value::from(Integer::from(
+                u16::wrapping_from(&x).swap_bytes(),
+            )))
+        }
+        "bswap32" => {
+            let [x] = unpack_ints(name, args)?;
+            Ok(Value::from(Integer::from(
+                u32::wrapping_from(&x).swap_bytes(),
+            )))
+        }
+        "bswap64" => {
+            let [x] = unpack_ints(name, args)?;
+            Ok(Value::from(Integer::from(
+                u64::wrapping_from(&x).swap_bytes(),
+            )))
+        }
+        "clz" => {
+            let [x, w] = unpack_ints(name, args)?;
+            Ok(Value::from(Integer::from(programmer::clz(
+                &x,
+                width(name, w)?,
+            ))))
+        }
+        "ctz" => {
+            let [x] = unpack_ints(name, args)?;
+            x.trailing_zeros()
+                .map(|n| Value::from(Integer::from(n)))
+                .ok_or_else(|| invalid(name, "argument must not be zero"))
+        }
+        "rotl" => {
+            let [x, n, w] = unpack_ints(name, args)?;
+            Ok(Integer::from(programmer::rotl(&x, &n, width(name, w)?)).into())
+        }
+        "clmul" | "gf2_mod" | "gf2_inv" => {
+            let [a, b] = unpack_ints(name, args)?;
+            let (Ok(a), Ok(b)) = (Natural::try_from(a), Natural::try_from(b)) else {
+                return Err(invalid(name, "arguments must not be negative"));
+            };
+            if name == "clmul" {
+                return Ok(Integer::from(gf2::clmul(&a, &b)).into());
+            }
+            if b == 0u32 {
+                return Err(invalid(name, "modulus must be positive"));
+            }
+            let result = match name {
+                "gf2_mod" => Some(gf2::div_rem(&a, &b).1),
+                _ => gf2::inverse(&a, &b),
+            };
+            result
+                .map(|x| Integer::from(x).into())
+                .ok_or_else(|| invalid(name, "value is not invertible"))
+        }
+        "crc32" => Ok(Value::from(i64::from(checksum::crc32(&bytes(name, args)?)))),
+        "crc16" => Ok(Value::from(i64::from(checksum::crc16(&bytes(name, args)?)))),
+        "adler32" => Ok(Value::from(i64::from(checksum::adler32(&bytes(
+            name, args,
+        )?)))),
+        "rotr" => {
+            let [x, n, w] = unpack_ints(name, args)?;
+            Ok(Integer::from(programmer::rotl(&x, &-n, width(name, w)?)).into())
+        }
+        "f32bits" => {
+            let [x] = unpack_reals(name, args)?;
+            Ok(Value::from(Integer::from(programmer::f32_bits(&x))))
+        }
+        "f64bits" => {
+            let [x] = unpack_reals(name, args)?;
+            Ok(Value::from(Integer::from(programmer::f64_bits(&x))))
+        }
+        "bitsf32" => {
+            let [bits] = unpack_ints(name, args)?;
+            let bits =
+                u32::try_from(&bits).map_err(|_| invalid(name, "32-bit pattern expected"))?;
+            programmer::from_f32_bits(bits)
+                .map(exact)
+                .ok_or_else(|| invalid(name, "bit pattern is not a finite number"))
+        }
+        "bitsf64" => {
+            let [bits] = unpack_ints(name, args)?;
+            let bits =
+                u64::try_from(&bits).map_err(|_| invalid(name, "64-bit pattern expected"))?;
+            programmer::from_f64_bits(bits)
+                .map(exact)
+                .ok_or_else(|| invalid(name, "bit pattern is not a finite number"))
+        }
+        "humansize" => {
+            let [x] = unpack_reals(name, args)?;
+            Ok(Value::Str(bytesize::humanize(&x).into()))
+        }
+        "roman" => {
+            let [x] = unpack_ints(name, args)?;
+            u32::try_from(&x)
+                .ok()
+                .and_then(roman::format)
+                .map(|r| Value::Str(r.into()))
+                .ok_or_else(|| invalid(name, "argument must be between 1 and 3999"))
+        }
+        // The number is unchanged, the result is displayed as a fraction or a decimal, see
+        // `Fractions::of_expr`
+        "frac" | "dec" => {
+            let [x] = unpack_reals(name, args)?;
+            Ok(exact(x))
+        }
+        "cfrac" => {
+            let [x, n] = unpack(name, args)?;
+            let x = real_arg(name, x)?;
+            let n = usize::try_from(&int(name, n)?)
+                .map_err(|_| invalid(name, "number of terms must not be negative"))?;
+            let terms = rational::continued_fraction(x, n);
+            Ok(Value::List(terms.into_iter().map(Value::from).collect()))
+        }
+        // Terms are given as arguments or as a list, e.g. one returned by `cfrac`
+        "fromcfrac" => {
+            let terms = non_empty(name, ints(name, elements(args))?)?;
+            rational::from_continued_fraction(&terms)
+                .map(exact)
+                .ok_or_else(|| invalid(name, "division by zero"))
+        }
+        "approx" => {
+            let [x, max] = unpack(name, args)?;
+            let x = real_arg(name, x)?;
+            let max = int(name, max)?;
+            let max = Natural::try_from(max)
+                .ok()
+                .filter(|max| *max != 0u32)
+                .ok_or_else(|| invalid(name, "maximum denominator must be positive"))?;
+            Ok(exact(rational::approximate(&x, &max)))
+        }
+        "sigfig" => {
+            let [x, n] = unpack(name, args)?;
+            let x = real_arg(name, x)?;
+            let digits = u64::try_from(&int(name, n)?)
+                .ok()
+                .filter(|digits| *digits > 0)
+                .ok_or_else(|| invalid(name, "number of figures must be positive"))?;
+            // A decimal digit takes about 3.3 bits
+            check_size(
+                name,
+                Integer::from(digits) * Integer::from(10) / Integer::from(3),
+            )?;
+            Ok(exact(format::significant(&x, digits, Rounding::default())))
+        }
+        "re" | "im" | "conj" | "absc" => {
+            let [x] = unpack(name, args)?;
+            let c = match x {
+                Value::Complex(c) => c,
+                x => Complex::new(real_arg(name, x)?, Rational::from(0)),
+            };
+            Ok(match name {
+                "re" => exact(c.re),
+                "im" => exact(c.im),
+                "conj" => c.conj().into(),
+                _ => exact(c.abs(precision)),
+            })
+        }
+        "list" => Ok(Value::List(args)),
+        "len" => Ok(Value::from(Integer::from(elements(args).len()))),
+        "sum" => Ok(exact(reals(name, elements(args))?.into_iter().sum())),
+        "product" => Ok(exact(reals(name, elements(args))?.into_iter().product())),
+        "mean" => {
+            let values = non_empty(name, reals(name, elements(args))?)?;
+            Ok(exact(stats::mean(&values)))
+        }
+        "median" => {
+            let values = non_empty(name, reals(name, elements(args))?)?;
+            Ok(exact(stats::percentile(values, &Rational::from(50))))
+        }
+        "variance" | "stddev" => {
+            let values = reals(name, elements(args))?;
+            if values.len() < 2 {
+                return Err(invalid(name, "sample variance needs at least two values"));
+            }
+            let variance = stats::variance(&values);
+            if name == "variance" {
+                return Ok(exact(variance));
+            }
+            let stddev = real::sqrt(&variance, precision).expect("the variance is not negative");
+            Ok(exact(stddev))
+        }
+        "percentile" => {
+            let [values, p] = unpack(name, args)?;
+            let Value::List(values) = values else {
+                return Err(invalid(name, "list expected"));
+            };
+            let values = non_empty(name, reals(name, values)?)?;
+            let p = real_arg(name, p)?;
+            if !(Rational::from(0)..=Rational::from(100)).contains(&p) {
+                return Err(invalid(name, "percentile must be between 0 and 100"));
+            }
+            Ok(exact(stats::percentile(values, &p)))
+        }
+        "sort" => {
+            let mut values = numbers(name, elements(args))?;
+            values.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Ok(Value::List(values.into_iter().map(|(_, v)| v).collect()))
+        }
+        "transpose" | "det" | "inv" => {
+            let [m] = unpack(name, args)?;
+            let m = Matrix::from_value(&m).ok_or_else(|| invalid(name, "matrix expected"))?;
+            let result = match name {
+                "transpose" => Ok(m.transpose().into()),
+                "det" => m.determinant().map(exact),
+                _ => m.inverse().map(Value::from),
+            };
+            result.map_err(|e| match e {
+                MatrixError::Singular => invalid(name, "matrix is singular"),
+                _ => invalid(name, "square matrix expected"),
+            })
+        }
+        "poly" => Ok(Value::Poly(Polynomial::new(reals(name, elements(args))?))),
+        "polyval" => {
+            let [p, x] = unpack(name, args)?;
+            Ok(exact(polynomial_arg(name, p)?.eval(&real_arg(name, x)?)))
+        }
+        "polydiv" => {
+            let [a, b] = unpack(name, args)?;
+            let (a, b) = (polynomial_arg(name, a)?, polynomial_arg(name, b)?);
+            let (quotient, remainder) = a
+                .div_rem(&b)
+                .ok_or_else(|| invalid(name, "division by zero"))?;
+            Ok(Value::List(vec![
+                Value::Poly(quotient),
+                Value::Poly(remainder),
+            ]))
+        }
+        "coeffs" => {
+            let [p] = unpack(name, args)?;
+            let p = polynomial_arg(name, p)?;
+            Ok(Value::List(p.coefficients().cloned().map(exact).collect()))
+        }
+        "now" => {
+            let [] = unpack(name, args)?;
+            Ok(Value::from(Integer::from(unix_time())))
+        }
+        "unix" => {
+            let [x] = unpack(name, args)?;
+            match x {
+                Value::Int(timestamp) => Ok(timestamp.into()),
+                Value::Str(date) => datetime::parse(&date)
+                    .map(|t| Value::from(Integer::from(t)))
+                    .ok_or_else(|| invalid(name, "invalid date")),
+                _ => Err(invalid(name, "date expected")),
+            }
+        }
+        "u8" => reinterpret(name, args, 8, false),
+        "u16" => reinterpret(name, args, 16, false),
+        "u32" => reinterpret(name, args, 32, false),
+        "u64" => reinterpret(name, args, 64, false),
+        "u128" => reinterpret(name, args, 128, false),
+        "i8" => reinterpret(name, args, 8, true),
+        "i16" => reinterpret(name, args, 16, true),
+        "i32" => reinterpret(name, args, 32, true),
+        "i64" => reinterpret(name, args, 64, true),
+        "i128" => reinterpret(name, args, 128, true),
+        _ => Err(FunctionError::UnknownFunction(name.into())),
+    }
+}
+
+/// Exact result, integral values are returned as integers
+pub fn exact(x: Rational) -> Value {
+    match Integer::try_from(&x) {
+        Ok(i) => Value::Int(i),
+        Err(_) => Value::Real(x),
+    }
+}
+
+/// Seconds since the Unix epoch
+#[cfg(not(target_arch = "wasm32"))]
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Seconds since the Unix epoch from the JavaScript clock, WebAssembly has no system time
+#[cfg(target_arch = "wasm32")]
+fn unix_time() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+/// Reduce an integer modulo 2^width and interpret it as a signed or unsigned number
+fn reinterpret(
+    name: &str,
+    args: Vec<Value>,
+    width: u64,
+    signed: bool,
+) -> Result<Value, FunctionError> {
+    let [x] = unpack_ints(name, args)?;
+    Ok(if signed {
+        programmer::wrap_signed(&x, width).into()
+    } else {
+        Integer::from(programmer::wrap(&x, width)).into()
+    })
+}
+
+/// Convert arguments to a fixed-size array, checking the argument count
+fn unpack<T, const N: usize>(name: &str, args: Vec<T>) -> Result<[T; N], FunctionError> {
+    args.try_into()
+        .map_err(|args: Vec<T>| FunctionError::ArgumentCount {
+            name: name.into(),
+            expected: N,
+            got: args.len(),
+        })
+}
+
+fn unpack_ints<const N: usize>(
+    name: &str,
+    args: Vec<Value>,
+) -> Result<[Integer; N], FunctionError> {
+    unpack(name, ints(name, args)?)
+}
+
+fn unpack_reals<const N: usize>(
+    name: &str,
+    args: Vec<Value>,
+) -> Result<[Rational; N], FunctionError> {
+    unpack(name, reals(name, args)?)
+}
+
+/// Elements of a single list argument, or all arguments
+fn elements(args: Vec<Value>) -> Vec<Value> {
+    match <[Value; 1]>::try_from(args) {
+        Ok([Value::List(elements)]) => elements,
+        Ok(args) => args.into(),
+        Err(args) => args,
+    }
+}
+
+/// Byte values, of a list or of a single number in big-endian order, e.g. `0xdeadbeef`
+fn bytes(name: &str, args: Vec<Value>) -> Result<Vec<u8>, FunctionError> {
+    let args = match <[Value; 1]>::try_from(args) {
+        Ok([Value::List(list)]) => list,
+        Ok([x]) => {
+            let x = Natural::try_from(int(name, x)?)
+                .map_err(|_| invalid(name, "bytes must not be negative"))?;
+            let bytes: Vec<u8> = x.to_power_of_2_digits_desc(8);
+            return Ok(if bytes.is_empty() { vec![0] } else { bytes });
+        }
+        Err(args) => args,
+    };
+    ints(name, args)?
+        .iter()
+        .map(|byte| u8::try_from(byte).map_err(|_| invalid(name, "bytes must be from 0 to 255")))
+        .collect()
+}
+
+/// Polynomial, or the list of its coefficients, or a constant
+fn polynomial_arg(name: &str, v: Value) -> Result<Polynomial, FunctionError> {
+    match v {
+        Value::Poly(p) => Ok(p),
+        Value::List(coefficients) => Ok(Polynomial::new(reals(name, coefficients)?)),
+        v => Ok(Polynomial::new(vec![real_arg(name, v)?])),
+    }
+}
+
+fn reals(name: &str, args: Vec<Value>) -> Result<Vec<Rational>, FunctionError> {
+    args.into_iter().map(|v| real_arg(name, v)).collect()
+}
+
+fn real_arg(name: &str, v: Value) -> Result<Rational, FunctionError> {
+    v.to_rational()
+        .ok_or_else(|| invalid(name, "number expected"))
+}
+
+/// Numeric arguments paired with their exact values for comparison
+fn numbers(name: &str, args: Vec<Value>) -> Result<Vec<(Rational, Value)>, FunctionError> {
+    args.into_iter()
+        .map(|v| Ok((real_arg(name, v.clone())?, v)))
+        .collect()
+}
+
+fn ints(name: &str, args: Vec<Value>) -> Result<Vec<Integer>, FunctionError> {
+    args.into_iter().map(|v| int(name, v)).collect()
+}
+
+fn int(name: &str, v: Value) -> Result<Integer, FunctionError> {
+    v.into_int()
+        .ok_or_else(|| invalid(name, "integer expected"))
+}
+
+fn non_negative(name: &str, x: Integer) -> Result<Natural, FunctionError> {
+    Natural::try_from(x).map_err(|_| invalid(name, "argument must not be negative"))
+}
+
+fn positive(name: &str, x: Integer) -> Result<Natural, FunctionError> {
+    match Natural::try_from(x) {
+        Ok(x) if x != 0u32 => Ok(x),
+        _ => Err(invalid(name, "argument must be positive")),
+    }
+}
+
+fn bit_index(name: &str, n: Integer) -> Result<u64, FunctionError> {
+    u64::try_from(&n).map_err(|_| invalid(name, "bit index must not be negative"))
+}
+
+/// Bit width of fixed-size integers
+fn width(name: &str, w: Integer) -> Result<u64, FunctionError> {
+    match u64::try_from(&w) {
+        Ok(w) if w > MAX_BITS => Err(invalid(name, "width is too large")),
+        Ok(w) if w > 0 => Ok(w),
+        _ => Err(invalid(name, "width must be positive")),
+    }
+}
+
+/// Fail before computing a result of about `estimate` bits if it's larger than [`MAX_BITS`]
+fn check_size(name: &str, estimate: Integer) -> Result<(), FunctionError> {
+    if estimate > MAX_BITS {
+        return Err(invalid(name, "result is too large"));
+    }
+    Ok(())
+}
+
+fn modulus(name: &str, m: Integer) -> Result<Natural, FunctionError> {
+    match Natural::try_from(m) {
+        Ok(m) if m != 0u32 => Ok(m),
+        _ => Err(invalid(name, "modulus must be positive")),
+    }
+}
+
+fn non_empty<T>(name: &str, args: Vec<T>) -> Result<Vec<T>, FunctionError> {
+    if args.is_empty() {
+        Err(FunctionError::NotEnoughArguments(name.into()))
+    } else {
+        Ok(args)
+    }
+}
+
+fn domain(name: &str) -> impl Fn(RealError) -> FunctionError + '_ {
+    move |error| FunctionError::Domain {
+        name: name.into(),
+        error,
+    }
+}
+
+fn invalid(name: &str, reason: &'static str) -> FunctionError {
+    FunctionError::InvalidArgument {
+        name: name.into(),
+        reason,
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum FunctionError {
+    #[error("Unknown function: {0}")]
+    UnknownFunction(CompactString),
+    #[error("Function {name} expects {expected} argument(s), got {got}")]
+    ArgumentCount {
+        name: CompactString,
+        expected: usize,
+        got: usize,
+    },
+    #[error("Function {0} expects at least one argument")]
+    NotEnoughArguments(CompactString),
+    #[error("Invalid argument for {name}: {reason}")]
+    InvalidArgument {
+        name: CompactString,
+        reason: &'static str,
+    },
+    #[error("Invalid argument for {name}: {error}")]
+    Domain {
+        name: CompactString,
+        error: RealError,
+    },
+}
+
+impl FunctionError {
+    /// Name of the function that caused the error
+    pub fn name(&self) -> &str {
+        match self {
+            FunctionError::UnknownFunction(name)
+            | FunctionError::NotEnoughArguments(name)
+            | FunctionError::ArgumentCount { name, .. }
+            | FunctionError::InvalidArgument { name, .. }
+            | FunctionError::Domain { name, .. } => name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: Vec<Value>) -> Result<Value, FunctionError> {
+        super::call(name, args, 20)
+    }
+
+    fn values(v: &[i64]) -> Vec<Value> {
+        v.iter().copied().map(Value::from).collect()
+    }
+
+    #[test]
+    fn test_basic() {
+        assert_eq!(call("abs", values(&[-5])), Ok(Value::from(5)));
+        assert_eq!(call("sign", values(&[-5])), Ok(Value::from(-1)));
+        assert_eq!(call("sign", values(&[0])), Ok(Value::from(0)));
+        assert_eq!(call("min", values(&[3, -1, 2])), Ok(Value::from(-1)));
+        assert_eq!(call("max", values(&[3, -1, 2])), Ok(Value::from(3)));
+        assert_eq!(call("clamp", values(&[15, 0, 10])), Ok(Value::from(10)));
+        assert_eq!(call("clamp", values(&[-5, 0, 10])), Ok(Value::from(0)));
+    }
+
+    #[test]
+    fn test_names() {
+        for name in CONSTANTS {
+            assert!(constant(name, 5).is_some(), "{name}");
+        }
+        for name in FUNCTIONS {
+            let res = call(name, vec![]);
+            assert_ne!(
+                res,
+                Err(FunctionError::UnknownFunction(name.into())),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            call("abs", values(&[1, 2])),
+            Err(FunctionError::ArgumentCount {
+                name: "abs".into(),
+                expected: 1,
+                got: 2
+            })
+        );
+        assert_eq!(
+            call("max", vec![]),
+            Err(FunctionError::NotEnoughArguments("max".into()))
+        );
+        assert!(matches!(
+            call("clamp", values(&[1, 10, 0])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        assert_eq!(
+            call("foo", vec![]),
+            Err(FunctionError::UnknownFunction("foo".into()))
+        );
+    }
+
+    #[test]
+    fn test_number_theory() {
+        assert_eq!(call("gcd", values(&[12, -18, 27])), Ok(Value::from(3)));
+        assert_eq!(call("lcm", values(&[4, 6, 10])), Ok(Value::from(60)));
+        assert_eq!(call("factorial", values(&[10])), Ok(Value::from(3628800)));
+        assert_eq!(call("isprime", values(&[97])), Ok(Value::from(1)));
+        assert_eq!(call("isprime", values(&[-7])), Ok(Value::from(0)));
+        assert_eq!(call("nextprime", values(&[97])), Ok(Value::from(101)));
+        assert_eq!(
+            call("factor", values(&[-360])),
+            Ok(Value::List(values(&[-1, 2, 2, 2, 3, 3, 5])))
+        );
+        assert_eq!(call("factor", values(&[1])), Ok(Value::List(vec![])));
+    }
+
+    #[test]
+    fn test_roots_and_logarithms() {
+        assert_eq!(call("isqrt", values(&[99])), Ok(Value::from(9)));
+        assert_eq!(call("iroot", values(&[-30, 3])), Ok(Value::from(-4)));
+        assert_eq!(call("ilog2", values(&[1024])), Ok(Value::from(10)));
+        assert_eq!(call("ilog10", values(&[999])), Ok(Value::from(2)));
+        assert_eq!(call("ilog", values(&[80, 3])), Ok(Value::from(3)));
+        assert!(matches!(
+            call("isqrt", values(&[-1])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            call("iroot", values(&[-16, 4])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            call("ilog2", values(&[0])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn test_combinatorics() {
+        assert_eq!(call("C", values(&[52, 5])), Ok(Value::from(2598960)));
+        assert_eq!(call("P", values(&[10, 3])), Ok(Value::from(720)));
+        assert_eq!(
+            call("fib", values(&[90])),
+            Ok(Value::from(2880067194370816120))
+        );
+        assert_eq!(
+            call("C", values(&[1_000_000_000, 999_999_999])),
+            Ok(1_000_000_000.into())
+        );
+        let too_large = |name: &str| Err(invalid(name, "result is too large"));
+        assert_eq!(call("C", values(&[1 << 40, 1 << 20])), too_large("C"));
+        assert_eq!(call("fib", values(&[1 << 30])), too_large("fib"));
+        assert_eq!(call("fib", values(&[-(1 << 30)])), too_large("fib"));
+        assert_eq!(
+            call("factorial", values(&[1 << 30])),
+            too_large("factorial")
+        );
+        assert_eq!(call("sigfig", values(&[1, 1 << 30])), too_large("sigfig"));
+    }
+
+    #[test]
+    fn test_programmer() {
+        assert_eq!(
+            call("clmul", values(&[0x57, 0x83])),
+            Ok(Value::from(0x2b79))
+        );
+        assert_eq!(
+            call("gf2_mod", values(&[0x2b79, 0x11b])),
+            Ok(Value::from(0xc1))
+        );
+        assert_eq!(
+            call("gf2_inv", values(&[0x53, 0x11b])),
+            Ok(Value::from(0xca))
+        );
+        assert!(call("gf2_inv", values(&[0b11, 0b101])).is_err());
+        assert!(call("gf2_mod", values(&[1, 0])).is_err());
+        assert!(call("clmul", values(&[-1, 1])).is_err());
+        let check = || values(&[0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39]);
+        assert_eq!(call("crc32", check()), Ok(Value::from(0xcbf43926)));
+        assert_eq!(
+            call("crc16", vec![Value::List(check())]),
+            Ok(Value::from(0xbb3d))
+        );
+        assert_eq!(
+            call(
+                "adler32",
+                vec![Integer::from(0x313233343536373839u128).into()]
+            ),
+            Ok(Value::from(0x091e01de))
+        );
+        assert_eq!(call("crc32", values(&[0])), Ok(Value::from(0xd202ef8d)));
+        assert!(call("crc32", values(&[1, 256])).is_err());
+        assert_eq!(call("popcount", values(&[0xf0f])), Ok(Value::from(8)));
+        assert_eq!(call("bit", values(&[0b100, 2])), Ok(Value::from(1)));
+        assert_eq!(call("bit", values(&[-1, 100])), Ok(Value::from(1)));
+        assert_eq!(call("setbit", values(&[0, 10])), Ok(Value::from(1024)));
+        assert_eq!(call("clearbit", values(&[0xff, 0])), Ok(Value::from(0xfe)));
+        assert_eq!(
+            call("bits", values(&[0x1234, 11, 4])),
+            Ok(Value::from(0x23))
+        );
+        assert_eq!(call("bswap16", values(&[0x1234])), Ok(Value::from(0x3412)));
+        assert_eq!(
+            call("bswap32", values(&[0x12345678])),
+            Ok(Value::from(0x78563412))
+        );
+        assert!(matches!(
+            call("bits", values(&[0x1234, 4, 11])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        assert_eq!(call("clz", values(&[0xff, 32])), Ok(Value::from(24)));
+        assert_eq!(call("ctz", values(&[0x80])), Ok(Value::from(7)));
+        assert_eq!(call("rotl", values(&[0x81, 1, 8])), Ok(Value::from(0x03)));
+        assert_eq!(call("rotr", values(&[0x81, 1, 8])), Ok(Value::from(0xc0)));
+        // Would need more memory than there is
+        assert!(matches!(
+            call("setbit", values(&[0, 1 << 40])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            call("rotl", values(&[1, 1, 1 << 40])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        assert_eq!(call("i32", values(&[0xfffffff0])), Ok(Value::from(-16)));
+        assert_eq!(call("u8", values(&[-1])), Ok(Value::from(255)));
+        assert_eq!(call("i8", values(&[200])), Ok(Value::from(-56)));
+        assert_eq!(
+            call("humansize", values(&[3 << 20])),
+            Ok(Value::Str("3 MiB".into()))
+        );
+        assert_eq!(
+            call("roman", values(&[2024])),
+            Ok(Value::Str("MMXXIV".into()))
+        );
+        assert!(call("roman", values(&[0])).is_err());
+        let half = || vec![Value::Real(Rational::from_signeds(-7, 2))];
+        assert_eq!(call("frac", half()), Ok(half()[0].clone()));
+        assert_eq!(call("dec", half()), Ok(half()[0].clone()));
+        assert_eq!(call("frac", values(&[3])), Ok(Value::from(3)));
+        let terms = call("cfrac", vec![half()[0].clone(), Value::from(5)]);
+        assert_eq!(terms, Ok(Value::List(values(&[-4, 2]))));
+        assert_eq!(
+            call("fromcfrac", vec![terms.unwrap()]),
+            Ok(half()[0].clone())
+        );
+        assert!(call("approx", values(&[1, 0])).is_err());
+        assert_eq!(call("sigfig", values(&[12345, 2])), Ok(Value::from(12000)));
+        let sigfig = call("sigfig", vec![half()[0].clone(), Value::from(1)]);
+        assert_eq!(sigfig, Ok(Value::from(-4)));
+        assert!(call("sigfig", values(&[1, 0])).is_err());
+        let list = || vec![Value::List(values(&[3, -1, 2]))];
+        assert_eq!(call("len", list()), Ok(Value::from(3)));
+        assert_eq!(call("sum", list()), Ok(Value::from(4)));
+        assert_eq!(
+            call("mean", values(&[1, 2])),
+            Ok(Value::Real(Rational::from_signeds(3, 2)))
+        );
+        assert_eq!(call("sort", list()), Ok(Value::List(values(&[-1, 2, 3]))));
+        assert_eq!(call("sum", vec![Value::List(vec![])]), Ok(Value::from(0)));
+        assert_eq!(call("product", list()), Ok(Value::from(-6)));
+        assert_eq!(call("product", vec![]), Ok(Value::from(1)));
+        assert!(call("mean", vec![Value::List(vec![])]).is_err());
+        assert_eq!(call("median", list()), Ok(Value::from(2)));
+        assert_eq!(
+            call("variance", list()),
+            Ok(Value::Real(Rational::from_signeds(13, 3)))
+        );
+        assert_eq!(
+            call("stddev", values(&[1, 3])),
+            Ok(Value::Real(real::sqrt(&Rational::from(2), 20).unwrap()))
+        );
+        assert_eq!(
+            call("stddev", values(&[1])),
+            Err(invalid(
+                "stddev",
+                "sample variance needs at least two values"
+            ))
+        );
+        assert_eq!(
+            call("percentile", vec![list().remove(0), Value::from(75)]),
+            Ok(Value::Real(Rational::from_signeds(5, 2)))
+        );
+        assert!(call("percentile", vec![list().remove(0), Value::from(101)]).is_err());
+        let c = || {
+            vec![Value::Complex(Complex::new(
+                Rational::from(3),
+                Rational::from(-4),
+            ))]
+        };
+        assert_eq!(call("re", c()), Ok(Value::from(3)));
+        assert_eq!(call("im", c()), Ok(Value::from(-4)));
+        assert_eq!(
+            call("conj", c()),
+            Ok(Value::Complex(Complex::new(
+                Rational::from(3),
+                Rational::from(4)
+            )))
+        );
+        assert_eq!(call("absc", c()), Ok(Value::from(5)));
+        assert_eq!(call("abs", c()), Ok(Value::from(5)));
+        assert_eq!(call("im", values(&[7])), Ok(Value::from(0)));
+        assert_eq!(
+            call("fromcfrac", values(&[3, 7])),
+            Ok(Value::Real(Rational::from_signeds(22, 7)))
+        );
+        assert_eq!(call("f32bits", values(&[1])), Ok(Value::from(0x3f800000)));
+        assert_eq!(
+            call("bitsf32", values(&[0x40490fdb])).map(|v| v.to_string()),
+            Ok("3.1415927410125732421875".into())
+        );
+        assert_eq!(
+            call("bitsf64", values(&[0x4000000000000000])),
+            Ok(Value::from(2))
+        );
+        assert!(matches!(
+            call("bitsf64", values(&[-1])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            call("rotl", values(&[1, 1, 0])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            call("ctz", values(&[0])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn test_time() {
+        let date = |s: &str| vec![Value::Str(s.into())];
+        assert_eq!(
+            call("unix", date("2024-05-01T12:00")),
+            Ok(Value::from(1714564800))
+        );
+        assert!(matches!(
+            call("unix", date("2024-02-30")),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        assert!(matches!(call("now", vec![]), Ok(Value::Int(_))));
+    }
+
+    #[test]
+    fn test_real() {
+        let real = |n, d| Value::Real(Rational::from_signeds(n, d));
+        assert_eq!(call("sqrt", values(&[144])), Ok(Value::from(12)));
+        assert_eq!(call("sqrt", vec![real(9, 4)]), Ok(real(3, 2)));
+        assert_eq!(
+            call("sqrt", values(&[2])).map(|v| v.to_string()),
+            Ok("1.41421356237309504880168872421".into())
+        );
+        assert_eq!(
+            call("log", values(&[1000])).map(|v| v.to_string()),
+            Ok("3".into())
+        );
+        assert_eq!(call("max", vec![real(5, 2), 2.into()]), Ok(real(5, 2)));
+        assert_eq!(call("abs", vec![real(-5, 2)]), Ok(real(5, 2)));
+        assert_eq!(
+            call("ln", values(&[-1])),
+            Err(FunctionError::Domain {
+                name: "ln".into(),
+                error: RealError::NonPositive
+            })
+        );
+    }
+
+    #[test]
+    fn test_modular() {
+        assert_eq!(call("powmod", values(&[3, 200, 13])), Ok(Value::from(9)));
+        assert_eq!(call("invmod", values(&[3, 7])), Ok(Value::from(5)));
+        assert_eq!(
+            call("crt", values(&[2, 3, 3, 5, 2, 7])),
+            Ok(Value::from(23))
+        );
+        assert!(matches!(
+            call("powmod", values(&[3, 2, 0])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            call("crt", values(&[2, 3, 3])),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+        let pairs = |pairs: &[[i64; 2]]| {
+            let pairs = pairs.iter().map(|pair| Value::List(values(pair))).collect();
+            vec![Value::List(pairs)]
+        };
+        assert_eq!(
+            call("crt", pairs(&[[2, 3], [3, 5], [2, 7]])),
+            Ok(Value::from(23))
+        );
+        assert!(matches!(
+            call(
+                "crt",
+                vec![Value::List(vec![Value::List(values(&[2, 3, 5]))])]
+            ),
+            Err(FunctionError::InvalidArgument { .. })
+        ));
+    }
+}
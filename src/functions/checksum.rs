@@ -0,0 +1,42 @@
+/// CRC-32 as used by zlib and Ethernet, reflected polynomial `0xedb88320`
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// CRC-16/ARC, reflected polynomial `0xa001` with a zero initial value
+pub fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xa001 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// Adler-32 as used by zlib
+pub fn adler32(bytes: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + u32::from(byte)) % MOD;
+        (a, (b + a) % MOD)
+    });
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_values() {
+        let check = b"123456789";
+        assert_eq!(crc32(check), 0xcbf43926);
+        assert_eq!(crc16(check), 0xbb3d);
+        assert_eq!(adler32(check), 0x091e01de);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(adler32(b""), 1);
+    }
+}
@@ -0,0 +1,74 @@
+use malachite::num::arithmetic::traits::{BinomialCoefficient, Parity};
+use malachite::num::basic::traits::{One, Zero};
+use malachite::{Integer, Natural};
+
+/// Number of `k`-element subsets of an `n`-element set, generalized to negative `n`
+pub fn binomial(n: Integer, k: Integer) -> Integer {
+    if k < 0 {
+        return Integer::ZERO;
+    }
+    Integer::binomial_coefficient(n, k)
+}
+
+/// Number of ordered `k`-element arrangements of `n` elements, `n! / (n - k)!`
+pub fn permutations(n: u64, k: u64) -> Integer {
+    if k > n {
+        return Integer::ZERO;
+    }
+    let product = (n - k + 1..=n).fold(Natural::ONE, |acc, i| acc * Natural::from(i));
+    Integer::from(product)
+}
+
+/// `n`-th Fibonacci number, extended to negative indices as `F(-n) = (-1)^(n + 1) * F(n)`
+pub fn fibonacci(n: Integer) -> Integer {
+    let negative = n < 0;
+    let n = n.unsigned_abs_ref().clone();
+    let (f, _) = fibonacci_pair(&n);
+    if negative && n.even() {
+        -f
+    } else {
+        f
+    }
+}
+
+/// `(F(n), F(n + 1))` using the fast doubling method
+fn fibonacci_pair(n: &Natural) -> (Integer, Integer) {
+    if *n == 0u32 {
+        return (Integer::ZERO, Integer::ONE);
+    }
+    let (a, b) = fibonacci_pair(&(n >> 1u64));
+    // F(2k) = F(k) * (2 * F(k + 1) - F(k))
+    let c = &a * ((&b << 1u64) - &a);
+    // F(2k + 1) = F(k)^2 + F(k + 1)^2
+    let d = &a * &a + &b * &b;
+    if n.odd() {
+        let sum = &c + &d;
+        (d, sum)
+    } else {
+        (c, d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fibonacci() {
+        let fib: Vec<Integer> = (-6..=10).map(|n| fibonacci(Integer::from(n))).collect();
+        let expected: Vec<Integer> = [-8, 5, -3, 2, -1, 1, 0, 1, 1, 2, 3, 5, 8, 13, 21, 34, 55]
+            .into_iter()
+            .map(Integer::from)
+            .collect();
+        assert_eq!(fib, expected);
+    }
+
+    #[test]
+    fn test_binomial() {
+        assert_eq!(binomial(5.into(), 2.into()), 10);
+        assert_eq!(binomial(5.into(), 6.into()), 0);
+        assert_eq!(binomial(5.into(), (-1).into()), 0);
+        assert_eq!(permutations(5, 2), 20);
+        assert_eq!(permutations(5, 6), 0);
+    }
+}
@@ -0,0 +1,76 @@
+use malachite::num::basic::traits::{One, Zero};
+use malachite::num::logic::traits::{BitIterable, SignificantBits};
+use malachite::Natural;
+
+/// Carry-less product, the product of polynomials over GF(2) whose coefficients are the bits
+pub fn clmul(a: &Natural, b: &Natural) -> Natural {
+    let mut product = Natural::ZERO;
+    for (i, bit) in b.bits().enumerate() {
+        if bit {
+            product ^= a << (i as u64);
+        }
+    }
+    product
+}
+
+/// Quotient and remainder of the polynomial division, `m` is not zero
+pub fn div_rem(a: &Natural, m: &Natural) -> (Natural, Natural) {
+    let degree = m.significant_bits();
+    let mut quotient = Natural::ZERO;
+    let mut rest = a.clone();
+    while rest.significant_bits() >= degree {
+        let shift = rest.significant_bits() - degree;
+        rest ^= m << shift;
+        quotient ^= Natural::ONE << shift;
+    }
+    (quotient, rest)
+}
+
+/// Inverse of `a` modulo `m`, `None` if they have a common factor
+pub fn inverse(a: &Natural, m: &Natural) -> Option<Natural> {
+    // Extended Euclidean algorithm, only the coefficients of `a` are needed
+    let (mut r0, mut r1) = (m.clone(), div_rem(a, m).1);
+    let (mut t0, mut t1) = (Natural::ZERO, Natural::ONE);
+    while r1 != 0u32 {
+        let (q, r) = div_rem(&r0, &r1);
+        let t = t0 ^ clmul(&q, &t1);
+        (r0, r1) = (r1, r);
+        (t0, t1) = (t1, t);
+    }
+    (r0 == 1u32).then(|| div_rem(&t0, m).1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(x: u32) -> Natural {
+        Natural::from(x)
+    }
+
+    #[test]
+    fn test_clmul() {
+        // (x + 1)(x + 1) = x**2 + 1
+        assert_eq!(clmul(&n(0b11), &n(0b11)), 0b101u32);
+        assert_eq!(clmul(&n(0x57), &n(0x83)), 0x2b79u32);
+        assert_eq!(clmul(&n(0x57), &n(0)), 0u32);
+    }
+
+    #[test]
+    fn test_div_rem() {
+        // The AES polynomial x**8 + x**4 + x**3 + x + 1
+        assert_eq!(div_rem(&n(0x2b79), &n(0x11b)).1, 0xc1u32);
+        let (q, r) = div_rem(&n(0b101), &n(0b11));
+        assert_eq!((q, r), (n(0b11), n(0)));
+        assert_eq!(div_rem(&n(0b1), &n(0b10)), (n(0), n(1)));
+    }
+
+    #[test]
+    fn test_inverse() {
+        assert_eq!(inverse(&n(0x53), &n(0x11b)), Some(n(0xca)));
+        assert_eq!(inverse(&n(1), &n(0x11b)), Some(n(1)));
+        assert_eq!(inverse(&n(0), &n(0x11b)), None);
+        // x + 1 divides x**2 + 1
+        assert_eq!(inverse(&n(0b11), &n(0b101)), None);
+    }
+}
@@ -0,0 +1,93 @@
+use malachite::num::arithmetic::traits::{ExtendedGcd, Mod, ModInverse, ModPow, UnsignedAbs};
+use malachite::num::basic::traits::{One, Zero};
+use malachite::{Integer, Natural};
+
+/// `b^e mod m` for a positive `m`. Negative exponents use the modular inverse of `b`, returns
+/// `None` if it doesn't exist.
+pub fn powmod(b: Integer, e: Integer, m: Natural) -> Option<Integer> {
+    if m == 1u32 {
+        return Some(Integer::ZERO);
+    }
+    let base = if e < 0 {
+        invmod(b, m.clone())?
+    } else {
+        b.mod_op(Integer::from(m.clone()))
+    };
+    let base = Natural::try_from(base).ok()?;
+    Some(Integer::from(base.mod_pow(e.unsigned_abs(), m)))
+}
+
+/// Inverse of `a` modulo a positive `m`, `None` if `a` and `m` are not coprime
+pub fn invmod(a: Integer, m: Natural) -> Option<Integer> {
+    if m == 1u32 {
+        return Some(Integer::ZERO);
+    }
+    let a = Natural::try_from(a.mod_op(Integer::from(m.clone()))).ok()?;
+    if a == 0u32 {
+        return None;
+    }
+    a.mod_inverse(m).map(Integer::from)
+}
+
+/// Solve a system of congruences `x = r (mod m)` given as `(r, m)` pairs with positive moduli.
+/// Moduli don't have to be coprime. Returns the smallest non-negative solution, `None` if the
+/// system is inconsistent.
+pub fn crt(congruences: Vec<(Integer, Natural)>) -> Option<Integer> {
+    let mut x = Integer::ZERO;
+    let mut modulus = Integer::ONE;
+
+    for (r, m) in congruences {
+        let m = Integer::from(m);
+        let (g, p, _) = (&modulus).extended_gcd(&m);
+        let g = Integer::from(g);
+        let diff = r - &x;
+        if &diff % &g != 0 {
+            return None;
+        }
+        let step = m / &g;
+        x += &modulus * (diff / &g * p).mod_op(&step);
+        modulus *= step;
+        x = x.mod_op(&modulus);
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_powmod() {
+        let res = powmod(Integer::from(4), Integer::from(13), Natural::from(497u32));
+        assert_eq!(res, Some(Integer::from(445)));
+
+        let res = powmod(Integer::from(-2), Integer::from(3), Natural::from(5u32));
+        assert_eq!(res, Some(Integer::from(2)));
+
+        // 3^-1 = 5 (mod 7)
+        let res = powmod(Integer::from(3), Integer::from(-2), Natural::from(7u32));
+        assert_eq!(res, Some(Integer::from(4)));
+
+        let res = powmod(Integer::from(2), Integer::from(-1), Natural::from(4u32));
+        assert_eq!(res, None);
+    }
+
+    #[test]
+    fn test_crt() {
+        let pairs = |v: &[(i64, u32)]| {
+            v.iter()
+                .map(|(r, m)| (Integer::from(*r), Natural::from(*m)))
+                .collect()
+        };
+
+        let res = crt(pairs(&[(2, 3), (3, 5), (2, 7)]));
+        assert_eq!(res, Some(Integer::from(23)));
+
+        // Non-coprime moduli
+        let res = crt(pairs(&[(1, 4), (3, 6)]));
+        assert_eq!(res, Some(Integer::from(9)));
+
+        let res = crt(pairs(&[(1, 4), (2, 6)]));
+        assert_eq!(res, None);
+    }
+}
@@ -0,0 +1,212 @@
+use malachite::num::arithmetic::traits::{
+    Factorial, Gcd, Lcm, ModMul, ModPow, ModSquare, UnsignedAbs,
+};
+use malachite::num::basic::traits::{One, Two, Zero};
+use malachite::{Integer, Natural};
+
+/// Miller-Rabin witnesses. Testing against the first 13 primes is deterministic for all numbers
+/// below 3.3 * 10^24, larger numbers are probable primes.
+const WITNESSES: [u32; 13] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41];
+
+/// Upper bound for trial division before falling back to Pollard's rho
+const TRIAL_DIVISION_LIMIT: u32 = 1000;
+
+pub fn gcd(values: Vec<Integer>) -> Integer {
+    let gcd = values
+        .into_iter()
+        .fold(Natural::ZERO, |acc, v| acc.gcd(v.unsigned_abs()));
+    Integer::from(gcd)
+}
+
+pub fn lcm(values: Vec<Integer>) -> Integer {
+    let lcm = values
+        .into_iter()
+        .fold(Natural::ONE, |acc, v| acc.lcm(v.unsigned_abs()));
+    Integer::from(lcm)
+}
+
+pub fn factorial(n: u64) -> Integer {
+    Integer::from(Natural::factorial(n))
+}
+
+pub fn is_prime(n: &Natural) -> bool {
+    if *n < 2u32 {
+        return false;
+    }
+    for p in WITNESSES {
+        if *n == p {
+            return true;
+        }
+        if n % Natural::from(p) == 0u32 {
+            return false;
+        }
+    }
+
+    let n_minus_one = n - Natural::ONE;
+    let s = n_minus_one.trailing_zeros().unwrap_or_default();
+    let d = &n_minus_one >> s;
+
+    'witness: for a in WITNESSES {
+        let mut x = Natural::from(a).mod_pow(&d, n);
+        if x == 1u32 || x == n_minus_one {
+            continue;
+        }
+        for _ in 1..s {
+            x = x.mod_square(n);
+            if x == n_minus_one {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/// Smallest prime strictly greater than `n`
+pub fn next_prime(n: &Integer) -> Integer {
+    let Ok(mut candidate) = Natural::try_from(n + Integer::ONE) else {
+        return Integer::TWO;
+    };
+    while !is_prime(&candidate) {
+        candidate += Natural::ONE;
+    }
+    Integer::from(candidate)
+}
+
+/// Prime factorization of `n` as `(prime, multiplicity)` pairs in ascending order
+pub fn factor(mut n: Natural) -> Vec<(Natural, u64)> {
+    let mut factors = vec![];
+
+    let mut p = 2u32;
+    while p < TRIAL_DIVISION_LIMIT && n > 1u32 {
+        let divisor = Natural::from(p);
+        let mut k = 0;
+        while &n % &divisor == 0u32 {
+            n /= &divisor;
+            k += 1;
+        }
+        if k > 0 {
+            factors.push((divisor, k));
+        }
+        p += if p == 2 { 1 } else { 2 };
+    }
+
+    let mut composites = vec![n];
+    while let Some(n) = composites.pop() {
+        if n == 1u32 {
+            continue;
+        }
+        if is_prime(&n) {
+            match factors.iter_mut().find(|(p, _)| *p == n) {
+                Some((_, k)) => *k += 1,
+                None => factors.push((n, 1)),
+            }
+            continue;
+        }
+        let divisor = pollard_rho(&n);
+        composites.push(&n / &divisor);
+        composites.push(divisor);
+    }
+
+    factors.sort();
+    factors
+}
+
+/// Find a non-trivial divisor of a composite `n` using Brent's variant of Pollard's rho
+fn pollard_rho(n: &Natural) -> Natural {
+    const BATCH: u64 = 128;
+
+    let diff = |a: &Natural, b: &Natural| if a > b { a - b } else { b - a };
+
+    let mut c = Natural::ONE;
+    loop {
+        let f = |x: Natural| (x.mod_square(n) + &c) % n;
+
+        let mut y = Natural::TWO;
+        let mut x = y.clone();
+        let mut ys = y.clone();
+        let mut q = Natural::ONE;
+        let mut g = Natural::ONE;
+        let mut r = 1u64;
+
+        while g == 1u32 {
+            x = y.clone();
+            for _ in 0..r {
+                y = f(y);
+            }
+            let mut k = 0;
+            while k < r && g == 1u32 {
+                ys = y.clone();
+                for _ in 0..BATCH.min(r - k) {
+                    y = f(y);
+                    q = q.mod_mul(diff(&x, &y), n);
+                }
+                g = (&q).gcd(n);
+                k += BATCH;
+            }
+            r *= 2;
+        }
+
+        if g == *n {
+            loop {
+                ys = f(ys);
+                g = diff(&x, &ys).gcd(n);
+                if g != 1u32 {
+                    break;
+                }
+            }
+        }
+
+        if g != *n {
+            return g;
+        }
+        // Cycle without finding a divisor, retry with a different polynomial
+        c += Natural::ONE;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_prime() {
+        let primes: Vec<u32> = (0..100).filter(|n| is_prime(&Natural::from(*n))).collect();
+        assert_eq!(
+            primes,
+            [
+                2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79,
+                83, 89, 97
+            ]
+        );
+        // Mersenne prime 2^127 - 1
+        let m127 = (Natural::ONE << 127u64) - Natural::ONE;
+        assert!(is_prime(&m127));
+        // Carmichael number
+        assert!(!is_prime(&Natural::from(561u32)));
+    }
+
+    #[test]
+    fn test_factor() {
+        // 1234567891011 = 3 * 7 * 13 * 67 * 107 * 630803
+        let n = Natural::from(1234567891011u64);
+        let factors: Vec<_> = factor(n)
+            .into_iter()
+            .map(|(p, k)| (u64::try_from(&p).unwrap(), k))
+            .collect();
+        assert_eq!(
+            factors,
+            [(3, 1), (7, 1), (13, 1), (67, 1), (107, 1), (630803, 1)]
+        );
+
+        // Product of two primes above the trial division limit
+        let n = Natural::from(1000003u64) * Natural::from(1000033u64) * Natural::from(1000033u64);
+        assert_eq!(
+            factor(n),
+            [
+                (Natural::from(1000003u64), 1),
+                (Natural::from(1000033u64), 2)
+            ]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use malachite::num::arithmetic::traits::{Abs, Mod, ModPowerOf2, PowerOf2};
+use malachite::num::conversion::traits::RoundingFrom;
+use malachite::num::logic::traits::{BitAccess, CountOnes, SignificantBits};
+use malachite::rounding_modes::RoundingMode;
+use malachite::{Integer, Natural, Rational};
+
+/// Lowest `width` bits of `x`, negative numbers are taken in two's complement
+pub fn wrap(x: &Integer, width: u64) -> Natural {
+    x.mod_power_of_2(width)
+}
+
+/// Lowest `width` bits of `x` interpreted as a signed two's complement number
+pub fn wrap_signed(x: &Integer, width: u64) -> Integer {
+    let x = wrap(x, width);
+    if x.get_bit(width - 1) {
+        Integer::from(x) - Integer::from(Natural::power_of_2(width))
+    } else {
+        Integer::from(x)
+    }
+}
+
+/// Bits `low..=high` of `x`
+pub fn bits(x: &Integer, high: u64, low: u64) -> Natural {
+    (x >> low).mod_power_of_2(high - low + 1)
+}
+
+/// Number of set bits, `None` for negative numbers which have infinitely many
+pub fn popcount(x: &Integer) -> Option<u64> {
+    Natural::try_from(x).ok().map(|x| x.count_ones())
+}
+
+/// Copy of `x` with bit `n` set or cleared
+pub fn with_bit(mut x: Integer, n: u64, set: bool) -> Integer {
+    x.assign_bit(n, set);
+    x
+}
+
+/// Rotate the lowest `width` bits of `x` left by `n`, negative `n` rotates right
+pub fn rotl(x: &Integer, n: &Integer, width: u64) -> Natural {
+    let x = wrap(x, width);
+    let n = u64::try_from(&n.mod_op(Integer::from(width))).unwrap_or_default();
+    if n == 0 {
+        return x;
+    }
+    ((&x << n) | (x >> (width - n))).mod_power_of_2(width)
+}
+
+/// Number of leading zeros within the lowest `width` bits of `x`
+pub fn clz(x: &Integer, width: u64) -> u64 {
+    width - wrap(x, width).significant_bits()
+}
+
+/// IEEE 754 single precision bit pattern of the nearest float, infinity beyond the largest one
+pub fn f32_bits(x: &Rational) -> u32 {
+    if overflows(x, 128, 24) {
+        return f32::INFINITY.copysign(sign(x)).to_bits();
+    }
+    f32::rounding_from(x, RoundingMode::Nearest).0.to_bits()
+}
+
+/// IEEE 754 double precision bit pattern of the nearest float, infinity beyond the largest one
+pub fn f64_bits(x: &Rational) -> u64 {
+    if overflows(x, 1024, 53) {
+        return f64::INFINITY.copysign(sign(x).into()).to_bits();
+    }
+    f64::rounding_from(x, RoundingMode::Nearest).0.to_bits()
+}
+
+/// Rounding to nearest overflows from halfway between the largest float and `2**max_exp`, the
+/// conversion from malachite saturates instead
+fn overflows(x: &Rational, max_exp: i64, precision: i64) -> bool {
+    let halfway = Rational::power_of_2(max_exp) - Rational::power_of_2(max_exp - precision - 1);
+    x.abs() >= halfway
+}
+
+fn sign(x: &Rational) -> f32 {
+    if *x < 0 {
+        -1.0
+    } else {
+        1.0
+    }
+}
+
+/// Exact value of a single precision bit pattern, `None` for infinities and NaN
+pub fn from_f32_bits(bits: u32) -> Option<Rational> {
+    Rational::try_from(f32::from_bits(bits)).ok()
+}
+
+/// Exact value of a double precision bit pattern, `None` for infinities and NaN
+pub fn from_f64_bits(bits: u64) -> Option<Rational> {
+    Rational::try_from(f64::from_bits(bits)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use malachite::num::arithmetic::traits::Pow;
+
+    #[test]
+    fn test_rotate() {
+        let rotl = |x: i64, n: i64, width| rotl(&x.into(), &n.into(), width);
+        assert_eq!(rotl(0b1001, 1, 4), 0b0011u32);
+        assert_eq!(rotl(0b1001, -1, 4), 0b1100u32);
+        assert_eq!(rotl(0b1001, 9, 4), 0b0011u32);
+        assert_eq!(rotl(-1, 3, 8), 0xffu32);
+        assert_eq!(rotl(0x12345678, 8, 32), 0x34567812u32);
+    }
+
+    #[test]
+    fn test_bits() {
+        assert_eq!(wrap_signed(&Integer::from(0xfffffff0u32), 32), -16);
+        assert_eq!(wrap_signed(&Integer::from(0x7f), 8), 0x7f);
+        assert_eq!(wrap_signed(&Integer::from(-129), 8), 127);
+        assert_eq!(bits(&Integer::from(0xabcd), 11, 4), 0xbcu32);
+        assert_eq!(bits(&Integer::from(-2), 3, 0), 0xeu32);
+        assert_eq!(popcount(&Integer::from(0xff)), Some(8));
+        assert_eq!(popcount(&Integer::from(-1)), None);
+        assert_eq!(clz(&Integer::from(1), 32), 31);
+        assert_eq!(clz(&Integer::from(-1), 32), 0);
+        assert_eq!(with_bit(Integer::from(-1), 0, false), -2);
+        assert_eq!(with_bit(Integer::from(0), 4, true), 16);
+    }
+
+    #[test]
+    fn test_float_bits() {
+        assert_eq!(f32_bits(&Rational::from_signeds(-3, 2)), 0xbfc00000);
+        assert_eq!(f64_bits(&Rational::from_signeds(1, 10)), 0x3fb999999999999a);
+        let big = Rational::from(10).pow(39u64);
+        assert_eq!(f32_bits(&big), 0x7f800000);
+        assert_eq!(f32_bits(&-big), 0xff800000);
+        let max = Rational::try_from(f32::MAX).unwrap();
+        assert_eq!(f32_bits(&max), 0x7f7fffff);
+        let halfway = Rational::power_of_2(128i64) - Rational::power_of_2(103i64);
+        assert_eq!(f32_bits(&(&halfway - Rational::from(1))), 0x7f7fffff);
+        assert_eq!(f32_bits(&halfway), 0x7f800000);
+        let big = Rational::from(10).pow(309u64);
+        assert_eq!(f64_bits(&big), 0x7ff0000000000000);
+        assert_eq!(
+            from_f64_bits(0x3fb999999999999a),
+            Some(Rational::from_signeds(
+                3602879701896397i64,
+                36028797018963968
+            ))
+        );
+        assert_eq!(from_f32_bits(0x7f800000), None);
+    }
+}
@@ -0,0 +1,112 @@
+use malachite::num::arithmetic::traits::{Abs, DivMod, Floor};
+use malachite::num::basic::traits::{One, Zero};
+use malachite::{Integer, Natural, Rational};
+
+/// First `n` terms of the continued fraction of `x`, fewer if the expansion ends
+pub fn continued_fraction(x: Rational, n: usize) -> Vec<Integer> {
+    let mut terms = vec![];
+    let mut x = x;
+    while terms.len() < n {
+        let term = (&x).floor();
+        let rest = x - Rational::from(&term);
+        terms.push(term);
+        if rest == 0u32 {
+            break;
+        }
+        x = Rational::ONE / rest;
+    }
+    terms
+}
+
+/// Value of a continued fraction `[a0; a1, a2, ...]`, `None` if it divides by zero
+pub fn from_continued_fraction(terms: &[Integer]) -> Option<Rational> {
+    let (last, rest) = terms.split_last()?;
+    let mut x = Rational::from(last);
+    for term in rest.iter().rev() {
+        if x == 0u32 {
+            return None;
+        }
+        x = Rational::from(term) + Rational::ONE / x;
+    }
+    Some(x)
+}
+
+/// Closest fraction to `x` with a denominator of at most `max_denominator`, which is positive
+///
+/// The result is a convergent or a semiconvergent of the continued fraction of `x`.
+pub fn approximate(x: &Rational, max_denominator: &Natural) -> Rational {
+    if x.denominator_ref() <= max_denominator {
+        return x.clone();
+    }
+    let max = Integer::from(max_denominator);
+    // Last two convergents p/q
+    let (mut p0, mut q0, mut p1, mut q1) =
+        (Integer::ZERO, Integer::ONE, Integer::ONE, Integer::ZERO);
+    let mut n = Integer::from_sign_and_abs_ref(*x >= 0u32, x.numerator_ref());
+    let mut d = Integer::from(x.denominator_ref());
+    loop {
+        let (a, rest) = (&n).div_mod(&d);
+        let q2 = &q0 + &a * &q1;
+        if q2 > max {
+            break;
+        }
+        let p2 = &p0 + &a * &p1;
+        (p0, q0, p1, q1) = (p1, q1, p2, q2);
+        (n, d) = (d, rest);
+    }
+    // Largest semiconvergent between the last two convergents
+    let k = (&max - &q0).div_mod(&q1).0;
+    let semiconvergent = Rational::from_integers(&p0 + &k * &p1, &q0 + &k * &q1);
+    let convergent = Rational::from_integers(p1, q1);
+    if (&convergent - x).abs() <= (&semiconvergent - x).abs() {
+        convergent
+    } else {
+        semiconvergent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ints(values: &[i64]) -> Vec<Integer> {
+        values.iter().map(|&v| Integer::from(v)).collect()
+    }
+
+    #[test]
+    fn test_continued_fraction() {
+        let x = Rational::from_signeds(415, 93);
+        assert_eq!(continued_fraction(x.clone(), 10), ints(&[4, 2, 6, 7]));
+        assert_eq!(continued_fraction(x, 2), ints(&[4, 2]));
+        let x = Rational::from_signeds(-7, 2);
+        assert_eq!(continued_fraction(x, 10), ints(&[-4, 2]));
+        assert_eq!(continued_fraction(Rational::from(0), 0), ints(&[]));
+    }
+
+    #[test]
+    fn test_approximate() {
+        let approximate = |x: &Rational, max: u32| approximate(x, &Natural::from(max));
+        let pi = Rational::from_signeds(314159265358979i64, 100000000000000);
+        assert_eq!(approximate(&pi, 1000), Rational::from_signeds(355, 113));
+        assert_eq!(approximate(&pi, 100), Rational::from_signeds(311, 99));
+        assert_eq!(approximate(&pi, 1), Rational::from(3));
+        assert_eq!(approximate(&-pi, 10), Rational::from_signeds(-22, 7));
+        let x = Rational::from_signeds(3, 8);
+        assert_eq!(approximate(&x, 8), x);
+        assert_eq!(approximate(&x, 4), Rational::from_signeds(1, 3));
+    }
+
+    #[test]
+    fn test_from_continued_fraction() {
+        assert_eq!(
+            from_continued_fraction(&ints(&[4, 2, 6, 7])),
+            Some(Rational::from_signeds(415, 93))
+        );
+        assert_eq!(
+            from_continued_fraction(&ints(&[-4, 2])),
+            Some(Rational::from_signeds(-7, 2))
+        );
+        assert_eq!(from_continued_fraction(&ints(&[1, 0])), None);
+        assert_eq!(from_continued_fraction(&[]), None);
+    }
+}
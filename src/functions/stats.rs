@@ -0,0 +1,77 @@
+use malachite::num::arithmetic::traits::Floor;
+use malachite::num::basic::traits::One;
+use malachite::Rational;
+
+/// Mean of the values, which are not empty
+pub fn mean(values: &[Rational]) -> Rational {
+    values.iter().sum::<Rational>() / Rational::from(values.len())
+}
+
+/// Sample variance of at least two values
+pub fn variance(values: &[Rational]) -> Rational {
+    let mean = mean(values);
+    let squares: Rational = values
+        .iter()
+        .map(|x| {
+            let d = x - &mean;
+            &d * &d
+        })
+        .sum();
+    squares / Rational::from(values.len() - 1)
+}
+
+/// Percentile `p` from 0 to 100 of the values, which are not empty, interpolated linearly
+/// between the closest ranks
+pub fn percentile(mut values: Vec<Rational>, p: &Rational) -> Rational {
+    values.sort();
+    let rank = p / Rational::from(100) * Rational::from(values.len() - 1);
+    let lower = usize::try_from(&(&rank).floor()).expect("the rank is within the values");
+    let fraction = rank - Rational::from(lower);
+    match values.get(lower + 1) {
+        Some(upper) if fraction != 0u32 => {
+            &values[lower] * (Rational::ONE - &fraction) + upper * fraction
+        }
+        _ => values.swap_remove(lower),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reals(values: &[i64]) -> Vec<Rational> {
+        values.iter().map(|&v| Rational::from(v)).collect()
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values = reals(&[7, 1, 3, 5]);
+        assert_eq!(
+            percentile(values.clone(), &Rational::from(50)),
+            Rational::from(4)
+        );
+        assert_eq!(
+            percentile(values.clone(), &Rational::from(0)),
+            Rational::from(1)
+        );
+        assert_eq!(
+            percentile(values.clone(), &Rational::from(100)),
+            Rational::from(7)
+        );
+        assert_eq!(
+            percentile(values, &Rational::from(90)),
+            Rational::from_signeds(64, 10)
+        );
+        assert_eq!(
+            percentile(reals(&[2]), &Rational::from(30)),
+            Rational::from(2)
+        );
+    }
+
+    #[test]
+    fn test_variance() {
+        let values = reals(&[2, 4, 4, 4, 5, 5, 7, 9]);
+        assert_eq!(mean(&values), Rational::from(5));
+        assert_eq!(variance(&values), Rational::from_signeds(32, 7));
+    }
+}
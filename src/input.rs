@@ -0,0 +1,70 @@
+use thiserror::Error;
+
+/// Column of delimited input lines, e.g. the third column of CSV with `--field 3 --delimiter ,`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    /// Column number, starting from 1
+    pub index: usize,
+    /// Column separator, runs of whitespace if not set
+    pub delimiter: Option<char>,
+}
+
+impl Field {
+    /// Text of the column in the line, with surrounding whitespace trimmed
+    pub fn extract<'a>(&self, line: &'a str) -> Result<&'a str, InputError> {
+        let column = match self.delimiter {
+            Some(delimiter) => line.split(delimiter).nth(self.index - 1),
+            None => line.split_whitespace().nth(self.index - 1),
+        };
+        column
+            .map(str::trim)
+            .ok_or(InputError::MissingField(self.index))
+    }
+}
+
+/// Text of a line with a trailing carriage return removed, bytes that aren't valid UTF-8 are
+/// decoded as Latin-1, e.g. a stray 0xA0 is a non-breaking space
+///
+/// Returns whether any bytes were invalid.
+pub fn decode(bytes: &[u8]) -> (String, bool) {
+    let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+    let mut text = String::with_capacity(bytes.len());
+    let mut invalid = false;
+    for chunk in bytes.utf8_chunks() {
+        text.push_str(chunk.valid());
+        text.extend(chunk.invalid().iter().map(|b| char::from(*b)));
+        invalid |= !chunk.invalid().is_empty();
+    }
+    (text, invalid)
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum InputError {
+    #[error("Line has no field {0}")]
+    MissingField(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"1 + 2\r"), ("1 + 2".into(), false));
+        assert_eq!(decode(b"1\xa0+ 2"), ("1\u{a0}+ 2".into(), true));
+        assert_eq!(decode(b"\xc2\xb5\xffs"), ("µÿs".into(), true));
+    }
+
+    #[test]
+    fn test_extract() {
+        let field = |index, delimiter| Field { index, delimiter };
+        assert_eq!(field(2, None).extract("  4096\t./target  "), Ok("./target"));
+        assert_eq!(field(1, None).extract("4096\t./target"), Ok("4096"));
+        assert_eq!(field(3, Some(',')).extract("a,b, 1.5 ,c"), Ok("1.5"));
+        assert_eq!(field(2, Some(',')).extract("a,,c"), Ok(""));
+        assert_eq!(
+            field(4, Some(',')).extract("a,b,c"),
+            Err(InputError::MissingField(4))
+        );
+    }
+}
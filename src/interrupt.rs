@@ -0,0 +1,59 @@
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by Ctrl-C, cleared when it's handled
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Handle Ctrl-C by setting a flag instead of exiting
+///
+/// A second Ctrl-C before the first one is handled exits as usual, so a stuck process can still
+/// be stopped.
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            // Exit status of a process killed by SIGINT
+            std::process::exit(130);
+        }
+    })
+}
+
+/// Whether Ctrl-C was pressed since the last call
+pub fn take() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+/// Line of interactive input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// Bytes of the line without the line ending
+    Line(Vec<u8>),
+    /// Ctrl-C was pressed, the partial line is discarded
+    Interrupted,
+    /// Ctrl-D or the end of the input
+    End,
+}
+
+/// Read a line of stdin, which is discarded if Ctrl-C was pressed while it was typed
+pub fn read_line() -> io::Result<Input> {
+    let mut line = vec![];
+    let read = io::stdin().lock().read_until(b'\n', &mut line)?;
+    if take() {
+        return Ok(Input::Interrupted);
+    }
+    Ok(match read {
+        0 => Input::End,
+        _ => Input::Line(line.strip_suffix(b"\n").unwrap_or(&line).to_vec()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take() {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        assert!(take());
+        assert!(!take());
+    }
+}
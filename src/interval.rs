@@ -0,0 +1,287 @@
+use crate::real::{self, RealError};
+use crate::tokenizer::Arithmetic;
+use crate::value::Value;
+use malachite::num::arithmetic::traits::{Abs, Pow};
+use malachite::num::basic::traits::{One, Zero};
+use malachite::{Integer, Rational};
+use thiserror::Error;
+
+/// Closed interval `[lo, hi]` of numbers with an uncertainty, written as `mid ± radius`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Interval {
+    pub lo: Rational,
+    pub hi: Rational,
+}
+
+impl Interval {
+    /// Interval of `mid ± radius`, the sign of the radius is ignored
+    pub fn around(mid: Rational, radius: Rational) -> Self {
+        let radius = radius.abs();
+        Self {
+            lo: &mid - &radius,
+            hi: mid + radius,
+        }
+    }
+
+    fn point(x: Rational) -> Self {
+        Self {
+            lo: x.clone(),
+            hi: x,
+        }
+    }
+
+    pub fn mid(&self) -> Rational {
+        (&self.lo + &self.hi) / Rational::from(2)
+    }
+
+    pub fn radius(&self) -> Rational {
+        (&self.hi - &self.lo) / Rational::from(2)
+    }
+
+    pub fn abs(&self) -> Self {
+        if self.lo >= 0u32 {
+            self.clone()
+        } else if self.hi <= 0u32 {
+            Self {
+                lo: -&self.hi,
+                hi: -&self.lo,
+            }
+        } else {
+            Self {
+                lo: Rational::ZERO,
+                hi: (-&self.lo).max(self.hi.clone()),
+            }
+        }
+    }
+
+    fn contains_zero(&self) -> bool {
+        self.lo <= 0u32 && self.hi >= 0u32
+    }
+
+    /// Smallest interval that contains all the numbers
+    fn hull(products: [Rational; 4]) -> Self {
+        let [a, b, c, d] = products;
+        Self {
+            lo: a.clone().min(b.clone()).min(c.clone()).min(d.clone()),
+            hi: a.max(b).max(c).max(d),
+        }
+    }
+
+    fn mul(&self, r: &Self) -> Self {
+        Self::hull([
+            &self.lo * &r.lo,
+            &self.lo * &r.hi,
+            &self.hi * &r.lo,
+            &self.hi * &r.hi,
+        ])
+    }
+
+    /// `1 / self`, `None` if the interval contains zero
+    fn recip(&self) -> Option<Self> {
+        if self.contains_zero() {
+            return None;
+        }
+        Some(Self {
+            lo: Rational::ONE / &self.hi,
+            hi: Rational::ONE / &self.lo,
+        })
+    }
+
+    /// Integer power, `None` for negative powers of intervals that contain zero
+    fn pow(&self, exp: i64) -> Option<Self> {
+        let n = exp.unsigned_abs();
+        let result = if n.is_multiple_of(2) {
+            // Even powers of the absolute value, which is non-negative
+            let abs = self.abs();
+            Self {
+                lo: (&abs.lo).pow(n),
+                hi: (&abs.hi).pow(n),
+            }
+        } else {
+            Self {
+                lo: (&self.lo).pow(n),
+                hi: (&self.hi).pow(n),
+            }
+        };
+        if exp < 0 {
+            result.recip()
+        } else {
+            Some(result)
+        }
+    }
+}
+
+fn interval(v: Value) -> Result<Interval, IntervalError> {
+    match v {
+        Value::Interval(i) => Ok(i),
+        v => v
+            .to_rational()
+            .map(Interval::point)
+            .ok_or(IntervalError::UnsupportedOperands),
+    }
+}
+
+/// Interval of the left operand widened by the right one, the result of `±`
+pub fn plus_minus(l: Value, r: Value) -> Result<Value, IntervalError> {
+    let radius = r.to_rational().ok_or(IntervalError::UnsupportedOperands)?;
+    let l = interval(l)?;
+    let result = Interval {
+        lo: l.lo - radius.clone().abs(),
+        hi: l.hi + radius.abs(),
+    };
+    Ok(Value::Interval(result))
+}
+
+/// Execute an operation where at least one of the operands is an interval
+pub fn execute(op: Arithmetic, l: Value, r: Value, precision: u64) -> Result<Value, IntervalError> {
+    let (l, r) = (interval(l)?, interval(r)?);
+    let result = match op {
+        Arithmetic::Add => Interval {
+            lo: l.lo + r.lo,
+            hi: l.hi + r.hi,
+        },
+        Arithmetic::Sub => Interval {
+            lo: l.lo - r.hi,
+            hi: l.hi - r.lo,
+        },
+        Arithmetic::Mul => l.mul(&r),
+        Arithmetic::Div => l.mul(&r.recip().ok_or(IntervalError::DivisionByZero)?),
+        // Only exact exponents are supported
+        Arithmetic::Pow if r.lo != r.hi => return Err(IntervalError::UnsupportedOperands),
+        Arithmetic::Pow => power(l, r.lo, precision)?,
+        Arithmetic::Eq => return Ok((l == r).into()),
+        Arithmetic::Ne => return Ok((l != r).into()),
+        Arithmetic::Lt => return compare(l.hi < r.lo, l.lo >= r.hi),
+        Arithmetic::Le => return compare(l.hi <= r.lo, l.lo > r.hi),
+        Arithmetic::Gt => return compare(l.lo > r.hi, l.hi <= r.lo),
+        Arithmetic::Ge => return compare(l.lo >= r.hi, l.hi < r.lo),
+    };
+    Ok(Value::Interval(result))
+}
+
+/// Result of a comparison that is true for all numbers of the intervals, or false for all
+fn compare(always: bool, never: bool) -> Result<Value, IntervalError> {
+    match (always, never) {
+        (true, _) => Ok(true.into()),
+        (_, true) => Ok(false.into()),
+        _ => Err(IntervalError::Overlapping),
+    }
+}
+
+/// Integer powers of any interval, other powers of non-negative intervals
+fn power(base: Interval, exp: Rational, precision: u64) -> Result<Interval, IntervalError> {
+    if let Ok(exp) = Integer::try_from(&exp) {
+        let exp = i64::try_from(&exp).map_err(|_| IntervalError::ExponentTooLarge)?;
+        return base.pow(exp).ok_or(IntervalError::DivisionByZero);
+    }
+    let pow = |x: &Rational| {
+        real::pow(x, &exp, precision).map_err(|e| match e {
+            RealError::DivisionByZero => IntervalError::DivisionByZero,
+            RealError::TooLarge => IntervalError::ExponentTooLarge,
+            _ => IntervalError::FractionalPowerOfNegative,
+        })
+    };
+    // Powers of non-negative numbers are monotonic
+    let (lo, hi) = (pow(&base.lo)?, pow(&base.hi)?);
+    Ok(if exp > 0u32 {
+        Interval { lo, hi }
+    } else {
+        Interval { lo: hi, hi: lo }
+    })
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum IntervalError {
+    #[error("Division by an interval that contains zero")]
+    DivisionByZero,
+    #[error("Comparison of overlapping intervals")]
+    Overlapping,
+    #[error("Fractional power of an interval with negative numbers")]
+    FractionalPowerOfNegative,
+    #[error("Exponent is too large")]
+    ExponentTooLarge,
+    #[error("Operation is not supported for intervals")]
+    UnsupportedOperands,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn around(mid: i64, radius: i64) -> Value {
+        Value::Interval(Interval::around(mid.into(), radius.into()))
+    }
+
+    fn range(lo: i64, hi: i64) -> Value {
+        Value::Interval(Interval {
+            lo: lo.into(),
+            hi: hi.into(),
+        })
+    }
+
+    #[test]
+    fn test_execute() {
+        let execute = |op, l, r| execute(op, l, r, 10);
+        assert_eq!(
+            plus_minus(Value::from(5), Value::from(-1)),
+            Ok(around(5, 1))
+        );
+        assert_eq!(
+            execute(Arithmetic::Add, around(5, 1), around(2, 1)),
+            Ok(around(7, 2))
+        );
+        assert_eq!(
+            execute(Arithmetic::Sub, around(5, 1), around(2, 1)),
+            Ok(around(3, 2))
+        );
+        assert_eq!(
+            execute(Arithmetic::Mul, range(-1, 2), range(3, 4)),
+            Ok(range(-4, 8))
+        );
+        assert_eq!(
+            execute(Arithmetic::Pow, range(-2, 1), Value::from(2)),
+            Ok(range(0, 4))
+        );
+        assert_eq!(
+            execute(Arithmetic::Pow, range(-2, 1), Value::from(3)),
+            Ok(range(-8, 1))
+        );
+        assert_eq!(
+            execute(Arithmetic::Pow, range(4, 9), half()),
+            Ok(range(2, 3))
+        );
+        assert_eq!(
+            execute(Arithmetic::Div, Value::from(1), range(-1, 1)),
+            Err(IntervalError::DivisionByZero)
+        );
+        assert_eq!(
+            execute(Arithmetic::Div, Value::from(1), range(2, 4)),
+            Ok(Value::Interval(Interval {
+                lo: Rational::from_signeds(1, 4),
+                hi: Rational::from_signeds(1, 2)
+            }))
+        );
+    }
+
+    fn half() -> Value {
+        Value::Real(Rational::from_signeds(1, 2))
+    }
+
+    #[test]
+    fn test_compare() {
+        let execute = |op, l, r| execute(op, l, r, 10);
+        assert_eq!(
+            execute(Arithmetic::Lt, range(1, 2), range(3, 4)),
+            Ok(true.into())
+        );
+        assert_eq!(
+            execute(Arithmetic::Ge, range(1, 2), range(3, 4)),
+            Ok(false.into())
+        );
+        assert_eq!(
+            execute(Arithmetic::Lt, range(1, 3), range(2, 4)),
+            Err(IntervalError::Overlapping)
+        );
+    }
+}
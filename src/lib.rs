@@ -0,0 +1,100 @@
+//! Arbitrary precision calculator
+//!
+//! Expressions are tokenized and parsed into an [`Expr`] tree, which is evaluated by a
+//! [`Calculator`]. [`CompiledExpr`] wraps both for repeated evaluation with different variables,
+//! [`Session`] evaluates lines that define variables and functions.
+//!
+//! The `serde` feature implements `Serialize` and `Deserialize` for tokens, expressions and
+//! values. The `wasm` feature exports [`eval`] and [`Session`] to JavaScript with wasm-bindgen.
+//!
+//! The `cli` feature, enabled by default, adds the command line tool and the modules that need an
+//! operating system: the config file, the clipboard, the line editor, Ctrl-C handling and parallel
+//! evaluation. The library itself always needs `std`.
+
+pub mod ast;
+pub mod bytesize;
+pub mod calculator;
+#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
+pub mod clipboard;
+pub mod color;
+pub mod command;
+pub mod compiled;
+pub mod complete;
+pub mod complex;
+#[cfg(feature = "cli")]
+pub mod config;
+pub mod datetime;
+pub mod derivative;
+pub mod duration;
+#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
+pub mod editor;
+pub mod format;
+pub mod functions;
+pub mod input;
+#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
+pub mod interrupt;
+pub mod interval;
+pub mod matrix;
+#[cfg(feature = "cli")]
+pub mod parallel;
+pub mod parser;
+pub mod polynomial;
+pub mod printer;
+pub mod random;
+pub mod real;
+pub mod reduce;
+pub mod roman;
+pub mod rpn;
+pub mod session;
+pub mod symbolic;
+pub mod tape;
+pub mod tokenizer;
+pub mod units;
+pub mod value;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use ast::Expr;
+pub use calculator::{Calculator, CalculatorError};
+pub use compiled::{CompileError, CompiledExpr};
+pub use session::Session;
+pub use value::Value;
+
+/// Evaluate the expression with the default settings, the result is formatted for display
+///
+/// Text in, text out, for embedding where [`Value`] can't be passed around. Any input is either
+/// evaluated or reported as an error without panicking, numbers are limited to
+/// [`calculator::MAX_BITS`]. There's no time limit, see [`Calculator::set_timeout`] for untrusted
+/// input.
+pub fn eval(expr: &str) -> Result<String, CompileError> {
+    let expr = CompiledExpr::new(expr, Calculator::default())?;
+    let format = format::Format::default().for_expr(expr.expr());
+    Ok(format.display(&expr.eval()?).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(
+            eval("2 ** 100").as_deref(),
+            Ok("1267650600228229401496703205376")
+        );
+        assert_eq!(eval("1.5 * 2h").as_deref(), Ok("3h"));
+        assert_eq!(
+            eval("x + 1").map_err(|e| e.to_string()),
+            Err("Unknown identifier: x".into())
+        );
+    }
+
+    #[test]
+    fn test_eval_deep() {
+        // Tests run on threads with a stack of 2 MiB
+        let nested = format!("{}1{}", "-(".repeat(1000), ")".repeat(1000));
+        assert_eq!(eval(&nested).as_deref(), Ok("1"));
+        let nested = format!("{}-1{}", "abs(".repeat(1000), ")".repeat(1000));
+        assert_eq!(eval(&nested).as_deref(), Ok("1"));
+    }
+}
@@ -1,73 +1,1392 @@
-use anyhow::{bail, Error};
+use anyhow::{bail, Context, Error};
+use clap::{CommandFactory, Parser};
+use clap_complete::Shell;
+use malachite::num::arithmetic::traits::UnsignedAbs;
+use malachite::num::logic::traits::SignificantBits;
+use malachite::{Integer, Natural};
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
 use std::io::BufRead;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
 
-mod calculator;
-mod tokenizer;
+use sc::ast::Expr;
+use sc::calculator::{self, Calculator, CalculatorError};
+use sc::color::{self, ColorChoice, Style};
+use sc::command::{self, Command};
+use sc::compiled::{CompileError, CompiledExpr};
+use sc::config::Config;
+use sc::editor::{self, Editor};
+use sc::format::{DecimalComma, DurationStyle, Format, Fractions, Output, Rounding};
+use sc::functions::number_theory;
+use sc::input::{self, Field};
+use sc::interrupt::{self, Input};
+use sc::parallel;
+use sc::reduce::Reduction;
+use sc::rpn::{Notation, Rpn, RpnError};
+use sc::session::{Defined, Session};
+use sc::tape::{Entry, Tape};
+use sc::tokenizer::{Token, Tokenizer};
+use sc::value::Value;
+
+/// Arbitrary precision calculator
+#[derive(Parser, Debug)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// Options of `sc eval`, which is the default subcommand
+    #[command(flatten)]
+    args: Args,
+    #[command(subcommand)]
+    command: Option<Subcommand>,
+}
+
+/// Options of the subcommands that evaluate expressions
+#[derive(clap::Args, Debug)]
+#[command(group(clap::ArgGroup::new("stdin").args(["map", "reduce"])))]
+struct Args {
+    /// Number of decimal places for non-integer results [default: 50]
+    #[arg(long, value_name = "N")]
+    precision: Option<u64>,
+    /// Rounding mode of displayed results: half-even, half-up, half-down, down, up, floor or
+    /// ceiling [default: half-even]
+    #[arg(long, value_name = "MODE")]
+    rounding: Option<Rounding>,
+    /// Display of non-integer results: decimal (3.5), fraction (7/2), mixed (3 1/2) or
+    /// repeating (0.(142857)) [default: decimal]
+    #[arg(long, value_name = "STYLE")]
+    fractions: Option<Fractions>,
+    /// Display results as exact decimals with N places, rounded half to even, e.g. `12.50`
+    #[arg(long, value_name = "N", conflicts_with_all = ["precision", "rounding", "fractions"])]
+    fixed: Option<u64>,
+    /// Notation of decimal results: plain (12345678), sci (1.2345678e7) or eng (12.345678e6), the
+    /// mantissa is rounded to the precision [default: plain]
+    #[arg(long, value_name = "NAME")]
+    notation: Option<sc::format::Notation>,
+    /// Round displayed results to N significant figures, e.g. `2.50` for `2.5` and three
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    sigfig: Option<u64>,
+    /// Use a decimal comma, e.g. `3,14`: never, always or locale [default: never]. Arguments and
+    /// list elements are then separated with `;`, e.g. `max(3,14; 2)`
+    #[arg(
+        long,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always"
+    )]
+    decimal_comma: Option<DecimalComma>,
+    /// Display durations normalized (1h30m) or as a number of seconds
+    #[arg(long, value_name = "STYLE", default_value_t = DurationStyle::default())]
+    format: DurationStyle,
+    /// Wrap integer results modulo 2^BITS, showing negative numbers in two's complement. One of
+    /// 8, 16, 32, 64 or 128
+    #[arg(long, value_name = "BITS", value_parser = width)]
+    width: Option<u64>,
+    /// Reduce integer results modulo N, with division meaning multiplication by the modular
+    /// inverse. N may be an expression, e.g. `2**61 - 1`
+    #[arg(long = "mod", value_name = "N", value_parser = modulus)]
+    modulus: Option<Natural>,
+    /// Seed the random number generator of `rand` and `randbits` for reproducible results
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+    /// Parse numbers with a leading zero as octal, as in C. Use the `0o` prefix otherwise
+    #[arg(long)]
+    c_octal: bool,
+    /// Accept numbers with an uncertainty, e.g. `2.5 ± 0.1` or `2.5 +- 0.1`, operations on them
+    /// propagate the bounds
+    #[arg(long)]
+    interval: bool,
+    /// Evaluate undefined identifiers as symbolic variables, e.g. `2*x + 3*x` is `5*x`
+    #[arg(long)]
+    symbolic: bool,
+    /// Truncate the quotient of integers toward zero, e.g. `7 / 2` is `3` rather than `3.5`
+    #[arg(long, conflicts_with = "fixed")]
+    integer_division: bool,
+    /// Print expressions with canonical spacing and minimal parentheses instead of evaluating
+    /// them
+    #[arg(long)]
+    fmt: bool,
+    /// Print expressions and results as LaTeX math, e.g. `\frac{3}{4} + 2^{10}` with `--fmt`
+    #[arg(long, conflicts_with_all = ["tape", "rpn"])]
+    latex: bool,
+    /// Print the tokens of each expression before the result
+    #[arg(long)]
+    tokens: bool,
+    /// Print the syntax tree of each expression before the result
+    #[arg(long)]
+    ast: bool,
+    /// Read expressions in reverse Polish notation, e.g. `3 4 + 2 *`, keeping the stack
+    #[arg(long, conflicts_with_all = ["fmt", "ast"])]
+    rpn: bool,
+    /// Add every line to a running total like an adding machine, e.g. `12.50` or `-3`
+    #[arg(
+        long,
+        conflicts_with_all = ["expression", "map", "reduce", "jobs", "fmt", "tokens", "ast", "rpn"]
+    )]
+    tape: bool,
+    /// Print every evaluated operation with its result, in evaluation order
+    #[arg(long)]
+    trace: bool,
+    /// Print each result after its expression, e.g. `2 + 3 * 4 = 14`, and the dice of rolls
+    #[arg(long, conflicts_with = "tape")]
+    verbose: bool,
+    /// Evaluate EXPR for every number read from stdin, one per line, with the number bound to
+    /// `x`
+    #[arg(
+        long,
+        value_name = "EXPR",
+        conflicts_with_all = ["expression", "fmt", "tokens", "ast", "rpn", "trace", "verbose"]
+    )]
+    map: Option<String>,
+    /// Fold all numbers read from stdin, one per line, into a single result: sum, product, mean,
+    /// min, max, count, median, variance, stddev or a percentile such as p90
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["expression", "map", "fmt", "tokens", "ast", "rpn", "trace", "verbose"]
+    )]
+    reduce: Option<Reduction>,
+    /// Use only the Nth column of each line read by `--map` or `--reduce`, starting from 1
+    #[arg(long, value_name = "N", requires = "stdin", value_parser = clap::value_parser!(u64).range(1..))]
+    field: Option<u64>,
+    /// Column separator for `--field`, columns are separated by whitespace if omitted
+    #[arg(long, value_name = "C", requires = "field")]
+    delimiter: Option<char>,
+    /// Evaluate every argument as a separate expression and print each result on its own line,
+    /// e.g. `sc --separate 1+1 2+2`
+    #[arg(long, requires = "expression", conflicts_with_all = ["map", "reduce"])]
+    separate: bool,
+    /// Terminate results with a NUL byte instead of a newline, for `xargs -0`
+    #[arg(long)]
+    print0: bool,
+    /// Don't print a newline after the last result, for shell command substitution
+    #[arg(short = 'n', long, conflicts_with = "print0")]
+    no_newline: bool,
+    /// Fail evaluations that take longer than SECS seconds
+    #[arg(long, value_name = "SECS", value_parser = seconds)]
+    timeout: Option<Duration>,
+    /// Fail evaluations with numbers larger than N bits, the numerator and the denominator of
+    /// fractions are limited separately. Larger values than the default are capped
+    /// [default: 16777216]
+    #[arg(long, value_name = "N")]
+    max_result_bits: Option<u64>,
+    /// Fail expressions nested deeper than N levels, e.g. parentheses, powers and recursive
+    /// function calls [default: 1024]
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+    /// Evaluate lines read from stdin on N threads, results are printed in the order of the
+    /// input. Definitions, commands and lines with `$N` are evaluated in order between the
+    /// others. Ignored in interactive sessions
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["expression", "map", "reduce", "fmt", "tokens", "ast", "rpn", "trace", "verbose", "time"]
+    )]
+    jobs: Option<u64>,
+    /// Print how long tokenizing and evaluating each expression took to stderr
+    #[arg(long)]
+    time: bool,
+    /// Fail on input that isn't valid UTF-8 instead of decoding invalid bytes as Latin-1
+    #[arg(long)]
+    strict_utf8: bool,
+    /// Print errors of input lines to stderr and continue with the next line instead of exiting.
+    /// The exit status is still non-zero if any line failed. Always on in interactive sessions
+    #[arg(long)]
+    keep_going: bool,
+    /// Evaluate the definitions of FILE before the first expression without printing them, e.g.
+    /// `tax = 0.21` or `f(x) = x * 2`. Can be repeated
+    #[arg(long, value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+    init: Vec<PathBuf>,
+    /// Place the last result on the system clipboard, as it's printed
+    #[arg(long, conflicts_with = "fmt")]
+    copy: bool,
+    /// Append every input with its result to FILE, e.g. `2 + 3 = 5`
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = clap::ValueHint::FilePath,
+        conflicts_with_all = ["map", "reduce", "fmt"]
+    )]
+    log: Option<PathBuf>,
+    /// Prompt of interactive sessions. `{base}` is replaced by the output format, `{precision}`
+    /// by the number of decimal places and `{next}` by the number of the next result
+    #[arg(long, value_name = "TEXT")]
+    prompt: Option<String>,
+    /// Color results and errors: auto (the default), always or never. Auto colors terminal
+    /// output unless `NO_COLOR` is set
+    #[arg(long, value_name = "WHEN")]
+    color: Option<ColorChoice>,
+    /// Config file with default settings, `$XDG_CONFIG_HOME/sc/config.toml` or
+    /// `~/.config/sc/config.toml` if omitted
+    #[arg(long, value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+    config: Option<PathBuf>,
+    /// Exit with status 0 if the last result is non-zero and 1 otherwise, errors exit with
+    /// status 2
+    #[arg(long)]
+    status: bool,
+    /// Expression to evaluate, expressions are read from stdin line by line if omitted
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    expression: Vec<OsString>,
+}
+
+/// Output of `sc convert`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Notation(Notation),
+    Base(Output),
+}
+
+impl Target {
+    const NAMES: [&'static str; 7] = ["infix", "rpn", "dec", "hex", "oct", "bin", "datetime"];
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match (s.parse(), s.parse()) {
+            (Ok(notation), _) => Ok(Target::Notation(notation)),
+            (_, Ok(output)) => Ok(Target::Base(output)),
+            _ => Err(format!("expected one of {}", Target::NAMES.join(", "))),
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Notation(notation) => write!(f, "{}", notation),
+            Target::Base(Output::Decimal) => write!(f, "dec"),
+            Target::Base(output) => write!(f, "{}", output),
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Subcommand {
+    /// Evaluate expressions, same as `sc EXPR`
+    Eval(Args),
+    /// Print expressions with canonical spacing and minimal parentheses, same as `sc --fmt`
+    Fmt(Args),
+    /// Start an interactive session even if stdin isn't a terminal
+    Repl(Args),
+    /// Convert an expression between infix and reverse Polish notation without evaluating it
+    Convert {
+        /// Comma separated notations or bases to convert to: infix, rpn, dec, hex, oct, bin or
+        /// datetime. The expression is read in RPN if infix is one of them, and evaluated for
+        /// the bases
+        #[arg(long, value_name = "TARGETS", required = true, value_delimiter = ',')]
+        to: Vec<Target>,
+        /// Parse numbers with a leading zero as octal, as in C
+        #[arg(long)]
+        c_octal: bool,
+        /// Expression to convert, options may follow it. Use `--` before expressions that start
+        /// with `-` and aren't a number
+        #[arg(required = true, allow_negative_numbers = true)]
+        expression: Vec<OsString>,
+    },
+    /// Print the prime factorization of an integer, e.g. `360 = 2**3 * 3**2 * 5`
+    Factor {
+        /// Print the factors as JSON, e.g. `{"number": 12, "factors": [[2, 2], [3, 1]]}`, integers
+        /// beyond 2**53 are strings
+        #[arg(long)]
+        json: bool,
+        /// Expression of the integer to factor
+        #[arg(required = true, allow_negative_numbers = true)]
+        expression: Vec<OsString>,
+    },
+    /// Print the completion script of a shell
+    Completions { shell: Shell },
+}
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("{}", e);
-        std::process::exit(1);
+    let Cli { args, command } = Cli::parse();
+    let (args, repl) = match command {
+        None => (args, false),
+        Some(Subcommand::Eval(args)) => (args, false),
+        Some(Subcommand::Fmt(args)) => (Args { fmt: true, ..args }, false),
+        Some(Subcommand::Repl(args)) => (args, true),
+        Some(command) => {
+            if let Err(e) = tool(command) {
+                report(&e, false);
+                std::process::exit(1);
+            }
+            return;
+        }
+    };
+    let status = args.status;
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            report(&e, false);
+            std::process::exit(if status { 2 } else { 1 });
+        }
+    };
+    let choice = args.color.or(config.color).unwrap_or_default();
+    let colors = Colors {
+        results: choice.enabled(atty::is(atty::Stream::Stdout)),
+        errors: choice.enabled(atty::is(atty::Stream::Stderr)),
+    };
+    // Deeply nested expressions need a larger stack than the one of the main thread
+    let result = thread::Builder::new()
+        .stack_size(calculator::STACK_SIZE)
+        .spawn(move || {
+            calculator::large_stack();
+            run(args, repl, config, colors)
+        })
+        .map_err(Error::from)
+        .and_then(|run| run.join().unwrap_or_else(|e| std::panic::resume_unwind(e)));
+    match result {
+        Ok(last) if status => {
+            if !last.and_then(|v| v.to_bool()).unwrap_or(false) {
+                std::process::exit(1);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => {
+            report(&e, colors.errors);
+            std::process::exit(if status { 2 } else { 1 });
+        }
+    }
+}
+
+fn seconds(s: &str) -> Result<Duration, String> {
+    let seconds: f64 = s
+        .parse()
+        .map_err(|_| "expected a number of seconds".to_owned())?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+/// Config file given on the command line or at the default location, a file that can't be read is
+/// ignored with a warning
+fn load_config(path: Option<&Path>) -> Result<Config, Error> {
+    let path = match path {
+        Some(path) if !path.exists() => bail!("Config file {} doesn't exist", path.display()),
+        Some(path) => path.to_owned(),
+        None => match Config::path() {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        },
+    };
+    Ok(Config::load(&path).unwrap_or_else(|e| {
+        eprintln!("warning: {} ({}), using the defaults", e, path.display());
+        Config::default()
+    }))
+}
+
+/// Evaluate on a separate thread if it can be interrupted or has a timeout
+fn evaluate(
+    calculator: &Calculator,
+    interruptible: bool,
+    expr: &Expr,
+) -> Result<Value, CalculatorError> {
+    if interruptible || calculator.timeout().is_some() {
+        calculator.evaluate_interruptible(expr, interrupt::take)
+    } else {
+        calculator.evaluate(expr)
+    }
+}
+
+/// Print the error, with the offending part of the input highlighted if colors are enabled
+fn report(error: &Error, color: bool) {
+    if !color {
+        eprintln!("{:#}", error);
+        return;
+    }
+    if let Some(e) = error.downcast_ref::<LocatedError>() {
+        let (start, end) = match e.width {
+            Some(width) => (e.column, e.column + width),
+            None => color::word_at(&e.line, e.column),
+        };
+        eprintln!("{}", color::highlight(&e.line, start, end));
+    }
+    eprintln!("{}", color::paint(format!("{:#}", error), Style::ERROR));
+}
+
+/// Position of the first occurrence of `word` in the line that isn't part of a longer name,
+/// counted in characters
+fn find_word(line: &str, word: &str) -> Option<usize> {
+    let is_name = |c: char| c.is_alphanumeric() || c == '_';
+    line.match_indices(word)
+        .find(|(i, _)| {
+            let before = line[..*i].chars().next_back();
+            let after = line[i + word.len()..].chars().next();
+            !before.is_some_and(is_name) && !after.is_some_and(is_name)
+        })
+        .map(|(i, _)| line[..i].chars().count())
+}
+
+/// Error caused by a specific character or word of an input line
+#[derive(Debug)]
+struct LocatedError {
+    line: String,
+    /// Position of the character, counted in characters
+    column: usize,
+    /// Number of characters of the culprit, the word at the column if omitted
+    width: Option<usize>,
+    error: Error,
+}
+
+/// Error of the library, which is located by the type of the inner error
+fn compile_error(error: CompileError) -> Error {
+    match error {
+        CompileError::Tokenize(e) => e.into(),
+        CompileError::Calculator(e) => e.into(),
+    }
+}
+
+impl LocatedError {
+    /// Error of an identifier or function, located at the first occurrence of its name
+    fn of_name(line: &str, error: Error) -> Error {
+        let name = error
+            .downcast_ref::<CalculatorError>()
+            .and_then(CalculatorError::name);
+        match name.and_then(|name| find_word(line, name)) {
+            Some(column) => LocatedError {
+                line: line.to_owned(),
+                column,
+                width: name.map(|name| name.chars().count()),
+                error,
+            }
+            .into(),
+            None => error,
+        }
+    }
+}
+
+impl std::fmt::Display for LocatedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl std::error::Error for LocatedError {}
+
+/// Whether to color results and error messages
+#[derive(Debug, Clone, Copy)]
+struct Colors {
+    results: bool,
+    errors: bool,
+}
+
+fn width(s: &str) -> Result<u64, String> {
+    command::parse_width(s).ok_or_else(|| {
+        let widths: Vec<_> = calculator::WIDTHS.iter().map(u64::to_string).collect();
+        format!("expected one of {}", widths.join(", "))
+    })
+}
+
+fn modulus(s: &str) -> Result<Natural, String> {
+    command::parse_modulus(s).ok_or_else(|| "expected a positive integer".to_string())
+}
+
+/// Run a subcommand that doesn't evaluate expressions
+fn tool(command: Subcommand) -> Result<(), Error> {
+    let stdout = std::io::stdout();
+    let mut w = std::io::BufWriter::new(stdout.lock());
+    match command {
+        Subcommand::Convert {
+            to,
+            c_octal,
+            expression,
+        } => convert(&mut w, &to, &expression, c_octal)?,
+        Subcommand::Factor { json, expression } => factor(&mut w, &expression, json)?,
+        Subcommand::Completions { shell } => completions(&mut w, shell),
+        Subcommand::Eval(_) | Subcommand::Fmt(_) | Subcommand::Repl(_) => {
+            unreachable!("evaluations are run by main")
+        }
     }
+    w.flush()?;
+    Ok(())
 }
 
-fn run() -> Result<(), Error> {
-    let mut tokenizer = tokenizer::Tokenizer::default();
-    let mut calculator = calculator::Calculator::default();
+/// Evaluate all expressions, returns the last result. `repl` starts an interactive session even
+/// if stdin isn't a terminal
+fn run(args: Args, repl: bool, config: Config, colors: Colors) -> Result<Option<Value>, Error> {
+    if repl && (!args.expression.is_empty() || args.map.is_some() || args.reduce.is_some()) {
+        bail!("The repl can't be combined with an expression, --map or --reduce");
+    }
+
+    let precision = args
+        .precision
+        .or(config.precision)
+        .unwrap_or(sc::real::DEFAULT_PRECISION);
+
+    let mut tokenizer = Tokenizer::default();
+    tokenizer.set_c_octal(args.c_octal);
+    tokenizer.set_intervals(args.interval);
+    let decimal_comma = args.decimal_comma.unwrap_or_default().enabled();
+    tokenizer.set_decimal_comma(decimal_comma);
+    let notation = args.notation.or(config.notation).unwrap_or_default();
+    let format = match args.fixed {
+        Some(places) => Format {
+            precision: places,
+            rounding: Rounding::HalfEven,
+            fractions: Fractions::Decimal,
+            durations: args.format,
+            output: config.output.unwrap_or_default(),
+            fixed: true,
+            decimal_comma,
+            notation,
+            significant: args.sigfig,
+        },
+        None => Format {
+            precision,
+            rounding: args.rounding.or(config.rounding).unwrap_or_default(),
+            fractions: args.fractions.or(config.fractions).unwrap_or_default(),
+            durations: args.format,
+            output: config.output.unwrap_or_default(),
+            fixed: false,
+            decimal_comma,
+            notation,
+            significant: args.sigfig,
+        },
+    };
+    let mut session = Session::new(calculator(&args, precision), format);
+    session.set_tokenizer(tokenizer);
+    let mut console = Console {
+        session,
+        fmt: args.fmt,
+        tokens: args.tokens.then(Vec::new),
+        ast: args.ast,
+        rpn: args.rpn.then(Rpn::default),
+        tape: args.tape.then(Tape::default),
+        verbose: args.verbose,
+        latex: args.latex,
+        transcript: vec![],
+        log: match &args.log {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?,
+            ),
+            None => None,
+        },
+        keep_going: args.keep_going,
+        failed: 0,
+        lines: 0,
+        terminator: if args.print0 {
+            Terminator::Null
+        } else if args.no_newline {
+            Terminator::None
+        } else {
+            Terminator::Newline
+        },
+        printed: false,
+        prompt: args
+            .prompt
+            .clone()
+            .or(config.prompt)
+            .unwrap_or_else(|| ">>> ".into()),
+        colors,
+        quit: false,
+        interruptible: false,
+        time: args.time,
+        started: None,
+    };
+    for prelude in config.prelude.iter().chain(&args.init) {
+        console.prelude(prelude)?;
+    }
 
-    let args = std::env::args_os().skip(1);
     let stdout = std::io::stdout();
     let lock = stdout.lock();
     let mut w = std::io::BufWriter::new(lock);
+    let mut last = None;
+    let field = args.field.map(|index| Field {
+        index: index as usize,
+        delimiter: args.delimiter,
+    });
 
-    if args.len() > 0 {
-        for arg in args {
-            let Some(utf8_arg) = arg.to_str() else {
-                bail!("Arguments contain invalid UTF-8 string");
-            };
+    if let Some(map) = &args.map {
+        // With the definitions of the preludes, `x` is bound without changing the session
+        let mut expr =
+            CompiledExpr::from_expr(console.parse(map)?, console.session.calculator().fork());
+        for line in input_lines(field, args.strict_utf8) {
+            let result = line.and_then(|line| {
+                let x = console.parse(&line)?;
+                let x = console.evaluate(&x)?;
+                Ok(expr.eval_with(&[("x", x)])?)
+            });
+            match result {
+                Ok(result) => {
+                    console.output_value(&mut w, &result)?;
+                    last = Some(result);
+                }
+                Err(e) => console.recover(e, &mut w)?,
+            }
+        }
+    } else if let Some(reduction) = args.reduce {
+        let mut values = vec![];
+        for line in input_lines(field, args.strict_utf8) {
+            let result = line.and_then(|line| {
+                let expr = console.parse(&line)?;
+                Ok(console.evaluate(&expr)?)
+            });
+            match result {
+                Ok(value) => values.push(value),
+                Err(e) => console.recover(e, &mut w)?,
+            }
+        }
+        let result = reduction.apply(values, console.session.calculator())?;
+        console.output_value(&mut w, &result)?;
+        last = Some(result);
+    } else if args.separate {
+        for arg in &args.expression {
+            match arguments_text(std::slice::from_ref(arg), args.strict_utf8)
+                .and_then(|e| console.line(&e, &mut w))
+            {
+                Ok(result) => last = result,
+                Err(e) => console.recover(e, &mut w)?,
+            }
+        }
+    } else if !args.expression.is_empty() {
+        let text = arguments_text(&args.expression, args.strict_utf8)?;
+        last = console.expression(&text, &mut w)?;
+        if let Some(value) = &last {
+            console.record(text.trim(), value)?;
+        }
+    } else {
+        let terminal = atty::is(atty::Stream::Stdin);
+        let is_interactive = repl || terminal;
+        // A typo or Ctrl-C shouldn't end an interactive session
+        console.keep_going |= is_interactive;
+        let mut lines = stdin_lines(args.strict_utf8);
+        let history_size = config.history_size.unwrap_or(editor::DEFAULT_HISTORY_SIZE);
+        let mut editor = terminal
+            .then(|| Editor::new(history_size, colors.results))
+            .transpose()?;
+        if is_interactive {
+            interrupt::install()?;
+            console.interruptible = true;
+        }
 
-            for char in utf8_arg.chars().chain(std::iter::once(' ')) {
-                tokenizer
-                    .update(char)?
-                    .map(|t| calculator.handle_token(t))
-                    .transpose()?;
+        if let Some(jobs) = args.jobs.filter(|_| !is_interactive) {
+            last = console.parallel(lines, jobs as usize, &mut w)?;
+        } else {
+            while !console.quit {
+                let line = if is_interactive {
+                    // Ctrl-C pressed during evaluation only applies to that evaluation
+                    interrupt::take();
+                    let prompt = console.prompt();
+                    let input = if let Some(editor) = &mut editor {
+                        w.flush()?;
+                        editor.edit_line(&prompt, console.session.calculator())?
+                    } else {
+                        write!(&mut w, "{}", prompt)?;
+                        w.flush()?;
+                        interrupt::read_line()?
+                    };
+                    match input {
+                        Input::Line(line) => match decode(&line, args.strict_utf8) {
+                            Ok(line) => line,
+                            Err(e) => {
+                                console.recover(e, &mut w)?;
+                                continue;
+                            }
+                        },
+                        // The editor already moved to the next line
+                        Input::Interrupted => {
+                            if editor.is_none() {
+                                writeln!(&mut w)?;
+                            }
+                            console.discard();
+                            continue;
+                        }
+                        Input::End => {
+                            if editor.is_none() {
+                                writeln!(&mut w)?;
+                            }
+                            break;
+                        }
+                    }
+                } else {
+                    match lines.next() {
+                        Some(line) => line?,
+                        None => break,
+                    }
+                };
+                match console.line(&line, &mut w) {
+                    Ok(Some(result)) => last = Some(result),
+                    Ok(None) => {}
+                    Err(e) => console.recover(e, &mut w)?,
+                }
             }
         }
+    }
+    w.flush()?;
+    if let Some(value) = last.as_ref().filter(|_| args.copy) {
+        sc::clipboard::copy(&console.display(value))?;
+    }
+
+    // Errors were already reported to the user of an interactive session
+    if console.failed > 0 && !console.interruptible {
+        bail!("{} of the inputs failed", console.failed);
+    }
+
+    Ok(last)
+}
+
+fn calculator(args: &Args, precision: u64) -> Calculator {
+    let mut calculator = Calculator::default();
+    calculator.set_precision(precision);
+    calculator.set_width(args.width);
+    calculator.set_modulus(args.modulus.clone());
+    calculator.set_seed(args.seed);
+    calculator.set_symbolic(args.symbolic);
+    calculator.set_exact_division(!args.integer_division);
+    calculator.set_trace(args.trace);
+    calculator.set_rolls(args.verbose);
+    calculator.set_timeout(args.timeout);
+    calculator.set_max_bits(args.max_result_bits);
+    if let Some(max_depth) = args.max_depth {
+        calculator.set_max_depth(max_depth);
+    }
+    calculator
+}
+
+/// Lines evaluated by each thread of `--jobs` at a time
+const BATCH_SIZE: usize = 256;
+
+/// Whether the line can be evaluated without the definitions and the results of the previous
+/// lines, which are read by `$1` and `out(1)`
+fn is_independent(line: &str, session: &Session) -> bool {
+    let history = |token| match token {
+        Ok(Token::Ident(name)) => name.starts_with('$') || name == "out",
+        _ => false,
+    };
+    !line.trim_start().starts_with(':')
+        && session.defined_name(line).is_none()
+        && !session
+            .tokenizer()
+            .clone()
+            .into_tokens(line.chars())
+            .any(history)
+}
+
+/// Lines of stdin, decoded with [`decode`]
+///
+/// Stdin is only locked while a line is read, so that the prompts of the repl can read it too.
+fn stdin_lines(strict: bool) -> impl Iterator<Item = Result<String, Error>> {
+    std::iter::from_fn(move || {
+        let mut line = vec![];
+        match std::io::stdin().lock().read_until(b'\n', &mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(decode(line.strip_suffix(b"\n").unwrap_or(&line), strict)),
+            Err(e) => Some(Err(e.into())),
+        }
+    })
+}
+
+/// Non-blank lines of stdin, or their column if `field` is set
+fn input_lines(field: Option<Field>, strict: bool) -> impl Iterator<Item = Result<String, Error>> {
+    stdin_lines(strict)
+        .filter(|line| !line.as_ref().is_ok_and(|l| l.trim().is_empty()))
+        .map(move |line| match field {
+            Some(field) => Ok(field.extract(&line?)?.to_owned()),
+            None => line,
+        })
+}
+
+/// Text of the input, invalid UTF-8 is decoded as Latin-1 with a warning unless `strict` is set
+fn decode(bytes: &[u8], strict: bool) -> Result<String, Error> {
+    let (text, replaced) = input::decode(bytes);
+    if replaced {
+        if strict {
+            bail!("Input contains invalid UTF-8");
+        }
+        eprintln!("warning: invalid UTF-8 in the input was decoded as Latin-1");
+    }
+    Ok(text)
+}
+
+/// Expression given as command line arguments, separated by spaces
+fn arguments_text(args: &[OsString], strict: bool) -> Result<String, Error> {
+    let mut text = String::new();
+    for arg in args {
+        text.push_str(&decode(arg.as_encoded_bytes(), strict)?);
+        text.push(' ');
+    }
+    Ok(text)
+}
+
+/// Completion script of the command line, with the values of the options parsed with `FromStr`
+/// and the meta-commands offered in place of an expression, e.g. `:precision`
+fn completions(w: &mut impl Write, shell: Shell) {
+    let values = |names: &'static [&'static str]| {
+        move |arg: clap::Arg| arg.value_parser(clap::builder::PossibleValuesParser::new(names))
+    };
+    let meta = Command::NAMES.map(|name| format!(":{}", name));
+    let options = |cmd: clap::Command| {
+        cmd.mut_arg("rounding", values(&Rounding::NAMES))
+            .mut_arg("fractions", values(&Fractions::NAMES))
+            .mut_arg("format", values(&DurationStyle::NAMES))
+            .mut_arg("reduce", values(&Reduction::NAMES))
+            .mut_arg("color", values(&ColorChoice::NAMES))
+            .mut_arg("decimal_comma", values(&DecimalComma::NAMES))
+            .mut_arg("notation", values(&sc::format::Notation::NAMES))
+            .mut_arg("expression", |arg| {
+                arg.value_parser(clap::builder::PossibleValuesParser::new(meta.clone()))
+            })
+    };
+    let mut cli = options(Cli::command())
+        .mut_subcommand("eval", options)
+        .mut_subcommand("fmt", options)
+        .mut_subcommand("repl", options)
+        .mut_subcommand("convert", |c| c.mut_arg("to", values(&Target::NAMES)));
+    clap_complete::generate(shell, &mut cli, "sc", w);
+}
+
+/// Print the expression converted from the other notation to `to`
+fn convert(
+    w: &mut impl Write,
+    to: &[Target],
+    expression: &[OsString],
+    c_octal: bool,
+) -> Result<(), Error> {
+    let infix = to.contains(&Target::Notation(Notation::Infix));
+    if infix && to.contains(&Target::Notation(Notation::Rpn)) {
+        bail!("Can't convert to both infix and rpn, the expression is read in the other one");
+    }
+    let mut tokenizer = Tokenizer::default();
+    tokenizer.set_c_octal(c_octal);
+    let tokens: Vec<Token> = tokenizer
+        .into_tokens(arguments_text(expression, false)?.chars())
+        .collect::<Result<_, _>>()?;
+    let expr = if infix {
+        sc::rpn::parse(tokens)?
+    } else {
+        let mut parser = sc::parser::Parser::default();
+        for token in tokens {
+            parser.handle_token(token)?;
+        }
+        parser.finalize()?
+    };
+
+    let mut value = None;
+    let mut rows = vec![];
+    for target in to {
+        let text = match target {
+            Target::Notation(Notation::Rpn) => sc::rpn::print(&expr)?,
+            Target::Notation(Notation::Infix) => expr.to_string(),
+            Target::Base(output) => {
+                if value.is_none() {
+                    value = Some(Calculator::default().evaluate(&expr)?);
+                }
+                let format = Format {
+                    output: *output,
+                    ..Format::default()
+                };
+                format.display(value.as_ref().unwrap()).to_string()
+            }
+        };
+        rows.push((target.to_string(), text));
+    }
+    if let [(_, text)] = rows.as_slice() {
+        writeln!(w, "{}", text)?;
+        return Ok(());
+    }
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, text) in rows {
+        writeln!(w, "{:width$}  {}", name, text, width = width)?;
+    }
+    Ok(())
+}
+
+/// Print the prime factors of the integer with their multiplicities
+fn factor(w: &mut impl Write, expression: &[OsString], json: bool) -> Result<(), Error> {
+    let text = arguments_text(expression, false)?;
+    let value = CompiledExpr::new(&text, Calculator::default())?.eval()?;
+    let Some(n) = value.into_int() else {
+        bail!("Only integers can be factored");
+    };
+    if n == 0 {
+        bail!("Zero can't be factored");
+    }
+    let factors = number_theory::factor((&n).unsigned_abs());
+    if json {
+        let factors: Vec<String> = factors
+            .iter()
+            .map(|(p, k)| format!("[{}, {}]", json_integer(&Integer::from(p)), k))
+            .collect();
+        writeln!(
+            w,
+            r#"{{"number": {}, "factors": [{}]}}"#,
+            json_integer(&n),
+            factors.join(", ")
+        )?;
+        return Ok(());
+    }
+    let mut terms: Vec<String> = factors
+        .iter()
+        .map(|(p, k)| match k {
+            1 => p.to_string(),
+            k => format!("{}**{}", p, k),
+        })
+        .collect();
+    if n < 0 {
+        terms.insert(0, "-1".into());
+    }
+    if terms.is_empty() {
+        terms.push("1".into());
+    }
+    writeln!(w, "{} = {}", n, terms.join(" * "))?;
+    Ok(())
+}
 
-        tokenizer.finalize()?.map(|t| calculator.handle_token(t));
-        let result = calculator.finalize()?;
-        writeln!(&mut w, "{}", result)?;
+/// JSON number of the integer, or a string outside of ±2**53 where parsers of JSON numbers as
+/// doubles lose precision
+fn json_integer(n: &Integer) -> String {
+    if n.unsigned_abs_ref().significant_bits() <= 53 {
+        n.to_string()
     } else {
-        let stdin = std::io::stdin();
-        let reader = std::io::BufReader::new(stdin);
-        let is_interactive = atty::is(atty::Stream::Stdin);
+        format!("\"{}\"", n)
+    }
+}
 
-        if is_interactive {
-            write!(&mut w, ">>> ")?;
-            w.flush()?;
+/// Calculator session with the output options of the command line
+struct Console {
+    session: Session,
+    /// Print expressions formatted instead of evaluating them
+    fmt: bool,
+    /// Tokens of the current expression, only recorded if they are printed
+    tokens: Option<Vec<Token>>,
+    /// Print the syntax tree of expressions
+    ast: bool,
+    /// Stack of the reverse Polish notation mode
+    rpn: Option<Rpn>,
+    /// Running total of the adding machine mode
+    tape: Option<Tape>,
+    /// Print results after their expressions
+    verbose: bool,
+    /// Print expressions and results as LaTeX math
+    latex: bool,
+    /// Inputs with their formatted results, written by `:export`
+    transcript: Vec<(String, String)>,
+    /// File that every input is appended to with its result
+    log: Option<File>,
+    /// Report errors of input lines and continue with the next line
+    keep_going: bool,
+    /// Number of input lines that failed with `keep_going`
+    failed: usize,
+    /// Number of input lines read, random numbers of each line are seeded by its number
+    lines: u64,
+    /// Written after every result
+    terminator: Terminator,
+    /// Whether any result was printed yet
+    printed: bool,
+    /// Prompt of interactive sessions, with placeholders
+    prompt: String,
+    colors: Colors,
+    /// Set by `:quit`, no more input is read
+    quit: bool,
+    /// Evaluate on a separate thread so that Ctrl-C can stop it
+    interruptible: bool,
+    /// Print the duration of tokenizing and evaluating each expression
+    time: bool,
+    /// When the first character of the current expression was read
+    started: Option<Instant>,
+}
+
+/// End of printed results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Terminator {
+    Newline,
+    /// NUL byte, as in `find -print0`
+    Null,
+    /// Newlines only between results
+    None,
+}
+
+impl Console {
+    fn update(&mut self, c: char) -> Result<(), Error> {
+        if self.time && self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+        if let Some(token) = self.session.tokenizer_mut().update(c)? {
+            self.handle_token(token)?;
+        }
+        Ok(())
+    }
+
+    fn handle_token(&mut self, token: Token) -> Result<(), Error> {
+        if let Some(tokens) = &mut self.tokens {
+            tokens.push(token.clone());
+        }
+        match &mut self.rpn {
+            Some(rpn) => {
+                let (calculator, interruptible) = (self.session.calculator(), self.interruptible);
+                Ok(rpn.handle_token(token, |expr| evaluate(calculator, interruptible, expr))?)
+            }
+            None => Ok(self.session.calculator_mut().handle_token(token)?),
+        }
+    }
+
+    /// Print a result followed by the terminator
+    fn output(&mut self, w: &mut impl Write, result: impl std::fmt::Display) -> Result<(), Error> {
+        let result = match self.colors.results {
+            true => color::paint(result, Style::RESULT),
+            false => result.to_string(),
+        };
+        match self.terminator {
+            Terminator::Newline => writeln!(w, "{}", result)?,
+            Terminator::Null => write!(w, "{}\0", result)?,
+            // Results are still separated, only the last one isn't followed by a newline
+            Terminator::None if self.printed => write!(w, "\n{}", result)?,
+            Terminator::None => write!(w, "{}", result)?,
+        }
+        self.printed = true;
+        Ok(())
+    }
+
+    fn prompt(&self) -> String {
+        let next_result = self.session.calculator().history().len() + 1;
+        sc::format::prompt(&self.prompt, self.session.format(), next_result)
+    }
+
+    fn output_value(&mut self, w: &mut impl Write, value: &Value) -> Result<(), Error> {
+        let text = self.display(value);
+        self.output(w, text)
+    }
+
+    /// Result as it's printed, in LaTeX with `--latex`
+    fn display(&self, value: &Value) -> String {
+        self.display_as(value, self.session.format())
+    }
+
+    /// Result of an expression as it's printed, see [`Format::for_expr`]
+    fn display_as(&self, value: &Value, format: &Format) -> String {
+        match self.latex {
+            true => sc::printer::latex_value(value, format),
+            false => format.display(value).to_string(),
+        }
+    }
+
+    /// Expression as it's printed by `--fmt` and `--verbose`
+    fn display_expr(&self, expr: &Expr) -> String {
+        match self.latex {
+            true => sc::printer::latex(expr),
+            false => expr.to_string(),
+        }
+    }
+
+    /// Evaluate a line of the input and add its result to the transcript
+    fn line(&mut self, line: &str, w: &mut impl Write) -> Result<Option<Value>, Error> {
+        self.session.calculator_mut().seed_line(self.lines);
+        self.lines += 1;
+        let result = self.evaluate_line(line, w)?;
+        if let Some(value) = &result {
+            self.record(line.trim(), value)?;
+        }
+        Ok(result)
+    }
+
+    /// Place the last result on the system clipboard, as it was printed
+    fn copy(&self) -> Result<(), Error> {
+        let Some(value) = self.session.calculator().history().last() else {
+            bail!("There's no result to copy yet");
+        };
+        Ok(sc::clipboard::copy(&self.display(value))?)
+    }
+
+    /// Add an input and its result to the transcript of `:export` and to the log file
+    fn record(&mut self, input: &str, value: &Value) -> Result<(), Error> {
+        let result = self.session.format().display(value).to_string();
+        if let Some(log) = &mut self.log {
+            // Variables are logged with their value, as with `--verbose`
+            let name = self.session.defined_name(input);
+            let lhs = match &name {
+                Some(name) if self.rpn.is_none() => name.as_str(),
+                _ => input,
+            };
+            writeln!(log, "{} = {}", lhs, result).context("Failed to write the log")?;
+        }
+        self.transcript.push((input.to_owned(), result));
+        Ok(())
+    }
+
+    /// Add a function definition to the transcript and to the log file, it has no result
+    fn record_definition(&mut self, input: &str) -> Result<(), Error> {
+        if let Some(log) = &mut self.log {
+            writeln!(log, "{}", input).context("Failed to write the log")?;
+        }
+        self.transcript.push((input.to_owned(), String::new()));
+        Ok(())
+    }
+
+    /// Evaluate a line of the input, either a command or an expression
+    fn evaluate_line(&mut self, line: &str, w: &mut impl Write) -> Result<Option<Value>, Error> {
+        if let Some(command) = line.trim_start().strip_prefix(':') {
+            let command = Command::parse(command)?;
+            if let Command::Load(path) = &command {
+                self.prelude(path)?;
+            } else if command == Command::Copy {
+                self.copy()?;
+            } else if let Command::Export(path) = &command {
+                std::fs::write(path, sc::command::export(&self.transcript))
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            } else if command == Command::Quit {
+                self.quit = true;
+            } else if let Some(message) = command.execute(&mut self.session)? {
+                writeln!(w, "{}", message)?;
+            }
+            return Ok(None);
+        }
+        if self.tape.is_some() {
+            return self.tape_line(line, w);
         }
+        if self.rpn.is_none() {
+            let interruptible = self.interruptible;
+            let evaluate =
+                |calculator: &Calculator, expr: &Expr| evaluate(calculator, interruptible, expr);
+            match self.session.define(line, evaluate) {
+                Some(Ok(Defined {
+                    name,
+                    value: Some((value, format)),
+                })) => {
+                    let text = self.display_as(&value, &format);
+                    match self.verbose {
+                        true => self.output(w, format!("{} = {}", name, text))?,
+                        false => self.output(w, text)?,
+                    }
+                    return Ok(Some(value));
+                }
+                Some(Ok(Defined { value: None, .. })) => {
+                    self.record_definition(line.trim())?;
+                    return Ok(None);
+                }
+                Some(Err(e)) => return Err(LocatedError::of_name(line, compile_error(e))),
+                None => {}
+            }
+        }
+        let result = self.expression(line, w)?;
+        if let Some(value) = &result {
+            self.session.calculator_mut().push_history(value.clone());
+        }
+        Ok(result)
+    }
 
-        for expr in reader.lines() {
-            for char in expr?.chars() {
-                if let Some(t) = tokenizer.update(char)? {
-                    calculator.handle_token(t)?;
+    /// Evaluate an expression and print its result, errors are located in the line
+    fn expression(&mut self, line: &str, w: &mut impl Write) -> Result<Option<Value>, Error> {
+        for (column, char) in line.chars().enumerate() {
+            if let Err(error) = self.update(char) {
+                let line = line.to_owned();
+                return Err(LocatedError {
+                    line,
+                    column,
+                    width: None,
+                    error,
                 }
+                .into());
             }
+        }
+        self.finish(w)
+            .map_err(|error| LocatedError::of_name(line, error))
+    }
 
-            if let Some(t) = tokenizer.finalize()? {
-                calculator.handle_token(t)?;
+    /// Add the amount of a line to the tape and print it with the subtotal, or print the total
+    fn tape_line(&mut self, line: &str, w: &mut impl Write) -> Result<Option<Value>, Error> {
+        let total = match Entry::parse(line) {
+            Entry::Amount("") => return Ok(None),
+            Entry::Amount(amount) => {
+                let expr = self.parse(amount)?;
+                let amount = self.evaluate(&expr)?;
+                let tape = self.tape.as_mut().expect("tape mode");
+                let subtotal = tape.add(amount.clone(), self.session.calculator())?.clone();
+                self.output(w, sc::tape::line(self.session.format(), &amount, '+'))?;
+                self.output(w, sc::tape::line(self.session.format(), &subtotal, '='))?;
+                return Ok(Some(subtotal));
+            }
+            Entry::Total => {
+                let total = self.tape.as_mut().expect("tape mode").clear();
+                self.output(w, sc::tape::line(self.session.format(), &total, 'T'))?;
+                total
             }
-            let result = calculator.finalize()?;
+            Entry::Clear => {
+                self.tape.as_mut().expect("tape mode").clear();
+                let zero = Value::from(0);
+                self.output(w, sc::tape::line(self.session.format(), &zero, 'C'))?;
+                zero
+            }
+        };
+        self.session.calculator_mut().push_history(total.clone());
+        Ok(Some(total))
+    }
 
-            writeln!(&mut w, "{}", result)?;
-            if is_interactive {
-                write!(&mut w, ">>> ")?;
-                w.flush()?;
+    /// Evaluate the lines on `jobs` threads, in batches between the lines that depend on the
+    /// previous ones
+    fn parallel(
+        &mut self,
+        mut lines: impl Iterator<Item = Result<String, Error>>,
+        jobs: usize,
+        w: &mut impl Write,
+    ) -> Result<Option<Value>, Error> {
+        let mut last = None;
+        let mut batch = vec![];
+        while !self.quit {
+            let mut dependent = None;
+            let end = match lines.next().transpose()? {
+                Some(line) if is_independent(&line, &self.session) => {
+                    batch.push(line);
+                    false
+                }
+                Some(line) => {
+                    dependent = Some(line);
+                    false
+                }
+                None => true,
+            };
+            if dependent.is_none() && !end && batch.len() < jobs * BATCH_SIZE {
+                continue;
+            }
+            let first = self.lines;
+            self.lines += batch.len() as u64;
+            let results = parallel::evaluate(
+                &batch,
+                first,
+                self.session.tokenizer(),
+                self.session.calculator(),
+                jobs,
+            );
+            for (line, result) in batch.drain(..).zip(results) {
+                match result {
+                    Ok((value, fractions)) => {
+                        let format = Format {
+                            fractions: fractions.unwrap_or(self.session.format().fractions),
+                            ..self.session.format().clone()
+                        };
+                        let text = self.display_as(&value, &format);
+                        self.output(w, text)?;
+                        self.session.calculator_mut().push_history(value.clone());
+                        self.record(line.trim(), &value)?;
+                        last = Some(value);
+                    }
+                    Err(e) => self.recover(e.into(), w)?,
+                }
+            }
+            if let Some(line) = dependent {
+                match self.line(&line, w) {
+                    Ok(Some(result)) => last = Some(result),
+                    Ok(None) => {}
+                    Err(e) => self.recover(e, w)?,
+                }
+            }
+            if end {
+                break;
             }
         }
+        Ok(last)
     }
-    w.flush()?;
 
-    Ok(())
+    /// Evaluate the definitions of a file without printing the results
+    fn prelude(&mut self, path: &Path) -> Result<(), Error> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            self.evaluate_line(line, &mut std::io::sink())
+                .with_context(|| format!("{}:{}", path.display(), i + 1))?;
+        }
+        self.printed = false;
+        Ok(())
+    }
+
+    /// Report the error of an input line and discard the rest of it if `keep_going` is set
+    fn recover(&mut self, error: Error, w: &mut impl Write) -> Result<(), Error> {
+        if !self.keep_going {
+            return Err(error);
+        }
+        // Keep the output in order with the error message
+        w.flush()?;
+        report(&error, self.colors.errors);
+        self.discard();
+        self.failed += 1;
+        Ok(())
+    }
+
+    /// Discard the incomplete expression
+    fn discard(&mut self) {
+        self.started = None;
+        self.session.tokenizer_mut().reset();
+        self.session.calculator_mut().reset();
+        if let Some(tokens) = &mut self.tokens {
+            tokens.clear();
+        }
+    }
+
+    /// Parse a complete expression without evaluating it
+    fn parse(&mut self, text: &str) -> Result<Expr, Error> {
+        for char in text.chars() {
+            self.update(char)?;
+        }
+        for token in self.session.tokenizer_mut().finalize()? {
+            self.handle_token(token)?;
+        }
+        Ok(self.session.calculator_mut().parse()?)
+    }
+
+    fn evaluate(&self, expr: &Expr) -> Result<Value, CalculatorError> {
+        evaluate(self.session.calculator(), self.interruptible, expr)
+    }
+
+    /// Complete the expression and print its result, or the expression itself with `--fmt`
+    fn finish(&mut self, w: &mut impl Write) -> Result<Option<Value>, Error> {
+        let started = self.started.take();
+        for token in self.session.tokenizer_mut().finalize()? {
+            self.handle_token(token)?;
+        }
+        if let Some(tokens) = &mut self.tokens {
+            for token in tokens.drain(..) {
+                writeln!(w, "{}", sc::printer::token(&token))?;
+            }
+        }
+
+        let (expr, result) = if let Some(rpn) = &self.rpn {
+            (None, rpn.top().cloned().ok_or(RpnError::StackUnderflow)?)
+        } else if self.ast || self.fmt {
+            let expr = self.session.calculator_mut().parse()?;
+            if self.ast {
+                write!(w, "{}", sc::printer::tree(&expr))?;
+            }
+            if self.fmt {
+                self.output(w, self.display_expr(&expr))?;
+                return Ok(None);
+            }
+            let result = self.evaluate(&expr)?;
+            (Some(expr), result)
+        } else {
+            let expr = self.session.calculator_mut().parse()?;
+            let result = self.evaluate(&expr)?;
+            (Some(expr), result)
+        };
+        if let Some(started) = started {
+            // Printed before the result, so it's seen on the terminal before a long result
+            w.flush()?;
+            eprintln!("time: {:?}", started.elapsed());
+        }
+        for (step, value) in self.session.calculator().take_trace() {
+            let value = self.session.format().display(&value);
+            writeln!(w, "{} => {}", step, value)?;
+        }
+        for roll in self.session.calculator().take_rolls() {
+            writeln!(w, "{}", roll)?;
+        }
+        let format = match &expr {
+            Some(expr) => self.session.format().for_expr(expr),
+            None => self.session.format().clone(),
+        };
+        let text = self.display_as(&result, &format);
+        match expr.filter(|_| self.verbose) {
+            Some(expr) => self.output(w, format!("{} = {}", self.display_expr(&expr), text))?,
+            None => self.output(w, text)?,
+        }
+        Ok(Some(result))
+    }
 }
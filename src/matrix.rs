@@ -0,0 +1,277 @@
+use crate::tokenizer::Arithmetic;
+use crate::value::Value;
+use malachite::num::arithmetic::traits::Abs;
+use malachite::num::basic::traits::{One, Zero};
+use malachite::{Integer, Rational};
+use thiserror::Error;
+
+/// Matrix of exact numbers, written as a list of rows, e.g. `[[1, 2], [3, 4]]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Matrix {
+    rows: Vec<Vec<Rational>>,
+}
+
+impl Matrix {
+    /// Matrix of a non-empty list of numeric rows of the same non-zero length
+    pub fn from_value(v: &Value) -> Option<Self> {
+        let Value::List(list) = v else {
+            return None;
+        };
+        let rows = list
+            .iter()
+            .map(|row| match row {
+                Value::List(row) => row.iter().map(Value::to_rational).collect(),
+                _ => None,
+            })
+            .collect::<Option<Vec<Vec<Rational>>>>()?;
+        let columns = rows.first()?.len();
+        if columns == 0 || rows.iter().any(|row| row.len() != columns) {
+            return None;
+        }
+        Some(Self { rows })
+    }
+
+    fn identity(n: usize) -> Self {
+        let rows = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| {
+                        if i == j {
+                            Rational::ONE
+                        } else {
+                            Rational::ZERO
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { rows }
+    }
+
+    fn columns(&self) -> usize {
+        self.rows[0].len()
+    }
+
+    fn square(&self) -> Result<usize, MatrixError> {
+        let n = self.rows.len();
+        if n != self.columns() {
+            return Err(MatrixError::NotSquare);
+        }
+        Ok(n)
+    }
+
+    /// Size times the largest absolute value of the elements, which bounds the growth of powers
+    pub fn norm(&self) -> Rational {
+        let max = self
+            .rows
+            .iter()
+            .flatten()
+            .map(|x| x.abs())
+            .max()
+            .unwrap_or_default();
+        max * Rational::from(self.rows.len())
+    }
+
+    pub fn transpose(&self) -> Self {
+        let rows = (0..self.columns())
+            .map(|j| self.rows.iter().map(|row| row[j].clone()).collect())
+            .collect();
+        Self { rows }
+    }
+
+    fn mul(&self, r: &Self) -> Result<Self, MatrixError> {
+        if self.columns() != r.rows.len() {
+            return Err(MatrixError::DimensionMismatch);
+        }
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                (0..r.columns())
+                    .map(|j| row.iter().zip(&r.rows).map(|(x, r)| x * &r[j]).sum())
+                    .collect()
+            })
+            .collect();
+        Ok(Self { rows })
+    }
+
+    /// Determinant by Gaussian elimination
+    pub fn determinant(&self) -> Result<Rational, MatrixError> {
+        let n = self.square()?;
+        let mut rows = self.rows.clone();
+        let mut det = Rational::ONE;
+        for i in 0..n {
+            let Some(pivot) = (i..n).find(|&k| rows[k][i] != 0u32) else {
+                return Ok(Rational::ZERO);
+            };
+            if pivot != i {
+                rows.swap(pivot, i);
+                det = -det;
+            }
+            det *= &rows[i][i];
+            let (top, bottom) = rows.split_at_mut(i + 1);
+            let pivot = &top[i];
+            for row in bottom {
+                let factor = &row[i] / &pivot[i];
+                for (x, p) in row.iter_mut().zip(pivot).skip(i) {
+                    *x -= &factor * p;
+                }
+            }
+        }
+        Ok(det)
+    }
+
+    /// Inverse by Gauss-Jordan elimination
+    pub fn inverse(&self) -> Result<Self, MatrixError> {
+        let n = self.square()?;
+        let mut rows = self.rows.clone();
+        let mut inverse = Self::identity(n).rows;
+        for i in 0..n {
+            let pivot = (i..n)
+                .find(|&k| rows[k][i] != 0u32)
+                .ok_or(MatrixError::Singular)?;
+            rows.swap(pivot, i);
+            inverse.swap(pivot, i);
+            let scale = Rational::ONE / &rows[i][i];
+            for j in 0..n {
+                rows[i][j] *= &scale;
+                inverse[i][j] *= &scale;
+            }
+            for k in 0..n {
+                if k == i || rows[k][i] == 0u32 {
+                    continue;
+                }
+                let factor = rows[k][i].clone();
+                for j in 0..n {
+                    let (x, y) = (&factor * &rows[i][j], &factor * &inverse[i][j]);
+                    rows[k][j] -= x;
+                    inverse[k][j] -= y;
+                }
+            }
+        }
+        Ok(Self { rows: inverse })
+    }
+
+    /// Integer power by squaring, negative powers of the inverse
+    fn pow(&self, exp: i64) -> Result<Self, MatrixError> {
+        let n = self.square()?;
+        let mut base = if exp < 0 {
+            self.inverse()?
+        } else {
+            self.clone()
+        };
+        let mut result = Self::identity(n);
+        let mut exp = exp.unsigned_abs();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.mul(&base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl From<Matrix> for Value {
+    fn from(m: Matrix) -> Self {
+        let number = |x: Rational| match Integer::try_from(&x) {
+            Ok(i) => Value::Int(i),
+            Err(_) => Value::Real(x),
+        };
+        let rows = m.rows.into_iter();
+        Value::List(
+            rows.map(|row| Value::List(row.into_iter().map(number).collect()))
+                .collect(),
+        )
+    }
+}
+
+/// Product of two matrices or integer power of a matrix, `None` for other operations, which
+/// apply to each element
+pub fn execute(op: Arithmetic, l: &Value, r: &Value) -> Option<Result<Value, MatrixError>> {
+    let l = match op {
+        Arithmetic::Mul | Arithmetic::Pow => Matrix::from_value(l)?,
+        _ => return None,
+    };
+    let result = match op {
+        Arithmetic::Mul => l.mul(&Matrix::from_value(r)?),
+        _ => r
+            .to_rational()
+            .and_then(|r| Integer::try_from(&r).ok())
+            .and_then(|r| i64::try_from(&r).ok())
+            .ok_or(MatrixError::InvalidPower)
+            .and_then(|exp| l.pow(exp)),
+    };
+    Some(result.map(Value::from))
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MatrixError {
+    #[error("Matrix dimensions don't match")]
+    DimensionMismatch,
+    #[error("Matrix is not square")]
+    NotSquare,
+    #[error("Matrix is singular")]
+    Singular,
+    #[error("Matrices can only be raised to integer powers")]
+    InvalidPower,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: &[&[i64]]) -> Matrix {
+        let rows = rows
+            .iter()
+            .map(|row| row.iter().map(|&x| Rational::from(x)).collect())
+            .collect();
+        Matrix { rows }
+    }
+
+    #[test]
+    fn test_from_value() {
+        let row = |values: &[i64]| Value::List(values.iter().map(|&x| x.into()).collect());
+        let value = Value::List(vec![row(&[1, 2]), row(&[3, 4])]);
+        assert_eq!(
+            Matrix::from_value(&value),
+            Some(matrix(&[&[1, 2], &[3, 4]]))
+        );
+        assert_eq!(Value::from(matrix(&[&[1, 2], &[3, 4]])), value);
+        let ragged = Value::List(vec![row(&[1, 2]), row(&[3])]);
+        assert_eq!(Matrix::from_value(&ragged), None);
+        assert_eq!(Matrix::from_value(&row(&[1, 2])), None);
+        assert_eq!(Matrix::from_value(&Value::List(vec![row(&[])])), None);
+    }
+
+    #[test]
+    fn test_operations() {
+        let m = matrix(&[&[1, 2], &[3, 4]]);
+        assert_eq!(m.transpose(), matrix(&[&[1, 3], &[2, 4]]));
+        assert_eq!(m.mul(&m), Ok(matrix(&[&[7, 10], &[15, 22]])));
+        assert_eq!(m.pow(2), m.mul(&m));
+        assert_eq!(m.pow(0), Ok(Matrix::identity(2)));
+        assert_eq!(m.determinant(), Ok(Rational::from(-2)));
+        let inverse = m.inverse().unwrap();
+        assert_eq!(m.mul(&inverse), Ok(Matrix::identity(2)));
+        assert_eq!(m.pow(-1), Ok(inverse));
+
+        let singular = matrix(&[&[1, 2], &[2, 4]]);
+        assert_eq!(singular.determinant(), Ok(Rational::ZERO));
+        assert_eq!(singular.inverse(), Err(MatrixError::Singular));
+        let row = matrix(&[&[1, 2, 3]]);
+        assert_eq!(row.determinant(), Err(MatrixError::NotSquare));
+        assert_eq!(row.mul(&row), Err(MatrixError::DimensionMismatch));
+        assert_eq!(row.mul(&row.transpose()), Ok(matrix(&[&[14]])));
+    }
+
+    #[test]
+    fn test_determinant() {
+        let m = matrix(&[&[0, 2, 1], &[3, -1, 2], &[1, 1, 1]]);
+        assert_eq!(m.determinant(), Ok(Rational::from(2)));
+        assert_eq!(m.mul(&m.inverse().unwrap()), Ok(Matrix::identity(3)));
+    }
+}
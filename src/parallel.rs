@@ -0,0 +1,120 @@
+use crate::calculator::{self, Calculator, CalculatorError, STACK_SIZE};
+use crate::compiled::CompileError;
+use crate::format::Fractions;
+use crate::tokenizer::Tokenizer;
+use crate::value::Value;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::sync::Mutex;
+
+/// Evaluate independent expressions on up to `jobs` threads, results are in the order of `lines`
+/// with the display chosen by the expression, see [`Fractions::of_expr`]
+///
+/// Every thread evaluates with a [`Calculator::fork`] of `calculator`, so the lines can't define
+/// variables for each other or refer to each other's results. `first` is the number of the first
+/// line in the input, see [`Calculator::seed_line`].
+pub fn evaluate<S: AsRef<str> + Sync>(
+    lines: &[S],
+    first: u64,
+    tokenizer: &Tokenizer,
+    calculator: &Calculator,
+    jobs: usize,
+) -> Vec<Result<(Value, Option<Fractions>), CompileError>> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(jobs.clamp(1, lines.len().max(1)))
+        .stack_size(STACK_SIZE)
+        .start_handler(|_| calculator::large_stack())
+        .build();
+    let Ok(pool) = pool else {
+        let not_started = || CalculatorError::ResourceLimit("failed to start the evaluation");
+        return lines.iter().map(|_| Err(not_started().into())).collect();
+    };
+    // The calculator isn't `Sync`, the threads fork it one at a time
+    let calculator = Mutex::new(calculator.fork());
+    pool.install(|| {
+        (lines.par_iter().enumerate())
+            .map_init(
+                || calculator.lock().unwrap().fork(),
+                |calculator, (i, line)| {
+                    calculator.seed_line(first + i as u64);
+                    let result = evaluate_line(line.as_ref(), tokenizer, calculator);
+                    if result.is_err() {
+                        calculator.reset();
+                    }
+                    result
+                },
+            )
+            .collect()
+    })
+}
+
+fn evaluate_line(
+    line: &str,
+    tokenizer: &Tokenizer,
+    calculator: &mut Calculator,
+) -> Result<(Value, Option<Fractions>), CompileError> {
+    for token in tokenizer.clone().into_tokens(line.chars()) {
+        calculator.handle_token(token?)?;
+    }
+    let expr = calculator.parse()?;
+    let result = match calculator.timeout() {
+        Some(_) => calculator.evaluate_interruptible(&expr, || false),
+        None => calculator.evaluate(&expr),
+    };
+    Ok((result?, Fractions::of_expr(&expr)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let lines: Vec<String> = (1..=100).map(|i| format!("{} * 2", i)).collect();
+        let calculator = Calculator::default();
+        let results = evaluate(&lines, 0, &Tokenizer::default(), &calculator, 4);
+        let expected: Vec<_> = (1..=100).map(|i| Ok((Value::from(i * 2), None))).collect();
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut calculator = Calculator::default();
+        calculator.set_variable("x", 5.into());
+        let results = evaluate(
+            &["x + 1", "1 +", "x"],
+            0,
+            &Tokenizer::default(),
+            &calculator,
+            2,
+        );
+        assert_eq!(results[0], Ok((6.into(), None)));
+        assert!(results[1].is_err());
+        assert_eq!(results[2], Ok((5.into(), None)));
+        assert_eq!(
+            evaluate::<&str>(&[], 0, &Tokenizer::default(), &calculator, 8),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_seed() {
+        let lines: Vec<String> = (0..64)
+            .map(|i| format!("rand(1, 10**{})", i % 5 + 1))
+            .collect();
+        let mut calculator = Calculator::default();
+        calculator.set_seed(Some(1));
+        let parallel = evaluate(&lines, 3, &Tokenizer::default(), &calculator, 4);
+        let in_order: Vec<_> = (lines.iter().enumerate())
+            .map(|(i, line)| {
+                calculator.seed_line(3 + i as u64);
+                evaluate_line(line, &Tokenizer::default(), &mut calculator)
+            })
+            .collect();
+        assert_eq!(parallel, in_order);
+        assert_eq!(
+            evaluate(&lines, 3, &Tokenizer::default(), &calculator, 4),
+            parallel
+        );
+    }
+}
@@ -0,0 +1,1057 @@
+use crate::ast::Expr;
+use crate::calculator::{CalculatorError, STACK_SIZE};
+use crate::tokenizer::{Operator, Token};
+use compact_str::CompactString;
+
+use ParserState::*;
+
+#[derive(Debug, Default, Clone)]
+enum ParserState {
+    #[default]
+    Empty,
+    Neg,
+    Operand(Node),
+    /// Function or constant name, function is called if followed by an opening parenthesis
+    Function {
+        name: CompactString,
+        negative: bool,
+    },
+}
+
+/// Stack used by a level of nesting when evaluated, measured as under 40 KiB in debug builds and
+/// 4 KiB in release builds
+const FRAME_SIZE: usize = 64 * 1024;
+/// Default maximum depth of expressions, deeper expressions would overflow the stack of the
+/// evaluation thread
+pub const DEFAULT_MAX_DEPTH: usize = STACK_SIZE / FRAME_SIZE;
+
+/// Builds an expression from a stream of tokens
+#[derive(Debug)]
+pub struct Parser {
+    state: ParserState,
+    pending: Vec<Action>,
+    /// Maximum depth of the expression tree and of unclosed groups and operations
+    max_depth: usize,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self {
+            state: ParserState::default(),
+            pending: vec![],
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+impl Parser {
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Discard the incomplete expression
+    pub fn reset(&mut self) {
+        self.state = ParserState::default();
+        self.pending.clear();
+    }
+
+    pub fn handle_token(&mut self, token: Token) -> Result<(), CalculatorError> {
+        self.update(token)?;
+        // Only the current operand and the last pending action can get deeper
+        let operand = match &self.state {
+            Operand(node) => node.depth,
+            _ => Depth::default(),
+        };
+        let pending = self.pending.last().map_or(Depth::default(), Action::depth);
+        if self.pending.len() > self.max_depth || self.too_deep(operand.max(pending)) {
+            return Err(CalculatorError::TooDeep);
+        }
+        Ok(())
+    }
+
+    fn too_deep(&self, depth: Depth) -> bool {
+        depth.nesting > self.max_depth
+    }
+
+    fn update(&mut self, token: Token) -> Result<(), CalculatorError> {
+        use Token::*;
+
+        match (std::mem::take(&mut self.state), token) {
+            // Definitions are split off by `definition`
+            (_, Assign(_)) => return Err(CalculatorError::UnexpectedAssignment),
+            (Empty, Val(v)) => self.state = Operand(Node::leaf(Expr::Val(v))),
+            (Neg, Val(v)) => self.state = Operand(Node::leaf(Expr::Val(-v))),
+            // Negative sign
+            (Empty, Op(Operator::Sub)) => self.state = Neg,
+            // Double negative sign, cancel each other out
+            (Neg, Op(Operator::Sub)) => self.state = Empty,
+            // Positive sign, do nothing
+            (state @ (Empty | Neg), Op(Operator::Add)) => self.state = state,
+            // Call without arguments or an empty list
+            (Empty, ParenClose) if self.argumentless_call_pending(Bracket::Round) => {
+                if let Some(Action::Call(call)) = self.pending.pop() {
+                    self.state = Operand(call.into_node());
+                }
+            }
+            (Empty, BracketClose) if self.argumentless_call_pending(Bracket::Square) => {
+                if let Some(Action::Call(call)) = self.pending.pop() {
+                    self.state = Operand(call.into_node());
+                }
+            }
+            (Empty | Neg, Op(_) | ParenClose) => return Err(CalculatorError::NumberExpected),
+            (Empty, ParenOpen) => self
+                .pending
+                .push(Action::Parentheses(Bracket::Round, false)),
+            (Neg, ParenOpen) => self.pending.push(Action::Parentheses(Bracket::Round, true)),
+            // List literal, e.g. `[1, 2, 3]`, is a call of `list`
+            (Empty, BracketOpen) => self.pending.push(Action::Call(Call::list(false))),
+            (Neg, BracketOpen) => self.pending.push(Action::Call(Call::list(true))),
+            (Empty, BraceOpen) => self
+                .pending
+                .push(Action::Parentheses(Bracket::Curly, false)),
+            (Neg, BraceOpen) => self.pending.push(Action::Parentheses(Bracket::Curly, true)),
+            (Empty, Not) => self.pending.push(Action::Not(false)),
+            (Neg, Not) => self.pending.push(Action::Not(true)),
+            (Empty, Bar) => self.pending.push(Action::Abs(false)),
+            (Neg, Bar) => self.pending.push(Action::Abs(true)),
+            (Empty, Ident(name)) => {
+                self.state = Function {
+                    name,
+                    negative: false,
+                }
+            }
+            (Neg, Ident(name)) => {
+                self.state = Function {
+                    name,
+                    negative: true,
+                }
+            }
+            (Function { name, negative }, ParenOpen) => {
+                self.pending.push(Action::Call(Call {
+                    name,
+                    args: vec![],
+                    depth: Depth::default(),
+                    negative,
+                    bracket: Bracket::Round,
+                }));
+            }
+            (Function { name, negative }, token) => {
+                self.state = Operand(Node::leaf(Expr::Ident(name)).negate_if(negative));
+                return self.update(token);
+            }
+            (Empty | Neg, Comma | Percent | Question | Colon | BracketClose | BraceClose) => {
+                return Err(CalculatorError::NumberExpected)
+            }
+            (Operand(_), Val(_) | Not) => return Err(CalculatorError::OperationExpected),
+            // Implicit multiplication, e.g. `2pi` or `5 km`
+            (Operand(l), Ident(name)) => {
+                self.prioritized_push(Operation {
+                    l,
+                    op: Operator::Mul,
+                    implicit: true,
+                });
+                self.state = Function {
+                    name,
+                    negative: false,
+                };
+            }
+            (Operand(l), Op(op)) => self.prioritized_push(Operation {
+                l,
+                op,
+                implicit: false,
+            }),
+            (Operand(l), ParenOpen) => {
+                self.pending.push(Action::Operation(Operation {
+                    l,
+                    op: Operator::Mul,
+                    implicit: false,
+                }));
+                self.pending
+                    .push(Action::Parentheses(Bracket::Round, false));
+            }
+            (Operand(l), BraceOpen) => {
+                self.pending.push(Action::Operation(Operation {
+                    l,
+                    op: Operator::Mul,
+                    implicit: false,
+                }));
+                self.pending
+                    .push(Action::Parentheses(Bracket::Curly, false));
+            }
+            (Operand(e), ParenClose) => self.finalize_expr(e, Some(Bracket::Round))?,
+            (Operand(e), BraceClose) => self.finalize_expr(e, Some(Bracket::Curly))?,
+            (Operand(e), Comma) => self.next_argument(e)?,
+            (Operand(e), Percent) => self.state = Operand(e.unary(Expr::Percent)),
+            (Operand(e), Question) => self.condition(e),
+            (Operand(e), Colon) => self.alternative(e)?,
+            (Operand(e), BracketOpen) => self.pending.push(Action::Slice(e)),
+            (Operand(e), BracketClose) => self.finalize_slice(e)?,
+            (Operand(e), Bar) => self.finalize_abs(e)?,
+        }
+
+        Ok(())
+    }
+
+    fn argumentless_call_pending(&self, bracket: Bracket) -> bool {
+        matches!(
+            self.pending.last(),
+            Some(Action::Call(call)) if call.args.is_empty() && call.bracket == bracket
+        )
+    }
+
+    /// Whether an element of a list is being parsed
+    fn list_pending(&self) -> bool {
+        matches!(self.pending.last(), Some(Action::Call(call)) if call.name == "list")
+    }
+
+    fn prioritized_push(&mut self, mut new: Operation) {
+        while let Some(pending) = self.pending.pop() {
+            match pending {
+                Action::Operation(op) if op.priority() >= new.priority() => new.l = op.apply(new.l),
+                Action::Not(negative) => new.l = new.l.not(negative),
+                _ => {
+                    self.pending.push(pending);
+                    break;
+                }
+            }
+        }
+        self.pending.push(Action::Operation(new));
+    }
+
+    /// Start a conditional expression with `e` as the condition
+    fn condition(&mut self, mut e: Node) {
+        // Conditional has the lowest priority, but is right associative
+        while let Some(pending) = self.pending.pop() {
+            match pending {
+                Action::Operation(op) => e = op.apply(e),
+                Action::Not(negative) => e = e.not(negative),
+                _ => {
+                    self.pending.push(pending);
+                    break;
+                }
+            }
+        }
+        self.pending.push(Action::Condition(e));
+    }
+
+    /// Complete the `then` branch of the innermost conditional expression
+    fn alternative(&mut self, mut e: Node) -> Result<(), CalculatorError> {
+        while let Some(pending) = self.pending.pop() {
+            match pending {
+                Action::Condition(cond) => {
+                    self.pending.push(Action::Alternative { cond, then: e });
+                    return Ok(());
+                }
+                Action::Slice(value) => {
+                    self.pending.push(Action::SliceLow { value, high: e });
+                    return Ok(());
+                }
+                Action::Alternative { cond, then } => e = Node::conditional(cond, then, e),
+                Action::Operation(op) => e = op.apply(e),
+                Action::Not(negative) => e = e.not(negative),
+                Action::Parentheses(..)
+                | Action::Call(_)
+                | Action::SliceLow { .. }
+                | Action::Abs(_) => break,
+            }
+        }
+        Err(CalculatorError::UnexpectedColon)
+    }
+
+    /// Complete the innermost group closed by `close`, or by any bracket at the end of input
+    fn finalize_expr(
+        &mut self,
+        mut e: Node,
+        close: Option<Bracket>,
+    ) -> Result<(), CalculatorError> {
+        while let Some(pending) = self.pending.pop() {
+            match pending {
+                Action::Parentheses(bracket, _) if close.is_some_and(|c| c != bracket) => {
+                    return Err(CalculatorError::MismatchedBracket(bracket.closing()))
+                }
+                Action::Call(call) if close.is_some_and(|c| c != call.bracket) => {
+                    return Err(CalculatorError::MismatchedBracket(call.bracket.closing()))
+                }
+                Action::Parentheses(_, negative) => {
+                    e = e.negate_if(negative);
+                    break;
+                }
+                Action::Call(mut call) => {
+                    call.push(e);
+                    e = call.into_node();
+                    break;
+                }
+                Action::Operation(op) => e = op.apply(e),
+                Action::Not(negative) => e = e.not(negative),
+                Action::Alternative { cond, then } => e = Node::conditional(cond, then, e),
+                Action::Condition(_) => return Err(CalculatorError::ColonExpected),
+                Action::Slice(_) | Action::SliceLow { .. } => {
+                    return Err(CalculatorError::UnmatchedBracket)
+                }
+                Action::Abs(_) => return Err(CalculatorError::UnmatchedBar),
+            }
+        }
+        self.state = Operand(e);
+        Ok(())
+    }
+
+    fn finalize_slice(&mut self, mut e: Node) -> Result<(), CalculatorError> {
+        while let Some(pending) = self.pending.pop() {
+            match pending {
+                Action::Slice(value) => {
+                    self.state = Operand(Node::slice(value, e, None));
+                    return Ok(());
+                }
+                Action::SliceLow { value, high } => {
+                    self.state = Operand(Node::slice(value, high, Some(e)));
+                    return Ok(());
+                }
+                Action::Operation(op) => e = op.apply(e),
+                Action::Not(negative) => e = e.not(negative),
+                Action::Alternative { cond, then } => e = Node::conditional(cond, then, e),
+                Action::Condition(_) => return Err(CalculatorError::ColonExpected),
+                Action::Call(mut call) if call.bracket == Bracket::Square => {
+                    call.push(e);
+                    self.state = Operand(call.into_node());
+                    return Ok(());
+                }
+                Action::Parentheses(bracket, _) => {
+                    return Err(CalculatorError::MismatchedBracket(bracket.closing()))
+                }
+                Action::Call(_) => {
+                    return Err(CalculatorError::MismatchedBracket(Bracket::Round.closing()))
+                }
+                Action::Abs(_) => break,
+            }
+        }
+        Err(CalculatorError::UnmatchedBracket)
+    }
+
+    fn finalize_abs(&mut self, mut e: Node) -> Result<(), CalculatorError> {
+        while let Some(pending) = self.pending.pop() {
+            match pending {
+                Action::Abs(negative) => {
+                    self.state = Operand(e.abs().negate_if(negative));
+                    return Ok(());
+                }
+                Action::Operation(op) => e = op.apply(e),
+                Action::Not(negative) => e = e.not(negative),
+                Action::Alternative { cond, then } => e = Node::conditional(cond, then, e),
+                Action::Condition(_) => return Err(CalculatorError::ColonExpected),
+                Action::Parentheses(..)
+                | Action::Call(_)
+                | Action::Slice(_)
+                | Action::SliceLow { .. } => break,
+            }
+        }
+        Err(CalculatorError::UnmatchedBar)
+    }
+
+    fn next_argument(&mut self, mut e: Node) -> Result<(), CalculatorError> {
+        while let Some(pending) = self.pending.pop() {
+            match pending {
+                Action::Call(mut call) => {
+                    call.push(e);
+                    self.pending.push(Action::Call(call));
+                    return Ok(());
+                }
+                Action::Operation(op) => e = op.apply(e),
+                Action::Not(negative) => e = e.not(negative),
+                Action::Alternative { cond, then } => e = Node::conditional(cond, then, e),
+                Action::Condition(_) => return Err(CalculatorError::ColonExpected),
+                // Elements of a list in parentheses are a list, e.g. `[(2, 3), (3, 5)]`
+                Action::Parentheses(Bracket::Round, negative) if self.list_pending() => {
+                    let mut tuple = Call::tuple(negative);
+                    tuple.push(e);
+                    self.pending.push(Action::Call(tuple));
+                    return Ok(());
+                }
+                Action::Parentheses(..)
+                | Action::Slice(_)
+                | Action::SliceLow { .. }
+                | Action::Abs(_) => break,
+            }
+        }
+        Err(CalculatorError::UnexpectedComma)
+    }
+
+    pub fn finalize(&mut self) -> Result<Expr, CalculatorError> {
+        let result = match std::mem::take(&mut self.state) {
+            Empty | Neg => Err(CalculatorError::NumberExpected),
+            Operand(e) => Ok(e),
+            Function { name, negative } => Ok(Node::leaf(Expr::Ident(name)).negate_if(negative)),
+        }
+        .and_then(|e| {
+            self.finalize_expr(e, None)?;
+            match std::mem::take(&mut self.state) {
+                Operand(e) if self.too_deep(e.depth) => Err(CalculatorError::TooDeep),
+                Operand(e) => Ok(e.expr),
+                _ => Err(CalculatorError::NumberExpected),
+            }
+        });
+
+        let unmatched = !self.pending.is_empty();
+        self.pending.clear();
+        match result {
+            Ok(_) if unmatched => Err(CalculatorError::UnmatchedParen),
+            result => result,
+        }
+    }
+}
+
+/// Definition of a variable or a function, e.g. `tax = 0.21` or `f(x, y) = x * y`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition<'a> {
+    pub name: &'a str,
+    /// Parameters of a function, `None` for a variable
+    pub params: Option<Vec<&'a str>>,
+    /// Operator of a compound assignment like `total += 42`
+    pub op: Option<Operator>,
+    /// Tokens after the `=`
+    pub expr: &'a [Token],
+}
+
+impl Definition<'_> {
+    /// New value of the variable, the parsed `expr` combined with the current value for compound
+    /// assignments
+    pub fn value(&self, expr: Expr) -> Expr {
+        match self.op {
+            Some(op) => Expr::binary(op, Expr::Ident(self.name.into()), expr),
+            None => expr,
+        }
+    }
+}
+
+/// Definition made by the tokens of a line, `None` if the line is an expression
+pub fn definition(tokens: &[Token]) -> Option<Definition<'_>> {
+    let i = tokens.iter().position(|t| matches!(t, Token::Assign(_)))?;
+    let (target, [Token::Assign(op), expr @ ..]) = tokens.split_at(i) else {
+        return None;
+    };
+    let (name, params) = match target {
+        [Token::Ident(name)] => (name.as_str(), None),
+        // Parameters are names separated by commas
+        [Token::Ident(name), Token::ParenOpen, params @ .., Token::ParenClose]
+            if params.len() % 2 == 1
+                && params.iter().skip(1).step_by(2).all(|t| *t == Token::Comma) =>
+        {
+            let params = params.iter().step_by(2).map(|t| match t {
+                Token::Ident(param) => Some(param.as_str()),
+                _ => None,
+            });
+            (name.as_str(), Some(params.collect::<Option<Vec<_>>>()?))
+        }
+        _ => return None,
+    };
+    // Results and environment variables can't be defined
+    let names_valid = !std::iter::once(name)
+        .chain(params.iter().flatten().copied())
+        .any(|name| name.starts_with('$'));
+    // Only variables can be updated
+    let valid = names_valid && (op.is_none() || params.is_none());
+    valid.then_some(Definition {
+        name,
+        params,
+        op: *op,
+        expr,
+    })
+}
+
+fn abs(e: Expr) -> Expr {
+    Expr::Call {
+        name: "abs".into(),
+        args: vec![e],
+    }
+}
+
+fn conditional(cond: Expr, then: Expr, otherwise: Expr) -> Expr {
+    Expr::Cond {
+        cond: Box::new(cond),
+        then: Box::new(then),
+        otherwise: Box::new(otherwise),
+    }
+}
+
+fn slice(value: Expr, high: Expr, low: Option<Expr>) -> Expr {
+    Expr::Slice {
+        value: Box::new(value),
+        high: Box::new(high),
+        low: low.map(Box::new),
+    }
+}
+
+/// Kind of grouping brackets, a group must be closed with the same kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bracket {
+    Round,
+    Square,
+    Curly,
+}
+
+impl Bracket {
+    fn closing(self) -> char {
+        match self {
+            Bracket::Round => ')',
+            Bracket::Square => ']',
+            Bracket::Curly => '}',
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Action {
+    /// Opening bracket, the group is negated if the flag is set
+    Parentheses(Bracket, bool),
+    Call(Call),
+    Operation(Operation),
+    /// Prefix logical negation, the result is negated if the flag is set
+    Not(bool),
+    /// Condition waiting for the `then` branch
+    Condition(Node),
+    /// Condition and `then` branch waiting for the `otherwise` branch
+    Alternative {
+        cond: Node,
+        then: Node,
+    },
+    /// Value waiting for the bit slice bounds
+    Slice(Node),
+    /// Value and upper bound waiting for the lower bound of the bit slice
+    SliceLow {
+        value: Node,
+        high: Node,
+    },
+    /// Opening absolute value bar, the result is negated if the flag is set
+    Abs(bool),
+}
+
+impl Action {
+    /// Depth of the expressions of the action
+    fn depth(&self) -> Depth {
+        match self {
+            Action::Call(call) => call.depth,
+            Action::Operation(op) => op.l.depth,
+            Action::Condition(node) | Action::Slice(node) => node.depth,
+            Action::Alternative { cond: a, then: b } | Action::SliceLow { value: a, high: b } => {
+                a.depth.max(b.depth)
+            }
+            Action::Parentheses(..) | Action::Not(_) | Action::Abs(_) => Depth::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Call {
+    name: CompactString,
+    args: Vec<Expr>,
+    /// Depth of the deepest argument
+    depth: Depth,
+    negative: bool,
+    /// Closing bracket of the arguments, `]` for list literals
+    bracket: Bracket,
+}
+
+impl Call {
+    fn list(negative: bool) -> Self {
+        Self {
+            name: "list".into(),
+            args: vec![],
+            depth: Depth::default(),
+            negative,
+            bracket: Bracket::Square,
+        }
+    }
+
+    /// Elements in parentheses, e.g. `(2, 3)` in `[(2, 3), (3, 5)]`
+    fn tuple(negative: bool) -> Self {
+        Self {
+            bracket: Bracket::Round,
+            ..Self::list(negative)
+        }
+    }
+
+    fn push(&mut self, arg: Node) {
+        self.depth = self.depth.max(arg.depth);
+        self.args.push(arg.expr);
+    }
+
+    fn into_node(self) -> Node {
+        let expr = Expr::Call {
+            name: self.name,
+            args: self.args,
+        };
+        Node::new(expr, self.depth).negate_if(self.negative)
+    }
+}
+
+/// Depth of an expression, tracked while parsing because deep trees can't be traversed
+/// recursively
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Depth {
+    /// Levels of nesting the calculator evaluates recursively
+    nesting: usize,
+}
+
+impl Depth {
+    const LEAF: Depth = Depth { nesting: 1 };
+
+    fn max(self, other: Depth) -> Depth {
+        Depth {
+            nesting: self.nesting.max(other.nesting),
+        }
+    }
+
+    /// Depth of a node with children of at most this depth
+    fn parent(self) -> Depth {
+        Depth {
+            nesting: self.nesting + 1,
+        }
+    }
+}
+
+/// Expression with the depth of its tree
+#[derive(Debug, Clone)]
+struct Node {
+    expr: Expr,
+    depth: Depth,
+}
+
+impl Node {
+    fn leaf(expr: Expr) -> Self {
+        Self {
+            expr,
+            depth: Depth::LEAF,
+        }
+    }
+
+    /// Node of `expr` with children of at most `depth`
+    fn new(expr: Expr, depth: Depth) -> Self {
+        Self {
+            expr,
+            depth: depth.parent(),
+        }
+    }
+
+    fn unary(self, f: fn(Box<Expr>) -> Expr) -> Self {
+        Self::new(f(Box::new(self.expr)), self.depth)
+    }
+
+    fn negate_if(self, negative: bool) -> Self {
+        if negative {
+            self.unary(Expr::Neg)
+        } else {
+            self
+        }
+    }
+
+    fn not(self, negative: bool) -> Self {
+        self.unary(Expr::Not).negate_if(negative)
+    }
+
+    fn abs(self) -> Self {
+        Self::new(abs(self.expr), self.depth)
+    }
+
+    fn conditional(cond: Node, then: Node, otherwise: Node) -> Self {
+        let depth = cond.depth.max(then.depth).max(otherwise.depth);
+        Self::new(conditional(cond.expr, then.expr, otherwise.expr), depth)
+    }
+
+    fn slice(value: Node, high: Node, low: Option<Node>) -> Self {
+        let depth = value.depth.max(high.depth);
+        let depth = low.as_ref().map_or(depth, |low| depth.max(low.depth));
+        Self::new(slice(value.expr, high.expr, low.map(|low| low.expr)), depth)
+    }
+}
+
+#[derive(Debug)]
+struct Operation {
+    l: Node,
+    op: Operator,
+    /// Multiplication by an identifier without an operator, binds tighter than explicit
+    /// multiplication
+    implicit: bool,
+}
+
+impl Operation {
+    fn apply(self, r: Node) -> Node {
+        let lazy = |op| matches!(op, Operator::And | Operator::Or);
+        let depth = match &self.l.expr {
+            // Left operand is folded into the chain instead of nested, like in `1 + 2 + 3`
+            Expr::Binary { op, .. } if !lazy(self.op) && !lazy(*op) => Depth {
+                nesting: self.l.depth.nesting.max(r.depth.nesting + 1),
+            },
+            _ => self.l.depth.max(r.depth).parent(),
+        };
+        Node {
+            expr: Expr::binary(self.op, self.l.expr, r.expr),
+            depth,
+        }
+    }
+
+    fn priority(&self) -> u8 {
+        if self.implicit {
+            return 25;
+        }
+        self.op.priority()
+    }
+}
+
+impl Operator {
+    /// Binding strength of the binary operator, all operators are left associative
+    pub fn priority(self) -> u8 {
+        match self {
+            Operator::To => 1,
+            Operator::Or => 3,
+            Operator::And => 4,
+            Operator::Eq
+            | Operator::Ne
+            | Operator::Lt
+            | Operator::Le
+            | Operator::Gt
+            | Operator::Ge => 5,
+            Operator::Range | Operator::RangeInclusive => 7,
+            Operator::Add | Operator::Sub | Operator::PlusMinus => 10,
+            Operator::Mul | Operator::Div => 20,
+            Operator::Pow => 30,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculator::Calculator;
+    use crate::printer;
+    use crate::tokenizer::Tokenizer;
+
+    fn tokens(line: &str) -> Vec<Token> {
+        Tokenizer::tokens(line.chars())
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_definition() {
+        let target = |line: &str| {
+            let tokens = tokens(line);
+            let definition = definition(&tokens)?;
+            let params = definition.params.map(|params| params.join(", "));
+            Some((definition.name.to_owned(), params, definition.op))
+        };
+        let variable = |name: &str| Some((name.to_owned(), None, None));
+        assert_eq!(target(" tax = 0.21"), variable("tax"));
+        assert_eq!(target("x_1=x_0*2"), variable("x_1"));
+        assert_eq!(target("x=-1"), variable("x"));
+        assert_eq!(
+            target("f (x, y) = x*y"),
+            Some(("f".into(), Some("x, y".into()), None))
+        );
+        assert_eq!(target("x == 1"), None);
+        assert_eq!(target("x != 1"), None);
+        assert_eq!(target("2 = 1"), None);
+        assert_eq!(target("$1 = 1"), None);
+        assert_eq!(target("f(x, 2) = 1"), None);
+        assert_eq!(target("f() = 1"), None);
+        assert_eq!(target("f(x,) = 1"), None);
+        assert_eq!(target("1 + 1"), None);
+
+        let line = tokens("f(x) = x * 2");
+        assert_eq!(definition(&line).unwrap().expr, &tokens("x * 2")[..]);
+
+        let update = |name: &str, op| Some((name.to_owned(), None, Some(op)));
+        assert_eq!(target("total += 42"), update("total", Operator::Add));
+        assert_eq!(target("x-=1"), update("x", Operator::Sub));
+        assert_eq!(target("x *= 2"), update("x", Operator::Mul));
+        assert_eq!(target("x /= 2"), update("x", Operator::Div));
+        assert_eq!(target("x ÷= 2"), update("x", Operator::Div));
+        assert_eq!(target("x <= 1"), None);
+        assert_eq!(target("x >= 1"), None);
+        assert_eq!(target("f(x) += 1"), None);
+        assert_eq!(target("x ** = 1"), None);
+        let line = tokens("x += 1");
+        assert_eq!(
+            definition(&line).unwrap().value(val(1)),
+            Expr::binary(Operator::Add, Expr::Ident("x".into()), val(1))
+        );
+        assert_eq!(
+            parse(tokens("x = 1")),
+            Err(CalculatorError::UnexpectedAssignment)
+        );
+    }
+
+    const AND: Token = Token::Op(Operator::And);
+    const OR: Token = Token::Op(Operator::Or);
+    const Q: Token = Token::Question;
+    const COLON: Token = Token::Colon;
+
+    fn parse(tokens: Vec<Token>) -> Result<Expr, CalculatorError> {
+        let mut parser = Parser::default();
+        for t in tokens {
+            parser.handle_token(t)?;
+        }
+        parser.finalize()
+    }
+
+    fn val(v: i64) -> Expr {
+        Expr::Val(v.into())
+    }
+
+    #[test]
+    fn test_logical_priority() {
+        // 1 || !2 && 3
+        let res = parse(vec![1.into(), OR, Token::Not, 2.into(), AND, 3.into()]);
+        let not = Expr::Not(Box::new(val(2)));
+        let and = Expr::binary(Operator::And, not, val(3));
+        assert_eq!(res, Ok(Expr::binary(Operator::Or, val(1), and)));
+    }
+
+    #[test]
+    fn test_conditional() {
+        // 1 ? 2 : 3 ? 4 : 5
+        let res = parse(vec![
+            1.into(),
+            Q,
+            2.into(),
+            COLON,
+            3.into(),
+            Q,
+            4.into(),
+            COLON,
+            5.into(),
+        ]);
+        let inner = conditional(val(3), val(4), val(5));
+        assert_eq!(res, Ok(conditional(val(1), val(2), inner)));
+
+        // 1 ? 2 ? 3 : 4 : 5
+        let res = parse(vec![
+            1.into(),
+            Q,
+            2.into(),
+            Q,
+            3.into(),
+            COLON,
+            4.into(),
+            COLON,
+            5.into(),
+        ]);
+        let inner = conditional(val(2), val(3), val(4));
+        assert_eq!(res, Ok(conditional(val(1), inner, val(5))));
+
+        // 1 ? 2
+        let res = parse(vec![1.into(), Q, 2.into()]);
+        assert_eq!(res, Err(CalculatorError::ColonExpected));
+
+        // 1 : 2
+        let res = parse(vec![1.into(), COLON, 2.into()]);
+        assert_eq!(res, Err(CalculatorError::UnexpectedColon));
+    }
+
+    #[test]
+    fn test_slice() {
+        const OPEN: Token = Token::BracketOpen;
+        const CLOSE: Token = Token::BracketClose;
+
+        // 1 + 2[3 - 1:0]
+        let res = parse(vec![
+            1.into(),
+            Token::Op(Operator::Add),
+            2.into(),
+            OPEN,
+            3.into(),
+            Token::Op(Operator::Sub),
+            1.into(),
+            COLON,
+            0.into(),
+            CLOSE,
+        ]);
+        let high = Expr::binary(Operator::Sub, val(3), val(1));
+        let bits = slice(val(2), high, Some(val(0)));
+        assert_eq!(res, Ok(Expr::binary(Operator::Add, val(1), bits)));
+
+        // 1[0 ? 1 : 2]
+        let res = parse(vec![
+            1.into(),
+            OPEN,
+            0.into(),
+            Q,
+            1.into(),
+            COLON,
+            2.into(),
+            CLOSE,
+        ]);
+        let cond = conditional(val(0), val(1), val(2));
+        assert_eq!(res, Ok(slice(val(1), cond, None)));
+
+        // (1[0)]
+        let res = parse(vec![
+            Token::ParenOpen,
+            1.into(),
+            OPEN,
+            0.into(),
+            Token::ParenClose,
+        ]);
+        assert_eq!(res, Err(CalculatorError::UnmatchedBracket));
+    }
+
+    #[test]
+    fn test_brackets() {
+        const ADD: Token = Token::Op(Operator::Add);
+        const MUL: Token = Token::Op(Operator::Mul);
+
+        // -[2 + {3 * 4}]
+        let res = parse(vec![
+            Token::Op(Operator::Sub),
+            Token::BracketOpen,
+            2.into(),
+            ADD,
+            Token::BraceOpen,
+            3.into(),
+            MUL,
+            4.into(),
+            Token::BraceClose,
+            Token::BracketClose,
+        ]);
+        let product = Expr::binary(Operator::Mul, val(3), val(4));
+        let sum = Expr::binary(Operator::Add, val(2), product);
+        let list = |args| Expr::Call {
+            name: "list".into(),
+            args,
+        };
+        assert_eq!(res, Ok(Expr::Neg(Box::new(list(vec![sum])))));
+
+        // [1, [], 2][0]
+        let res = parse(vec![
+            Token::BracketOpen,
+            1.into(),
+            Token::Comma,
+            Token::BracketOpen,
+            Token::BracketClose,
+            Token::Comma,
+            2.into(),
+            Token::BracketClose,
+            Token::BracketOpen,
+            0.into(),
+            Token::BracketClose,
+        ]);
+        let elements = list(vec![val(1), list(vec![]), val(2)]);
+        assert_eq!(res, Ok(slice(elements, val(0), None)));
+
+        // [1 + 2)
+        let res = parse(vec![
+            Token::BracketOpen,
+            1.into(),
+            ADD,
+            2.into(),
+            Token::ParenClose,
+        ]);
+        assert_eq!(res, Err(CalculatorError::MismatchedBracket(']')));
+
+        // max(1]
+        let res = parse(vec![
+            Token::Ident("max".into()),
+            Token::ParenOpen,
+            1.into(),
+            Token::BracketClose,
+        ]);
+        assert_eq!(res, Err(CalculatorError::MismatchedBracket(')')));
+    }
+
+    #[test]
+    fn test_implicit_unit() {
+        let ident = |name: &str| Expr::Ident(name.into());
+        let mul = |l, r| Expr::binary(Operator::Mul, l, r);
+
+        // 90 km / 2 h to m / s
+        let res = parse(vec![
+            90.into(),
+            Token::Ident("km".into()),
+            Token::Op(Operator::Div),
+            2.into(),
+            Token::Ident("h".into()),
+            Token::Op(Operator::To),
+            Token::Ident("m".into()),
+            Token::Op(Operator::Div),
+            Token::Ident("s".into()),
+        ]);
+        let speed = Expr::binary(
+            Operator::Div,
+            mul(val(90), ident("km")),
+            mul(val(2), ident("h")),
+        );
+        let unit = Expr::binary(Operator::Div, ident("m"), ident("s"));
+        assert_eq!(res, Ok(Expr::binary(Operator::To, speed, unit)));
+
+        // 2 m ** 2
+        let res = parse(vec![
+            2.into(),
+            Token::Ident("m".into()),
+            Token::Op(Operator::Pow),
+            2.into(),
+        ]);
+        let area = Expr::binary(Operator::Pow, ident("m"), val(2));
+        assert_eq!(res, Ok(mul(val(2), area)));
+    }
+
+    #[test]
+    fn test_depth() {
+        // Rejected while parsing, before the tree is built or evaluated
+        let mut parens = vec![Token::ParenOpen; 50_000];
+        parens.push(1.into());
+        assert_eq!(parse(parens), Err(CalculatorError::TooDeep));
+
+        // Chains aren't nested when evaluated, however long they are
+        let chain = |length| {
+            let mut chain = vec![1.into()];
+            for _ in 1..length {
+                chain.extend([Token::Op(Operator::Add), 1.into()]);
+            }
+            chain
+        };
+        let expr = parse(chain(200_000)).unwrap();
+        assert_eq!(
+            Calculator::default().evaluate(&expr.clone()),
+            Ok(200_000.into())
+        );
+        assert!(printer::print(&expr).ends_with("1 + 1"));
+        let mut parser = Parser::default();
+        parser.set_max_depth(2);
+        for t in chain(100) {
+            parser.handle_token(t).unwrap();
+        }
+        assert!(parser.finalize().is_ok());
+
+        // Powers are left associative too
+        let mut tower = vec![2.into()];
+        for _ in 0..50_000 {
+            tower.extend([Token::Op(Operator::Pow), 2.into()]);
+        }
+        assert!(parse(tower).is_ok());
+
+        // ((1) + 1) has the depth of 2
+        let tokens = || {
+            vec![
+                Token::ParenOpen,
+                Token::ParenOpen,
+                1.into(),
+                Token::ParenClose,
+                Token::Op(Operator::Add),
+                1.into(),
+                Token::ParenClose,
+            ]
+        };
+        let mut parser = Parser::default();
+        parser.set_max_depth(2);
+        for t in tokens() {
+            parser.handle_token(t).unwrap();
+        }
+        assert!(parser.finalize().is_ok());
+        parser.set_max_depth(1);
+        let res = tokens()
+            .into_iter()
+            .try_for_each(|t| parser.handle_token(t));
+        assert_eq!(
+            res.and_then(|_| parser.finalize()),
+            Err(CalculatorError::TooDeep)
+        );
+    }
+}
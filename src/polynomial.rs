@@ -0,0 +1,220 @@
+use crate::tokenizer::Arithmetic;
+use crate::value::Value;
+use malachite::num::arithmetic::traits::Abs;
+use malachite::num::basic::traits::Zero;
+use malachite::{Integer, Rational};
+use thiserror::Error;
+
+/// Largest degree of products and powers, multiplication takes quadratic time
+const MAX_DEGREE: usize = 10_000;
+
+/// Polynomial in `x` with exact coefficients, e.g. `poly([1, 0, -2])` is `x**2 - 2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Polynomial {
+    /// Coefficients from the constant term up, without trailing zeros
+    coefficients: Vec<Rational>,
+}
+
+impl Polynomial {
+    /// Polynomial of the coefficients from the highest degree down to the constant term
+    pub fn new(coefficients: Vec<Rational>) -> Self {
+        let mut coefficients = coefficients;
+        coefficients.reverse();
+        Self::from_ascending(coefficients)
+    }
+
+    fn from_ascending(mut coefficients: Vec<Rational>) -> Self {
+        while coefficients.last() == Some(&Rational::ZERO) {
+            coefficients.pop();
+        }
+        Self { coefficients }
+    }
+
+    fn constant(x: Rational) -> Self {
+        Self::from_ascending(vec![x])
+    }
+
+    /// Coefficients from the highest degree down to the constant term, empty for zero
+    pub fn coefficients(&self) -> impl Iterator<Item = &Rational> {
+        self.coefficients.iter().rev()
+    }
+
+    /// Degree of the polynomial, `None` for zero
+    pub fn degree(&self) -> Option<usize> {
+        self.coefficients.len().checked_sub(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coefficients.is_empty()
+    }
+
+    /// Sum of the absolute values of the coefficients, which bounds the growth of powers
+    pub fn norm(&self) -> Rational {
+        self.coefficients.iter().map(|c| c.abs()).sum()
+    }
+
+    /// Value at `x` by Horner's method
+    pub fn eval(&self, x: &Rational) -> Rational {
+        self.coefficients()
+            .fold(Rational::ZERO, |acc, c| acc * x + c)
+    }
+
+    fn add(&self, r: &Self) -> Self {
+        let len = self.coefficients.len().max(r.coefficients.len());
+        let coefficient = |p: &Self, i: usize| p.coefficients.get(i).cloned().unwrap_or_default();
+        let sum = (0..len).map(|i| coefficient(self, i) + coefficient(r, i));
+        Self::from_ascending(sum.collect())
+    }
+
+    fn mul(&self, r: &Self) -> Result<Self, PolynomialError> {
+        let (Some(l_degree), Some(r_degree)) = (self.degree(), r.degree()) else {
+            return Ok(Self::from_ascending(vec![]));
+        };
+        if l_degree + r_degree > MAX_DEGREE {
+            return Err(PolynomialError::DegreeTooLarge);
+        }
+        let mut product = vec![Rational::ZERO; l_degree + r_degree + 1];
+        for (i, a) in self.coefficients.iter().enumerate() {
+            for (j, b) in r.coefficients.iter().enumerate() {
+                product[i + j] += a * b;
+            }
+        }
+        Ok(Self::from_ascending(product))
+    }
+
+    fn pow(&self, exp: u64) -> Result<Self, PolynomialError> {
+        let degree = self.degree().unwrap_or(0) as u64;
+        if degree.saturating_mul(exp) > MAX_DEGREE as u64 {
+            return Err(PolynomialError::DegreeTooLarge);
+        }
+        let mut result = Self::constant(Rational::from(1));
+        let mut base = self.clone();
+        let mut exp = exp;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.mul(&base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Quotient and remainder of long division, `None` for division by zero
+    pub fn div_rem(&self, divisor: &Self) -> Option<(Self, Self)> {
+        let divisor_degree = divisor.degree()?;
+        let lead = &divisor.coefficients[divisor_degree];
+        let mut rest = self.coefficients.clone();
+        let mut quotient = vec![Rational::ZERO; rest.len().saturating_sub(divisor_degree)];
+        while rest.len() > divisor_degree {
+            let shift = rest.len() - 1 - divisor_degree;
+            let factor = rest[rest.len() - 1].clone() / lead;
+            for (i, c) in divisor.coefficients.iter().enumerate() {
+                rest[shift + i] -= &factor * c;
+            }
+            quotient[shift] = factor;
+            rest.pop();
+        }
+        Some((Self::from_ascending(quotient), Self::from_ascending(rest)))
+    }
+}
+
+impl std::ops::Neg for Polynomial {
+    type Output = Polynomial;
+
+    fn neg(self) -> Self::Output {
+        let coefficients = self.coefficients.into_iter().map(|c| -c).collect();
+        Self { coefficients }
+    }
+}
+
+fn polynomial(v: Value) -> Result<Polynomial, PolynomialError> {
+    match v {
+        Value::Poly(p) => Ok(p),
+        v => v
+            .to_rational()
+            .map(Polynomial::constant)
+            .ok_or(PolynomialError::UnsupportedOperands),
+    }
+}
+
+/// Execute an operation where at least one of the operands is a polynomial, division results in
+/// the quotient
+pub fn execute(op: Arithmetic, l: Value, r: Value) -> Result<Value, PolynomialError> {
+    if op == Arithmetic::Pow {
+        let exp = r
+            .to_rational()
+            .and_then(|r| Integer::try_from(&r).ok())
+            .and_then(|r| u64::try_from(&r).ok())
+            .ok_or(PolynomialError::InvalidPower)?;
+        return Ok(Value::Poly(polynomial(l)?.pow(exp)?));
+    }
+
+    let (l, r) = (polynomial(l)?, polynomial(r)?);
+    let result = match op {
+        Arithmetic::Add => l.add(&r),
+        Arithmetic::Sub => l.add(&-r),
+        Arithmetic::Mul => l.mul(&r)?,
+        Arithmetic::Div => l.div_rem(&r).ok_or(PolynomialError::DivisionByZero)?.0,
+        Arithmetic::Eq => return Ok((l == r).into()),
+        Arithmetic::Ne => return Ok((l != r).into()),
+        _ => return Err(PolynomialError::UnsupportedOperands),
+    };
+    Ok(Value::Poly(result))
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PolynomialError {
+    #[error("Polynomials can only be raised to non-negative integer powers")]
+    InvalidPower,
+    #[error("Polynomial degree is too large")]
+    DegreeTooLarge,
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Operation is not supported for polynomials")]
+    UnsupportedOperands,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poly(coefficients: &[i64]) -> Polynomial {
+        Polynomial::new(coefficients.iter().map(|&c| Rational::from(c)).collect())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let p = poly(&[1, 0, -2]);
+        assert_eq!(p.degree(), Some(2));
+        assert_eq!(poly(&[0, 0, 3]).degree(), Some(0));
+        assert_eq!(p.add(&poly(&[-1, 1, 2])), poly(&[1, 0]));
+        assert_eq!(p.add(&-p.clone()).degree(), None);
+        assert_eq!(p.mul(&poly(&[1, 1])), Ok(poly(&[1, 1, -2, -2])));
+        assert_eq!(poly(&[1, 1]).pow(3), Ok(poly(&[1, 3, 3, 1])));
+        assert_eq!(
+            poly(&[1, 0]).pow(MAX_DEGREE as u64 + 1),
+            Err(PolynomialError::DegreeTooLarge)
+        );
+        assert_eq!(p.eval(&Rational::from(3)), Rational::from(7));
+    }
+
+    #[test]
+    fn test_div_rem() {
+        // x**3 - 2x**2 - 4 = (x - 3)(x**2 + x + 3) + 5
+        let (q, r) = poly(&[1, -2, 0, -4]).div_rem(&poly(&[1, -3])).unwrap();
+        assert_eq!((q, r), (poly(&[1, 1, 3]), poly(&[5])));
+        let (q, r) = poly(&[1, 0]).div_rem(&poly(&[2])).unwrap();
+        assert_eq!(
+            q,
+            Polynomial::new(vec![Rational::from_signeds(1, 2), Rational::ZERO])
+        );
+        assert!(r.is_zero());
+        let (q, r) = poly(&[1]).div_rem(&poly(&[1, 0])).unwrap();
+        assert_eq!((q, r), (poly(&[]), poly(&[1])));
+        assert_eq!(poly(&[1]).div_rem(&poly(&[])), None);
+    }
+}
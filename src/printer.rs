@@ -0,0 +1,843 @@
+use crate::ast::Expr;
+use crate::complex::Complex;
+use crate::format::Format;
+use crate::tokenizer::{Operator, Token};
+use crate::value::Value;
+use malachite::num::arithmetic::traits::Abs;
+use malachite::{Integer, Natural, Rational};
+use std::fmt::{self, Write};
+
+/// Expression source with canonical spacing and only the necessary parentheses
+pub fn print(expr: &Expr) -> String {
+    let mut result = String::new();
+    // Writing to a string can't fail
+    let _ = write_expr(&mut result, expr);
+    result
+}
+
+/// Value as an expression that evaluates to exactly the same value
+pub fn literal(value: &Value) -> String {
+    let r = match value {
+        Value::Real(r) => r,
+        Value::Complex(c) => return complex_literal(c),
+        Value::List(list) => {
+            let elements: Vec<String> = list.iter().map(literal).collect();
+            return format!("[{}]", elements.join(", "));
+        }
+        Value::Interval(i) => {
+            let (mid, radius) = (Value::Real(i.mid()), Value::Real(i.radius()));
+            return format!("{} ± {}", literal(&mid), literal(&radius));
+        }
+        Value::Symbolic(s) => return print(&s.to_expr()),
+        // Durations of whole seconds are written exactly anyway
+        Value::Duration(d) if d.denominator_ref() != &1u32 => {
+            return format!("{} * 1s", grouped(literal(&Value::Real(d.clone()))));
+        }
+        Value::Quantity(q) => {
+            let magnitude = grouped(literal(&Value::Real(q.magnitude.clone())));
+            return format!("{} {}", magnitude, q.unit.name);
+        }
+        Value::Poly(p) => {
+            let coefficients: Vec<String> = p
+                .coefficients()
+                .map(|c| match Integer::try_from(c) {
+                    Ok(i) => i.to_string(),
+                    Err(_) => literal(&Value::Real(c.clone())),
+                })
+                .collect();
+            return format!("poly([{}])", coefficients.join(", "));
+        }
+        _ => return Format::default().display(value).to_string(),
+    };
+    let (numerator, denominator) = r.to_numerator_and_denominator();
+    // Only fractions with powers of 2 and 5 in the denominator have a finite decimal form
+    let twos = denominator.trailing_zeros().unwrap_or(0);
+    let mut rest = &denominator >> twos;
+    let mut fives = 0;
+    while &rest % Natural::from(5u32) == 0u32 {
+        rest /= Natural::from(5u32);
+        fives += 1;
+    }
+    if denominator == 1u32 {
+        // Keep the number real, `3` would be read as an integer
+        let sign = if *r < 0u32 { "-" } else { "" };
+        return format!("{}{}.0", sign, numerator);
+    }
+    if rest != 1u32 {
+        let sign = if *r < 0u32 { "-" } else { "" };
+        return format!("{}{} / {}", sign, numerator, denominator);
+    }
+    let format = Format {
+        precision: twos.max(fives),
+        ..Format::default()
+    };
+    format.display(value).to_string()
+}
+
+/// Literal in parentheses if it's a division, e.g. `(1 / 3)`
+fn grouped(literal: String) -> String {
+    match literal.contains(' ') {
+        true => format!("({})", literal),
+        false => literal,
+    }
+}
+
+/// Complex number as a sum, e.g. `2 - 3i` or `1 / 3 * i`
+fn complex_literal(c: &Complex) -> String {
+    let part = |x: &Rational| match Integer::try_from(x) {
+        Ok(i) => i.to_string(),
+        Err(_) => literal(&Value::Real(x.clone())),
+    };
+    let im = match part(&(&c.im).abs()) {
+        one if one == "1" => "i".to_string(),
+        // `1 / 3i` would divide by `3i`
+        text if text.contains(' ') => format!("{} * i", text),
+        text => format!("{}i", text),
+    };
+    let sign = if c.im < 0u32 { "-" } else { "+" };
+    match (c.re == 0u32, sign) {
+        (true, "-") => format!("-{}", im),
+        (true, _) => im,
+        (false, sign) => format!("{} {} {}", part(&c.re), sign, im),
+    }
+}
+
+/// Expression as LaTeX math, e.g. `\frac{3}{4} + 2^{10}` for `3/4 + 2**10`
+pub fn latex(expr: &Expr) -> String {
+    let mut result = String::new();
+    let _ = write_latex(&mut result, expr);
+    result
+}
+
+/// Displayed result as LaTeX math, e.g. `\frac{7}{2}`, `1.5 \times 10^{7}` or `5\,\mathrm{km}`
+pub fn latex_value(value: &Value, format: &Format) -> String {
+    match value {
+        Value::List(list) => {
+            let elements: Vec<String> = list.iter().map(|v| latex_value(v, format)).collect();
+            format!("\\left[{}\\right]", elements.join(", "))
+        }
+        Value::Int(_) | Value::Real(_) => latex_number(&format.display(value).to_string()),
+        Value::Complex(c) => latex_complex(c, format),
+        Value::Quantity(q) => {
+            let magnitude = latex_value(&Value::Real(q.magnitude.clone()), format);
+            format!("{}\\,{}", magnitude, latex_unit(&q.unit.name))
+        }
+        _ => format!("\\text{{{}}}", format.display(value)),
+    }
+}
+
+/// Complex number as a sum, e.g. `2 - \frac{1}{3}i`
+fn latex_complex(c: &Complex, format: &Format) -> String {
+    let part = |x: &Rational| latex_value(&Value::Real(x.clone()), format);
+    let im = match (&c.im).abs() {
+        one if one == 1u32 => "i".to_string(),
+        abs => format!("{}i", part(&abs)),
+    };
+    let sign = if c.im < 0u32 { "-" } else { "+" };
+    match (c.re == 0u32, sign) {
+        (true, "-") => format!("-{}", im),
+        (true, _) => im,
+        (false, sign) => format!("{} {} {}", part(&c.re), sign, im),
+    }
+}
+
+/// Unit in upright letters, e.g. `\mathrm{m \cdot s^{-2}}` for `m*s^-2`
+fn latex_unit(name: &str) -> String {
+    let mut result = String::new();
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => result.push_str(" \\cdot "),
+            '^' => {
+                let mut exponent = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '-') {
+                    exponent.push(c);
+                }
+                write!(result, "^{{{}}}", exponent).unwrap();
+            }
+            c => result.push(c),
+        }
+    }
+    format!("\\mathrm{{{}}}", result)
+}
+
+/// Decimal, fraction, mixed fraction, repeating decimal or scientific notation in LaTeX
+fn latex_number(text: &str) -> String {
+    let (sign, abs) = match text.strip_prefix('-') {
+        Some(abs) => ("-", abs),
+        None => ("", text),
+    };
+    let abs = match abs.split_once('e') {
+        Some((mantissa, exponent)) => format!("{} \\times 10^{{{}}}", mantissa, exponent),
+        None => match abs.split_once('/') {
+            Some((numerator, denominator)) => match numerator.split_once(' ') {
+                Some((whole, numerator)) => {
+                    format!("{}\\frac{{{}}}{{{}}}", whole, numerator, denominator)
+                }
+                None => format!("\\frac{{{}}}{{{}}}", numerator, denominator),
+            },
+            None => match abs.split_once('(') {
+                Some((digits, repetend)) => {
+                    let repetend = repetend.trim_end_matches(')');
+                    format!("{}\\overline{{{}}}", digits, repetend)
+                }
+                None => abs.to_string(),
+            },
+        },
+    };
+    format!("{}{}", sign, abs)
+}
+
+/// LaTeX command of an operator
+fn latex_symbol(op: Operator) -> &'static str {
+    match op {
+        Operator::Mul => "\\cdot",
+        Operator::Eq => "=",
+        Operator::Ne => "\\neq",
+        Operator::Le => "\\leq",
+        Operator::Ge => "\\geq",
+        Operator::And => "\\land",
+        Operator::Or => "\\lor",
+        Operator::To => "\\to",
+        Operator::PlusMinus => "\\pm",
+        Operator::Range | Operator::RangeInclusive => "\\ldots",
+        op => symbol(op),
+    }
+}
+
+/// Functions with their own LaTeX command, e.g. `\sin`
+const LATEX_FUNCTIONS: [(&str, &str); 15] = [
+    ("sin", "\\sin"),
+    ("cos", "\\cos"),
+    ("tan", "\\tan"),
+    ("asin", "\\arcsin"),
+    ("acos", "\\arccos"),
+    ("atan", "\\arctan"),
+    ("sinh", "\\sinh"),
+    ("cosh", "\\cosh"),
+    ("tanh", "\\tanh"),
+    ("exp", "\\exp"),
+    ("ln", "\\ln"),
+    ("log", "\\log"),
+    ("max", "\\max"),
+    ("min", "\\min"),
+    ("gcd", "\\gcd"),
+];
+
+/// Binding strength in LaTeX, fractions and function calls are grouped by their braces
+fn latex_priority(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Binary {
+            op: Operator::Div, ..
+        } => u8::MAX,
+        Expr::Val(v) if literal(v).starts_with('-') => priority(&Expr::Neg(Box::new(expr.clone()))),
+        expr => priority(expr),
+    }
+}
+
+fn write_latex(w: &mut String, expr: &Expr) -> fmt::Result {
+    match expr {
+        Expr::Val(Value::Int(i)) => write!(w, "{}", i),
+        // Fractions that aren't finite decimals
+        Expr::Val(v @ Value::Real(r)) if literal(v).contains('/') => {
+            let (numerator, denominator) = r.to_numerator_and_denominator();
+            let sign = if *r < 0u32 { "-" } else { "" };
+            write!(w, "{}\\frac{{{}}}{{{}}}", sign, numerator, denominator)
+        }
+        Expr::Val(v @ (Value::Real(_) | Value::Complex(_))) => write!(w, "{}", literal(v)),
+        Expr::Val(v) => write!(w, "\\text{{{}}}", literal(v)),
+        Expr::Ident(name) => match name.as_str() {
+            "pi" | "tau" => write!(w, "\\{}", name),
+            name if name.chars().count() == 1 => write!(w, "{}", name),
+            name => write!(w, "\\mathrm{{{}}}", name.replace('_', "\\_")),
+        },
+        Expr::Neg(e) => {
+            write!(w, "-")?;
+            write_latex_grouped(w, e, latex_priority(e) <= priority(expr))
+        }
+        Expr::Not(e) => {
+            write!(w, "\\lnot ")?;
+            write_latex_grouped(w, e, latex_priority(e) < priority(expr))
+        }
+        Expr::Percent(e) => {
+            write_latex_grouped(w, e, latex_priority(e) < priority(expr))?;
+            write!(w, "\\%")
+        }
+        Expr::Binary {
+            op: Operator::Div,
+            l,
+            r,
+        } => {
+            write!(w, "\\frac{{")?;
+            write_latex(w, l)?;
+            write!(w, "}}{{")?;
+            write_latex(w, r)?;
+            write!(w, "}}")
+        }
+        // Bases that aren't a single symbol are grouped, `(2**3)**2` included
+        Expr::Binary {
+            op: Operator::Pow,
+            l,
+            r,
+        } => {
+            let atomic = match &**l {
+                Expr::Val(Value::Int(i)) => *i >= 0u32,
+                Expr::Ident(_) | Expr::Call { .. } => true,
+                _ => false,
+            };
+            write_latex_grouped(w, l, !atomic)?;
+            write!(w, "^{{")?;
+            write_latex(w, r)?;
+            write!(w, "}}")
+        }
+        Expr::Binary { op, l, r } => {
+            write_latex_grouped(w, l, latex_priority(l) < op.priority())?;
+            write!(w, " {} ", latex_symbol(*op))?;
+            write_latex_grouped(w, r, latex_priority(r) <= op.priority())
+        }
+        Expr::Call { name, args } => {
+            match (name.as_str(), args.as_slice()) {
+                ("sqrt", [x]) => {
+                    write!(w, "\\sqrt{{")?;
+                    write_latex(w, x)?;
+                    return write!(w, "}}");
+                }
+                ("abs", [x]) => {
+                    write!(w, "\\left|")?;
+                    write_latex(w, x)?;
+                    return write!(w, "\\right|");
+                }
+                ("dice", _) => return write!(w, "\\text{{{}}}", print(expr)),
+                _ => {}
+            }
+            let (open, close) = match name.as_str() {
+                "list" => ("\\left[", "\\right]"),
+                _ => ("\\left(", "\\right)"),
+            };
+            match LATEX_FUNCTIONS.iter().find(|(n, _)| n == name) {
+                _ if *name == "list" => {}
+                Some((_, command)) => write!(w, "{}", command)?,
+                None => write!(w, "\\operatorname{{{}}}", name.replace('_', "\\_"))?,
+            }
+            write!(w, "{}", open)?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(w, ", ")?;
+                }
+                write_latex(w, arg)?;
+            }
+            write!(w, "{}", close)
+        }
+        Expr::Slice { value, high, low } => {
+            write_latex_grouped(w, value, latex_priority(value) < priority(expr))?;
+            write!(w, "\\left[")?;
+            write_latex(w, high)?;
+            if let Some(low) = low {
+                write!(w, ":")?;
+                write_latex(w, low)?;
+            }
+            write!(w, "\\right]")
+        }
+        Expr::Cond {
+            cond,
+            then,
+            otherwise,
+        } => {
+            write!(w, "\\begin{{cases}} ")?;
+            write_latex(w, then)?;
+            write!(w, " & \\text{{if }} ")?;
+            write_latex(w, cond)?;
+            write!(w, " \\\\ ")?;
+            write_latex(w, otherwise)?;
+            write!(w, " & \\text{{otherwise}} \\end{{cases}}")
+        }
+    }
+}
+
+fn write_latex_grouped(w: &mut String, expr: &Expr, parentheses: bool) -> fmt::Result {
+    if parentheses {
+        write!(w, "\\left(")?;
+        write_latex(w, expr)?;
+        write!(w, "\\right)")
+    } else {
+        write_latex(w, expr)
+    }
+}
+
+/// Token kind and text for debugging the tokenizer, e.g. `Op +`
+pub fn token(token: &Token) -> String {
+    match token {
+        Token::Val(v) => {
+            let mut result = String::from("Val ");
+            let _ = write_value(&mut result, v);
+            result
+        }
+        Token::Op(op) => format!("Op {}", symbol(*op)),
+        Token::Ident(name) => format!("Ident {}", name),
+        Token::Assign(op) => format!("Assign {}=", op.map_or("", symbol)),
+        token => format!("{:?}", token),
+    }
+}
+
+/// Indented syntax tree for debugging the parser, one node per line
+pub fn tree(expr: &Expr) -> String {
+    let mut result = String::new();
+    let _ = write_tree(&mut result, expr, 0);
+    result
+}
+
+/// Operator as it is written in expressions
+pub fn symbol(op: Operator) -> &'static str {
+    match op {
+        Operator::Add => "+",
+        Operator::Sub => "-",
+        Operator::Mul => "*",
+        Operator::Div => "/",
+        Operator::Pow => "**",
+        Operator::Eq => "==",
+        Operator::Ne => "!=",
+        Operator::Lt => "<",
+        Operator::Le => "<=",
+        Operator::Gt => ">",
+        Operator::Ge => ">=",
+        Operator::And => "&&",
+        Operator::Or => "||",
+        Operator::To => "to",
+        Operator::PlusMinus => "±",
+        Operator::Range => "..",
+        Operator::RangeInclusive => "..=",
+    }
+}
+
+fn write_tree(w: &mut String, expr: &Expr, depth: usize) -> fmt::Result {
+    write!(w, "{:1$}", "", depth * 2)?;
+    let children: Vec<&Expr> = match expr {
+        Expr::Val(v) => {
+            write_value(w, v)?;
+            vec![]
+        }
+        Expr::Ident(name) => {
+            write!(w, "{}", name)?;
+            vec![]
+        }
+        Expr::Neg(e) => {
+            write!(w, "neg")?;
+            vec![e]
+        }
+        Expr::Not(e) => {
+            write!(w, "!")?;
+            vec![e]
+        }
+        Expr::Percent(e) => {
+            write!(w, "%")?;
+            vec![e]
+        }
+        Expr::Binary { op, l, r } => {
+            write!(w, "{}", symbol(*op))?;
+            vec![l, r]
+        }
+        Expr::Call { name, args } => {
+            write!(w, "{}()", name)?;
+            args.iter().collect()
+        }
+        Expr::Slice { value, high, low } => {
+            write!(w, "[:]")?;
+            [value, high].into_iter().chain(low).map(|e| &**e).collect()
+        }
+        Expr::Cond {
+            cond,
+            then,
+            otherwise,
+        } => {
+            write!(w, "?:")?;
+            vec![cond, then, otherwise]
+        }
+    };
+    writeln!(w)?;
+    for child in children {
+        write_tree(w, child, depth + 1)?;
+    }
+    Ok(())
+}
+
+/// Binding strength of the expression, higher binds tighter
+fn priority(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Cond { .. } => 0,
+        Expr::Binary { op, .. } => op.priority(),
+        // Unary operators bind tighter than binary ones, `-x%` is `(-x)%` and `!x%` is `!(x%)`
+        Expr::Not(_) => 40,
+        Expr::Percent(_) | Expr::Slice { .. } => 50,
+        Expr::Neg(_) => 60,
+        _ => u8::MAX,
+    }
+}
+
+fn write_expr(w: &mut String, expr: &Expr) -> fmt::Result {
+    match expr {
+        // Values that aren't finite decimals are written as an exact division
+        Expr::Val(v) => match literal(v) {
+            text if text.contains(' ') => write!(w, "({})", text),
+            text => write!(w, "{}", text),
+        },
+        Expr::Ident(name) => write!(w, "{}", name),
+        // `--x` isn't a valid expression
+        Expr::Neg(e) => {
+            write!(w, "-")?;
+            write_operand(w, e, priority(expr) + 1)
+        }
+        Expr::Not(e) => {
+            write!(w, "!")?;
+            write_operand(w, e, priority(expr))
+        }
+        Expr::Percent(e) => {
+            write_operand(w, e, priority(expr))?;
+            write!(w, "%")
+        }
+        Expr::Binary { .. } => {
+            // Chains are written in a loop until a left operand needs parentheses
+            let mut chain = vec![];
+            let mut first = expr;
+            while let Expr::Binary { op, l, r } = first {
+                chain.push((*op, r));
+                first = l;
+                if priority(l) < op.priority() {
+                    break;
+                }
+            }
+            let grouped = chain
+                .last()
+                .is_some_and(|(op, _)| priority(first) < op.priority());
+            write_grouped(w, first, grouped)?;
+            // Operators are left associative, so an equal priority on the right needs parentheses
+            for (op, r) in chain.into_iter().rev() {
+                write!(w, " {} ", symbol(op))?;
+                write_grouped(w, r, priority(r) <= op.priority())?;
+            }
+            Ok(())
+        }
+        Expr::Call { name, args } => {
+            // Dice literals are parsed as calls of `dice`, e.g. `3d6` and `d20`
+            if let ("dice", [Expr::Val(Value::Int(count)), Expr::Val(Value::Int(sides))]) =
+                (name.as_str(), args.as_slice())
+            {
+                if *count == 1u32 && *sides > 0u32 {
+                    return write!(w, "d{}", sides);
+                } else if *count > 0u32 && *sides > 0u32 {
+                    return write!(w, "{}d{}", count, sides);
+                }
+            }
+            // List literals are parsed as calls of `list`
+            let (open, close) = match name.as_str() {
+                "list" => ("[", "]"),
+                _ => ("(", ")"),
+            };
+            if open == "(" {
+                write!(w, "{}", name)?;
+            }
+            write!(w, "{}", open)?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(w, ", ")?;
+                }
+                write_expr(w, arg)?;
+            }
+            write!(w, "{}", close)
+        }
+        Expr::Slice { value, high, low } => {
+            write_operand(w, value, priority(expr))?;
+            write!(w, "[")?;
+            write_expr(w, high)?;
+            if let Some(low) = low {
+                write!(w, ":")?;
+                write_expr(w, low)?;
+            }
+            write!(w, "]")
+        }
+        Expr::Cond {
+            cond,
+            then,
+            otherwise,
+        } => {
+            // Conditionals are right associative
+            write_grouped(w, cond, matches!(**cond, Expr::Cond { .. }))?;
+            write!(w, " ? ")?;
+            write_expr(w, then)?;
+            write!(w, " : ")?;
+            write_expr(w, otherwise)
+        }
+    }
+}
+
+/// Operand of a prefix or postfix operator, grouped if it binds weaker than `priority`
+fn write_operand(w: &mut String, expr: &Expr, priority: u8) -> fmt::Result {
+    let negative_literal = match expr {
+        Expr::Val(v) => literal(v).starts_with('-'),
+        _ => false,
+    };
+    write_grouped(w, expr, self::priority(expr) < priority || negative_literal)
+}
+
+fn write_grouped(w: &mut String, expr: &Expr, parentheses: bool) -> fmt::Result {
+    if parentheses {
+        write!(w, "(")?;
+        write_expr(w, expr)?;
+        write!(w, ")")
+    } else {
+        write_expr(w, expr)
+    }
+}
+
+fn write_value(w: &mut String, v: &Value) -> fmt::Result {
+    write!(w, "{}", Format::default().display(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculator::Calculator;
+    use crate::parser::Parser;
+    use crate::polynomial::Polynomial;
+    use crate::tokenizer::Tokenizer;
+    use proptest::collection::vec;
+    use proptest::option;
+    use proptest::prelude::*;
+    use proptest::sample::select;
+
+    fn format(expr: &str) -> String {
+        let mut parser = Parser::default();
+        for t in Tokenizer::tokens(expr.chars()) {
+            parser.handle_token(t.unwrap()).unwrap();
+        }
+        print(&parser.finalize().unwrap())
+    }
+
+    #[test]
+    fn test_print() {
+        assert_eq!(format("2+ 3*(4 -1)"), "2 + 3 * (4 - 1)");
+        assert_eq!(format("((1+2))+(3+4)"), "1 + 2 + (3 + 4)");
+        assert_eq!(format("(2**3)**2 / (1/2)"), "2 ** 3 ** 2 / (1 / 2)");
+        assert_eq!(format("-(x+1)%"), "-(x + 1)%");
+        assert_eq!(format("-(-5)"), "-(-5)");
+        assert_eq!(format("max(1,[2]) ? 1:0"), "max(1, [2]) ? 1 : 0");
+        assert_eq!(format("-[1,[]][0]"), "-[1, []][0]");
+        assert_eq!(format("(1 ? 2 : 3) ? 4 : 5"), "(1 ? 2 : 3) ? 4 : 5");
+        assert_eq!(format("!(a&&b)|||x|"), "!(a && b) || abs(x)");
+        assert_eq!(format("1.5 + 1h30m"), "1.5 + 1h30m");
+        assert_eq!(format("0xff[7:4]"), "255[7:4]");
+        assert_eq!(format("(!x)[1] + -(x%) + (-x)%"), "(!x)[1] + -(x%) + -x%");
+        assert_eq!(format("-(-1h30m)"), "-(-1h30m)");
+        assert_eq!(format("3d6+d20 * 2"), "3d6 + d20 * 2");
+        assert_eq!(format("dice(0, 6)"), "dice(0, 6)");
+
+        let third = Value::Real(Rational::from_signeds(1, 3));
+        let expr = Expr::binary(Operator::Mul, Expr::Val(third), Expr::Ident("x".into()));
+        assert_eq!(expr.to_string(), "(1 / 3) * x");
+        assert_eq!(format("1 / 3.0"), "1 / 3.0");
+
+        let complex = |re, im| Value::Complex(Complex::new(re, im));
+        let value = complex(Rational::from_signeds(1, 3), Rational::from(-2));
+        assert_eq!(literal(&value), "1 / 3 - 2i");
+        let value = complex(Rational::from(0), Rational::from_signeds(-1, 3));
+        let expr = Expr::binary(Operator::Pow, Expr::Val(value), Expr::Val(2.into()));
+        assert_eq!(expr.to_string(), "(-1 / 3 * i) ** 2");
+
+        let coefficients = vec![
+            Rational::from_signeds(1, 2),
+            Rational::from(0),
+            Rational::from(-1),
+        ];
+        let value = Value::Poly(Polynomial::new(coefficients));
+        assert_eq!(literal(&value), "poly([0.5, 0, -1])");
+    }
+
+    #[test]
+    fn test_latex() {
+        let latex = |text: &str| super::latex(&parse(text));
+        assert_eq!(latex("3/4 + 2**10"), "\\frac{3}{4} + 2^{10}");
+        assert_eq!(latex("(1 + x) * y"), "\\left(1 + x\\right) \\cdot y");
+        assert_eq!(latex("(2**3)**2"), "\\left(2^{3}\\right)^{2}");
+        assert_eq!(latex("(x+1)**(1/2)"), "\\left(x + 1\\right)^{\\frac{1}{2}}");
+        assert_eq!(
+            latex("sqrt(pi) <= sin(x)"),
+            "\\sqrt{\\pi} \\leq \\sin\\left(x\\right)"
+        );
+        assert_eq!(
+            latex("-(a - b) + |y|"),
+            "-\\left(a - b\\right) + \\left|y\\right|"
+        );
+        assert_eq!(
+            latex("1 / 3.0 * rate"),
+            "\\frac{1}{3.0} \\cdot \\mathrm{rate}"
+        );
+        assert_eq!(
+            latex("x > 0 ? x : 0"),
+            "\\begin{cases} x & \\text{if } x > 0 \\\\ 0 & \\text{otherwise} \\end{cases}"
+        );
+
+        let format = Format::default();
+        let third = Value::Real(Rational::from_signeds(-1, 3));
+        assert_eq!(latex_value(&Value::from(42), &format), "42");
+        assert_eq!(
+            latex_value(&Value::Real(Rational::from_signeds(7, 2)), &format),
+            "3.5"
+        );
+        let fractions = Format {
+            fractions: crate::format::Fractions::Fraction,
+            ..Format::default()
+        };
+        assert_eq!(latex_value(&third, &fractions), "-\\frac{1}{3}");
+        let repeating = Format {
+            fractions: crate::format::Fractions::Repeating,
+            ..Format::default()
+        };
+        assert_eq!(latex_value(&third, &repeating), "-0.\\overline{3}");
+        let sci = Format {
+            notation: crate::format::Notation::Scientific,
+            ..Format::default()
+        };
+        assert_eq!(
+            latex_value(&Value::from(15000000), &sci),
+            "1.5 \\times 10^{7}"
+        );
+        let complex = |re, im| Value::Complex(Complex::new(Rational::from(re), Rational::from(im)));
+        assert_eq!(latex_value(&complex(1, 2), &format), "1 + 2i");
+        assert_eq!(latex_value(&complex(0, -1), &format), "-i");
+        let c = Value::Complex(Complex::new(
+            Rational::from(0),
+            Rational::from_signeds(-1, 3),
+        ));
+        assert_eq!(latex_value(&c, &fractions), "-\\frac{1}{3}i");
+        let quantity = |text: &str| {
+            let value = Calculator::default().evaluate(&parse(text)).unwrap();
+            latex_value(&value, &format)
+        };
+        assert_eq!(quantity("5 km"), "5\\,\\mathrm{km}");
+        assert_eq!(quantity("3 m/s**2"), "3\\,\\mathrm{m/(s^{2})}");
+        assert_eq!(quantity("2 m * 3 m"), "6\\,\\mathrm{m \\cdot m}");
+    }
+
+    #[test]
+    fn test_tree() {
+        let expr = Expr::binary(
+            Operator::Add,
+            Expr::Val(2.into()),
+            Expr::Call {
+                name: "max".into(),
+                args: vec![Expr::Ident("x".into()), Expr::Val((-1).into())],
+            },
+        );
+        assert_eq!(tree(&expr), "+\n  2\n  max()\n    x\n    -1\n");
+    }
+
+    const OPERATORS: [Operator; 13] = [
+        Operator::Add,
+        Operator::Sub,
+        Operator::Mul,
+        Operator::Div,
+        Operator::Pow,
+        Operator::Eq,
+        Operator::Ne,
+        Operator::Lt,
+        Operator::Le,
+        Operator::Gt,
+        Operator::Ge,
+        Operator::And,
+        Operator::Or,
+    ];
+
+    /// Expressions up to 4 levels deep. Literals are positive, `-1` would be read back as a
+    /// negation and `-0` as zero
+    fn expr() -> impl Strategy<Value = Expr> {
+        let values = vec![
+            Value::from(1),
+            Value::from(7),
+            Value::from(255),
+            Value::Real(Rational::from_signeds(3, 2)),
+            Value::Real(Rational::from_signeds(1, 3)),
+            Value::Real(Rational::from(4)),
+            Value::Duration(Rational::from(5400)),
+        ];
+        let leaf = prop_oneof![
+            select(values).prop_map(Expr::Val),
+            select(&["x", "y", "pi"][..]).prop_map(|name| Expr::Ident(name.into())),
+        ];
+        leaf.prop_recursive(4, 64, 3, |inner| {
+            let boxed = || inner.clone().prop_map(Box::new);
+            prop_oneof![
+                boxed().prop_map(Expr::Neg),
+                boxed().prop_map(Expr::Not),
+                boxed().prop_map(Expr::Percent),
+                (
+                    select(&["max", "min", "abs"][..]),
+                    vec(inner.clone(), 1..=3)
+                )
+                    .prop_map(|(name, args)| Expr::Call {
+                        name: name.into(),
+                        args
+                    }),
+                (boxed(), boxed(), option::of(boxed()))
+                    .prop_map(|(value, high, low)| { Expr::Slice { value, high, low } }),
+                (boxed(), boxed(), boxed()).prop_map(|(cond, then, otherwise)| Expr::Cond {
+                    cond,
+                    then,
+                    otherwise
+                }),
+                (select(&OPERATORS[..]), boxed(), boxed()).prop_map(|(op, l, r)| Expr::Binary {
+                    op,
+                    l,
+                    r
+                }),
+            ]
+        })
+    }
+
+    fn parse(text: &str) -> Expr {
+        let mut parser = Parser::default();
+        for t in Tokenizer::tokens(text.chars()) {
+            let t = t.unwrap_or_else(|e| panic!("{}: {}", text, e));
+            parser
+                .handle_token(t)
+                .unwrap_or_else(|e| panic!("{}: {}", text, e));
+        }
+        parser
+            .finalize()
+            .unwrap_or_else(|e| panic!("{}: {}", text, e))
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2_000))]
+
+        #[test]
+        fn test_round_trip(expr in expr()) {
+            let mut calculator = Calculator::default();
+            calculator.set_max_bits(Some(1024));
+            calculator.set_variable("x", Value::from(12));
+            calculator.set_variable("y", Value::Real(Rational::from_signeds(-5, 4)));
+            let text = print(&expr);
+            let parsed = parse(&text);
+            // Fractions are written as a division, which is parsed back as the same division
+            prop_assert_eq!(parse(&print(&parsed)), parsed.clone(), "{}", text);
+            prop_assert_eq!(
+                calculator.evaluate(&parsed),
+                calculator.evaluate(&expr),
+                "{}",
+                text
+            );
+        }
+
+        #[test]
+        fn test_token_round_trip(expr in expr()) {
+            let text = print(&expr);
+            let tokens: Vec<Token> = Tokenizer::tokens(text.chars())
+                .map(Result::unwrap)
+                .collect();
+            let printed: Vec<String> = tokens.iter().map(Token::to_string).collect();
+            let joined = printed.join(" ");
+            let again: Vec<Token> = Tokenizer::tokens(joined.chars())
+                .map(Result::unwrap)
+                .collect();
+            prop_assert_eq!(again, tokens, "{}", joined);
+        }
+    }
+}
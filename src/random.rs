@@ -0,0 +1,132 @@
+use malachite::num::arithmetic::traits::ModPowerOf2;
+use malachite::num::basic::traits::One;
+use malachite::num::conversion::traits::PowerOf2Digits;
+use malachite::num::logic::traits::SignificantBits;
+use malachite::Natural;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::fmt;
+
+/// Names of the random number functions, which are evaluated by the calculator
+pub const FUNCTIONS: [&str; 3] = ["rand", "randbits", "dice"];
+
+/// Maximum number of dice of a single roll
+pub const MAX_DICE: u64 = 1 << 20;
+
+/// Dice rolled by an expression like `3d6`, with the results of the individual dice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Roll {
+    pub sides: Natural,
+    pub results: Vec<Natural>,
+}
+
+impl Roll {
+    pub fn total(&self) -> Natural {
+        self.results.iter().sum()
+    }
+}
+
+impl fmt::Display for Roll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}d{}: [", self.results.len(), self.sides)?;
+        for (i, result) in self.results.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", result)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Cryptographically secure random number generator
+#[derive(Debug, Clone)]
+pub struct Rng(ChaCha20Rng);
+
+impl Rng {
+    /// Generator with a key from the operating system
+    pub fn from_entropy() -> Self {
+        Self(ChaCha20Rng::from_os_rng())
+    }
+
+    /// Generator that always produces the same numbers for the same seed
+    pub fn from_seed(seed: u64) -> Self {
+        Self::from_line(seed, 0)
+    }
+
+    /// Generator of line `line` of the input, lines have the same numbers in any order
+    pub fn from_line(seed: u64, line: u64) -> Self {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        rng.set_stream(line);
+        Self(rng)
+    }
+
+    /// Independent generator keyed by this one, for another thread
+    pub fn fork(&mut self) -> Self {
+        Self(ChaCha20Rng::from_rng(&mut self.0))
+    }
+
+    pub fn fill(&mut self, bytes: &mut [u8]) {
+        self.0.fill_bytes(bytes);
+    }
+
+    /// Random number of `n` bits, less than `2**n`
+    pub fn bits(&mut self, n: u64) -> Natural {
+        let mut bytes = vec![0u8; n.div_ceil(8) as usize];
+        self.fill(&mut bytes);
+        Natural::from_power_of_2_digits_asc(8, bytes.into_iter())
+            .expect("bytes are digits of base 256")
+            .mod_power_of_2(n)
+    }
+
+    /// Random number less than `n`, which is positive
+    pub fn below(&mut self, n: &Natural) -> Natural {
+        let bits = n.significant_bits();
+        // Rejection sampling, at least half of the candidates are accepted
+        loop {
+            let x = self.bits(bits);
+            if x < *n {
+                return x;
+            }
+        }
+    }
+
+    /// Roll `count` dice with `sides` sides, which is positive
+    pub fn roll(&mut self, count: u64, sides: Natural) -> Roll {
+        let results = (0..count)
+            .map(|_| self.below(&sides) + Natural::ONE)
+            .collect();
+        Roll { sides, results }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed() {
+        let (mut a, mut b) = (Rng::from_seed(42), Rng::from_seed(42));
+        assert_eq!(a.bits(256), b.bits(256));
+        assert_ne!(a.bits(256), Rng::from_seed(43).bits(256));
+        assert!(a.bits(5) < 32u32);
+        let n = Natural::from(6u32);
+        assert!((0..100).all(|_| a.below(&n) < n));
+        let mut fork = a.fork();
+        assert_ne!(fork.bits(64), a.bits(64));
+    }
+
+    #[test]
+    fn test_roll() {
+        let roll = Rng::from_seed(1).roll(100, Natural::from(6u32));
+        assert_eq!(roll.results.len(), 100);
+        assert!(roll.results.iter().all(|x| (1u32..=6).contains(x)));
+        assert!(roll.total() >= 100u32 && roll.total() <= 600u32);
+        let roll = Roll {
+            sides: Natural::from(6u32),
+            results: vec![Natural::from(2u32), Natural::from(5u32)],
+        };
+        assert_eq!(roll.to_string(), "2d6: [2, 5]");
+        assert_eq!(Rng::from_seed(1).roll(0, Natural::ONE).total(), 0u32);
+    }
+}
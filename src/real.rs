@@ -0,0 +1,517 @@
+//! Arbitrary precision evaluation of irrational functions.
+//!
+//! Computations are done in binary fixed point on big integers with a number of guard bits. The
+//! result keeps [`GUARD_DIGITS`] decimal places beyond the requested ones, so that results used in
+//! further computations like `atan(1) * 4` are still correct when displayed, which rounds them
+//! once to the requested places.
+
+use malachite::num::arithmetic::traits::{
+    Abs, CheckedSqrt, DivRound, FloorSqrt, Pow, PowerOf2, Reciprocal, Sign, UnsignedAbs,
+};
+use malachite::num::basic::traits::{One, Zero};
+use malachite::num::conversion::traits::RoundingFrom;
+use malachite::num::logic::traits::SignificantBits;
+use malachite::rounding_modes::RoundingMode;
+use malachite::{Integer, Natural, Rational};
+use std::cmp::Ordering;
+use thiserror::Error;
+
+/// Default number of decimal places for irrational results
+pub const DEFAULT_PRECISION: u64 = 50;
+
+/// Decimal places of irrational results beyond the requested precision
+pub const GUARD_DIGITS: u64 = 10;
+
+const GUARD_BITS: u64 = 32;
+
+/// Largest argument of exponential functions, the result has about 1.44 bits per unit of the
+/// argument
+const MAX_EXP: u64 = 1 << 25;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RealError {
+    #[error("argument must not be negative")]
+    Negative,
+    #[error("argument must be positive")]
+    NonPositive,
+    #[error("argument must be between -1 and 1")]
+    OutOfRange,
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("result is too large")]
+    TooLarge,
+}
+
+pub fn sqrt(x: &Rational, digits: u64) -> Result<Rational, RealError> {
+    if *x < 0u32 {
+        return Err(RealError::Negative);
+    }
+    if let Some(exact) = x.checked_sqrt() {
+        return Ok(exact);
+    }
+    let bits = bits_for_digits(digits);
+    Ok(approximate(&sqrt_fixed(x, bits), digits))
+}
+
+pub fn exp(x: &Rational, digits: u64) -> Result<Rational, RealError> {
+    if *x > MAX_EXP {
+        return Err(RealError::TooLarge);
+    }
+    Ok(approximate(&exp_fixed(x, bits_for_digits(digits)), digits))
+}
+
+pub fn ln(x: &Rational, digits: u64) -> Result<Rational, RealError> {
+    if *x <= 0u32 {
+        return Err(RealError::NonPositive);
+    }
+    Ok(approximate(&ln_fixed(x, bits_for_digits(digits)), digits))
+}
+
+/// Logarithm of `x` with an arbitrary base
+pub fn log(x: &Rational, base: &Rational, digits: u64) -> Result<Rational, RealError> {
+    if *x <= 0u32 || *base <= 0u32 {
+        return Err(RealError::NonPositive);
+    }
+    if *base == 1u32 {
+        return Err(RealError::DivisionByZero);
+    }
+    // Quotient error is amplified by the inverse of the base logarithm
+    let ln_base_bits = magnitude(&(base - Rational::ONE)).min(0).unsigned_abs();
+    let bits = bits_for_digits(digits) + magnitude(x).unsigned_abs() + 2 * ln_base_bits;
+    Ok(approximate(
+        &(ln_fixed(x, bits) / ln_fixed(base, bits)),
+        digits,
+    ))
+}
+
+/// `b^e` for non-integer exponents
+pub fn pow(b: &Rational, e: &Rational, digits: u64) -> Result<Rational, RealError> {
+    match b.sign() {
+        Ordering::Less => return Err(RealError::Negative),
+        Ordering::Equal if *e > 0u32 => return Ok(Rational::ZERO),
+        Ordering::Equal => return Err(RealError::DivisionByZero),
+        Ordering::Greater => {}
+    }
+    let bits = bits_for_digits(digits);
+    // Estimate result magnitude to get enough bits for the exponent
+    let ln_b = ln_fixed(b, bits + GUARD_BITS);
+    let t = e * ln_b;
+    if t > MAX_EXP {
+        return Err(RealError::TooLarge);
+    }
+    let extra = magnitude(&t).max(0).unsigned_abs() * 2 + magnitude(e).max(0).unsigned_abs();
+    let t = e * ln_fixed(b, bits + extra + GUARD_BITS);
+    Ok(approximate(&exp_fixed(&t, bits), digits))
+}
+
+/// Whether `|b^e|` rounds to zero at `digits` decimal places, for powers too large to compute
+/// exactly like `e ** -(10 ** 10)`
+pub fn underflows(b: &Rational, e: &Rational, digits: u64) -> bool {
+    if *b == 0u32 {
+        return false;
+    }
+    // Upper bound of the binary logarithm of the result
+    let log = match e.sign() {
+        Ordering::Greater => b.ceiling_log_base_2_abs(),
+        _ => b.floor_log_base_2_abs(),
+    };
+    e * Rational::from(log) < -Rational::from(bits_for_digits(digits))
+}
+
+pub fn pi(digits: u64) -> Rational {
+    approximate(&pi_fixed(bits_for_digits(digits)), digits)
+}
+
+pub fn e(digits: u64) -> Rational {
+    approximate(&exp_fixed(&Rational::ONE, bits_for_digits(digits)), digits)
+}
+
+pub fn sin(x: &Rational, digits: u64) -> Rational {
+    let (sin, _) = sin_cos_fixed(x, bits_for_digits(digits));
+    approximate(&sin, digits)
+}
+
+pub fn cos(x: &Rational, digits: u64) -> Rational {
+    let (_, cos) = sin_cos_fixed(x, bits_for_digits(digits));
+    approximate(&cos, digits)
+}
+
+pub fn tan(x: &Rational, digits: u64) -> Rational {
+    // Close to the poles cosine is small, compensate for the loss of precision
+    let bits = bits_for_digits(digits);
+    let (_, cos) = sin_cos_fixed(x, bits);
+    let extra = magnitude(&cos).min(0).unsigned_abs() * 2;
+    let (sin, cos) = sin_cos_fixed(x, bits + extra);
+    approximate(&(sin / cos), digits)
+}
+
+pub fn atan(x: &Rational, digits: u64) -> Rational {
+    approximate(&atan_fixed(x, bits_for_digits(digits)), digits)
+}
+
+pub fn asin(x: &Rational, digits: u64) -> Result<Rational, RealError> {
+    Ok(approximate(
+        &asin_fixed(x, bits_for_digits(digits))?,
+        digits,
+    ))
+}
+
+pub fn acos(x: &Rational, digits: u64) -> Result<Rational, RealError> {
+    let bits = bits_for_digits(digits);
+    let acos = pi_fixed(bits) / Rational::from(2u32) - asin_fixed(x, bits)?;
+    Ok(approximate(&acos, digits))
+}
+
+pub fn sinh(x: &Rational, digits: u64) -> Result<Rational, RealError> {
+    if x.abs() > MAX_EXP {
+        return Err(RealError::TooLarge);
+    }
+    let bits = bits_for_digits(digits);
+    let (exp, exp_neg) = exp_pair(x, bits);
+    Ok(approximate(
+        &((exp - exp_neg) / Rational::from(2u32)),
+        digits,
+    ))
+}
+
+pub fn cosh(x: &Rational, digits: u64) -> Result<Rational, RealError> {
+    if x.abs() > MAX_EXP {
+        return Err(RealError::TooLarge);
+    }
+    let bits = bits_for_digits(digits);
+    let (exp, exp_neg) = exp_pair(x, bits);
+    Ok(approximate(
+        &((exp + exp_neg) / Rational::from(2u32)),
+        digits,
+    ))
+}
+
+pub fn tanh(x: &Rational, digits: u64) -> Rational {
+    let bits = bits_for_digits(digits) + GUARD_BITS;
+    // 1 - |tanh(x)| is about 2 * exp(-2 * |x|), which rounds away
+    if x.abs() > bits {
+        return Rational::from(x.sign() as i8);
+    }
+    let (exp, exp_neg) = exp_pair(x, bits);
+    approximate(&((&exp - &exp_neg) / (exp + exp_neg)), digits)
+}
+
+/// Round to `digits` decimal places
+pub fn round(x: &Rational, digits: u64) -> Rational {
+    let scale = Integer::from(10u32).pow(digits);
+    let scaled = x * Rational::from(&scale);
+    let (rounded, _) = Integer::rounding_from(scaled, RoundingMode::Nearest);
+    Rational::from_integers(rounded, scale)
+}
+
+/// Round to `digits` decimal places and the guard digits
+fn approximate(x: &Rational, digits: u64) -> Rational {
+    round(x, digits + GUARD_DIGITS)
+}
+
+fn bits_for_digits(digits: u64) -> u64 {
+    // log2(10) < 3.322
+    (digits + GUARD_DIGITS) * 3322 / 1000 + 1 + GUARD_BITS
+}
+
+/// Rough binary logarithm of `|x|`, `0` for zero
+fn magnitude(x: &Rational) -> i64 {
+    let num = x.numerator_ref().significant_bits() as i64;
+    let den = x.denominator_ref().significant_bits() as i64;
+    if num == 0 {
+        0
+    } else {
+        num - den
+    }
+}
+
+/// Convert to a fixed point number with `bits` fractional bits
+fn to_fixed(x: &Rational, bits: u64) -> Integer {
+    let (num, den) = x.to_numerator_and_denominator();
+    let num = Integer::from(num) << bits;
+    let num = if *x < 0u32 { -num } else { num };
+    num.div_round(Integer::from(den), RoundingMode::Nearest).0
+}
+
+fn from_fixed(x: Integer, bits: u64) -> Rational {
+    Rational::from_integers(x, Integer::power_of_2(bits))
+}
+
+fn sqrt_fixed(x: &Rational, bits: u64) -> Rational {
+    let fixed = to_fixed(x, 2 * bits).unsigned_abs();
+    from_fixed(Integer::from(fixed.floor_sqrt()), bits)
+}
+
+/// `exp(x)` with absolute error around `2^-bits`
+fn exp_fixed(x: &Rational, bits: u64) -> Rational {
+    if -x > bits {
+        return Rational::ZERO;
+    }
+    // Reduce the argument by halving it `k` times, then square the result `k` times
+    let k = (magnitude(x) + 8).max(0).unsigned_abs();
+    // Squaring amplifies the relative error, large results need more precision
+    let result_bits = if *x > 0u32 {
+        let (int, _) = Integer::rounding_from(x, RoundingMode::Ceiling);
+        u64::try_from(&int).unwrap_or(u64::MAX / 4) * 3 / 2
+    } else {
+        0
+    };
+    let wp = bits + k + result_bits + GUARD_BITS;
+
+    let one = Integer::power_of_2(wp);
+    let y = to_fixed(&(x / Rational::from(Natural::power_of_2(k))), wp);
+    let mut sum = one.clone();
+    let mut term = one;
+    let mut n = 1u32;
+    loop {
+        term = ((term * &y) >> wp) / Integer::from(n);
+        if term == 0 {
+            break;
+        }
+        sum += &term;
+        n += 1;
+    }
+    for _ in 0..k {
+        sum = (&sum * &sum) >> wp;
+    }
+    from_fixed(sum, wp)
+}
+
+/// `(exp(x), exp(-x))` with absolute error around `2^-bits`
+fn exp_pair(x: &Rational, bits: u64) -> (Rational, Rational) {
+    let exp = exp_fixed(x, bits);
+    let exp_neg = exp_fixed(&-x, bits);
+    (exp, exp_neg)
+}
+
+/// `ln(x)` for a positive `x` with absolute error around `2^-bits`
+fn ln_fixed(x: &Rational, bits: u64) -> Rational {
+    // x = m * 2^e, 1 <= m < 2
+    let mut e = magnitude(x);
+    let mut m = x / Rational::power_of_2(e);
+    while m < 1u32 {
+        m *= Rational::from(2u32);
+        e -= 1;
+    }
+    while m >= 2u32 {
+        m /= Rational::from(2u32);
+        e += 1;
+    }
+
+    let wp = bits + 64 - e.unsigned_abs().leading_zeros() as u64 + GUARD_BITS;
+    // ln(m) = 2 * atanh((m - 1) / (m + 1))
+    let y = (&m - Rational::ONE) / (m + Rational::ONE);
+    let ln_m = atanh_fixed(&y, wp) * Rational::from(2u32);
+    let ln2 = atanh_fixed(&Rational::from_signeds(1, 3), wp) * Rational::from(2u32);
+    ln_m + ln2 * Rational::from(e)
+}
+
+/// `atanh(y)` for `|y| <= 1/3` using the Taylor series
+fn atanh_fixed(y: &Rational, bits: u64) -> Rational {
+    let y = to_fixed(y, bits);
+    let y2 = (&y * &y) >> bits;
+    let mut sum = Integer::ZERO;
+    let mut power = y;
+    let mut n = 1u32;
+    while power != 0 {
+        sum += &power / Integer::from(n);
+        power = (power * &y2) >> bits;
+        n += 2;
+    }
+    from_fixed(sum, bits)
+}
+
+/// `atan(1/n)` for an integer `n > 1` using the Taylor series
+fn atan_inv_fixed(n: u32, bits: u64) -> Integer {
+    let n2 = Integer::from(n) * Integer::from(n);
+    let mut power = Integer::power_of_2(bits) / Integer::from(n);
+    let mut sum = Integer::ZERO;
+    let mut k = 1u32;
+    let mut negative = false;
+    while power != 0 {
+        let term = &power / Integer::from(k);
+        if negative {
+            sum -= term;
+        } else {
+            sum += term;
+        }
+        power /= &n2;
+        k += 2;
+        negative = !negative;
+    }
+    sum
+}
+
+fn pi_fixed(bits: u64) -> Rational {
+    // Machin's formula: pi = 16 * atan(1/5) - 4 * atan(1/239)
+    let wp = bits + GUARD_BITS;
+    let pi = atan_inv_fixed(5, wp) * Integer::from(16) - atan_inv_fixed(239, wp) * Integer::from(4);
+    from_fixed(pi, wp)
+}
+
+fn atan_fixed(x: &Rational, bits: u64) -> Rational {
+    if *x < 0u32 {
+        return -atan_fixed(&-x, bits);
+    }
+    if *x > 1u32 {
+        return pi_fixed(bits) / Rational::from(2u32) - atan_fixed(&x.reciprocal(), bits);
+    }
+
+    let wp = bits + GUARD_BITS;
+    // atan(x) = 2 * atan(x / (1 + sqrt(1 + x^2))), applied twice gets x below tan(pi/16)
+    let mut x = x.clone();
+    for _ in 0..2 {
+        let root = sqrt_fixed(&(Rational::ONE + &x * &x), wp);
+        x /= Rational::ONE + root;
+    }
+
+    let y = to_fixed(&x, wp);
+    let y2 = (&y * &y) >> wp;
+    let mut sum = Integer::ZERO;
+    let mut power = y;
+    let mut n = 1u32;
+    let mut negative = false;
+    while power != 0 {
+        let term = &power / Integer::from(n);
+        if negative {
+            sum -= term;
+        } else {
+            sum += term;
+        }
+        power = (power * &y2) >> wp;
+        n += 2;
+        negative = !negative;
+    }
+    from_fixed(sum, wp) * Rational::from(4u32)
+}
+
+fn asin_fixed(x: &Rational, bits: u64) -> Result<Rational, RealError> {
+    let half_pi = || pi_fixed(bits) / Rational::from(2u32);
+    match x.abs().partial_cmp(&Rational::ONE) {
+        Some(Ordering::Greater) => Err(RealError::OutOfRange),
+        Some(Ordering::Equal) if *x < 0u32 => Ok(-half_pi()),
+        Some(Ordering::Equal) => Ok(half_pi()),
+        _ => {
+            // asin(x) = atan(x / sqrt(1 - x^2)), the derivative grows near the edges
+            let extra = magnitude(&(Rational::ONE - x.abs())).min(0).unsigned_abs();
+            let wp = bits + extra + GUARD_BITS;
+            let root = sqrt_fixed(&(Rational::ONE - x * x), wp);
+            Ok(atan_fixed(&(x / root), bits))
+        }
+    }
+}
+
+/// `(sin(x), cos(x))` with absolute error around `2^-bits`
+fn sin_cos_fixed(x: &Rational, bits: u64) -> (Rational, Rational) {
+    // Reduce the argument to [-pi, pi], large arguments need more digits of pi
+    let wp = bits + magnitude(x).max(0).unsigned_abs() + GUARD_BITS;
+    let two_pi = pi_fixed(wp) * Rational::from(2u32);
+    let (k, _) = Integer::rounding_from(x / &two_pi, RoundingMode::Nearest);
+    let r = to_fixed(&(x - two_pi * Rational::from(k)), wp);
+
+    let r2 = (&r * &r) >> wp;
+    let one = Integer::power_of_2(wp);
+    let mut sin = Integer::ZERO;
+    let mut cos = Integer::ZERO;
+    // Terms r^n / n! alternate between cosine (even n) and sine (odd n)
+    let mut term = one;
+    let mut n = 0u32;
+    while term != 0 {
+        let cos_term = term.clone();
+        let sin_term = ((&term * &r) >> wp) / Integer::from(n + 1);
+        if n.is_multiple_of(4) {
+            cos += cos_term;
+            sin += sin_term;
+        } else {
+            cos -= cos_term;
+            sin -= sin_term;
+        }
+        term = ((term * &r2) >> wp) / Integer::from((n + 1) * (n + 2));
+        n += 2;
+    }
+    (from_fixed(sin, wp), from_fixed(cos, wp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use malachite::num::conversion::traits::FromSciString;
+
+    fn assert_digits(x: Rational, expected: &str) {
+        let digits = expected.len() - expected.find('.').unwrap() - 1;
+        let expected = Rational::from_sci_string(expected).unwrap();
+        assert_eq!(round(&x, digits as u64), expected);
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_digits(
+            pi(50),
+            "3.14159265358979323846264338327950288419716939937511",
+        );
+        assert_digits(e(40), "2.7182818284590452353602874713526624977572");
+    }
+
+    #[test]
+    fn test_functions() {
+        let x = Rational::from_signeds(1, 2);
+        assert_digits(
+            sqrt(&Rational::from(2u32), 40).unwrap(),
+            "1.4142135623730950488016887242096980785697",
+        );
+        assert_digits(
+            ln(&Rational::from(10u32), 40).unwrap(),
+            "2.3025850929940456840179914546843642076011",
+        );
+        assert_digits(sin(&x, 30), "0.479425538604203000273287935216");
+        assert_digits(cos(&x, 30), "0.877582561890372716116281582604");
+        assert_digits(
+            atan(&Rational::from(3u32), 30),
+            "1.249045772398254425829917077281",
+        );
+        assert_digits(asin(&x, 30).unwrap(), "0.523598775598298873077107230547");
+        assert_digits(
+            exp(&Rational::from(-10), 30).unwrap(),
+            "0.000045399929762484851535591516",
+        );
+        assert_digits(
+            pow(&Rational::from(2u32), &x, 30).unwrap(),
+            "1.414213562373095048801688724210",
+        );
+        // sin(1000) requires a precise argument reduction
+        assert_digits(
+            sin(&Rational::from(1000u32), 30),
+            "0.826879540532002560255887429109",
+        );
+    }
+
+    #[test]
+    fn test_domain() {
+        assert_eq!(sqrt(&Rational::from(-1), 10), Err(RealError::Negative));
+        assert_eq!(ln(&Rational::ZERO, 10), Err(RealError::NonPositive));
+        assert_eq!(asin(&Rational::from(2u32), 10), Err(RealError::OutOfRange));
+        assert_eq!(
+            sqrt(&Rational::from_signeds(9, 4), 10),
+            Ok(Rational::from_signeds(3, 2))
+        );
+    }
+
+    #[test]
+    fn test_large() {
+        let huge = Rational::from(Natural::power_of_2(64));
+        assert_eq!(exp(&huge, 10), Err(RealError::TooLarge));
+        assert_eq!(exp(&-&huge, 10), Ok(Rational::ZERO));
+        assert_eq!(sinh(&-&huge, 10), Err(RealError::TooLarge));
+        assert_eq!(cosh(&huge, 10), Err(RealError::TooLarge));
+        assert_eq!(tanh(&huge, 10), Rational::ONE);
+        assert_eq!(tanh(&-&huge, 10), -Rational::ONE);
+        let x = Rational::from_signeds(3, 2);
+        assert_eq!(pow(&x, &(&huge + &x), 10), Err(RealError::TooLarge));
+        let x = Rational::from_signeds(5, 2);
+        assert!(underflows(&x, &-&huge, 10));
+        assert!(underflows(&(&x).reciprocal(), &huge, 10));
+        assert!(!underflows(&x, &huge, 10));
+        assert!(!underflows(&x, &Rational::from(-10), 10));
+        assert!(!underflows(&Rational::ZERO, &huge, 10));
+    }
+}
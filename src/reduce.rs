@@ -0,0 +1,192 @@
+use crate::ast::Expr;
+use crate::calculator::{Calculator, CalculatorError};
+use crate::tokenizer::Operator;
+use crate::value::Value;
+use compact_str::CompactString;
+use malachite::Rational;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Aggregation of many values into one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reduction {
+    Sum,
+    Product,
+    Mean,
+    Min,
+    Max,
+    Count,
+    Median,
+    Variance,
+    Stddev,
+    /// Percentile from 0 to 100, written as `p90`
+    Percentile(u8),
+}
+
+impl Reduction {
+    /// Names of the reductions, except for percentiles
+    pub const NAMES: [&'static str; 9] = [
+        "sum", "product", "mean", "min", "max", "count", "median", "variance", "stddev",
+    ];
+
+    /// Fold the values with the calculator operations, so units and durations are preserved
+    pub fn apply(self, values: Vec<Value>, calculator: &Calculator) -> Result<Value, ReduceError> {
+        let execute = |op, l: Value, r: Value| {
+            calculator.evaluate(&Expr::binary(op, Expr::Val(l), Expr::Val(r)))
+        };
+        let count = values.len();
+        let statistic = match self {
+            Reduction::Median => Some(("median", None)),
+            Reduction::Variance => Some(("variance", None)),
+            Reduction::Stddev => Some(("stddev", None)),
+            Reduction::Percentile(p) => Some(("percentile", Some(p))),
+            _ => None,
+        };
+        // Statistics of numbers are computed by the list functions
+        if let Some((name, p)) = statistic {
+            if values.is_empty() {
+                return Err(ReduceError::Empty);
+            }
+            let list = Expr::Val(Value::List(values));
+            let args = std::iter::once(list)
+                .chain(p.map(|p| Expr::Val(Value::from(i64::from(p)))))
+                .collect();
+            let call = Expr::Call {
+                name: name.into(),
+                args,
+            };
+            return Ok(calculator.evaluate(&call)?);
+        }
+        let mut values = values.into_iter();
+        let init = match self {
+            Reduction::Count => return Ok(Value::from(count as i64)),
+            // Start from the first value, durations and quantities can't be added to a number
+            Reduction::Sum => values.next().unwrap_or(Value::from(0)),
+            Reduction::Product => values.next().unwrap_or(Value::from(1)),
+            Reduction::Mean | Reduction::Min | Reduction::Max => {
+                values.next().ok_or(ReduceError::Empty)?
+            }
+            _ => unreachable!("statistics are returned early"),
+        };
+        let result = match self {
+            Reduction::Sum | Reduction::Mean => {
+                values.try_fold(init, |l, r| execute(Operator::Add, l, r))?
+            }
+            Reduction::Product => values.try_fold(init, |l, r| execute(Operator::Mul, l, r))?,
+            Reduction::Min | Reduction::Max => {
+                let op = if self == Reduction::Min {
+                    Operator::Lt
+                } else {
+                    Operator::Gt
+                };
+                values.try_fold(init, |best, v| {
+                    let better = execute(op, v.clone(), best.clone())?.to_bool() == Some(true);
+                    Ok::<_, CalculatorError>(if better { v } else { best })
+                })?
+            }
+            _ => unreachable!("count and statistics are returned early"),
+        };
+        if self == Reduction::Mean {
+            // Divide by a real number so that integer sums don't truncate
+            let count = Value::Real(Rational::from(count));
+            return Ok(execute(Operator::Div, result, count)?);
+        }
+        Ok(result)
+    }
+}
+
+impl FromStr for Reduction {
+    type Err = ReduceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sum" => Ok(Reduction::Sum),
+            "product" => Ok(Reduction::Product),
+            "mean" => Ok(Reduction::Mean),
+            "min" => Ok(Reduction::Min),
+            "max" => Ok(Reduction::Max),
+            "count" => Ok(Reduction::Count),
+            "median" => Ok(Reduction::Median),
+            "variance" => Ok(Reduction::Variance),
+            "stddev" => Ok(Reduction::Stddev),
+            _ => s
+                .strip_prefix('p')
+                .and_then(|p| p.parse().ok())
+                .filter(|p| *p <= 100)
+                .map(Reduction::Percentile)
+                .ok_or_else(|| ReduceError::Unknown(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Reduction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Reduction::Percentile(p) = self {
+            return write!(f, "p{}", p);
+        }
+        let name = match self {
+            Reduction::Sum => "sum",
+            Reduction::Product => "product",
+            Reduction::Mean => "mean",
+            Reduction::Min => "min",
+            Reduction::Max => "max",
+            Reduction::Count => "count",
+            Reduction::Median => "median",
+            Reduction::Variance => "variance",
+            Reduction::Stddev => "stddev",
+            Reduction::Percentile(_) => unreachable!("percentiles are written above"),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ReduceError {
+    #[error(
+        "Unknown reduction: {0}, expected one of: {}, or pN for a percentile",
+        Reduction::NAMES.join(", ")
+    )]
+    Unknown(CompactString),
+    #[error("No values to reduce")]
+    Empty,
+    #[error(transparent)]
+    Calculator(#[from] CalculatorError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let calculator = Calculator::default();
+        let values = || vec![Value::from(3), Value::from(1), Value::from(2)];
+        let apply = |r: Reduction, values| r.apply(values, &calculator).map(|v| v.to_string());
+        assert_eq!(apply(Reduction::Sum, values()), Ok("6".into()));
+        assert_eq!(apply(Reduction::Product, values()), Ok("6".into()));
+        assert_eq!(
+            apply(Reduction::Mean, vec![1.into(), 2.into()]),
+            Ok("1.5".into())
+        );
+        assert_eq!(apply(Reduction::Min, values()), Ok("1".into()));
+        assert_eq!(apply(Reduction::Max, values()), Ok("3".into()));
+        assert_eq!(apply(Reduction::Count, values()), Ok("3".into()));
+        assert_eq!(apply(Reduction::Sum, vec![]), Ok("0".into()));
+        assert_eq!(apply(Reduction::Mean, vec![]), Err(ReduceError::Empty));
+        assert_eq!(apply(Reduction::Median, values()), Ok("2".into()));
+        assert_eq!(apply(Reduction::Variance, values()), Ok("1".into()));
+        assert_eq!(apply(Reduction::Percentile(75), values()), Ok("2.5".into()));
+        assert_eq!(apply(Reduction::Median, vec![]), Err(ReduceError::Empty));
+    }
+
+    #[test]
+    fn test_names() {
+        for name in Reduction::NAMES.iter().chain(&["p0", "p95", "p100"]) {
+            let reduction: Reduction = name.parse().unwrap();
+            assert_eq!(reduction.to_string(), *name);
+        }
+        assert!("p101".parse::<Reduction>().is_err());
+        assert!("median5".parse::<Reduction>().is_err());
+    }
+}
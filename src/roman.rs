@@ -0,0 +1,76 @@
+/// Roman numeral symbols with their values, including subtractive pairs, largest first
+const SYMBOLS: [(&str, u32); 13] = [
+    ("M", 1000),
+    ("CM", 900),
+    ("D", 500),
+    ("CD", 400),
+    ("C", 100),
+    ("XC", 90),
+    ("L", 50),
+    ("XL", 40),
+    ("X", 10),
+    ("IX", 9),
+    ("V", 5),
+    ("IV", 4),
+    ("I", 1),
+];
+
+/// Largest number that can be written in standard Roman numerals
+const MAX: u32 = 3999;
+
+/// Roman numeral of a number between 1 and [`MAX`]
+pub fn format(mut n: u32) -> Option<String> {
+    if !(1..=MAX).contains(&n) {
+        return None;
+    }
+    let mut result = String::new();
+    for (symbol, value) in SYMBOLS {
+        while n >= value {
+            result.push_str(symbol);
+            n -= value;
+        }
+    }
+    Some(result)
+}
+
+/// Value of a Roman numeral, only the standard form is accepted, e.g. `IV` but not `IIII`
+pub fn parse(text: &str) -> Option<u32> {
+    let mut rest = text;
+    let mut n = 0;
+    for (symbol, value) in SYMBOLS {
+        while let Some(r) = rest.strip_prefix(symbol) {
+            rest = r;
+            n += value;
+        }
+    }
+    // Non-standard forms don't survive the round trip
+    if rest.is_empty() && format(n).as_deref() == Some(text) {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format(2024).as_deref(), Some("MMXXIV"));
+        assert_eq!(format(1994).as_deref(), Some("MCMXCIV"));
+        assert_eq!(format(3999).as_deref(), Some("MMMCMXCIX"));
+        assert_eq!(format(0), None);
+        assert_eq!(format(4000), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("MMXXIV"), Some(2024));
+        assert_eq!(parse("XLII"), Some(42));
+        assert_eq!(parse("IIII"), None);
+        assert_eq!(parse("IC"), None);
+        assert_eq!(parse("ABC"), None);
+        assert_eq!(parse(""), None);
+    }
+}
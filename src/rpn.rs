@@ -0,0 +1,380 @@
+use crate::ast::Expr;
+use crate::calculator::CalculatorError;
+use crate::format::Format;
+use crate::functions;
+use crate::parser::DEFAULT_MAX_DEPTH;
+use crate::printer;
+use crate::tokenizer::Token;
+use crate::units::Unit;
+use crate::value::Value;
+use compact_str::CompactString;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Reverse Polish notation evaluator, e.g. `3 4 + 2 *`
+///
+/// Operations are evaluated by the calculator as soon as their operands are on the stack, the
+/// stack is kept between expressions.
+#[derive(Debug, Default)]
+pub struct Rpn {
+    stack: Vec<Value>,
+}
+
+impl Rpn {
+    /// Push a value or apply an operation, expressions are evaluated by `evaluate`
+    pub fn handle_token(
+        &mut self,
+        token: Token,
+        evaluate: impl Fn(&Expr) -> Result<Value, CalculatorError>,
+    ) -> Result<(), RpnError> {
+        let expr = match token {
+            Token::Val(v) => {
+                self.stack.push(v);
+                return Ok(());
+            }
+            Token::Op(op) => {
+                let [l, r] = self.pop()?;
+                Expr::binary(op, Expr::Val(l), Expr::Val(r))
+            }
+            Token::Not => {
+                let [x] = self.pop()?;
+                Expr::Not(Box::new(Expr::Val(x)))
+            }
+            Token::Percent => {
+                let [x] = self.pop()?;
+                Expr::Percent(Box::new(Expr::Val(x)))
+            }
+            Token::Ident(name) => match name.as_str() {
+                "neg" => {
+                    let [x] = self.pop()?;
+                    Expr::Neg(Box::new(Expr::Val(x)))
+                }
+                "dup" => {
+                    let [x] = self.pop()?;
+                    self.stack.extend([x.clone(), x]);
+                    return Ok(());
+                }
+                "swap" => {
+                    let [a, b] = self.pop()?;
+                    self.stack.extend([b, a]);
+                    return Ok(());
+                }
+                "drop" => {
+                    self.pop::<1>()?;
+                    return Ok(());
+                }
+                // Constants and units push their value, anything else is a function of one argument
+                _ => match evaluate(&Expr::Ident(name.clone())) {
+                    Ok(value) => {
+                        self.stack.push(value);
+                        return Ok(());
+                    }
+                    Err(_) => {
+                        let [x] = self.pop()?;
+                        Expr::Call {
+                            name,
+                            args: vec![Expr::Val(x)],
+                        }
+                    }
+                },
+            },
+            _ => return Err(RpnError::UnexpectedToken),
+        };
+        let result = evaluate(&expr)?;
+        self.stack.push(result);
+        Ok(())
+    }
+
+    /// Value at the top of the stack
+    pub fn top(&self) -> Option<&Value> {
+        self.stack.last()
+    }
+
+    /// Pop `N` values, the top of the stack is the last one
+    fn pop<const N: usize>(&mut self) -> Result<[Value; N], RpnError> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(N)
+            .ok_or(RpnError::StackUnderflow)?;
+        let values: Vec<Value> = self.stack.drain(start..).collect();
+        values.try_into().map_err(|_| RpnError::StackUnderflow)
+    }
+}
+
+/// Build the expression tree of an expression in reverse Polish notation
+pub fn parse(tokens: impl IntoIterator<Item = Token>) -> Result<Expr, RpnError> {
+    // Expressions with the levels of nesting the calculator evaluates recursively
+    let mut stack: Vec<(Expr, usize)> = vec![];
+    for token in tokens {
+        let (expr, depth) = match token {
+            Token::Val(v) => (Expr::Val(v), 1),
+            Token::Op(op) => {
+                let (r, r_depth) = pop(&mut stack)?;
+                let (l, l_depth) = pop(&mut stack)?;
+                // Left operand is folded into the chain instead of nested, like in `1 2 + 3 +`
+                let depth = match l {
+                    Expr::Binary { .. } => l_depth.max(r_depth + 1),
+                    _ => l_depth.max(r_depth) + 1,
+                };
+                (Expr::binary(op, l, r), depth)
+            }
+            Token::Not => unary(&mut stack, Expr::Not)?,
+            Token::Percent => unary(&mut stack, Expr::Percent)?,
+            Token::Ident(name) => match name.as_str() {
+                "neg" => unary(&mut stack, Expr::Neg)?,
+                "dup" => {
+                    let x = pop(&mut stack)?;
+                    stack.push(x.clone());
+                    x
+                }
+                "swap" => {
+                    let b = pop(&mut stack)?;
+                    let a = pop(&mut stack)?;
+                    stack.push(b);
+                    a
+                }
+                "drop" => {
+                    pop(&mut stack)?;
+                    continue;
+                }
+                _ if is_constant(&name) => (Expr::Ident(name), 1),
+                _ => {
+                    let (arg, depth) = pop(&mut stack)?;
+                    let args = vec![arg];
+                    (Expr::Call { name, args }, depth + 1)
+                }
+            },
+            _ => return Err(RpnError::UnexpectedToken),
+        };
+        if depth > DEFAULT_MAX_DEPTH {
+            return Err(CalculatorError::TooDeep.into());
+        }
+        stack.push((expr, depth));
+    }
+    match (stack.pop(), stack.is_empty()) {
+        (Some((expr, _)), true) => Ok(expr),
+        (Some(_), false) => Err(RpnError::UnusedValues),
+        (None, _) => Err(RpnError::StackUnderflow),
+    }
+}
+
+fn pop(stack: &mut Vec<(Expr, usize)>) -> Result<(Expr, usize), RpnError> {
+    stack.pop().ok_or(RpnError::StackUnderflow)
+}
+
+fn unary(
+    stack: &mut Vec<(Expr, usize)>,
+    f: fn(Box<Expr>) -> Expr,
+) -> Result<(Expr, usize), RpnError> {
+    let (e, depth) = pop(stack)?;
+    Ok((f(Box::new(e)), depth + 1))
+}
+
+/// Whether the name is a constant or a unit rather than a function
+fn is_constant(name: &str) -> bool {
+    functions::constant(name, 0).is_some() || Unit::lookup(name).is_some()
+}
+
+/// Expression in reverse Polish notation, the inverse of [`parse`]
+pub fn print(expr: &Expr) -> Result<String, RpnError> {
+    let mut words = vec![];
+    write_words(&mut words, expr)?;
+    Ok(words.join(" "))
+}
+
+fn write_words(words: &mut Vec<String>, expr: &Expr) -> Result<(), RpnError> {
+    match expr {
+        // Negative numbers would be read as subtraction
+        Expr::Val(v) if v.to_rational().is_some_and(|r| r < 0u32) => {
+            words.push(Format::default().display(&-v.clone()).to_string());
+            words.push("neg".into());
+        }
+        Expr::Val(v) => words.push(Format::default().display(v).to_string()),
+        Expr::Ident(name) => words.push(name.to_string()),
+        Expr::Neg(e) => {
+            write_words(words, e)?;
+            words.push("neg".into());
+        }
+        Expr::Not(e) => {
+            write_words(words, e)?;
+            words.push("!".into());
+        }
+        Expr::Percent(e) => {
+            write_words(words, e)?;
+            words.push("%".into());
+        }
+        // Chains are written in a loop, the leftmost operand first
+        Expr::Binary { .. } => {
+            let mut chain = vec![];
+            let mut first = expr;
+            while let Expr::Binary { op, l, r } = first {
+                chain.push((*op, r));
+                first = l;
+            }
+            write_words(words, first)?;
+            for (op, r) in chain.into_iter().rev() {
+                write_words(words, r)?;
+                words.push(printer::symbol(op).into());
+            }
+        }
+        Expr::Call { name, args } if args.len() == 1 => {
+            write_words(words, &args[0])?;
+            words.push(name.to_string());
+        }
+        Expr::Call { name, .. } => return Err(RpnError::NotExpressible(name.clone())),
+        Expr::Slice { .. } => return Err(RpnError::NotExpressible("bit slice".into())),
+        Expr::Cond { .. } => return Err(RpnError::NotExpressible("conditional".into())),
+    }
+    Ok(())
+}
+
+/// Notation of expressions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Notation {
+    /// Operators between operands, e.g. `2 * (3 + 4)`
+    #[default]
+    Infix,
+    /// Reverse Polish notation, operators after operands, e.g. `2 3 4 + *`
+    Rpn,
+}
+
+impl Notation {
+    pub const NAMES: [&'static str; 2] = ["infix", "rpn"];
+}
+
+impl FromStr for Notation {
+    type Err = RpnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "infix" => Ok(Notation::Infix),
+            "rpn" => Ok(Notation::Rpn),
+            _ => Err(RpnError::Notation(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Notation::Infix => write!(f, "infix"),
+            Notation::Rpn => write!(f, "rpn"),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RpnError {
+    #[error("Not enough values on the stack")]
+    StackUnderflow,
+    #[error("Only numbers, operators and functions are supported in RPN mode")]
+    UnexpectedToken,
+    #[error("More than one value is left on the stack")]
+    UnusedValues,
+    #[error("Can't be written in RPN: {0}")]
+    NotExpressible(CompactString),
+    #[error("Unknown notation: {0}, expected one of: {}", Notation::NAMES.join(", "))]
+    Notation(CompactString),
+    #[error(transparent)]
+    Calculator(#[from] CalculatorError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculator::Calculator;
+    use crate::tokenizer::Operator;
+
+    fn evaluate(tokens: Vec<Token>) -> Result<Option<Value>, RpnError> {
+        let calculator = Calculator::default();
+        let mut rpn = Rpn::default();
+        for t in tokens {
+            rpn.handle_token(t, |expr| calculator.evaluate(expr))?;
+        }
+        Ok(rpn.top().cloned())
+    }
+
+    #[test]
+    fn test_evaluate() {
+        // 3 4 + 2 *
+        let res = evaluate(vec![
+            3.into(),
+            4.into(),
+            Token::Op(Operator::Add),
+            2.into(),
+            Token::Op(Operator::Mul),
+        ]);
+        assert_eq!(res, Ok(Some(Value::from(14))));
+
+        // 2 10 swap - neg abs
+        let res = evaluate(vec![
+            2.into(),
+            10.into(),
+            Token::Ident("swap".into()),
+            Token::Op(Operator::Sub),
+            Token::Ident("neg".into()),
+            Token::Ident("abs".into()),
+        ]);
+        assert_eq!(res, Ok(Some(Value::from(8))));
+
+        // 1 +
+        let res = evaluate(vec![1.into(), Token::Op(Operator::Add)]);
+        assert_eq!(res, Err(RpnError::StackUnderflow));
+
+        // 1 (
+        let res = evaluate(vec![1.into(), Token::ParenOpen]);
+        assert_eq!(res, Err(RpnError::UnexpectedToken));
+    }
+
+    #[test]
+    fn test_convert() {
+        let val = |v: i64| Expr::Val(v.into());
+        // 2 * (pi - -3)
+        let expr = Expr::binary(
+            Operator::Mul,
+            val(2),
+            Expr::binary(Operator::Sub, Expr::Ident("pi".into()), val(-3)),
+        );
+        assert_eq!(print(&expr).as_deref(), Ok("2 pi 3 neg - *"));
+
+        let tokens = vec![
+            2.into(),
+            Token::Ident("pi".into()),
+            3.into(),
+            Token::Ident("neg".into()),
+            Token::Op(Operator::Sub),
+            Token::Op(Operator::Mul),
+        ];
+        let neg = Expr::Neg(Box::new(val(3)));
+        let expected = Expr::binary(
+            Operator::Mul,
+            val(2),
+            Expr::binary(Operator::Sub, Expr::Ident("pi".into()), neg),
+        );
+        assert_eq!(parse(tokens), Ok(expected));
+
+        assert_eq!(parse(vec![1.into(), 2.into()]), Err(RpnError::UnusedValues));
+        let max = Expr::Call {
+            name: "max".into(),
+            args: vec![val(1), val(2)],
+        };
+        assert_eq!(print(&max), Err(RpnError::NotExpressible("max".into())));
+    }
+
+    #[test]
+    fn test_parse_depth() {
+        let mut tokens = vec![1.into()];
+        for _ in 0..100_000 {
+            tokens.push(Token::Ident("neg".into()));
+        }
+        assert_eq!(parse(tokens), Err(CalculatorError::TooDeep.into()));
+
+        let mut chain = vec![1.into()];
+        for _ in 0..100_000 {
+            chain.extend([1.into(), Token::Op(Operator::Add)]);
+        }
+        assert!(parse(chain).is_ok());
+    }
+}
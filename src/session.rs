@@ -0,0 +1,269 @@
+use crate::ast::Expr;
+use crate::calculator::{Calculator, CalculatorError, Function};
+use crate::compiled::CompileError;
+use crate::format::Format;
+use crate::parser;
+use crate::tokenizer::{Token, TokenizeError, Tokenizer};
+use crate::value::Value;
+use compact_str::CompactString;
+
+/// Evaluation of lines that keeps variables, functions and results between them
+///
+/// Lines are expressions or definitions like `x = 2` and `f(x) = x * 2`, as in interactive
+/// sessions of the command line tool.
+#[derive(Debug, Default)]
+pub struct Session {
+    tokenizer: Tokenizer,
+    calculator: Calculator,
+    format: Format,
+}
+
+/// Variable or function defined by a line, see [`Session::define`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Defined {
+    pub name: CompactString,
+    /// New value of a variable and the format it's displayed in, `None` for a function
+    pub value: Option<(Value, Format)>,
+}
+
+impl Session {
+    pub fn new(calculator: Calculator, format: Format) -> Self {
+        Self {
+            tokenizer: Tokenizer::default(),
+            calculator,
+            format,
+        }
+    }
+
+    /// Read lines with the settings of the tokenizer, e.g. a decimal comma
+    pub fn set_tokenizer(&mut self, tokenizer: Tokenizer) {
+        self.tokenizer = tokenizer;
+    }
+
+    pub fn tokenizer(&self) -> &Tokenizer {
+        &self.tokenizer
+    }
+
+    pub fn tokenizer_mut(&mut self) -> &mut Tokenizer {
+        &mut self.tokenizer
+    }
+
+    pub fn calculator(&self) -> &Calculator {
+        &self.calculator
+    }
+
+    pub fn calculator_mut(&mut self) -> &mut Calculator {
+        &mut self.calculator
+    }
+
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+
+    pub fn format_mut(&mut self) -> &mut Format {
+        &mut self.format
+    }
+
+    /// Evaluate the line, function definitions have no value
+    pub fn eval(&mut self, line: &str) -> Result<Option<Value>, CompileError> {
+        Ok(self.evaluate(line)?.map(|(value, _)| value))
+    }
+
+    /// Like [`Session::eval`], the value is formatted for display
+    pub fn eval_text(&mut self, line: &str) -> Result<Option<String>, CompileError> {
+        let result = self.evaluate(line)?;
+        Ok(result.map(|(value, format)| format.display(&value).to_string()))
+    }
+
+    /// Value of the line and the format it's displayed in, see [`Format::for_expr`]
+    fn evaluate(&mut self, line: &str) -> Result<Option<(Value, Format)>, CompileError> {
+        match self.define(line, Calculator::evaluate) {
+            Some(defined) => Ok(defined?.value),
+            None => {
+                let expr = self.parse(&self.tokens(line)?)?;
+                let value = self.calculator.evaluate(&expr)?;
+                self.calculator.push_history(value.clone());
+                Ok(Some((value, self.format.for_expr(&expr))))
+            }
+        }
+    }
+
+    /// Name of the variable or function defined by the line, `None` for expressions
+    pub fn defined_name(&self, line: &str) -> Option<CompactString> {
+        let (tokens, _) = self.read(line);
+        parser::definition(&tokens).map(|definition| definition.name.into())
+    }
+
+    /// Define the variable or function of a definition line, `None` if the line is an
+    /// expression. Values of variables are computed by `evaluate`, e.g. to stop it on Ctrl-C
+    pub fn define(
+        &mut self,
+        line: &str,
+        evaluate: impl FnOnce(&Calculator, &Expr) -> Result<Value, CalculatorError>,
+    ) -> Option<Result<Defined, CompileError>> {
+        let (tokens, error) = self.read(line);
+        let definition = parser::definition(&tokens)?;
+        if let Some(error) = error {
+            return Some(Err(error.into()));
+        }
+        let expr = match self.parse(definition.expr) {
+            Ok(expr) => expr,
+            Err(e) => return Some(Err(e)),
+        };
+        let name = CompactString::from(definition.name);
+        let Some(params) = definition.params else {
+            let format = self.format.for_expr(&expr);
+            let value = match evaluate(&self.calculator, &definition.value(expr)) {
+                Ok(value) => value,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.calculator.set_variable(name.clone(), value.clone());
+            return Some(Ok(Defined {
+                name,
+                value: Some((value, format)),
+            }));
+        };
+        let params = params.into_iter().map(Into::into).collect();
+        let function = Function { params, body: expr };
+        self.calculator.define_function(name.clone(), function);
+        Some(Ok(Defined { name, value: None }))
+    }
+
+    /// Tokens of the line up to the first error, and the error
+    fn read(&self, line: &str) -> (Vec<Token>, Option<TokenizeError>) {
+        let mut tokens = vec![];
+        for token in self.tokenizer.clone().into_tokens(line.chars()) {
+            match token {
+                Ok(token) => tokens.push(token),
+                Err(e) => return (tokens, Some(e)),
+            }
+        }
+        (tokens, None)
+    }
+
+    fn tokens(&self, line: &str) -> Result<Vec<Token>, CompileError> {
+        match self.read(line) {
+            (_, Some(e)) => Err(e.into()),
+            (tokens, None) => Ok(tokens),
+        }
+    }
+
+    fn parse(&mut self, tokens: &[Token]) -> Result<Expr, CompileError> {
+        let result = tokens
+            .iter()
+            .try_for_each(|token| self.calculator.handle_token(token.clone()));
+        match result.and_then(|_| self.calculator.parse()) {
+            Ok(expr) => Ok(expr),
+            Err(e) => {
+                self.calculator.reset();
+                Err(e.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculator::CalculatorError;
+
+    #[test]
+    fn test_eval() {
+        let mut session = Session::default();
+        assert_eq!(session.eval("x = 2 ** 10"), Ok(Some(1024.into())));
+        assert_eq!(session.eval("f(a) = a + x"), Ok(None));
+        assert_eq!(session.eval_text("f(1) / 5.0"), Ok(Some("205".into())));
+        assert_eq!(session.eval_text("$1 * 2"), Ok(Some("410".into())));
+        assert!(session.eval("1 +").is_err());
+        assert_eq!(session.eval("x"), Ok(Some(1024.into())));
+        assert_eq!(
+            session.eval("y"),
+            Err(CompileError::Calculator(
+                CalculatorError::UnknownIdentifier("y".into())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_define() {
+        let mut session = Session::default();
+        let mut tokenizer = Tokenizer::default();
+        tokenizer.set_decimal_comma(true);
+        session.set_tokenizer(tokenizer);
+        let defined = session.define("f(x; y) = x * y", Calculator::evaluate);
+        assert_eq!(
+            defined,
+            Some(Ok(Defined {
+                name: "f".into(),
+                value: None
+            }))
+        );
+        let defined = session.define("a = f(1,5; 2)", |_, _| Ok(Value::from(7)));
+        let format = Format::default();
+        let value = Some((Value::from(7), format));
+        assert_eq!(
+            defined,
+            Some(Ok(Defined {
+                name: "a".into(),
+                value
+            }))
+        );
+        assert_eq!(session.eval_text("a + f(0,5; 3)"), Ok(Some("8.5".into())));
+        assert_eq!(session.define("a == 7", Calculator::evaluate), None);
+        assert_eq!(session.defined_name("g(x) = x"), Some("g".into()));
+        assert_eq!(session.defined_name("$1 = 2"), None);
+        assert_eq!(
+            session.define("b = 1 @ 2", Calculator::evaluate),
+            Some(Err(CompileError::Tokenize(
+                TokenizeError::UnknownOperation("@".into())
+            )))
+        );
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let mut session = Session::default();
+        let mut eval = |line| session.eval_text(line);
+        assert_eq!(eval("total = 0"), Ok(Some("0".into())));
+        assert_eq!(eval("total += 42"), Ok(Some("42".into())));
+        assert_eq!(eval("total -= 2"), Ok(Some("40".into())));
+        assert_eq!(eval("total *= 1 + 1"), Ok(Some("80".into())));
+        assert_eq!(eval("total /= 16.0"), Ok(Some("5".into())));
+        assert_eq!(eval("total"), Ok(Some("5".into())));
+        assert_eq!(
+            eval("missing += 1"),
+            Err(CompileError::Calculator(
+                CalculatorError::UnknownIdentifier("missing".into())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_display_hint() {
+        let mut session = Session::default();
+        let mut eval = |line| session.eval_text(line);
+        assert_eq!(eval("frac(0.75)"), Ok(Some("3/4".into())));
+        assert_eq!(eval("frac(0.75) * 2"), Ok(Some("1.5".into())));
+        assert_eq!(eval("h = frac(0.5)"), Ok(Some("1/2".into())));
+        assert_eq!(eval("dec(frac(1 / 4.0))"), Ok(Some("0.25".into())));
+        assert_eq!(eval("h"), Ok(Some("0.5".into())));
+        assert_eq!(eval("approx(pi, 1000)"), Ok(Some("355/113".into())));
+    }
+
+    #[test]
+    fn test_higher_order() {
+        let mut session = Session::default();
+        session.eval("sq(x) = x * x").unwrap();
+        session.eval("add(a, b) = a + b").unwrap();
+        session.eval("positive(x) = x > 0").unwrap();
+        let mut eval = |line| session.eval_text(line);
+        assert_eq!(eval("map(sq, 1..=4)"), Ok(Some("[1, 4, 9, 16]".into())));
+        assert_eq!(eval("map(abs, [-1, 2])"), Ok(Some("[1, 2]".into())));
+        assert_eq!(eval("filter(positive, -2..=2)"), Ok(Some("[1, 2]".into())));
+        assert_eq!(eval("reduce(add, 10, [1, 2, 3])"), Ok(Some("16".into())));
+        assert_eq!(eval("reduce(max, 0, [])"), Ok(Some("0".into())));
+        assert!(eval("map(1, [1])").is_err());
+        assert!(eval("map(sq, 1)").is_err());
+        assert!(eval("filter(positive)").is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use crate::ast::Expr;
+use crate::polynomial::Polynomial;
+use crate::tokenizer::{Arithmetic, Operator};
+use crate::value::Value;
+use compact_str::CompactString;
+use malachite::num::arithmetic::traits::Abs;
+use malachite::num::basic::traits::{One, Zero};
+use malachite::{Integer, Rational};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Largest total degree of products and powers
+const MAX_DEGREE: u64 = 10_000;
+/// Largest number of term products of a multiplication
+const MAX_PRODUCTS: usize = 1 << 20;
+
+/// Product of variables with their exponents, sorted by name, empty for the constant term
+type Monomial = Vec<(CompactString, u64)>;
+
+/// Polynomial in named variables with exact coefficients, e.g. `2*x*y + 3`. Undefined
+/// identifiers evaluate to these in symbolic mode, like terms are collected by the arithmetic
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Symbolic {
+    /// Non-zero coefficients of the monomials
+    terms: BTreeMap<Monomial, Rational>,
+}
+
+impl Symbolic {
+    pub fn variable(name: &str) -> Self {
+        let terms = BTreeMap::from([(vec![(name.into(), 1)], Rational::ONE)]);
+        Self { terms }
+    }
+
+    fn constant(x: Rational) -> Self {
+        let mut terms = BTreeMap::new();
+        if x != 0u32 {
+            terms.insert(vec![], x);
+        }
+        Self { terms }
+    }
+
+    /// Value of a polynomial without variables
+    fn to_constant(&self) -> Option<Rational> {
+        match self.terms.iter().next() {
+            None => Some(Rational::ZERO),
+            Some((monomial, c)) if monomial.is_empty() && self.terms.len() == 1 => Some(c.clone()),
+            _ => None,
+        }
+    }
+
+    /// Terms with their coefficients, highest total degree first, then by the powers of the
+    /// variables in alphabetical order, e.g. `x**2 + x*y + y**2`
+    pub fn terms(&self) -> Vec<(&[(CompactString, u64)], &Rational)> {
+        let mut terms: Vec<_> = self.terms.iter().map(|(m, c)| (m.as_slice(), c)).collect();
+        let powers = |m: &[(CompactString, u64)]| {
+            m.iter()
+                .map(|(name, exponent)| (name.clone(), Reverse(*exponent)))
+                .collect::<Vec<_>>()
+        };
+        terms.sort_by_key(|(m, _)| (Reverse(degree(m)), powers(m)));
+        terms
+    }
+
+    /// Polynomial in the only variable `name`, `None` if other variables are left
+    pub fn to_polynomial(&self, name: &str) -> Option<Polynomial> {
+        let mut coefficients = vec![Rational::ZERO; self.degree() as usize + 1];
+        for (monomial, c) in &self.terms {
+            let power = match monomial.as_slice() {
+                [] => 0,
+                [(variable, exponent)] if *variable == name => *exponent as usize,
+                _ => return None,
+            };
+            coefficients[power] = c.clone();
+        }
+        coefficients.reverse();
+        Some(Polynomial::new(coefficients))
+    }
+
+    /// Sum of the absolute values of the coefficients, which bounds the growth of powers
+    pub fn norm(&self) -> Rational {
+        self.terms.values().map(|c| c.abs()).sum()
+    }
+
+    /// Largest total degree of the terms
+    fn degree(&self) -> u64 {
+        self.terms.keys().map(|m| degree(m)).max().unwrap_or(0)
+    }
+
+    /// Expression that evaluates to the same polynomial, e.g. `5 * x ** 2 - y`
+    pub fn to_expr(&self) -> Expr {
+        let mut expr: Option<Expr> = None;
+        for (monomial, c) in self.terms() {
+            let factors = monomial.iter().map(|(name, exponent)| match exponent {
+                1 => Expr::Ident(name.clone()),
+                _ => Expr::binary(
+                    Operator::Pow,
+                    Expr::Ident(name.clone()),
+                    Expr::Val(Value::from(*exponent as i64)),
+                ),
+            });
+            let abs = c.abs();
+            let coefficient = (abs != 1u32 || monomial.is_empty()).then(|| {
+                Expr::Val(match Integer::try_from(&abs) {
+                    Ok(i) => Value::Int(i),
+                    Err(_) => Value::Real(abs),
+                })
+            });
+            let term = coefficient
+                .into_iter()
+                .chain(factors)
+                .reduce(|l, r| Expr::binary(Operator::Mul, l, r))
+                .expect("terms have a coefficient or a variable");
+            expr = Some(match (expr, *c < 0u32) {
+                (None, false) => term,
+                (None, true) => Expr::Neg(Box::new(term)),
+                (Some(l), false) => Expr::binary(Operator::Add, l, term),
+                (Some(l), true) => Expr::binary(Operator::Sub, l, term),
+            });
+        }
+        expr.unwrap_or(Expr::Val(Value::from(0)))
+    }
+
+    fn add(mut self, r: Self) -> Self {
+        for (monomial, c) in r.terms {
+            let sum = self.terms.remove(&monomial).unwrap_or_default() + c;
+            if sum != 0u32 {
+                self.terms.insert(monomial, sum);
+            }
+        }
+        self
+    }
+
+    fn mul(&self, r: &Self) -> Result<Self, SymbolicError> {
+        if self.degree() + r.degree() > MAX_DEGREE
+            || self.terms.len().saturating_mul(r.terms.len()) > MAX_PRODUCTS
+        {
+            return Err(SymbolicError::TooLarge);
+        }
+        let mut product = Self::constant(Rational::ZERO);
+        for (l_monomial, a) in &self.terms {
+            for (r_monomial, b) in &r.terms {
+                let term = BTreeMap::from([(multiply(l_monomial, r_monomial), a * b)]);
+                product = product.add(Self { terms: term });
+            }
+        }
+        Ok(product)
+    }
+
+    fn pow(&self, exp: u64) -> Result<Self, SymbolicError> {
+        if self.degree().saturating_mul(exp) > MAX_DEGREE {
+            return Err(SymbolicError::TooLarge);
+        }
+        let mut result = Self::constant(Rational::ONE);
+        let mut base = self.clone();
+        let mut exp = exp;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.mul(&base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Number if no variables are left, e.g. in `x - x`
+    fn into_value(self) -> Value {
+        match self.to_constant() {
+            Some(x) => match Integer::try_from(&x) {
+                Ok(i) => Value::Int(i),
+                Err(_) => Value::Real(x),
+            },
+            None => Value::Symbolic(self),
+        }
+    }
+}
+
+impl std::ops::Neg for Symbolic {
+    type Output = Symbolic;
+
+    fn neg(self) -> Self::Output {
+        let terms = self.terms.into_iter().map(|(m, c)| (m, -c)).collect();
+        Self { terms }
+    }
+}
+
+fn degree(monomial: &[(CompactString, u64)]) -> u64 {
+    monomial.iter().map(|(_, exponent)| exponent).sum()
+}
+
+/// Product of two monomials, adding the exponents of common variables
+fn multiply(l: &Monomial, r: &Monomial) -> Monomial {
+    let mut exponents: BTreeMap<CompactString, u64> = l.iter().cloned().collect();
+    for (name, exponent) in r {
+        *exponents.entry(name.clone()).or_default() += exponent;
+    }
+    exponents.into_iter().collect()
+}
+
+fn symbolic(v: Value) -> Result<Symbolic, SymbolicError> {
+    match v {
+        Value::Symbolic(s) => Ok(s),
+        v => v
+            .to_rational()
+            .map(Symbolic::constant)
+            .ok_or(SymbolicError::UnsupportedOperands),
+    }
+}
+
+/// Execute an operation where at least one of the operands is symbolic, only division by
+/// numbers is supported
+pub fn execute(op: Arithmetic, l: Value, r: Value) -> Result<Value, SymbolicError> {
+    let (l, r) = (symbolic(l)?, symbolic(r)?);
+    let result = match op {
+        Arithmetic::Add => l.add(r),
+        Arithmetic::Sub => l.add(-r),
+        Arithmetic::Mul => l.mul(&r)?,
+        Arithmetic::Div => match r.to_constant() {
+            Some(r) if r == 0u32 => return Err(SymbolicError::DivisionByZero),
+            Some(r) => l.mul(&Symbolic::constant(Rational::ONE / r))?,
+            None => return Err(SymbolicError::SymbolicDivisor),
+        },
+        Arithmetic::Pow => {
+            let exp = r
+                .to_constant()
+                .and_then(|r| Integer::try_from(&r).ok())
+                .and_then(|r| u64::try_from(&r).ok())
+                .ok_or(SymbolicError::InvalidPower)?;
+            l.pow(exp)?
+        }
+        Arithmetic::Eq => return Ok((l == r).into()),
+        Arithmetic::Ne => return Ok((l != r).into()),
+        Arithmetic::Lt | Arithmetic::Le | Arithmetic::Gt | Arithmetic::Ge => {
+            return Err(SymbolicError::UnsupportedOperands)
+        }
+    };
+    Ok(result.into_value())
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SymbolicError {
+    #[error("Symbolic expressions can only be raised to non-negative integer powers")]
+    InvalidPower,
+    #[error("Symbolic expressions can only be divided by numbers")]
+    SymbolicDivisor,
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Symbolic expression is too large")]
+    TooLarge,
+    #[error("Operation is not supported for symbolic expressions")]
+    UnsupportedOperands,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str) -> Value {
+        Value::Symbolic(Symbolic::variable(name))
+    }
+
+    fn eval(op: Arithmetic, l: Value, r: Value) -> Value {
+        execute(op, l, r).unwrap()
+    }
+
+    #[test]
+    fn test_like_terms() {
+        // 2*x + 3*x
+        let l = eval(Arithmetic::Mul, 2.into(), var("x"));
+        let r = eval(Arithmetic::Mul, 3.into(), var("x"));
+        let sum = eval(Arithmetic::Add, l, r);
+        assert_eq!(sum, eval(Arithmetic::Mul, var("x"), 5.into()));
+        assert_eq!(eval(Arithmetic::Sub, sum.clone(), sum.clone()), 0.into());
+        let half = eval(Arithmetic::Div, var("x"), 2.into());
+        assert_eq!(eval(Arithmetic::Add, half.clone(), half), var("x"));
+        assert_eq!(eval(Arithmetic::Eq, var("x"), var("x")), true.into());
+        assert_eq!(eval(Arithmetic::Eq, var("x"), var("y")), false.into());
+    }
+
+    #[test]
+    fn test_products() {
+        // (x + y)**2 - x*y
+        let sum = eval(Arithmetic::Add, var("x"), var("y"));
+        let square = eval(Arithmetic::Pow, sum, 2.into());
+        let xy = eval(Arithmetic::Mul, var("y"), var("x"));
+        let Value::Symbolic(s) = eval(Arithmetic::Sub, square, xy) else {
+            panic!("symbolic result expected");
+        };
+        assert_eq!(s.terms().len(), 3);
+        assert_eq!(s.degree(), 2);
+        assert_eq!(s.to_expr().to_string(), "x ** 2 + x * y + y ** 2");
+        assert_eq!(s.to_polynomial("x"), None);
+
+        let cube = eval(Arithmetic::Pow, var("x"), 3.into());
+        let Value::Symbolic(s) = eval(Arithmetic::Sub, 1.into(), cube) else {
+            panic!("symbolic result expected");
+        };
+        assert_eq!(s.to_expr().to_string(), "-(x ** 3) + 1");
+        let coefficients = [-1, 0, 0, 1].map(Rational::from).to_vec();
+        assert_eq!(s.to_polynomial("x"), Some(Polynomial::new(coefficients)));
+        assert_eq!(s.to_polynomial("y"), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |op, l, r| execute(op, l, r).unwrap_err();
+        assert_eq!(
+            err(Arithmetic::Div, 1.into(), var("x")),
+            SymbolicError::SymbolicDivisor
+        );
+        assert_eq!(
+            err(Arithmetic::Div, var("x"), 0.into()),
+            SymbolicError::DivisionByZero
+        );
+        assert_eq!(
+            err(Arithmetic::Pow, var("x"), (-1).into()),
+            SymbolicError::InvalidPower
+        );
+        assert_eq!(
+            err(Arithmetic::Pow, var("x"), (MAX_DEGREE as i64 + 1).into()),
+            SymbolicError::TooLarge
+        );
+        assert_eq!(
+            err(Arithmetic::Lt, var("x"), 1.into()),
+            SymbolicError::UnsupportedOperands
+        );
+    }
+}
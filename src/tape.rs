@@ -0,0 +1,109 @@
+use crate::ast::Expr;
+use crate::calculator::{Calculator, CalculatorError};
+use crate::format::Format;
+use crate::tokenizer::Operator;
+use crate::value::Value;
+
+/// Width the amounts of the tape are right-aligned to
+const WIDTH: usize = 16;
+
+/// Running total of an adding machine, every entered amount is added to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tape {
+    total: Value,
+}
+
+impl Default for Tape {
+    fn default() -> Self {
+        Self {
+            total: Value::from(0),
+        }
+    }
+}
+
+/// Line of the input of a tape
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry<'a> {
+    /// Expression of an amount, e.g. `12.50`, `+3` or `-2 * 4.99`
+    Amount(&'a str),
+    /// Print the total and start over
+    Total,
+    /// Discard the total
+    Clear,
+}
+
+impl<'a> Entry<'a> {
+    pub fn parse(line: &'a str) -> Self {
+        match line.trim() {
+            "total" => Entry::Total,
+            "clear" => Entry::Clear,
+            amount => Entry::Amount(amount),
+        }
+    }
+}
+
+impl Tape {
+    /// Add the amount to the total, returns the subtotal
+    pub fn add(
+        &mut self,
+        amount: Value,
+        calculator: &Calculator,
+    ) -> Result<&Value, CalculatorError> {
+        let sum = Expr::binary(
+            Operator::Add,
+            Expr::Val(self.total.clone()),
+            Expr::Val(amount),
+        );
+        self.total = calculator.evaluate(&sum)?;
+        Ok(&self.total)
+    }
+
+    pub fn total(&self) -> &Value {
+        &self.total
+    }
+
+    /// Reset the total to zero, returns the previous one
+    pub fn clear(&mut self) -> Value {
+        std::mem::take(self).total
+    }
+}
+
+/// Line of the printed tape: the amount right-aligned, followed by a mark such as `+` for added
+/// amounts, `=` for subtotals and `T` for totals. Negative amounts are printed without their sign
+/// and marked `-` instead of `+`
+pub fn line(format: &Format, amount: &Value, mark: char) -> String {
+    let negative = amount.to_rational().is_some_and(|r| r < 0u32);
+    let (amount, mark) = match (negative, mark) {
+        (true, '+') => (format.display(&-amount.clone()).to_string(), '-'),
+        _ => (format.display(amount).to_string(), mark),
+    };
+    format!("{:>WIDTH$} {}", amount, mark)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        assert_eq!(Entry::parse(" total "), Entry::Total);
+        assert_eq!(Entry::parse("clear"), Entry::Clear);
+        assert_eq!(Entry::parse("-3.5"), Entry::Amount("-3.5"));
+    }
+
+    #[test]
+    fn test_tape() {
+        let calculator = Calculator::default();
+        let format = Format::default();
+        let mut tape = Tape::default();
+        tape.add(Value::from(12), &calculator).unwrap();
+        let subtotal = tape.add(Value::from(-5), &calculator).unwrap();
+        assert_eq!(subtotal, &Value::from(7));
+        assert_eq!(tape.clear(), Value::from(7));
+        assert_eq!(tape.total(), &Value::from(0));
+
+        assert_eq!(line(&format, &Value::from(12), '+'), "              12 +");
+        assert_eq!(line(&format, &Value::from(-5), '+'), "               5 -");
+        assert_eq!(line(&format, &Value::from(-5), '='), "              -5 =");
+    }
+}
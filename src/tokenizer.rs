@@ -1,5 +1,14 @@
-use compact_str::{CompactString, ToCompactString};
-use malachite::Integer;
+use crate::bytesize;
+use crate::calculator::MAX_BITS;
+use crate::duration;
+use crate::printer;
+use crate::roman;
+use crate::value::Value;
+use compact_str::{format_compact, CompactString, ToCompactString};
+use malachite::num::arithmetic::traits::Pow;
+use malachite::{Integer, Natural, Rational};
+use std::collections::VecDeque;
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Debug, Default, Clone)]
@@ -7,31 +16,247 @@ enum TokenizerState {
     #[default]
     Clean,
     Pending(Token),
+    /// Zero at the start of a number, may be followed by a radix prefix
+    LeadingZero,
     InNumber {
         value: Integer,
         radix: u32,
     },
+    /// Fractional part of a decimal number, `value` contains all digits read so far
+    InFraction {
+        value: Integer,
+        digits: u64,
+    },
+    /// Unit suffix of a number, e.g. `KiB` in `4KiB`
+    InSuffix {
+        value: Value,
+        suffix: CompactString,
+    },
+    /// Exponent of a number in scientific notation, e.g. `-3` in `1.5e-3`, falls back to a
+    /// multiplication by `e` like `2e-x` without digits
+    InExponent {
+        mantissa: Value,
+        marker: CompactString,
+        negative: bool,
+        exponent: Option<u64>,
+    },
+    /// Sides of dice, e.g. `6` in `3d6`, falls back to a compound duration like `3d6h`
+    InDice {
+        count: Integer,
+        sides: Integer,
+    },
+    /// Date literal `YYYY-MM-DD[THH:MM[:SS]]`, falls back to subtraction if incomplete
+    InDate(CompactString),
+    /// Roman numeral literal, e.g. `0rMMXXIV`
+    InRoman(CompactString),
+    /// Exponent written in superscript, e.g. `³` in `2³`
+    InSuperscript(CompactString),
     InOperator(CompactString),
+    InIdent(CompactString),
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Tokenizer {
     state: TokenizerState,
+    /// Seconds of the leading components of a compound duration, e.g. `1h` in `1h30m`
+    duration: Option<Rational>,
+    /// Tokens completed ahead of the ones already returned
+    queue: VecDeque<Token>,
+    /// Numbers with a leading zero are octal, as in C
+    c_octal: bool,
+    /// `±` and `+-` are accepted for intervals
+    intervals: bool,
+    /// `,` is the decimal separator and `;` separates arguments, e.g. `max(3,14; 2)`
+    decimal_comma: bool,
+    /// Leading bytes of a character split between calls to [`Tokenizer::update_bytes`]
+    partial: Vec<u8>,
+    /// Brackets open at the current character, tracked with a decimal comma
+    brackets: Vec<Bracket>,
+    /// The last token was an identifier, an opening parenthesis after it starts arguments
+    after_ident: bool,
+    /// The last character was a decimal comma in arguments, which must be followed by a digit
+    argument_comma: bool,
+    /// The last token was the identifier `unix`
+    after_unix: bool,
+    /// The last token opened the argument of `unix`, the only place where a date literal is
+    /// read, elsewhere `2024-05-01` is a subtraction
+    dates: bool,
 }
 
 impl Tokenizer {
+    /// Tokens of the characters, the last token is completed at the end of the input
+    pub fn tokens<I: IntoIterator<Item = char>>(chars: I) -> Tokens<I::IntoIter> {
+        Self::default().into_tokens(chars)
+    }
+
+    /// Like [`Tokenizer::tokens`], with the settings of this tokenizer
+    pub fn into_tokens<I: IntoIterator<Item = char>>(self, chars: I) -> Tokens<I::IntoIter> {
+        Tokens {
+            tokenizer: self,
+            chars: chars.into_iter(),
+            rest: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    pub fn set_c_octal(&mut self, c_octal: bool) {
+        self.c_octal = c_octal;
+    }
+
+    pub fn set_intervals(&mut self, intervals: bool) {
+        self.intervals = intervals;
+    }
+
+    pub fn set_decimal_comma(&mut self, decimal_comma: bool) {
+        self.decimal_comma = decimal_comma;
+    }
+
+    /// Discard the incomplete expression, e.g. after an error
+    pub fn reset(&mut self) {
+        self.state = TokenizerState::default();
+        self.duration = None;
+        self.queue.clear();
+        self.partial.clear();
+        self.brackets.clear();
+        self.after_ident = false;
+        self.argument_comma = false;
+        self.after_unix = false;
+        self.dates = false;
+    }
+
     pub fn update(&mut self, c: char) -> Result<Option<Token>, TokenizeError> {
         use TokenizerState::*;
 
+        if std::mem::take(&mut self.argument_comma) && !c.is_ascii_digit() {
+            return Err(TokenizeError::ArgumentComma);
+        }
+        let arguments = self.brackets.last_mut().filter(|b| b.arguments);
+        let c = match c {
+            ',' if self.decimal_comma => match (&self.state, arguments) {
+                (LeadingZero | InNumber { radix: 10, .. } | InFraction { .. }, arguments) => {
+                    if let Some(bracket) = arguments {
+                        bracket.decimal_comma = true;
+                        self.argument_comma = true;
+                    }
+                    '.'
+                }
+                (_, Some(_)) => return Err(TokenizeError::ArgumentComma),
+                // Report the comma as typed rather than the decimal point it stands for
+                (_, None) => return Err(TokenizeError::UnknownOperation(",".into())),
+            },
+            ';' if self.decimal_comma => ',',
+            c => c,
+        };
+        if let Some(token) = self.next_token(c)? {
+            if token == Token::Op(Operator::PlusMinus) && !self.intervals {
+                return Err(TokenizeError::UnknownOperation("±".into()));
+            }
+            if self.decimal_comma {
+                self.track_brackets(&token)?;
+            }
+            self.dates = self.after_unix && token == Token::ParenOpen;
+            self.after_ident = matches!(token, Token::Ident(_));
+            self.after_unix = matches!(&token, Token::Ident(name) if name.as_str() == "unix");
+            self.queue.push_back(token);
+        }
+        Ok(self.queue.pop_front())
+    }
+
+    /// Fail at the end of arguments that have a decimal comma without being separated by `;`,
+    /// e.g. `max(1,2)` which would be the maximum of `1.2`
+    fn track_brackets(&mut self, token: &Token) -> Result<(), TokenizeError> {
+        match token {
+            Token::ParenOpen => self.brackets.push(Bracket::new(self.after_ident)),
+            Token::BracketOpen => self.brackets.push(Bracket::new(true)),
+            Token::BraceOpen => self.brackets.push(Bracket::new(false)),
+            Token::Comma => {
+                if let Some(bracket) = self.brackets.last_mut() {
+                    bracket.separated = true;
+                }
+            }
+            Token::ParenClose | Token::BracketClose | Token::BraceClose => {
+                match self.brackets.pop() {
+                    Some(b) if b.arguments && b.decimal_comma && !b.separated => {
+                        return Err(TokenizeError::ArgumentComma)
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn next_token(&mut self, c: char) -> Result<Option<Token>, TokenizeError> {
+        use TokenizerState::*;
+
         match std::mem::take(&mut self.state) {
             Clean => self.state = begin_token(c),
+            Pending(Token::Op(Operator::Add)) if c == '-' && self.intervals => {
+                self.state = Pending(Token::Op(Operator::PlusMinus));
+            }
+            // Compound assignment, e.g. `x += 1`
+            Pending(Token::Op(
+                op @ (Operator::Add | Operator::Sub | Operator::Mul | Operator::Div),
+            )) if c == '=' => {
+                self.state = Pending(Token::Assign(Some(op)));
+            }
             Pending(token) => {
                 self.state = begin_token(c);
                 return Ok(Some(token));
             }
+            LeadingZero => match c {
+                'r' => self.state = InRoman(CompactString::default()),
+                '.' => {
+                    self.state = InFraction {
+                        value: Integer::from(0),
+                        digits: 0,
+                    }
+                }
+                c => {
+                    let radix = match c {
+                        'x' => 16,
+                        'o' => 8,
+                        'b' => 2,
+                        _ if self.c_octal => 8,
+                        _ => 10,
+                    };
+                    self.state = InNumber {
+                        value: Integer::from(0),
+                        radix,
+                    };
+                    if !matches!(c, 'x' | 'o' | 'b') {
+                        return self.next_token(c);
+                    }
+                }
+            },
             InNumber { mut value, radix } => match c {
-                'x' if value == 0 && radix == 8 => self.state = InNumber { value, radix: 16 },
-                'b' if value == 0 && radix == 8 => self.state = InNumber { value, radix: 2 },
+                // Explicit radix, e.g. `36#zz`
+                '#' if radix == 10 => {
+                    let radix = u32::try_from(&value)
+                        .ok()
+                        .filter(|radix| (2..=36).contains(radix))
+                        .ok_or(TokenizeError::InvalidBase)?;
+                    self.state = InNumber {
+                        radix,
+                        value: Integer::from(0),
+                    }
+                }
+                '.' if radix == 10 => self.state = InFraction { value, digits: 0 },
+                // Four digit year
+                '-' if radix == 10
+                    && (1000..10000).contains(&value)
+                    && self.duration.is_none()
+                    && self.dates =>
+                {
+                    self.state = InDate(format_compact!("{}-", value))
+                }
+                _ if c.is_ascii_alphabetic() && !c.is_digit(radix) => {
+                    self.state = InSuffix {
+                        value: value.into(),
+                        suffix: c.to_compact_string(),
+                    }
+                }
                 '0'..='9' | 'a'..='z' | 'A'..='Z' => {
                     value *= Integer::from(radix);
                     let Some(digit) = c.to_digit(radix) else {
@@ -41,13 +266,207 @@ impl Tokenizer {
                     self.state = InNumber { value, radix };
                 }
                 c => {
-                    let token = Token::Val(value);
+                    let token = self.number(value.into(), None)?;
+                    self.state = begin_token(c);
+                    return Ok(Some(token));
+                }
+            },
+            InFraction { mut value, digits } => match c {
+                // Range of integers, e.g. `1..=10`
+                '.' if digits == 0 => {
+                    let token = self.number(value.into(), None)?;
+                    self.state = InOperator("..".into());
+                    return Ok(Some(token));
+                }
+                'a'..='z' | 'A'..='Z' => {
+                    self.state = InSuffix {
+                        value: decimal(value, digits),
+                        suffix: c.to_compact_string(),
+                    }
+                }
+                '0'..='9' | '.' => {
+                    let Some(digit) = c.to_digit(10) else {
+                        return Err(TokenizeError::InvalidNumber);
+                    };
+                    value = value * Integer::from(10) + Integer::from(digit);
+                    self.state = InFraction {
+                        value,
+                        digits: digits + 1,
+                    };
+                }
+                c => {
+                    let token = self.number(decimal(value, digits), None)?;
+                    self.state = begin_token(c);
+                    return Ok(Some(token));
+                }
+            },
+            InSuffix { value, mut suffix } => match c {
+                // Exponent of a number in scientific notation, e.g. `1.5e-3`
+                '0'..='9' | '+' | '-'
+                    if matches!(suffix.as_str(), "e" | "E") && self.duration.is_none() =>
+                {
+                    self.state = InExponent {
+                        mantissa: value,
+                        marker: suffix,
+                        negative: c == '-',
+                        exponent: c.to_digit(10).map(u64::from),
+                    }
+                }
+                'a'..='z' | 'A'..='Z' => {
+                    suffix.push(c);
+                    self.state = InSuffix { value, suffix };
+                }
+                // Dice, e.g. `3d6`
+                '0'..='9' if suffix == "d" && self.duration.is_none() => match value {
+                    Value::Int(count) => {
+                        self.state = InDice {
+                            count,
+                            sides: (c as u32 - '0' as u32).into(),
+                        }
+                    }
+                    value => {
+                        self.duration = Some(duration_seconds(value, &suffix)?);
+                        self.state = InNumber {
+                            value: (c as u32 - '0' as u32).into(),
+                            radix: 10,
+                        };
+                    }
+                },
+                // Next component of a compound duration
+                '0'..='9' if duration::unit(&suffix).is_some() => {
+                    let seconds = duration_seconds(value, &suffix)?;
+                    self.duration = Some(self.duration.take().unwrap_or_default() + seconds);
+                    self.state = InNumber {
+                        value: (c as u32 - '0' as u32).into(),
+                        radix: 10,
+                    };
+                }
+                '0'..='9' | '.' | '_' => return Err(TokenizeError::InvalidNumber),
+                c => {
+                    let token = self.number(value, Some(&suffix))?;
+                    self.state = begin_token(c);
+                    return Ok(Some(token));
+                }
+            },
+            InExponent {
+                mantissa,
+                marker,
+                negative,
+                exponent,
+            } => match (c.to_digit(10), exponent) {
+                (Some(digit), exponent) => {
+                    let exponent = exponent.unwrap_or(0) * 10 + u64::from(digit);
+                    if exponent > MAX_EXPONENT {
+                        return Err(TokenizeError::InvalidNumber);
+                    }
+                    self.state = InExponent {
+                        mantissa,
+                        marker,
+                        negative,
+                        exponent: Some(exponent),
+                    };
+                }
+                (None, None) => {
+                    self.replay_exponent(mantissa, &marker, negative)?;
+                    return self.next_token(c);
+                }
+                (None, Some(exponent)) => {
+                    let value = scientific(mantissa, negative, exponent)?;
+                    if c.is_ascii_alphabetic() {
+                        self.state = InSuffix {
+                            value,
+                            suffix: c.to_compact_string(),
+                        };
+                    } else {
+                        let token = self.number(value, None)?;
+                        self.state = begin_token(c);
+                        return Ok(Some(token));
+                    }
+                }
+            },
+            InDice { count, mut sides } => match c {
+                '0'..='9' => {
+                    sides = sides * Integer::from(10) + Integer::from(c as u32 - '0' as u32);
+                    self.state = InDice { count, sides };
+                }
+                // Compound duration after all, e.g. `3d6h`
+                'a'..='z' | 'A'..='Z' | '.' => {
+                    self.duration = Some(duration_seconds(count.into(), "d")?);
+                    self.state = InNumber {
+                        value: sides,
+                        radix: 10,
+                    };
+                    return self.next_token(c);
+                }
+                c => {
+                    self.state = begin_token(c);
+                    return Ok(Some(self.dice(count, sides)));
+                }
+            },
+            InDate(mut text) => match DATE_TEMPLATE.as_bytes().get(text.len()) {
+                Some(b'0') if c.is_ascii_digit() => {
+                    text.push(c);
+                    self.state = InDate(text);
+                }
+                Some(expected) if c == *expected as char => {
+                    text.push(c);
+                    self.state = InDate(text);
+                }
+                _ if DATE_LENGTHS.contains(&text.len()) => {
+                    self.state = begin_token(c);
+                    return Ok(Some(Token::Val(Value::Str(text))));
+                }
+                _ if text.len() < DATE_LENGTHS[0] => {
+                    self.replay_date(&text)?;
+                    return self.next_token(c);
+                }
+                _ => return Err(TokenizeError::InvalidNumber),
+            },
+            InSuperscript(mut text) => match c {
+                _ if superscript_digit(c).is_some() => {
+                    text.push(c);
+                    self.state = InSuperscript(text);
+                }
+                c => {
+                    self.superscript(&text)?;
+                    self.state = begin_token(c);
+                }
+            },
+            InRoman(mut text) => match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => {
+                    text.push(c);
+                    self.state = InRoman(text);
+                }
+                c => {
+                    let token = roman_numeral(&text)?;
                     self.state = begin_token(c);
                     return Ok(Some(token));
                 }
             },
             InOperator(mut op) => match c {
-                '0'..='9' | '+' | '-' | '(' | ')' | 'a'..='z' | 'A'..='Z' => {
+                '0'..='9'
+                | '+'
+                | '-'
+                | '−'
+                | '('
+                | ')'
+                | '['
+                | ']'
+                | '{'
+                | '}'
+                | ','
+                | '!'
+                | 'a'..='z'
+                | 'A'..='Z'
+                | '_'
+                | '$' => {
+                    let token = finalize_operator(op.as_str())
+                        .ok_or_else(|| TokenizeError::UnknownOperation(op))?;
+                    self.state = begin_token(c);
+                    return Ok(Some(token));
+                }
+                // Absolute value bars don't combine with other operators, e.g. `|x|*2`
+                _ if (c == '|') != (op == "|") => {
                     let token = finalize_operator(op.as_str())
                         .ok_or_else(|| TokenizeError::UnknownOperation(op))?;
                     self.state = begin_token(c);
@@ -64,44 +483,334 @@ impl Tokenizer {
                     self.state = TokenizerState::InOperator(op)
                 }
             },
+            InIdent(mut ident) => match c {
+                '0'..='9' | 'a'..='z' | 'A'..='Z' | '_' => {
+                    ident.push(c);
+                    self.state = InIdent(ident);
+                }
+                c => {
+                    self.state = begin_token(c);
+                    return self.identifier(ident).map(Some);
+                }
+            },
         }
         Ok(None)
     }
 
-    pub fn finalize(&mut self) -> Result<Option<Token>, TokenizeError> {
+    /// Feed UTF-8 encoded input, characters may be split between calls
+    pub fn update_bytes(&mut self, mut bytes: &[u8]) -> Result<Vec<Token>, TokenizeError> {
+        let mut tokens = vec![];
+        if let Some(&lead) = self.partial.first() {
+            let width = match lead {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let needed = (width - self.partial.len()).min(bytes.len());
+            self.partial.extend_from_slice(&bytes[..needed]);
+            bytes = &bytes[needed..];
+            if self.partial.len() < width {
+                return Ok(tokens);
+            }
+            let partial = std::mem::take(&mut self.partial);
+            let text = std::str::from_utf8(&partial).map_err(|_| TokenizeError::InvalidUtf8)?;
+            for c in text.chars() {
+                tokens.extend(self.update(c)?);
+            }
+        }
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                match e.error_len() {
+                    // Incomplete character at the end, completed by the next call
+                    None => self.partial = rest.to_vec(),
+                    Some(_) => return Err(TokenizeError::InvalidUtf8),
+                }
+                std::str::from_utf8(valid).map_err(|_| TokenizeError::InvalidUtf8)?
+            }
+        };
+        for c in text.chars() {
+            tokens.extend(self.update(c)?);
+        }
+        Ok(tokens)
+    }
+
+    /// Complete the last token, returns all tokens that weren't returned yet
+    pub fn finalize(&mut self) -> Result<Vec<Token>, TokenizeError> {
+        if !std::mem::take(&mut self.partial).is_empty() {
+            return Err(TokenizeError::InvalidUtf8);
+        }
+        let last = self.finalize_state()?;
+        let tracked = match &last {
+            Some(token) if self.decimal_comma => self.track_brackets(token),
+            _ => Ok(()),
+        };
+        self.brackets.clear();
+        self.after_ident = false;
+        self.argument_comma = false;
+        tracked?;
+        let mut tokens: Vec<Token> = self.queue.drain(..).collect();
+        tokens.extend(last);
+        Ok(tokens)
+    }
+
+    fn finalize_state(&mut self) -> Result<Option<Token>, TokenizeError> {
         use TokenizerState::*;
         match std::mem::take(&mut self.state) {
             Clean => Ok(None),
             Pending(token) => Ok(Some(token)),
-            InNumber { value, .. } => Ok(Some(Token::Val(value))),
+            LeadingZero => Ok(Some(Token::from(0))),
+            InNumber { value, .. } => self.number(value.into(), None).map(Some),
+            InFraction { value, digits } => self.number(decimal(value, digits), None).map(Some),
+            InSuffix { value, suffix } => self.number(value, Some(&suffix)).map(Some),
+            InExponent {
+                mantissa,
+                exponent: Some(exponent),
+                negative,
+                ..
+            } => self
+                .number(scientific(mantissa, negative, exponent)?, None)
+                .map(Some),
+            InExponent {
+                mantissa,
+                marker,
+                negative,
+                exponent: None,
+            } => {
+                self.replay_exponent(mantissa, &marker, negative)?;
+                self.finalize_state()
+            }
+            InDate(text) if DATE_LENGTHS.contains(&text.len()) => {
+                Ok(Some(Token::Val(Value::Str(text))))
+            }
+            InDate(text) if text.len() < DATE_LENGTHS[0] => {
+                self.replay_date(&text)?;
+                self.finalize_state()
+            }
+            InDate(_) => Err(TokenizeError::InvalidNumber),
+            InRoman(text) => roman_numeral(&text).map(Some),
+            InSuperscript(text) => self.superscript(&text).map(|_| None),
             InOperator(op) => finalize_operator(op.as_str())
                 .ok_or_else(|| TokenizeError::UnknownOperation(op))
                 .map(Some),
+            InDice { count, sides } => Ok(Some(self.dice(count, sides))),
+            InIdent(ident) => self.identifier(ident).map(Some),
+        }
+    }
+
+    /// Tokenize an incomplete date literal as a subtraction, e.g. `2024-5`
+    fn replay_date(&mut self, text: &str) -> Result<(), TokenizeError> {
+        let (year, rest) = text.split_at(4);
+        let year: Integer = year.parse().map_err(|_| TokenizeError::InvalidNumber)?;
+        self.queue.push_back(Token::Val(year.into()));
+        self.state = TokenizerState::Pending(Token::Op(Operator::Sub));
+        for c in rest.chars().skip(1) {
+            if let Some(token) = self.next_token(c)? {
+                self.queue.push_back(token);
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue the power operation of a superscript exponent
+    fn superscript(&mut self, text: &str) -> Result<(), TokenizeError> {
+        let (negative, digits) = match text.strip_prefix('⁻') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let mut value = Integer::from(0);
+        for c in digits.chars() {
+            // Only a leading minus sign is allowed
+            let digit = superscript_digit(c)
+                .filter(|_| c != '⁻')
+                .ok_or(TokenizeError::InvalidNumber)?;
+            value = value * Integer::from(10) + Integer::from(digit);
+        }
+        if digits.is_empty() {
+            return Err(TokenizeError::InvalidNumber);
+        }
+        self.queue.push_back(Token::Op(Operator::Pow));
+        if negative {
+            self.queue.push_back(Token::Op(Operator::Sub));
+        }
+        self.queue.push_back(Token::Val(value.into()));
+        Ok(())
+    }
+
+    /// Identifier token, or the tokens of a single die like `d20`
+    fn identifier(&mut self, ident: CompactString) -> Result<Token, TokenizeError> {
+        if ident == "$" {
+            return Err(TokenizeError::EmptyReference);
+        }
+        let sides = ident
+            .strip_prefix('d')
+            .filter(|sides| sides.starts_with(|c: char| c.is_ascii_digit()))
+            .and_then(|sides| sides.parse::<Integer>().ok());
+        match sides {
+            Some(sides) => Ok(self.dice(Integer::from(1), sides)),
+            None => Ok(identifier(ident)),
+        }
+    }
+
+    /// Queue the tokens of the call `dice(count, sides)`, returns the last one
+    fn dice(&mut self, count: Integer, sides: Integer) -> Token {
+        self.queue.extend([
+            Token::Ident("dice".into()),
+            Token::ParenOpen,
+            Token::Val(count.into()),
+            Token::Comma,
+            Token::Val(sides.into()),
+        ]);
+        Token::ParenClose
+    }
+
+    /// Tokenize a number followed by a sign without exponent digits as a multiplication by `e`,
+    /// e.g. `2e-x` as `2 * e - x`
+    fn replay_exponent(
+        &mut self,
+        mantissa: Value,
+        marker: &str,
+        negative: bool,
+    ) -> Result<(), TokenizeError> {
+        let token = self.number(mantissa, Some(marker))?;
+        self.queue.push_back(token);
+        self.state = begin_token(if negative { '-' } else { '+' });
+        Ok(())
+    }
+
+    /// Number token with an optional unit suffix
+    fn number(&mut self, value: Value, suffix: Option<&str>) -> Result<Token, TokenizeError> {
+        let leading = self.duration.take();
+        match suffix {
+            // A single `m` is the metre like `5 m`, minutes are `min` unless they're a component of
+            // a compound duration such as `1h30m`. The calculator reads metres added to a duration
+            // as minutes, e.g. `1h30m + 45m`
+            Some("m") if leading.is_none() => {
+                self.queue.push_back(Token::Val(value));
+                Ok(identifier("m".into()))
+            }
+            Some(suffix) if duration::unit(suffix).is_some() => {
+                let seconds = duration_seconds(value, suffix)? + leading.unwrap_or_default();
+                Ok(Token::Val(Value::Duration(seconds)))
+            }
+            // Only durations can have multiple components
+            _ if leading.is_some() => Err(TokenizeError::InvalidNumber),
+            Some(suffix) => match bytesize::multiplier(suffix) {
+                Some(multiplier) => byte_size(value, multiplier),
+                // Implicit multiplication by an identifier, e.g. `2pi`
+                None => {
+                    self.queue.push_back(Token::Val(value));
+                    Ok(identifier(suffix.into()))
+                }
+            },
+            None => Ok(Token::Val(value)),
         }
     }
 }
 
+/// Largest exponent of a number in scientific notation, `10 ** MAX_EXPONENT` has fewer bits than
+/// [`MAX_BITS`]
+const MAX_EXPONENT: u64 = MAX_BITS / 4;
+
+/// Characters of a date literal, `0` stands for any digit
+const DATE_TEMPLATE: &str = "0000-00-00T00:00:00";
+/// Lengths of complete date literals
+const DATE_LENGTHS: [usize; 3] = [10, 16, 19];
+
 fn begin_token(c: char) -> TokenizerState {
     match c {
-        // 0b = binary, 0 = oct, 0x = hex
-        '0' => TokenizerState::InNumber {
-            value: 0.into(),
-            radix: 8,
-        },
+        // 0b = binary, 0o = oct, 0x = hex
+        '0' => TokenizerState::LeadingZero,
         '1'..='9' => TokenizerState::InNumber {
             value: (c as u32 - '0' as u32).into(),
             radix: 10,
         },
         '+' => TokenizerState::Pending(Token::Op(Operator::Add)),
-        '-' => TokenizerState::Pending(Token::Op(Operator::Sub)),
+        '-' | '−' => TokenizerState::Pending(Token::Op(Operator::Sub)),
+        '×' | '·' => TokenizerState::Pending(Token::Op(Operator::Mul)),
+        '÷' => TokenizerState::Pending(Token::Op(Operator::Div)),
+        '±' => TokenizerState::Pending(Token::Op(Operator::PlusMinus)),
+        _ if superscript_digit(c).is_some() => TokenizerState::InSuperscript(c.to_compact_string()),
         '(' => TokenizerState::Pending(Token::ParenOpen),
         ')' => TokenizerState::Pending(Token::ParenClose),
+        ',' => TokenizerState::Pending(Token::Comma),
+        '%' => TokenizerState::Pending(Token::Percent),
+        '?' => TokenizerState::Pending(Token::Question),
+        ':' => TokenizerState::Pending(Token::Colon),
+        '[' => TokenizerState::Pending(Token::BracketOpen),
+        ']' => TokenizerState::Pending(Token::BracketClose),
+        '{' => TokenizerState::Pending(Token::BraceOpen),
+        '}' => TokenizerState::Pending(Token::BraceClose),
+        // `$` starts the name of an environment variable
+        'a'..='z' | 'A'..='Z' | '_' | '$' => TokenizerState::InIdent(c.to_compact_string()),
         // Ignore whitespace
         _ if c.is_whitespace() => TokenizerState::Clean,
         _ => TokenizerState::InOperator(c.to_compact_string()),
     }
 }
 
+/// Value of a superscript digit, the superscript minus sign is accepted with a value of zero
+fn superscript_digit(c: char) -> Option<u32> {
+    match c {
+        '⁰' => Some(0),
+        '¹' => Some(1),
+        '²' => Some(2),
+        '³' => Some(3),
+        '⁴'..='⁹' => Some(c as u32 - '⁴' as u32 + 4),
+        '⁻' => Some(0),
+        _ => None,
+    }
+}
+
+fn decimal(value: Integer, digits: u64) -> Value {
+    let scale = Integer::from(10).pow(digits);
+    Value::Real(Rational::from_integers(value, scale))
+}
+
+/// Number in scientific notation, the mantissa multiplied by a power of ten
+fn scientific(mantissa: Value, negative: bool, exponent: u64) -> Result<Value, TokenizeError> {
+    let scale = Integer::from(10).pow(exponent);
+    Ok(match mantissa {
+        Value::Int(i) if !negative => Value::Int(i * scale),
+        mantissa => {
+            let mantissa = mantissa.to_rational().ok_or(TokenizeError::InvalidNumber)?;
+            Value::Real(match negative {
+                true => mantissa / Rational::from(scale),
+                false => mantissa * Rational::from(scale),
+            })
+        }
+    })
+}
+
+fn duration_seconds(value: Value, unit: &str) -> Result<Rational, TokenizeError> {
+    let unit = duration::unit(unit).ok_or(TokenizeError::InvalidNumber)?;
+    Ok(value.to_rational().ok_or(TokenizeError::InvalidNumber)? * unit)
+}
+
+fn roman_numeral(text: &str) -> Result<Token, TokenizeError> {
+    let value = roman::parse(text).ok_or(TokenizeError::InvalidNumber)?;
+    Ok(Token::Val(Value::from(i64::from(value))))
+}
+
+/// Number of bytes with a byte size suffix applied
+fn byte_size(value: Value, multiplier: Natural) -> Result<Token, TokenizeError> {
+    let value =
+        value.to_rational().ok_or(TokenizeError::InvalidNumber)? * Rational::from(multiplier);
+    Ok(Token::Val(match Integer::try_from(&value) {
+        Ok(i) => Value::Int(i),
+        Err(_) => Value::Real(value),
+    }))
+}
+
+/// Identifier token, or an operator for keywords
+fn identifier(ident: CompactString) -> Token {
+    match ident.as_str() {
+        "to" => Token::Op(Operator::To),
+        _ => Token::Ident(ident),
+    }
+}
+
 fn finalize_operator(op: &str) -> Option<Token> {
     match op {
         "+" => Some(Token::Op(Operator::Add)),
@@ -109,26 +818,155 @@ fn finalize_operator(op: &str) -> Option<Token> {
         "/" => Some(Token::Op(Operator::Div)),
         "(" => Some(Token::ParenOpen),
         ")" => Some(Token::ParenClose),
+        "," => Some(Token::Comma),
         "*" => Some(Token::Op(Operator::Mul)),
         "**" => Some(Token::Op(Operator::Pow)),
+        ".." => Some(Token::Op(Operator::Range)),
+        "..=" => Some(Token::Op(Operator::RangeInclusive)),
+        "==" => Some(Token::Op(Operator::Eq)),
+        "!=" => Some(Token::Op(Operator::Ne)),
+        "<" => Some(Token::Op(Operator::Lt)),
+        "<=" => Some(Token::Op(Operator::Le)),
+        ">" => Some(Token::Op(Operator::Gt)),
+        ">=" => Some(Token::Op(Operator::Ge)),
+        "&&" => Some(Token::Op(Operator::And)),
+        "||" => Some(Token::Op(Operator::Or)),
+        "|" => Some(Token::Bar),
+        "!" => Some(Token::Not),
+        "=" => Some(Token::Assign(None)),
+        "*=" => Some(Token::Assign(Some(Operator::Mul))),
+        "/=" => Some(Token::Assign(Some(Operator::Div))),
         _ => None,
     }
 }
 
+/// Bracket open at the current character
+#[derive(Debug, Clone, Copy)]
+struct Bracket {
+    /// Arguments of a call or elements of a list, rather than a group
+    arguments: bool,
+    /// A number in the arguments has a decimal comma
+    decimal_comma: bool,
+    /// The arguments are separated with `;`
+    separated: bool,
+}
+
+impl Bracket {
+    fn new(arguments: bool) -> Self {
+        Self {
+            arguments,
+            decimal_comma: false,
+            separated: false,
+        }
+    }
+}
+
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum TokenizeError {
     #[error("Invalid number")]
     InvalidNumber,
     #[error("Unknown operation: {0}")]
     UnknownOperation(CompactString),
+    #[error("Ambiguous ',' in arguments, separate them with ';' or write decimals with '.'")]
+    ArgumentComma,
+    #[error("Invalid UTF-8")]
+    InvalidUtf8,
+    #[error("Base must be between 2 and 36")]
+    InvalidBase,
+    #[error("Expected a result number or an environment variable after $")]
+    EmptyReference,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Token {
     Val(Value),
     Op(Operator),
     ParenOpen,
     ParenClose,
+    Comma,
+    /// Postfix percent sign
+    Percent,
+    /// Prefix logical negation
+    Not,
+    /// Conditional expression `cond ? then : otherwise`
+    Question,
+    Colon,
+    /// Postfix bit slice `x[high:low]`, or grouping like parentheses
+    BracketOpen,
+    BracketClose,
+    BraceOpen,
+    BraceClose,
+    /// Absolute value bar
+    Bar,
+    Ident(CompactString),
+    /// `=` of a definition, with the operator of a compound assignment like `+=`
+    Assign(Option<Operator>),
+}
+
+/// Iterator over the tokens of characters, see [`Tokenizer::tokens`]
+///
+/// Iteration stops after the first error.
+#[derive(Debug)]
+pub struct Tokens<I> {
+    tokenizer: Tokenizer,
+    chars: I,
+    /// Tokens completed at the end of the input
+    rest: VecDeque<Token>,
+    done: bool,
+}
+
+impl<I: Iterator<Item = char>> Iterator for Tokens<I> {
+    type Item = Result<Token, TokenizeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let result = match self.chars.next() {
+                Some(c) => self.tokenizer.update(c),
+                None => {
+                    self.done = true;
+                    self.tokenizer.finalize().map(|tokens| {
+                        self.rest = tokens.into();
+                        None
+                    })
+                }
+            };
+            match result {
+                Ok(Some(token)) => return Some(Ok(token)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.rest.pop_front().map(Ok)
+    }
+}
+
+/// Source text of the token
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Token::Val(v) => return f.write_str(&printer::literal(v)),
+            Token::Op(op) => return write!(f, "{}", op),
+            Token::Assign(op) => return write!(f, "{}=", op.map_or("", printer::symbol)),
+            Token::Ident(name) => name.as_str(),
+            Token::ParenOpen => "(",
+            Token::ParenClose => ")",
+            Token::Comma => ",",
+            Token::Percent => "%",
+            Token::Not => "!",
+            Token::Question => "?",
+            Token::Colon => ":",
+            Token::BracketOpen => "[",
+            Token::BracketClose => "]",
+            Token::BraceOpen => "{",
+            Token::BraceClose => "}",
+            Token::Bar => "|",
+        };
+        f.write_str(text)
+    }
 }
 
 impl From<i64> for Token {
@@ -138,32 +976,85 @@ impl From<i64> for Token {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operator {
     Add,
     Sub,
     Mul,
     Div,
     Pow,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    /// Unit conversion
+    To,
+    /// Interval of a number with an uncertainty, e.g. `2.5 ± 0.1`
+    PlusMinus,
+    /// Integers from the left operand up to the right one, excluding it
+    Range,
+    /// Integers from the left operand up to the right one, including it
+    RangeInclusive,
+}
+
+impl Operator {
+    /// Arithmetic operator, `None` for the logical operators that are evaluated lazily and the
+    /// ones that build values out of their operands: conversions, intervals and ranges
+    pub fn arithmetic(self) -> Option<Arithmetic> {
+        Some(match self {
+            Operator::Add => Arithmetic::Add,
+            Operator::Sub => Arithmetic::Sub,
+            Operator::Mul => Arithmetic::Mul,
+            Operator::Div => Arithmetic::Div,
+            Operator::Pow => Arithmetic::Pow,
+            Operator::Eq => Arithmetic::Eq,
+            Operator::Ne => Arithmetic::Ne,
+            Operator::Lt => Arithmetic::Lt,
+            Operator::Le => Arithmetic::Le,
+            Operator::Gt => Arithmetic::Gt,
+            Operator::Ge => Arithmetic::Ge,
+            Operator::And
+            | Operator::Or
+            | Operator::To
+            | Operator::PlusMinus
+            | Operator::Range
+            | Operator::RangeInclusive => return None,
+        })
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(printer::symbol(*self))
+    }
 }
 
-pub type Value = Integer;
+/// Operator computed on numbers of every kind, see [`Operator::arithmetic`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arithmetic {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
     fn tokenize(expr: &str) -> Result<Vec<Token>, TokenizeError> {
-        let mut tokens = vec![];
-        let mut tokenizer = Tokenizer::default();
-        for c in expr.chars() {
-            if let Some(token) = tokenizer.update(c)? {
-                tokens.push(token)
-            }
-        }
-        if let Some(token) = tokenizer.finalize()? {
-            tokens.push(token)
-        }
-        Ok(tokens)
+        Tokenizer::tokens(expr.chars()).collect()
     }
 
     #[test]
@@ -214,32 +1105,605 @@ mod tests {
     }
 
     #[test]
-    fn test_non_decimal() {
-        let result = tokenize("0");
-        assert_eq!(result, Ok(vec![Token::from(0),]));
+    fn test_decimal() {
+        let real = |n, d| Token::Val(Value::Real(Rational::from_signeds(n, d)));
 
-        let result = tokenize("123456789");
-        assert_eq!(result, Ok(vec![Token::from(123456789),]));
+        let result = tokenize("2.5*0.125+3.");
+        assert_eq!(
+            result,
+            Ok(vec![
+                real(5, 2),
+                Token::Op(Operator::Mul),
+                real(1, 8),
+                Token::Op(Operator::Add),
+                real(3, 1),
+            ])
+        );
 
-        let result = tokenize("123456789A");
+        let result = tokenize("1.2.3");
         assert_eq!(result, Err(TokenizeError::InvalidNumber));
 
-        let result = tokenize("0x123456789abcdef");
-        assert_eq!(result, Ok(vec![Token::from(0x123456789abcdef),]));
+        let result = tokenize("0x1.5");
+        assert_eq!(result, Err(TokenizeError::UnknownOperation(".".into())));
+    }
 
-        let result = tokenize("0x123456789abcdefg");
+    #[test]
+    fn test_byte_size() {
+        let result = tokenize("4KiB+1.5MB-1B");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::from(4096),
+                Token::Op(Operator::Add),
+                Token::from(1500000),
+                Token::Op(Operator::Sub),
+                Token::from(1),
+            ])
+        );
+
+        let result = tokenize("4KiB2");
         assert_eq!(result, Err(TokenizeError::InvalidNumber));
 
-        let result = tokenize("0b10");
-        assert_eq!(result, Ok(vec![Token::from(0b10),]));
+        // Not a byte size, multiplication by an identifier
+        let result = tokenize("4Kb");
+        assert_eq!(result, Ok(vec![Token::from(4), Token::Ident("Kb".into())]));
+    }
 
-        let result = tokenize("0b102");
+    #[test]
+    fn test_duration() {
+        let seconds = |s| Token::Val(Value::Duration(Rational::from(s)));
+        let result = tokenize("1h30m+45min-27s 1m08.5s");
+        assert_eq!(
+            result,
+            Ok(vec![
+                seconds(5400),
+                Token::Op(Operator::Add),
+                seconds(2700),
+                Token::Op(Operator::Sub),
+                seconds(27),
+                Token::Val(Value::Duration(Rational::from_signeds(137, 2))),
+            ])
+        );
+
+        let result = tokenize("1h30");
         assert_eq!(result, Err(TokenizeError::InvalidNumber));
 
-        let result = tokenize("01234567");
-        assert_eq!(result, Ok(vec![Token::from(0o1234567),]));
+        // Metres rather than minutes, the same as `5 m`
+        let result = tokenize("5m");
+        assert_eq!(result, Ok(vec![5.into(), Token::Ident("m".into())]));
 
-        let result = tokenize("012345678");
+        let result = tokenize("1h2KiB");
         assert_eq!(result, Err(TokenizeError::InvalidNumber));
     }
+
+    #[test]
+    fn test_scientific() {
+        let real = |n, d| Token::Val(Value::Real(Rational::from_signeds(n, d)));
+        assert_eq!(tokenize("1e3"), Ok(vec![1000.into()]));
+        assert_eq!(tokenize("1.5e-3"), Ok(vec![real(3, 2000)]));
+        assert_eq!(tokenize("120E-6"), Ok(vec![real(3, 25000)]));
+        assert_eq!(tokenize("2.5e+2"), Ok(vec![real(250, 1)]));
+        assert_eq!(
+            tokenize("1e3h"),
+            Ok(vec![Token::Val(Value::Duration(Rational::from(3_600_000)))])
+        );
+        assert_eq!(tokenize("1e99999999"), Err(TokenizeError::InvalidNumber));
+
+        // Multiplication by `e` without exponent digits
+        let e = Token::Ident("e".into());
+        assert_eq!(tokenize("2e"), Ok(vec![2.into(), e.clone()]));
+        assert_eq!(
+            tokenize("2e-x"),
+            Ok(vec![
+                2.into(),
+                e,
+                Token::Op(Operator::Sub),
+                Token::Ident("x".into())
+            ])
+        );
+    }
+
+    #[test]
+    fn test_dice() {
+        let dice = |count: i64, sides: i64| {
+            vec![
+                Token::Ident("dice".into()),
+                Token::ParenOpen,
+                count.into(),
+                Token::Comma,
+                sides.into(),
+                Token::ParenClose,
+            ]
+        };
+        let result = tokenize("3d6 + 2");
+        let expected = [dice(3, 6), vec![Token::Op(Operator::Add), 2.into()]].concat();
+        assert_eq!(result, Ok(expected));
+        assert_eq!(tokenize("d20"), Ok(dice(1, 20)));
+        assert_eq!(tokenize("2d10*d4"), {
+            Ok([dice(2, 10), vec![Token::Op(Operator::Mul)], dice(1, 4)].concat())
+        });
+
+        // Durations and identifiers are unchanged
+        let seconds = |s| Token::Val(Value::Duration(Rational::from(s)));
+        assert_eq!(tokenize("3d6h"), Ok(vec![seconds(3 * 86400 + 6 * 3600)]));
+        assert_eq!(tokenize("1d12.5h"), Ok(vec![seconds(86400 + 45000)]));
+        assert_eq!(tokenize("2d"), Ok(vec![seconds(2 * 86400)]));
+        assert_eq!(tokenize("dx"), Ok(vec![Token::Ident("dx".into())]));
+    }
+
+    #[test]
+    fn test_date() {
+        let date = |s: &str| Token::Val(Value::Str(s.into()));
+        let result = tokenize("unix(2024-05-01T12:00)-2024-05-01");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::Ident("unix".into()),
+                Token::ParenOpen,
+                date("2024-05-01T12:00"),
+                Token::ParenClose,
+                Token::Op(Operator::Sub),
+                Token::from(2024),
+                Token::Op(Operator::Sub),
+                Token::from(5),
+                Token::Op(Operator::Sub),
+                Token::from(1),
+            ])
+        );
+
+        let result = tokenize("unix( 2024-05-01 )");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::Ident("unix".into()),
+                Token::ParenOpen,
+                date("2024-05-01"),
+                Token::ParenClose,
+            ])
+        );
+
+        let result = tokenize("unix(2024-123+1");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::Ident("unix".into()),
+                Token::ParenOpen,
+                Token::from(2024),
+                Token::Op(Operator::Sub),
+                Token::from(123),
+                Token::Op(Operator::Add),
+                Token::from(1),
+            ])
+        );
+
+        let result = tokenize("unix(2024-12-");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::Ident("unix".into()),
+                Token::ParenOpen,
+                Token::from(2024),
+                Token::Op(Operator::Sub),
+                Token::from(12),
+                Token::Op(Operator::Sub),
+            ])
+        );
+
+        let result = tokenize("unix(2024-05-01T1");
+        assert_eq!(result, Err(TokenizeError::InvalidNumber));
+    }
+
+    #[test]
+    fn test_percent() {
+        let result = tokenize("200+10%*2");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::from(200),
+                Token::Op(Operator::Add),
+                Token::from(10),
+                Token::Percent,
+                Token::Op(Operator::Mul),
+                Token::from(2),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_comparison() {
+        let result = tokenize("1<=2!=3>-4");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::from(1),
+                Token::Op(Operator::Le),
+                Token::from(2),
+                Token::Op(Operator::Ne),
+                Token::from(3),
+                Token::Op(Operator::Gt),
+                Token::Op(Operator::Sub),
+                Token::from(4),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_logical() {
+        let result = tokenize("!!1&&0||x?1:2");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::Not,
+                Token::Not,
+                Token::from(1),
+                Token::Op(Operator::And),
+                Token::from(0),
+                Token::Op(Operator::Or),
+                Token::Ident("x".into()),
+                Token::Question,
+                Token::from(1),
+                Token::Colon,
+                Token::from(2),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_abs() {
+        let result = tokenize("|x-5|*-|2| ||1");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::Bar,
+                Token::Ident("x".into()),
+                Token::Op(Operator::Sub),
+                Token::from(5),
+                Token::Bar,
+                Token::Op(Operator::Mul),
+                Token::Op(Operator::Sub),
+                Token::Bar,
+                Token::from(2),
+                Token::Bar,
+                Token::Op(Operator::Or),
+                Token::from(1),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_slice() {
+        let result = tokenize("x[11:4]");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::Ident("x".into()),
+                Token::BracketOpen,
+                Token::from(11),
+                Token::Colon,
+                Token::from(4),
+                Token::BracketClose,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_unicode() {
+        let result = tokenize("2×3÷4·5−1");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::from(2),
+                Token::Op(Operator::Mul),
+                Token::from(3),
+                Token::Op(Operator::Div),
+                Token::from(4),
+                Token::Op(Operator::Mul),
+                Token::from(5),
+                Token::Op(Operator::Sub),
+                Token::from(1),
+            ])
+        );
+
+        let result = tokenize("2³+x⁻¹²");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::from(2),
+                Token::Op(Operator::Pow),
+                Token::from(3),
+                Token::Op(Operator::Add),
+                Token::Ident("x".into()),
+                Token::Op(Operator::Pow),
+                Token::Op(Operator::Sub),
+                Token::from(12),
+            ])
+        );
+
+        let result = tokenize("2²⁻");
+        assert_eq!(result, Err(TokenizeError::InvalidNumber));
+    }
+
+    #[test]
+    fn test_ranges() {
+        const RANGE: Token = Token::Op(Operator::Range);
+        const INCLUSIVE: Token = Token::Op(Operator::RangeInclusive);
+        assert_eq!(
+            tokenize("1..=100"),
+            Ok(vec![1.into(), INCLUSIVE, 100.into()])
+        );
+        assert_eq!(
+            tokenize("0..n"),
+            Ok(vec![0.into(), RANGE, Token::Ident("n".into())])
+        );
+        assert_eq!(
+            tokenize("(a)..-2"),
+            Ok(vec![
+                Token::ParenOpen,
+                Token::Ident("a".into()),
+                Token::ParenClose,
+                RANGE,
+                Token::Op(Operator::Sub),
+                2.into()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_intervals() {
+        let tokens = |tokenizer: Tokenizer, text: &str| {
+            tokenizer
+                .into_tokens(text.chars())
+                .collect::<Result<Vec<_>, _>>()
+        };
+        const PM: Token = Token::Op(Operator::PlusMinus);
+        let mut tokenizer = Tokenizer::default();
+        tokenizer.set_intervals(true);
+        assert_eq!(
+            tokens(tokenizer.clone(), "5 ± 1"),
+            Ok(vec![5.into(), PM, 1.into()])
+        );
+        assert_eq!(tokens(tokenizer, "5+-1"), Ok(vec![5.into(), PM, 1.into()]));
+        assert_eq!(
+            tokens(Tokenizer::default(), "5+-1"),
+            Ok(vec![
+                5.into(),
+                Token::Op(Operator::Add),
+                Token::Op(Operator::Sub),
+                1.into()
+            ])
+        );
+        assert_eq!(
+            tokens(Tokenizer::default(), "5 ± 1"),
+            Err(TokenizeError::UnknownOperation("±".into()))
+        );
+    }
+
+    #[test]
+    fn test_c_octal() {
+        let mut tokenizer = Tokenizer::default();
+        tokenizer.set_c_octal(true);
+        let mut tokens = vec![];
+        for c in "010 0x10 0.5".chars() {
+            tokens.extend(tokenizer.update(c).unwrap());
+        }
+        tokens.extend(tokenizer.finalize().unwrap());
+        let half = Token::Val(Value::Real(Rational::from_signeds(1, 2)));
+        assert_eq!(tokens, vec![Token::from(8), Token::from(16), half]);
+
+        assert_eq!(tokenizer.update('0'), Ok(None));
+        assert_eq!(tokenizer.update('8'), Err(TokenizeError::InvalidNumber));
+    }
+
+    #[test]
+    fn test_decimal_comma() {
+        let mut tokenizer = Tokenizer::default();
+        tokenizer.set_decimal_comma(true);
+        let result: Result<Vec<_>, _> = tokenizer.into_tokens("max(3,14; 2)".chars()).collect();
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::Ident("max".into()),
+                Token::ParenOpen,
+                Token::Val(Value::Real(Rational::from_signeds(157, 50))),
+                Token::Comma,
+                Token::from(2),
+                Token::ParenClose,
+            ])
+        );
+
+        let tokenize = |text: &str| {
+            let mut tokenizer = Tokenizer::default();
+            tokenizer.set_decimal_comma(true);
+            tokenizer
+                .into_tokens(text.chars())
+                .collect::<Result<Vec<_>, _>>()
+        };
+        assert_eq!(
+            tokenize("1 , 2"),
+            Err(TokenizeError::UnknownOperation(",".into()))
+        );
+        // Arguments are separated with `;`, `max(1,2)` would be the maximum of `1.2`
+        for text in ["max(1 , 2)", "max(1, 2)", "max(1,2)", "[1,5]", "f(x,y)"] {
+            assert_eq!(tokenize(text), Err(TokenizeError::ArgumentComma), "{text}");
+        }
+        assert!(tokenize("[1,5; 2]").is_ok());
+        assert!(tokenize("sqrt((2,25))").is_ok());
+        assert_eq!(
+            tokenize("0,5 + 1,,3"),
+            Ok(vec![
+                Token::Val(Value::Real(Rational::from_signeds(1, 2))),
+                Token::Op(Operator::Add),
+                Token::from(1),
+                Token::Op(Operator::Range),
+                Token::from(3),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_conversion() {
+        let result = tokenize("72 F to C");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::from(72),
+                Token::Ident("F".into()),
+                Token::Op(Operator::To),
+                Token::Ident("C".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_function_call() {
+        let result = tokenize("max(x_1, -2,3)");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::Ident("max".into()),
+                Token::ParenOpen,
+                Token::Ident("x_1".into()),
+                Token::Comma,
+                Token::Op(Operator::Sub),
+                Token::from(2),
+                Token::Comma,
+                Token::from(3),
+                Token::ParenClose,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_non_decimal() {
+        let result = tokenize("0");
+        assert_eq!(result, Ok(vec![Token::from(0),]));
+
+        let result = tokenize("123456789");
+        assert_eq!(result, Ok(vec![Token::from(123456789),]));
+
+        let result = tokenize("123456789A");
+        assert_eq!(
+            result,
+            Ok(vec![Token::from(123456789), Token::Ident("A".into())])
+        );
+
+        let result = tokenize("0x123456789abcdef");
+        assert_eq!(result, Ok(vec![Token::from(0x123456789abcdef),]));
+
+        let result = tokenize("0x123456789abcdefg");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::from(0x123456789abcdef),
+                Token::Ident("g".into())
+            ])
+        );
+
+        let result = tokenize("0b10");
+        assert_eq!(result, Ok(vec![Token::from(0b10),]));
+
+        let result = tokenize("0b102");
+        assert_eq!(result, Err(TokenizeError::InvalidNumber));
+
+        let result = tokenize("0o1234567");
+        assert_eq!(result, Ok(vec![Token::from(0o1234567),]));
+
+        let result = tokenize("0o12345678");
+        assert_eq!(result, Err(TokenizeError::InvalidNumber));
+
+        let result = tokenize("010");
+        assert_eq!(result, Ok(vec![Token::from(10),]));
+
+        let result = tokenize("0rMMXXIV+1");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::from(2024),
+                Token::Op(Operator::Add),
+                Token::from(1)
+            ])
+        );
+
+        let result = tokenize("0rIIII");
+        assert_eq!(result, Err(TokenizeError::InvalidNumber));
+
+        let result = tokenize("36#zz 3#1021 16#FF");
+        assert_eq!(
+            result,
+            Ok(vec![Token::from(1295), Token::from(34), Token::from(255)])
+        );
+
+        let result = tokenize("2#102");
+        assert_eq!(result, Err(TokenizeError::InvalidNumber));
+
+        assert_eq!(tokenize("37#1"), Err(TokenizeError::InvalidBase));
+        assert_eq!(tokenize("1#1"), Err(TokenizeError::InvalidBase));
+        assert_eq!(tokenize("0#1"), Err(TokenizeError::InvalidBase));
+    }
+
+    #[test]
+    fn test_dollar() {
+        let result = tokenize("$1*$HOME_2");
+        assert_eq!(
+            result,
+            Ok(vec![
+                Token::Ident("$1".into()),
+                Token::Op(Operator::Mul),
+                Token::Ident("$HOME_2".into()),
+            ])
+        );
+        assert_eq!(tokenize("$"), Err(TokenizeError::EmptyReference));
+        assert_eq!(tokenize("$ + 1"), Err(TokenizeError::EmptyReference));
+    }
+
+    #[test]
+    fn test_update_bytes() {
+        // `2×3` with the multiplication sign split between the chunks
+        let bytes = "2×3".as_bytes();
+        let mut tokenizer = Tokenizer::default();
+        let mut tokens = tokenizer.update_bytes(&bytes[..2]).unwrap();
+        tokens.extend(tokenizer.update_bytes(&bytes[2..3]).unwrap());
+        tokens.extend(tokenizer.update_bytes(&bytes[3..]).unwrap());
+        tokens.extend(tokenizer.finalize().unwrap());
+        assert_eq!(tokens, tokenize("2*3").unwrap());
+
+        let mut tokenizer = Tokenizer::default();
+        assert_eq!(
+            tokenizer.update_bytes(b"1\xff"),
+            Err(TokenizeError::InvalidUtf8)
+        );
+        let mut tokenizer = Tokenizer::default();
+        tokenizer.update_bytes(b"1 \xc3").unwrap();
+        assert_eq!(tokenizer.finalize(), Err(TokenizeError::InvalidUtf8));
+    }
+
+    #[test]
+    fn test_tokens() {
+        let mut tokens = Tokenizer::tokens("1 + 2".chars());
+        assert_eq!(tokens.next(), Some(Ok(1.into())));
+        assert_eq!(tokens.next(), Some(Ok(Token::Op(Operator::Add))));
+        assert_eq!(tokens.next(), Some(Ok(2.into())));
+        assert_eq!(tokens.next(), None);
+
+        let mut tokens = Tokenizer::tokens("1 @ 2".chars());
+        assert_eq!(tokens.next(), Some(Ok(1.into())));
+        assert!(matches!(tokens.next(), Some(Err(_))));
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let text = |expr: &str| {
+            let tokens = tokenize(expr).unwrap();
+            tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            text("max(2.5, x) ** 2"),
+            ["max", "(", "2.5", ",", "x", ")", "**", "2"]
+        );
+        assert_eq!(
+            text("|a| ? 1h30m : 4KiB"),
+            ["|", "a", "|", "?", "1h30m", ":", "4096"]
+        );
+    }
 }
@@ -0,0 +1,444 @@
+use crate::tokenizer::Arithmetic;
+use crate::value::Value;
+use compact_str::{format_compact, CompactString};
+use malachite::num::arithmetic::traits::Pow;
+use malachite::num::basic::traits::{One, Zero};
+use malachite::{Integer, Rational};
+use thiserror::Error;
+
+/// Exponents of the SI base dimensions: length, mass, time, current, temperature, amount and
+/// luminous intensity
+pub type Dimension = [i8; 7];
+
+const NONE: Dimension = [0, 0, 0, 0, 0, 0, 0];
+const LENGTH: Dimension = [1, 0, 0, 0, 0, 0, 0];
+const AREA: Dimension = [2, 0, 0, 0, 0, 0, 0];
+const VOLUME: Dimension = [3, 0, 0, 0, 0, 0, 0];
+const MASS: Dimension = [0, 1, 0, 0, 0, 0, 0];
+const TIME: Dimension = [0, 0, 1, 0, 0, 0, 0];
+const CURRENT: Dimension = [0, 0, 0, 1, 0, 0, 0];
+const TEMPERATURE: Dimension = [0, 0, 0, 0, 1, 0, 0];
+const AMOUNT: Dimension = [0, 0, 0, 0, 0, 1, 0];
+const LUMINOSITY: Dimension = [0, 0, 0, 0, 0, 0, 1];
+const FREQUENCY: Dimension = [0, 0, -1, 0, 0, 0, 0];
+const SPEED: Dimension = [1, 0, -1, 0, 0, 0, 0];
+const FORCE: Dimension = [1, 1, -2, 0, 0, 0, 0];
+const PRESSURE: Dimension = [-1, 1, -2, 0, 0, 0, 0];
+const ENERGY: Dimension = [2, 1, -2, 0, 0, 0, 0];
+const POWER: Dimension = [2, 1, -3, 0, 0, 0, 0];
+const VOLTAGE: Dimension = [2, 1, -3, -1, 0, 0, 0];
+
+/// Name, size in SI units as a fraction, and dimension
+const UNITS: &[(&str, i64, i64, Dimension)] = &[
+    ("m", 1, 1, LENGTH),
+    ("km", 1000, 1, LENGTH),
+    ("cm", 1, 100, LENGTH),
+    ("mm", 1, 1000, LENGTH),
+    ("um", 1, 1_000_000, LENGTH),
+    ("nm", 1, 1_000_000_000, LENGTH),
+    ("in", 254, 10000, LENGTH),
+    ("ft", 3048, 10000, LENGTH),
+    ("yd", 9144, 10000, LENGTH),
+    ("mi", 1609344, 1000, LENGTH),
+    ("nmi", 1852, 1, LENGTH),
+    ("au", 149597870700, 1, LENGTH),
+    ("ha", 10000, 1, AREA),
+    ("acre", 40468564224, 10000000, AREA),
+    ("L", 1, 1000, VOLUME),
+    ("l", 1, 1000, VOLUME),
+    ("mL", 1, 1_000_000, VOLUME),
+    ("ml", 1, 1_000_000, VOLUME),
+    ("gal", 3785411784, 1_000_000_000_000, VOLUME),
+    ("kg", 1, 1, MASS),
+    ("g", 1, 1000, MASS),
+    ("mg", 1, 1_000_000, MASS),
+    ("t", 1000, 1, MASS),
+    ("lb", 45359237, 100_000_000, MASS),
+    ("oz", 45359237, 1_600_000_000, MASS),
+    ("s", 1, 1, TIME),
+    ("ms", 1, 1000, TIME),
+    ("us", 1, 1_000_000, TIME),
+    ("ns", 1, 1_000_000_000, TIME),
+    ("min", 60, 1, TIME),
+    ("h", 3600, 1, TIME),
+    ("d", 86400, 1, TIME),
+    ("yr", 31557600, 1, TIME),
+    ("A", 1, 1, CURRENT),
+    ("mA", 1, 1000, CURRENT),
+    ("K", 1, 1, TEMPERATURE),
+    ("mol", 1, 1, AMOUNT),
+    ("cd", 1, 1, LUMINOSITY),
+    ("Hz", 1, 1, FREQUENCY),
+    ("kHz", 1000, 1, FREQUENCY),
+    ("MHz", 1_000_000, 1, FREQUENCY),
+    ("GHz", 1_000_000_000, 1, FREQUENCY),
+    ("mph", 1609344, 3_600_000, SPEED),
+    ("kn", 1852, 3600, SPEED),
+    ("N", 1, 1, FORCE),
+    ("Pa", 1, 1, PRESSURE),
+    ("kPa", 1000, 1, PRESSURE),
+    ("bar", 100000, 1, PRESSURE),
+    ("atm", 101325, 1, PRESSURE),
+    ("psi", 8896443230521, 1290320000, PRESSURE),
+    ("J", 1, 1, ENERGY),
+    ("kJ", 1000, 1, ENERGY),
+    ("cal", 4184, 1000, ENERGY),
+    ("kcal", 4184, 1, ENERGY),
+    ("Wh", 3600, 1, ENERGY),
+    ("kWh", 3_600_000, 1, ENERGY),
+    ("W", 1, 1, POWER),
+    ("kW", 1000, 1, POWER),
+    ("V", 1, 1, VOLTAGE),
+];
+
+type Fraction = (i64, i64);
+
+/// Temperature scales with a zero point different from absolute zero: name, size in kelvins and
+/// zero point in kelvins as fractions
+const TEMPERATURES: &[(&str, Fraction, Fraction)] = &[
+    ("C", (1, 1), (27315, 100)),
+    ("F", (5, 9), (45967, 180)),
+    ("R", (5, 9), (0, 1)),
+];
+
+/// Unit of measurement, possibly composed of several named units
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Unit {
+    pub name: CompactString,
+    /// Size of the unit in SI units
+    factor: Rational,
+    /// Value of the unit zero point in SI units, only used by temperature scales
+    offset: Rational,
+    dimension: Dimension,
+}
+
+impl Unit {
+    /// Look up a unit by name
+    pub fn lookup(name: &str) -> Option<Unit> {
+        if let Some((_, n, d, dimension)) = UNITS.iter().find(|(unit, ..)| *unit == name) {
+            return Some(Unit {
+                name: name.into(),
+                factor: Rational::from_signeds(*n, *d),
+                offset: Rational::ZERO,
+                dimension: *dimension,
+            });
+        }
+        let (_, factor, offset) = TEMPERATURES.iter().find(|(unit, ..)| *unit == name)?;
+        Some(Unit {
+            name: name.into(),
+            factor: Rational::from_signeds(factor.0, factor.1),
+            offset: Rational::from_signeds(offset.0, offset.1),
+            dimension: TEMPERATURE,
+        })
+    }
+
+    /// Unit of plain numbers
+    fn one() -> Unit {
+        Unit {
+            name: CompactString::default(),
+            factor: Rational::ONE,
+            offset: Rational::ZERO,
+            dimension: NONE,
+        }
+    }
+
+    /// Name for error messages, plain numbers are shown as `1`
+    fn display_name(&self) -> CompactString {
+        if self.is_one() {
+            "1".into()
+        } else {
+            self.name.clone()
+        }
+    }
+
+    fn is_one(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    fn mul(&self, other: &Unit) -> Unit {
+        if self.is_one() {
+            return other.clone();
+        } else if other.is_one() {
+            return self.clone();
+        }
+        Unit {
+            name: format_compact!("{}*{}", self.name, other.name),
+            factor: &self.factor * &other.factor,
+            offset: Rational::ZERO,
+            dimension: combine(&self.dimension, &other.dimension, 1),
+        }
+    }
+
+    fn div(&self, other: &Unit) -> Unit {
+        if other.is_one() {
+            return self.clone();
+        }
+        let name = if self.is_one() {
+            format_compact!("1/{}", other.name)
+        } else {
+            format_compact!("{}/{}", self.name, grouped(&other.name))
+        };
+        Unit {
+            name,
+            factor: &self.factor / &other.factor,
+            offset: Rational::ZERO,
+            dimension: combine(&self.dimension, &other.dimension, -1),
+        }
+    }
+
+    fn pow(&self, exp: i64) -> Option<Unit> {
+        let mut dimension = NONE;
+        for (d, s) in dimension.iter_mut().zip(self.dimension) {
+            *d = i8::try_from(i64::from(s).checked_mul(exp)?).ok()?;
+        }
+        Some(Unit {
+            name: format_compact!("{}^{}", grouped(&self.name), exp),
+            factor: (&self.factor).pow(exp),
+            offset: Rational::ZERO,
+            dimension,
+        })
+    }
+}
+
+/// Name wrapped in parentheses if it consists of several units
+fn grouped(name: &str) -> CompactString {
+    if name.contains(['*', '/', '^']) {
+        format_compact!("({})", name)
+    } else {
+        name.into()
+    }
+}
+
+fn combine(l: &Dimension, r: &Dimension, sign: i8) -> Dimension {
+    let mut result = NONE;
+    for ((d, l), r) in result.iter_mut().zip(l).zip(r) {
+        *d = l + sign * r;
+    }
+    result
+}
+
+/// Number with a unit of measurement
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quantity {
+    pub magnitude: Rational,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    /// One of the unit
+    pub fn unit(unit: Unit) -> Quantity {
+        Quantity {
+            magnitude: Rational::ONE,
+            unit,
+        }
+    }
+
+    /// Value in SI units, including the zero point offset
+    fn absolute(&self) -> Rational {
+        &self.magnitude * &self.unit.factor + &self.unit.offset
+    }
+
+    /// Convert to a different unit of the same dimension
+    pub fn convert(&self, unit: Unit) -> Result<Quantity, UnitError> {
+        self.check_dimension(&unit)?;
+        Ok(Quantity {
+            magnitude: (self.absolute() - &unit.offset) / &unit.factor,
+            unit,
+        })
+    }
+
+    fn check_dimension(&self, unit: &Unit) -> Result<(), UnitError> {
+        if self.unit.dimension == unit.dimension {
+            Ok(())
+        } else {
+            Err(UnitError::Incompatible(
+                self.unit.display_name(),
+                unit.display_name(),
+            ))
+        }
+    }
+
+    /// Magnitude of a difference in the other unit, zero points don't matter for differences
+    fn difference_in(&self, unit: &Unit) -> Rational {
+        &self.magnitude * &self.unit.factor / &unit.factor
+    }
+
+    /// Quantity as a plain number if it has no dimension
+    fn into_value(self) -> Value {
+        if self.unit.dimension != NONE {
+            return Value::Quantity(self);
+        }
+        let value = self.magnitude * self.unit.factor;
+        match Integer::try_from(&value) {
+            Ok(i) => Value::Int(i),
+            Err(_) => Value::Real(value),
+        }
+    }
+}
+
+impl From<Rational> for Quantity {
+    fn from(magnitude: Rational) -> Self {
+        Quantity {
+            magnitude,
+            unit: Unit::one(),
+        }
+    }
+}
+
+fn quantity(v: Value) -> Result<Quantity, UnitError> {
+    match v {
+        Value::Quantity(q) => Ok(q),
+        Value::Duration(seconds) => Ok(Quantity {
+            magnitude: seconds,
+            unit: Unit::lookup("s").unwrap_or_else(Unit::one),
+        }),
+        v => v
+            .to_rational()
+            .map(Quantity::from)
+            .ok_or(UnitError::UnsupportedOperands),
+    }
+}
+
+/// Convert the left operand to the unit of the right one, the result of `to`
+pub fn convert(l: Value, r: Value) -> Result<Value, UnitError> {
+    let Value::Quantity(target) = r else {
+        return Err(UnitError::NotAUnit);
+    };
+    if target.magnitude != 1u32 {
+        return Err(UnitError::NotAUnit);
+    }
+    Ok(Value::Quantity(quantity(l)?.convert(target.unit)?))
+}
+
+/// Execute an operation where at least one of the operands is a quantity
+pub fn execute(op: Arithmetic, l: Value, r: Value) -> Result<Value, UnitError> {
+    let (l, r) = (quantity(l)?, quantity(r)?);
+    let result = match op {
+        Arithmetic::Add | Arithmetic::Sub => {
+            l.check_dimension(&r.unit)?;
+            let r = r.difference_in(&l.unit);
+            let magnitude = if op == Arithmetic::Add {
+                l.magnitude + r
+            } else {
+                l.magnitude - r
+            };
+            Quantity {
+                magnitude,
+                unit: l.unit,
+            }
+        }
+        Arithmetic::Mul => Quantity {
+            magnitude: l.magnitude * r.magnitude,
+            unit: l.unit.mul(&r.unit),
+        },
+        Arithmetic::Div if r.magnitude == 0u32 => return Err(UnitError::DivisionByZero),
+        Arithmetic::Div => Quantity {
+            magnitude: l.magnitude / r.magnitude,
+            unit: l.unit.div(&r.unit),
+        },
+        Arithmetic::Pow => {
+            let exp = match (r.unit.is_one(), Integer::try_from(&r.magnitude)) {
+                (true, Ok(exp)) => i64::try_from(&exp).map_err(|_| UnitError::InvalidPower)?,
+                _ => return Err(UnitError::InvalidPower),
+            };
+            if l.magnitude == 0u32 && exp < 0 {
+                return Err(UnitError::DivisionByZero);
+            }
+            Quantity {
+                magnitude: l.magnitude.pow(exp),
+                unit: l.unit.pow(exp).ok_or(UnitError::InvalidPower)?,
+            }
+        }
+        Arithmetic::Eq
+        | Arithmetic::Ne
+        | Arithmetic::Lt
+        | Arithmetic::Le
+        | Arithmetic::Gt
+        | Arithmetic::Ge => {
+            l.check_dimension(&r.unit)?;
+            let (l, r) = (l.absolute(), r.absolute());
+            let result = match op {
+                Arithmetic::Eq => l == r,
+                Arithmetic::Ne => l != r,
+                Arithmetic::Lt => l < r,
+                Arithmetic::Le => l <= r,
+                Arithmetic::Gt => l > r,
+                _ => l >= r,
+            };
+            return Ok(result.into());
+        }
+    };
+    Ok(result.into_value())
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum UnitError {
+    #[error("Incompatible units: {0} and {1}")]
+    Incompatible(CompactString, CompactString),
+    #[error("Conversion target must be a unit")]
+    NotAUnit,
+    #[error("Units can only be raised to integer powers")]
+    InvalidPower,
+    #[error("Operation is not supported for quantities")]
+    UnsupportedOperands,
+    #[error("Division by zero")]
+    DivisionByZero,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q(magnitude: i64, unit: &str) -> Value {
+        Value::Quantity(Quantity {
+            magnitude: Rational::from(magnitude),
+            unit: Unit::lookup(unit).unwrap(),
+        })
+    }
+
+    fn unit(name: &str) -> Value {
+        q(1, name)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let sum = execute(Arithmetic::Add, q(5, "km"), q(300, "m"));
+        assert_eq!(sum.map(|v| v.to_string()), Ok("5.3 km".into()));
+
+        let speed = execute(Arithmetic::Div, q(90, "km"), q(2, "h"));
+        assert_eq!(speed.map(|v| v.to_string()), Ok("45 km/h".into()));
+
+        let ratio = execute(Arithmetic::Div, q(3, "km"), q(300, "m"));
+        assert_eq!(ratio, Ok(Value::from(10)));
+
+        let area = execute(Arithmetic::Pow, q(3, "m"), Value::from(2));
+        assert_eq!(area.map(|v| v.to_string()), Ok("9 m^2".into()));
+
+        let mismatch = execute(Arithmetic::Add, q(1, "m"), q(1, "s"));
+        assert_eq!(
+            mismatch,
+            Err(UnitError::Incompatible("m".into(), "s".into()))
+        );
+    }
+
+    #[test]
+    fn test_convert() {
+        let c = convert(q(212, "F"), unit("C"));
+        assert_eq!(c.map(|v| v.to_string()), Ok("100 C".into()));
+
+        let k = convert(q(-40, "C"), unit("F"));
+        assert_eq!(k.map(|v| v.to_string()), Ok("-40 F".into()));
+
+        let mph = execute(Arithmetic::Div, unit("mi"), unit("h")).unwrap();
+        let kmh = execute(Arithmetic::Div, unit("km"), unit("h")).unwrap();
+        let speed = convert(q(60, "mph"), kmh);
+        assert_eq!(speed.map(|v| v.to_string()), Ok("96.56064 km/h".into()));
+        let speed = convert(q(60, "mph"), mph);
+        assert_eq!(speed.map(|v| v.to_string()), Ok("60 mi/h".into()));
+
+        let hot = execute(Arithmetic::Gt, q(100, "C"), q(200, "F"));
+        assert_eq!(hot, Ok(Value::from(1)));
+    }
+}
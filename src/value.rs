@@ -0,0 +1,160 @@
+use crate::complex::Complex;
+use crate::format::Format;
+use crate::interval::Interval;
+use crate::polynomial::Polynomial;
+use crate::symbolic::Symbolic;
+use crate::units::Quantity;
+use compact_str::CompactString;
+use malachite::{Integer, Rational};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Int(Integer),
+    /// Non-integer number. Exact for rational results, irrational results are rounded to the
+    /// calculator precision.
+    Real(Rational),
+    List(Vec<Value>),
+    /// Time duration in seconds
+    Duration(Rational),
+    /// Formatted text, only produced by functions for display
+    Str(CompactString),
+    /// Number with a unit of measurement
+    Quantity(Quantity),
+    /// Number with a non-zero imaginary part
+    Complex(Complex),
+    /// Number with an uncertainty, e.g. `2.5 ± 0.1`
+    Interval(Interval),
+    /// Polynomial in `x`, e.g. `poly([1, 0, -2])`
+    Poly(Polynomial),
+    /// Polynomial in undefined identifiers, e.g. `5*x`, only produced in symbolic mode
+    Symbolic(Symbolic),
+}
+
+impl Value {
+    pub fn into_int(self) -> Option<Integer> {
+        match self {
+            Value::Int(i) => Some(i),
+            Value::Real(_)
+            | Value::List(_)
+            | Value::Duration(_)
+            | Value::Str(_)
+            | Value::Quantity(_)
+            | Value::Complex(_)
+            | Value::Interval(_)
+            | Value::Poly(_)
+            | Value::Symbolic(_) => None,
+        }
+    }
+
+    pub fn to_rational(&self) -> Option<Rational> {
+        match self {
+            Value::Int(i) => Some(Rational::from(i)),
+            Value::Real(r) => Some(r.clone()),
+            Value::List(_)
+            | Value::Duration(_)
+            | Value::Str(_)
+            | Value::Quantity(_)
+            | Value::Complex(_)
+            | Value::Interval(_)
+            | Value::Poly(_)
+            | Value::Symbolic(_) => None,
+        }
+    }
+
+    /// Whether the number is non-zero, `None` for lists
+    pub fn to_bool(&self) -> Option<bool> {
+        match self {
+            Value::Int(i) => Some(*i != 0),
+            Value::Real(r) | Value::Duration(r) => Some(*r != 0u32),
+            Value::Quantity(q) => Some(q.magnitude != 0u32),
+            // The imaginary part is never zero
+            Value::Complex(_) => Some(true),
+            // Unknown if the interval contains zero and other numbers
+            Value::Interval(i) if i.lo == 0u32 && i.hi == 0u32 => Some(false),
+            Value::Interval(i) => (i.lo > 0u32 || i.hi < 0u32).then_some(true),
+            Value::Poly(p) => Some(!p.is_zero()),
+            // Depends on the values of the variables
+            Value::List(_) | Value::Str(_) | Value::Symbolic(_) => None,
+        }
+    }
+}
+
+impl From<Integer> for Value {
+    fn from(value: Integer) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<Rational> for Value {
+    fn from(value: Rational) -> Self {
+        Value::Real(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value.into())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::from(i64::from(value))
+    }
+}
+
+impl std::ops::Neg for Value {
+    type Output = Value;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Value::Int(i) => Value::Int(-i),
+            Value::Real(r) => Value::Real(-r),
+            Value::List(l) => Value::List(l.into_iter().map(|v| -v).collect()),
+            Value::Duration(d) => Value::Duration(-d),
+            Value::Quantity(q) => Value::Quantity(Quantity {
+                magnitude: -q.magnitude,
+                unit: q.unit,
+            }),
+            Value::Complex(c) => Value::Complex(Complex::new(-c.re, -c.im)),
+            Value::Interval(i) => Value::Interval(Interval {
+                lo: -i.hi,
+                hi: -i.lo,
+            }),
+            Value::Poly(p) => Value::Poly(-p),
+            Value::Symbolic(s) => Value::Symbolic(-s),
+            // Text can't be negated, evaluation rejects it before getting here
+            Value::Str(s) => Value::Str(s),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Format::default().display(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let real = |n, d| Value::Real(Rational::from_signeds(n, d)).to_string();
+        assert_eq!(real(5, 2), "2.5");
+        assert_eq!(real(-1, 8), "-0.125");
+        assert_eq!(real(4, 1), "4");
+        assert_eq!(
+            real(2, 3),
+            "0.66666666666666666666666666666666666666666666666667"
+        );
+        let list = Value::List(vec![
+            Value::from(1),
+            Value::Real(Rational::from_signeds(1, 2)),
+        ]);
+        assert_eq!(list.to_string(), "[1, 0.5]");
+    }
+}
@@ -0,0 +1,56 @@
+//! JavaScript API of the `wasm` feature, built with wasm-bindgen
+
+use wasm_bindgen::prelude::*;
+
+/// Evaluate the expression with the default settings, see [`crate::eval`]
+#[wasm_bindgen]
+pub fn eval(expr: &str) -> Result<String, JsError> {
+    Ok(crate::eval(expr)?)
+}
+
+/// Evaluation of lines that keeps variables, functions and results, see [`crate::Session`]
+#[wasm_bindgen]
+#[derive(Debug, Default)]
+pub struct Session(crate::Session);
+
+#[wasm_bindgen]
+impl Session {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Result of the line formatted for display, `undefined` for function definitions
+    pub fn eval(&mut self, line: &str) -> Result<Option<String>, JsError> {
+        Ok(self.0.eval_text(line)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // Run with `wasm-pack test --node -- --features wasm` on WebAssembly
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    // Errors can only be created on WebAssembly targets
+    #[test]
+    fn test_session() {
+        assert_eq!(eval("2 ** 64").unwrap(), "18446744073709551616");
+        let mut session = Session::new();
+        assert_eq!(session.eval("f(x) = x * 2").unwrap(), None);
+        assert_eq!(session.eval("f(21)").unwrap().as_deref(), Some("42"));
+    }
+
+    // Evaluated on the stack of the caller, without a thread
+    #[test]
+    fn test_depth() {
+        let nested = format!("{}1{}", "-(".repeat(14), ")".repeat(14));
+        assert_eq!(eval(&nested).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_now() {
+        assert_eq!(eval("now() > 1700000000").unwrap(), "1");
+    }
+}
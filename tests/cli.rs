@@ -0,0 +1,282 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Standard output of `sc` with the arguments and the input, and whether it succeeded
+fn sc(args: &[&str], input: &str) -> (String, bool) {
+    let output = run(args, input);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    (stdout, output.status.success())
+}
+
+/// Error output of `sc` with the arguments and the input
+fn sc_errors(args: &[&str], input: &str) -> String {
+    String::from_utf8(run(args, input).stderr).unwrap()
+}
+
+fn run(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sc"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_jobs() {
+    let input = "1 + 1\nout(1) * 2\n$2 + 1\nx = 5\nx + $1\n";
+    let expected = "2\n4\n5\n5\n7\n";
+    assert_eq!(sc(&[], input), (expected.into(), true));
+    assert_eq!(sc(&["--jobs", "4"], input), (expected.into(), true));
+}
+
+#[test]
+fn test_factor() {
+    let factor = |args: &[&str]| sc(&[&["factor"], args].concat(), "");
+    assert_eq!(factor(&["360"]), ("360 = 2**3 * 3**2 * 5\n".into(), true));
+    assert_eq!(factor(&["13"]), ("13 = 13\n".into(), true));
+    assert_eq!(factor(&["1"]), ("1 = 1\n".into(), true));
+    assert_eq!(
+        factor(&["--", "-12"]),
+        ("-12 = -1 * 2**2 * 3\n".into(), true)
+    );
+    assert_eq!(
+        factor(&["--json", "1"]),
+        ("{\"number\": 1, \"factors\": []}\n".into(), true)
+    );
+    assert_eq!(
+        factor(&["--json", "-12"]),
+        (
+            "{\"number\": -12, \"factors\": [[2, 2], [3, 1]]}\n".into(),
+            true
+        )
+    );
+    // Integers beyond 2**53 lose precision as JSON numbers
+    assert_eq!(
+        factor(&["--json", "2**53 + 1"]),
+        (
+            "{\"number\": \"9007199254740993\", \"factors\": [[3, 1], [107, 1], [28059810762433, 1]]}\n"
+                .into(),
+            true
+        )
+    );
+    assert_eq!(
+        factor(&["--json", "2**61 - 1"]),
+        (
+            "{\"number\": \"2305843009213693951\", \"factors\": [[\"2305843009213693951\", 1]]}\n"
+                .into(),
+            true
+        )
+    );
+    assert!(!factor(&["0"]).1);
+}
+
+#[test]
+fn test_completions() {
+    let (bash, success) = sc(&["completions", "bash"], "");
+    assert!(success);
+    assert!(bash
+        .contains("--color)\n                    COMPREPLY=($(compgen -W \"auto always never\""));
+    assert!(bash.contains(":precision"));
+    let (fish, success) = sc(&["completions", "fish"], "");
+    assert!(success);
+    assert!(fish.contains("__fish_sc_using_subcommand convert\" -l to"));
+    assert!(!sc(&["completions", "tcsh"], "").1);
+}
+
+#[test]
+fn test_error_location() {
+    let color = ["--color", "always"];
+    let highlighted = |line: &str| {
+        let errors = sc_errors(&color, line);
+        errors.lines().next().unwrap_or_default().to_owned()
+    };
+    assert_eq!(highlighted("1 +* 2\n"), "1 \x1b[4;31m+*\x1b[0m 2");
+    assert_eq!(highlighted("x = foo\n"), "x = \x1b[4;31mfoo\x1b[0m");
+    assert_eq!(
+        highlighted("foo_bar = foo\n"),
+        "foo_bar = \x1b[4;31mfoo\x1b[0m"
+    );
+    assert_eq!(
+        highlighted("1 + sqrt(1, 2)\n"),
+        "1 + \x1b[4;31msqrt\x1b[0m(1, 2)"
+    );
+    let errors = sc_errors(&["--color", "always", "2 * foo"], "");
+    assert!(errors.starts_with("2 * \x1b[4;31mfoo\x1b[0m"));
+    assert_eq!(sc_errors(&["2 * foo"], ""), "Unknown identifier: foo\n");
+}
+
+#[test]
+fn test_repl() {
+    // Piped lines are read after each prompt, and `:quit` ends the session
+    let input = "2 * 3\nx = 4\n:quit\n5\n";
+    assert_eq!(sc(&["repl"], input), (">>> 6\n>>> 4\n>>> ".into(), true));
+    assert_eq!(sc(&[], input), ("6\n4\n".into(), true));
+    assert!(!sc(&["repl", "1 + 1"], "").1);
+}
+
+#[test]
+fn test_status() {
+    let code = |expr: &str| run(&["--status", expr], "").status.code();
+    assert_eq!(code("2 > 1"), Some(0));
+    assert_eq!(code("0 - 3"), Some(0));
+    assert_eq!(code("2 < 1"), Some(1));
+    assert_eq!(code("0"), Some(1));
+    // Errors are told apart from false results
+    assert_eq!(code("x"), Some(2));
+    assert_eq!(sc(&["--status", "1 + 1"], ""), ("2\n".into(), true));
+}
+
+#[test]
+fn test_separate() {
+    let separate = |args: &[&str]| sc(&[&["--separate"], args].concat(), "");
+    assert_eq!(separate(&["1 + 1", "2 * 3"]), ("2\n6\n".into(), true));
+    // Otherwise the arguments are a single expression
+    assert_eq!(sc(&["1 +", "2"], ""), ("3\n".into(), true));
+    assert_eq!(separate(&["1 +", "2"]), ("".into(), false));
+    assert_eq!(
+        separate(&["--keep-going", "1 +", "2"]),
+        ("2\n".into(), false)
+    );
+}
+
+#[test]
+fn test_separators() {
+    assert_eq!(
+        sc(&["--print0", "--separate", "1", "2"], ""),
+        ("1\x002\0".into(), true)
+    );
+    assert_eq!(
+        sc(&["-n", "--separate", "1", "2"], ""),
+        ("1\n2".into(), true)
+    );
+    assert_eq!(sc(&["--no-newline", "2 * 3"], ""), ("6".into(), true));
+}
+
+#[test]
+fn test_time() {
+    let output = run(&["--time", "--separate", "2**10", "3"], "");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1024\n3\n");
+    let errors = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(errors.lines().count(), 2);
+    assert!(errors.lines().all(|line| line.starts_with("time: ")));
+    assert_eq!(sc_errors(&["2**10"], ""), "");
+}
+
+#[test]
+fn test_subcommands() {
+    // A bare expression is evaluated as with `eval`
+    assert_eq!(sc(&["eval", "1 + 1"], ""), ("2\n".into(), true));
+    assert_eq!(sc(&["eval", "1", "+", "1"], ""), sc(&["1 + 1"], ""));
+    assert_eq!(sc(&["eval"], "2*3\n"), ("6\n".into(), true));
+    assert_eq!(sc(&["fmt", "1+2*3"], ""), ("1 + 2 * 3\n".into(), true));
+    assert_eq!(sc(&["fmt"], "1+2\n"), ("1 + 2\n".into(), true));
+}
+
+#[test]
+fn test_convert() {
+    assert_eq!(
+        sc(&["convert", "255", "--to", "bin,dec,hex,oct"], ""),
+        (
+            "bin  0b11111111\ndec  255\nhex  0xff\noct  0o377\n".into(),
+            true
+        )
+    );
+    // A single radix is printed without a label
+    assert_eq!(
+        sc(&["convert", "0xff", "--to", "bin"], ""),
+        ("0b11111111\n".into(), true)
+    );
+    assert_eq!(
+        sc(&["convert", "1 + 2 * 3", "--to", "rpn,dec"], ""),
+        ("rpn  1 2 3 * +\ndec  7\n".into(), true)
+    );
+}
+
+#[test]
+fn test_fractions() {
+    let fractions = |style: &str| sc(&["--fractions", style, "7/2"], "");
+    assert_eq!(fractions("decimal"), ("3.5\n".into(), true));
+    assert_eq!(fractions("fraction"), ("7/2\n".into(), true));
+    assert_eq!(fractions("mixed"), ("3 1/2\n".into(), true));
+    assert_eq!(sc(&["--fixed", "2", "7/2"], ""), ("3.50\n".into(), true));
+    assert_eq!(
+        sc(&[], "7/2\n:fractions mixed\n7/2\n"),
+        ("3.5\n3 1/2\n".into(), true)
+    );
+}
+
+#[test]
+fn test_repeating() {
+    let repeating = |expr: &str| sc(&["--fractions", "repeating", expr], "");
+    assert_eq!(repeating("1/7"), ("0.(142857)\n".into(), true));
+    assert_eq!(repeating("-1/6"), ("-0.1(6)\n".into(), true));
+    assert_eq!(repeating("1/4"), ("0.25\n".into(), true));
+    let input = "1/3\n:fractions repeating\n1/3\n:fractions\n";
+    assert_eq!(
+        sc(&[], input),
+        (
+            "0.33333333333333333333333333333333333333333333333333\n0.(3)\nrepeating\n".into(),
+            true
+        )
+    );
+}
+
+#[test]
+fn test_verbose() {
+    assert_eq!(
+        sc(&["--verbose", "2+3*4"], ""),
+        ("2 + 3 * 4 = 14\n".into(), true)
+    );
+    // Definitions are echoed as they are
+    assert_eq!(
+        sc(&["--verbose"], "x = 2\nx*3\n"),
+        ("x = 2\nx * 3 = 6\n".into(), true)
+    );
+}
+
+#[test]
+fn test_division() {
+    assert_eq!(sc(&["7/2"], ""), ("3.5\n".into(), true));
+    assert_eq!(sc(&["5 ÷ 2"], ""), ("2.5\n".into(), true));
+    assert_eq!(sc(&[], "z = 6\nz /= 4\n"), ("6\n1.5\n".into(), true));
+    assert_eq!(sc(&["--integer-division", "7/2"], ""), ("3\n".into(), true));
+    assert_eq!(sc(&["--width", "8", "7/2"], ""), ("3\n".into(), true));
+}
+
+#[test]
+fn test_decimal_comma() {
+    let comma = |expr: &str| sc(&["--decimal-comma", expr], "");
+    assert_eq!(comma("max(3,14; 2)"), ("3,14\n".into(), true));
+    assert_eq!(comma("[1,5; 2] * 2"), ("[3; 4]\n".into(), true));
+    assert_eq!(
+        sc_errors(&["--decimal-comma", "max(1,2)"], ""),
+        "Ambiguous ',' in arguments, separate them with ';' or write decimals with '.'\n"
+    );
+    assert!(!comma("max(1, 2)").1);
+}
+
+#[test]
+fn test_export() {
+    let path = std::env::temp_dir().join(format!("sc-export-{}.md", std::process::id()));
+    let input = format!("a = 2\nf(x) = x*a\nf(3)\n:export {}\n", path.display());
+    assert_eq!(sc(&[], &input), ("2\n6\n".into(), true));
+    let exported = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        exported,
+        "| Expression | Result |\n\
+         | --- | --- |\n\
+         | `a = 2` | `2` |\n\
+         | `f(x) = x*a` |  |\n\
+         | `f(3)` | `6` |\n"
+    );
+}